@@ -0,0 +1,50 @@
+use {
+    criterion::{criterion_group, criterion_main, Criterion, Throughput},
+    lib_extract::Runner,
+    std::{
+        env, fs,
+        os::unix::fs::PermissionsExt,
+        path::{Path, PathBuf},
+        process,
+    },
+};
+
+const DIRS: usize = 100;
+const FILES_PER_DIR: usize = 200;
+
+/// Builds an exec root of twenty thousand executables, spread over a hundred directories with
+/// plenty of ties in priority, roughly the shape of a large fleet's collection of scripts
+fn exec_root() -> PathBuf {
+    let root = env::temp_dir().join(format!("extract-discover-{}", process::id()));
+    for dir in 0..DIRS {
+        let dir = root.join(format!("{}_dir{}", dir % 10, dir));
+        fs::create_dir_all(&dir).unwrap();
+        for file in 0..FILES_PER_DIR {
+            let path = dir.join(format!("{}_script{}.sh", file % 20, file));
+            fs::write(&path, "#!/bin/sh\n").unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+    }
+
+    root
+}
+
+fn discover(c: &mut Criterion) {
+    let root = exec_root();
+    let runner = Runner::from_args(vec![Path::new("extract"), &root, Path::new("stdout")])
+        .expect("Unable to configure the runner");
+    let mut group = c.benchmark_group("discover");
+    group
+        .sample_size(10)
+        .throughput(Throughput::Elements((DIRS * FILES_PER_DIR) as u64));
+
+    group.bench_function("sorted", |b| {
+        b.iter(|| runner.executables(&root).filter(Result::is_ok).count())
+    });
+
+    group.finish();
+    fs::remove_dir_all(&root).unwrap();
+}
+
+criterion_group!(benches, discover);
+criterion_main!(benches);
@@ -0,0 +1,1551 @@
+#![allow(deprecated)]
+use {
+    crate::{
+        compare::Priority,
+        config::IoPriority,
+        ids::{IdStrategy, OnCollision},
+        patterns::{validate as validate_pattern, Patterns},
+        rotate::Rotation,
+        tls::Tls,
+    },
+    clap::{crate_authors, crate_version, App, Arg, ArgMatches, SubCommand},
+    lib_transport::{
+        Checksum, Compression, Encryption, Key, Meta, BINARY_DATA_VERSION, DEFAULT_ZSTD_LEVEL,
+    },
+    std::{
+        collections::HashMap,
+        ffi::OsString,
+        iter,
+        path::{Path, PathBuf},
+        sync::Arc,
+        time::{Duration, Instant},
+    },
+};
+
+/// Most bytes kept in the spool at once, unless told otherwise
+const DEFAULT_SPOOL_SIZE: u64 = 64 * 1024 * 1024;
+
+#[cfg(unix)]
+pub fn generate_cli<'a, 'b>() -> App<'a, 'b> {
+    __generate_cli().subcommand(
+        SubCommand::with_name("socket")
+            .about("Use a unix socket for output")
+            .arg(
+                Arg::with_name("socket_connect")
+                    .takes_value(false)
+                    .value_name("PATH")
+                    .required(true)
+                    .validator(|val| match PathBuf::from(&val).exists() {
+                        true => Ok(()),
+                        false => Err(format!("'{}' does not exist or is an invalid path", &val)),
+                    })
+                    .help("Connect to socket at PATH"),
+            ),
+    )
+}
+
+#[cfg(not(unix))]
+pub fn generate_cli<'a, 'b>() -> App<'a, 'b> {
+    __generate_cli()
+}
+
+/// Generates base CLI without architecture specific options
+fn __generate_cli<'a, 'b>() -> App<'a, 'b> {
+    App::new("skipframe")
+        .about("Reads and executes files from a given directory")
+        .author(crate_authors!("\n"))
+        .version(crate_version!())
+        .arg(
+            Arg::with_name("exec_root")
+                .takes_value(false)
+                .value_name("PATH")
+                .required(true)
+                .multiple(true)
+                .help(
+                    "Point at directory root of files to execute. Given several, each is run \
+                    concurrently as its own stream over the one connection. An executable's \
+                    arguments and environment can be set in a sidecar '<name>.yaml' file, and a \
+                    root's 'manifest.yaml' can order its executables in place of their names. \
+                    A YAML file of commands, each with a name, path, args and priority, may be \
+                    given in place of a directory to run them instead",
+                ),
+        )
+        .arg(
+            Arg::with_name("checksum")
+                .long("checksum")
+                .takes_value(false)
+                .help(
+                    "Append a CRC32 checksum to every record, the receiver must also enable this",
+                ),
+        )
+        .arg(
+            Arg::with_name("binary")
+                .long("binary")
+                .takes_value(false)
+                .help(
+                    "Send child output as raw bytes rather than lossily converting it to UTF-8, \
+                    the receiver must support protocol version 2",
+                ),
+        )
+        .arg(
+            Arg::with_name("max_line")
+                .long("max-line")
+                .takes_value(true)
+                .value_name("BYTES")
+                .validator(|val| match val.parse::<usize>() {
+                    Ok(bytes) if bytes > 0 => Ok(()),
+                    _ => Err(format!("'{}' is not a valid number of bytes", &val)),
+                })
+                .help(
+                    "Split child output lines longer than BYTES across several records, each \
+                    but the last labelled 'truncated', so no more than BYTES of a line is held \
+                    in memory at once",
+                ),
+        )
+        .arg(
+            Arg::with_name("max_output")
+                .long("max-output")
+                .takes_value(true)
+                .value_name("BYTES")
+                .validator(|val| match val.parse::<u64>() {
+                    Ok(bytes) if bytes > 0 => Ok(()),
+                    _ => Err(format!("'{}' is not a valid number of bytes", &val)),
+                })
+                .help(
+                    "Stop reading a child's output once it has written more than BYTES across \
+                    stdout and stderr, killing it and sending an error in place of the rest. \
+                    Overridden by its sidecar's 'max_output_bytes'",
+                ),
+        )
+        .arg(
+            Arg::with_name("lines_per_sec")
+                .long("lines-per-sec")
+                .takes_value(true)
+                .value_name("LINES")
+                .validator(|val| match val.parse::<u64>() {
+                    Ok(lines) if lines > 0 => Ok(()),
+                    _ => Err(format!("'{}' is not a valid number of lines", &val)),
+                })
+                .help(
+                    "Send at most LINES lines of a child's output a second, across stdout and \
+                    stderr, with bursts of up to a second's worth. Lines over the limit are \
+                    dropped, and how many were is sent as a log once the child's output ends",
+                ),
+        )
+        .arg(
+            Arg::with_name("bytes_per_sec")
+                .long("bytes-per-sec")
+                .takes_value(true)
+                .value_name("BYTES")
+                .validator(|val| match val.parse::<u64>() {
+                    Ok(bytes) if bytes > 0 => Ok(()),
+                    _ => Err(format!("'{}' is not a valid number of bytes", &val)),
+                })
+                .help("As --lines-per-sec, limiting the bytes of a child's output sent a second"),
+        )
+        .arg(
+            Arg::with_name("dedup")
+                .long("dedup")
+                .takes_value(false)
+                .help(
+                    "Collapse runs of identical lines of child output, sending the first as is \
+                    and the rest as one record labelled with how many times it 'repeated'",
+                ),
+        )
+        .arg(
+            Arg::with_name("key_file")
+                .long("key-file")
+                .takes_value(true)
+                .value_name("PATH")
+                .validator(|val| Key::from_file(&val).map(|_| ()).map_err(|e| e.to_string()))
+                .help(
+                    "Encrypt every record with the hex encoded 256 bit key in PATH, the receiver \
+                    must be given the same key",
+                ),
+        )
+        .arg(
+            Arg::with_name("compress")
+                .long("compress")
+                .takes_value(true)
+                .value_name("zstd[:LEVEL]")
+                .validator(|val| match parse_compression(&val) {
+                    Some(_) => Ok(()),
+                    None => Err(format!(
+                        "'{}' is not a valid compression, expected zstd or zstd:1 to zstd:22",
+                        &val
+                    )),
+                })
+                .help(
+                    "Compress every record with zstd at LEVEL, 3 by default, before any checksum \
+                    or encryption. The receiver must also support this",
+                ),
+        )
+        .arg(
+            Arg::with_name("heartbeat")
+                .long("heartbeat")
+                .takes_value(true)
+                .value_name("SECONDS")
+                .validator(|val| match val.parse::<u64>() {
+                    Ok(secs) if secs > 0 => Ok(()),
+                    _ => Err(format!("'{}' is not a valid number of seconds", &val)),
+                })
+                .help("Send a heartbeat record whenever output has been idle for SECONDS"),
+        )
+        .arg(
+            Arg::with_name("metrics")
+                .long("metrics")
+                .takes_value(true)
+                .value_name("SECONDS")
+                .validator(|val| match val.parse::<u64>() {
+                    Ok(secs) if secs > 0 => Ok(()),
+                    _ => Err(format!("'{}' is not a valid number of seconds", &val)),
+                })
+                .help("Report throughput in a metrics record every SECONDS"),
+        )
+        .arg(
+            Arg::with_name("reconnect")
+                .long("reconnect")
+                .takes_value(true)
+                .value_name("SECONDS")
+                .validator(|val| match val.parse::<u64>() {
+                    Ok(secs) if secs > 0 => Ok(()),
+                    _ => Err(format!("'{}' is not a valid number of seconds", &val)),
+                })
+                .help(
+                    "Keep trying to connect, or reconnect once the connection is lost, with \
+                    exponential backoff for up to SECONDS before giving up",
+                ),
+        )
+        .arg(
+            Arg::with_name("spool")
+                .long("spool")
+                .takes_value(true)
+                .value_name("DIR")
+                .requires("reconnect")
+                .help(
+                    "While reconnecting, keep records on disk in DIR rather than holding up \
+                    executables, sending them once reconnected. Only applies to the output \
+                    selected, not those given by --tee",
+                ),
+        )
+        .arg(
+            Arg::with_name("spool_size")
+                .long("spool-size")
+                .takes_value(true)
+                .value_name("BYTES")
+                .requires("spool")
+                .validator(|val| match val.parse::<u64>() {
+                    Ok(bytes) if bytes > 0 => Ok(()),
+                    _ => Err(format!("'{}' is not a valid number of bytes", &val)),
+                })
+                .help("Most bytes kept in the spool at once [default: 64MiB]"),
+        )
+        .arg(
+            Arg::with_name("ack_window")
+                .long("ack-window")
+                .takes_value(true)
+                .value_name("RECORDS")
+                .validator(|val| match val.parse::<u64>() {
+                    Ok(records) if records > 0 => Ok(()),
+                    _ => Err(format!("'{}' is not a valid window size", &val)),
+                })
+                .help("Wait for the receiver to acknowledge records once RECORDS are in flight"),
+        )
+        .arg(
+            Arg::with_name("batch")
+                .long("batch")
+                .takes_value(true)
+                .value_name("RECORDS")
+                .validator(|val| match val.parse::<usize>() {
+                    Ok(records) if records > 0 => Ok(()),
+                    _ => Err(format!("'{}' is not a valid batch size", &val)),
+                })
+                .help("Pack up to RECORDS records into each frame sent"),
+        )
+        .arg(
+            Arg::with_name("watch")
+                .long("watch")
+                .takes_value(false)
+                .help(
+                    "Keep running after the first run, re-running any executable that is added \
+                    or modified. Only directories modified since the last look are rescanned, so \
+                    a file made executable in place is only noticed once its directory changes",
+                ),
+        )
+        .arg(
+            Arg::with_name("every")
+                .long("every")
+                .takes_value(true)
+                .value_name("INTERVAL")
+                .conflicts_with("watch")
+                .validator(|val| parse_interval(&val).map(|_| ()))
+                .help(
+                    "Keep running, re-running every executable each INTERVAL (i.e 90s, 5m, 1h \
+                    or 1d) as a fresh logical stream over the one connection. As with --watch, \
+                    only directories modified since the last run are rescanned",
+                ),
+        )
+        .arg(
+            Arg::with_name("splay")
+                .long("splay")
+                .takes_value(true)
+                .value_name("INTERVAL")
+                .validator(|val| parse_interval(&val).map(|_| ()))
+                .help(
+                    "Wait a random time of up to INTERVAL before running, and before each run \
+                    under --every, so that a fleet started at once doesn't connect and send \
+                    at once. The seed of each wait is sent as a log",
+                ),
+        )
+        .arg(
+            Arg::with_name("dry_run")
+                .long("dry-run")
+                .takes_value(false)
+                .conflicts_with_all(&["watch", "every"])
+                .help(
+                    "Run nothing, instead sending a header for each executable found describing \
+                    the priority group it would run in and in what order",
+                ),
+        )
+        .arg(
+            Arg::with_name("group_policy")
+                .long("group-policy")
+                .takes_value(true)
+                .value_name("POLICY")
+                .default_value("serial")
+                .validator(|val| match parse_group_policy(&val) {
+                    Some(_) => Ok(()),
+                    None => Err(format!(
+                        "'{}' is not a valid policy, i.e serial, overlap=N or parallel",
+                        &val
+                    )),
+                })
+                .help(
+                    "When each priority group starts: 'serial' once every executable of the \
+                    groups before it has finished, 'overlap=N' once at most N are still \
+                    running, or 'parallel' straight away, keeping only the order they start in",
+                ),
+        )
+        .arg(
+            Arg::with_name("group_jobs")
+                .long("group-jobs")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("PRIORITY=JOBS")
+                .validator(|val| match parse_group_jobs(&val) {
+                    Some(_) => Ok(()),
+                    None => Err(format!("'{}' is not a PRIORITY=JOBS pair", &val)),
+                })
+                .help(
+                    "Run at most JOBS executables of priority PRIORITY at once, i.e '10=1' for \
+                    a group that must run one at a time, or 'none=2' for those without a \
+                    priority. Applies within --jobs, overriding any 'jobs' a manifest's groups \
+                    set, whose priority is the level they run at. May be repeated",
+                ),
+        )
+        .arg(
+            Arg::with_name("id_strategy")
+                .long("id-strategy")
+                .takes_value(true)
+                .value_name("STRATEGY")
+                .default_value("name")
+                .validator(|val| match IdStrategy::parse(&val) {
+                    Some(_) => Ok(()),
+                    None => Err(format!(
+                        "'{}' is not a valid strategy, i.e name, path, hash or uuid",
+                        &val
+                    )),
+                })
+                .help(
+                    "How each executable's records are identified: 'name' by its file name, \
+                    'path' by its path under its exec root, 'hash' by its file name and a hash \
+                    of that path, or 'uuid' by a random UUID each time it runs",
+                ),
+        )
+        .arg(
+            Arg::with_name("id_collision")
+                .long("id-collision")
+                .takes_value(true)
+                .value_name("ACTION")
+                .default_value("warn")
+                .validator(|val| match OnCollision::parse(&val) {
+                    Some(_) => Ok(()),
+                    None => Err(format!(
+                        "'{}' is not a valid action, i.e warn, fail or suffix",
+                        &val
+                    )),
+                })
+                .help(
+                    "What to do when executables of an exec root would share an id: 'warn' and \
+                    run them anyway, 'fail' and run none of them, or 'suffix' every id after \
+                    the first with ~2, ~3 and so on",
+                ),
+        )
+        .arg(
+            Arg::with_name("summary")
+                .long("summary")
+                .takes_value(false)
+                .conflicts_with_all(&["watch", "every"])
+                .help(
+                    "Once everything has run, send a metrics record summarizing the run, and \
+                    each priority group in it: the executables run, failures, lines and bytes \
+                    of output, and wall time. The listener must support metrics",
+                ),
+        )
+        .arg(
+            Arg::with_name("facts")
+                .long("facts")
+                .takes_value(false)
+                .help(
+                    "Start the stream with a record describing this host: its hostname, OS, \
+                    kernel, CPU count and the version of this program. The listener must \
+                    support facts",
+                ),
+        )
+        .arg(
+            Arg::with_name("pipeline")
+                .long("pipeline")
+                .takes_value(true)
+                .value_name("NAME")
+                .help(
+                    "Ask the listener to send this connection's records through its pipeline \
+                    NAME, rather than its usual exec chain",
+                ),
+        )
+        .arg(
+            Arg::with_name("timings")
+                .long("timings")
+                .takes_value(false)
+                .help(
+                    "Label each executable's closing header with how long it took to spawn, \
+                    to first output and in total, in microseconds",
+                ),
+        )
+        .arg(
+            Arg::with_name("retries")
+                .long("retries")
+                .takes_value(true)
+                .value_name("N")
+                .validator(|val| match val.parse::<u32>() {
+                    Ok(_) => Ok(()),
+                    _ => Err(format!("'{}' is not a valid number of retries", &val)),
+                })
+                .help("Run an executable that fails to start or exits unsuccessfully again, up to N times"),
+        )
+        .arg(
+            Arg::with_name("retry_backoff")
+                .long("retry-backoff")
+                .takes_value(true)
+                .value_name("SECONDS")
+                .default_value("1")
+                .validator(|val| match val.parse::<u64>() {
+                    Ok(_) => Ok(()),
+                    _ => Err(format!("'{}' is not a valid number of seconds", &val)),
+                })
+                .help("Wait SECONDS before the first retry, doubling before each after"),
+        )
+        .arg(
+            Arg::with_name("user")
+                .long("user")
+                .takes_value(true)
+                .value_name("USER")
+                .help(
+                    "Run every executable as USER (a name or uid), unless its sidecar file says \
+                    otherwise. Requires root, or CAP_SETUID and CAP_SETGID",
+                ),
+        )
+        .arg(
+            Arg::with_name("group")
+                .long("group")
+                .takes_value(true)
+                .value_name("GROUP")
+                .help(
+                    "Run every executable as GROUP (a name or gid), unless its sidecar file says \
+                    otherwise. Defaults to the primary group of --user",
+                ),
+        )
+        .arg(
+            Arg::with_name("nice")
+                .long("nice")
+                .takes_value(true)
+                .value_name("NICENESS")
+                .allow_hyphen_values(true)
+                .validator(|val| match val.parse::<i32>() {
+                    Ok(nice) if (-20..=19).contains(&nice) => Ok(()),
+                    _ => Err(format!("'{}' is not a valid niceness, i.e -20 to 19", &val)),
+                })
+                .help(
+                    "Run every executable at NICENESS, from -20 (most favourable) to 19, unless \
+                    its sidecar file says otherwise. Lower than this program's requires root",
+                ),
+        )
+        .arg(
+            Arg::with_name("ionice")
+                .long("ionice")
+                .takes_value(true)
+                .value_name("CLASS[:LEVEL]")
+                .validator(|val| IoPriority::parse(&val).map(|_| ()))
+                .help(
+                    "Run every executable in the IO scheduling CLASS, realtime, best-effort or \
+                    idle, at LEVEL from 0 (highest) to 7, unless its sidecar file says otherwise",
+                ),
+        )
+        .arg(
+            Arg::with_name("env_allow")
+                .long("env-allow")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("GLOB")
+                .validator(|val| validate_pattern(&val))
+                .help(
+                    "Only pass on environment variables whose name matches GLOB to executables, \
+                    may be repeated",
+                ),
+        )
+        .arg(
+            Arg::with_name("env_strip")
+                .long("env-strip")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("GLOB")
+                .validator(|val| validate_pattern(&val))
+                .help(
+                    "Never pass on environment variables whose name matches GLOB (i.e \
+                    '*_TOKEN') to executables, even those allowed by --env-allow, may be \
+                    repeated",
+                ),
+        )
+        .arg(
+            Arg::with_name("include")
+                .long("include")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("GLOB")
+                .validator(|val| validate_pattern(&val))
+                .help(
+                    "Only run executables whose path under their exec root matches GLOB, \
+                    may be repeated",
+                ),
+        )
+        .arg(
+            Arg::with_name("exclude")
+                .long("exclude")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("GLOB")
+                .validator(|val| validate_pattern(&val))
+                .help(
+                    "Never run executables, or look in directories, whose path under their exec \
+                    root matches GLOB (i.e '*.disabled'), may be repeated",
+                ),
+        )
+        .arg(
+            Arg::with_name("strict_discovery")
+                .long("strict-discovery")
+                .takes_value(false)
+                .help(
+                    "Only run what resolves to a regular executable file, following symlinks, \
+                    and report anything else found as a log record",
+                ),
+        )
+        .arg(
+            Arg::with_name("jobs")
+                .long("jobs")
+                .short("j")
+                .takes_value(true)
+                .value_name("N")
+                .validator(|val| match val.parse::<usize>() {
+                    Ok(jobs) if jobs > 0 => Ok(()),
+                    _ => Err(format!("'{}' is not a valid number of jobs", &val)),
+                })
+                .help("Run at most N executables at once, across every exec root"),
+        )
+        .arg(
+            Arg::with_name("timeout")
+                .long("timeout")
+                .takes_value(true)
+                .value_name("SECONDS")
+                .validator(|val| match val.parse::<u64>() {
+                    Ok(secs) if secs > 0 => Ok(()),
+                    _ => Err(format!("'{}' is not a valid number of seconds", &val)),
+                })
+                .help(
+                    "Kill any executable still running after SECONDS along with its process \
+                    group, reporting an error and moving on",
+                ),
+        )
+        .arg(
+            Arg::with_name("max_runtime")
+                .long("max-runtime")
+                .takes_value(true)
+                .value_name("INTERVAL")
+                .conflicts_with_all(&["watch", "every"])
+                .validator(|val| parse_interval(&val).map(|_| ()))
+                .help(
+                    "End the run after INTERVAL, i.e 90s, 5m, 1h or 1d, killing anything still \
+                    running and reporting an error for each executable that never ran",
+                ),
+        )
+        .arg(
+            Arg::with_name("fail_fast")
+                .long("fail-fast")
+                .takes_value(false)
+                .conflicts_with_all(&["watch", "every"])
+                .help(
+                    "Stop running anything more once an executable fails to spawn, times out or \
+                    exits unexpectedly, ending the stream with an error and exiting non-zero. \
+                    Anything already running is left to finish",
+                ),
+        )
+        .arg(
+            Arg::with_name("interpreter")
+                .long("interpreter")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("EXT=COMMAND")
+                .validator(|val| match parse_interpreter(&val) {
+                    Some(_) => Ok(()),
+                    None => Err(format!("'{}' is not an EXT=COMMAND pair", &val)),
+                })
+                .help(
+                    "Run files ending in EXT with COMMAND (i.e 'py=python3 -u'), whether or not \
+                    they are executable themselves, may be repeated",
+                ),
+        )
+        .arg(
+            Arg::with_name("ssh")
+                .long("ssh")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("HOST")
+                .validator(|val| match val.starts_with('-') || val.is_empty() {
+                    false => Ok(()),
+                    true => Err(format!("'{}' is not a host", &val)),
+                })
+                .help(
+                    "Run every executable on HOST through ssh rather than here, labelling its \
+                    records with the host, may be repeated to run them across a fleet. Scripts \
+                    are copied over as they run, so nothing needs installing on HOST",
+                ),
+        )
+        .arg(
+            Arg::with_name("meta")
+                .long("meta")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("KEY=VALUE")
+                .validator(|val| match val.find('=') {
+                    Some(idx) if idx > 0 => Ok(()),
+                    _ => Err(format!("'{}' is not a KEY=VALUE pair", &val)),
+                })
+                .help(
+                    "Attach a label to every record, may be repeated. Headers are always \
+                    labelled with this host's 'hostname' and 'machine_id', and the \
+                    'instance_id' of this run, which these can't override",
+                ),
+        )
+        .arg(
+            Arg::with_name("tee")
+                .long("tee")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("SINK")
+                .validator(|val| match parse_tee(&val) {
+                    Some(_) => Ok(()),
+                    None => Err(format!(
+                        "'{}' is not a valid sink, i.e tcp:HOST:PORT, socket:PATH, file:PATH or stdout",
+                        &val
+                    )),
+                })
+                .help(
+                    "Also send every record to SINK, alongside the output selected, may be \
+                    repeated. A file sink archives the records as they would be sent. \
+                    Each sink fails independently of the others, and one too far behind the \
+                    output selected is dropped rather than slowing it down",
+                ),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .value_name("FORMAT")
+                .default_value("debug")
+                .validator(|val| match Format::parse(&val) {
+                    Some(_) => Ok(()),
+                    None => Err(format!("'{}' is not a valid format, i.e debug or ndjson", &val)),
+                })
+                .help(
+                    "How records are printed to stdout: 'debug' as a one line summary of each, \
+                    or 'ndjson' as one JSON object per line, as load prints them",
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("tcp")
+                .about("Use a tcp socket for output")
+                .arg(
+                    Arg::with_name("tcp_addr")
+                        .value_name("HOST / IP")
+                        .required(true)
+                        .help("Connect to the given host"),
+                )
+                .arg(
+                    Arg::with_name("tcp_port")
+                        .value_name("PORT")
+                        .default_value("49999")
+                        .validator(|val| {
+                            val.parse::<u16>()
+                                .map(|_| ())
+                                .map_err(|_| format!("'{}' is not a valid port", &val))
+                        })
+                        .help("On the given port"),
+                )
+                .arg(
+                    Arg::with_name("tls")
+                        .long("tls")
+                        .takes_value(false)
+                        .help("Encrypt the connection with TLS, checking the listener's certificate"),
+                )
+                .arg(
+                    Arg::with_name("tls_ca")
+                        .long("tls-ca")
+                        .takes_value(true)
+                        .value_name("PATH")
+                        .requires("tls")
+                        .validator(file_exists)
+                        .help(
+                            "Check the listener's certificate against those in the PEM file at \
+                            PATH, rather than the usual web roots",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("tls_cert")
+                        .long("tls-cert")
+                        .takes_value(true)
+                        .value_name("PATH")
+                        .requires_all(&["tls", "tls_key"])
+                        .validator(file_exists)
+                        .help("Identify with the certificate chain in the PEM file at PATH"),
+                )
+                .arg(
+                    Arg::with_name("tls_key")
+                        .long("tls-key")
+                        .takes_value(true)
+                        .value_name("PATH")
+                        .requires("tls_cert")
+                        .validator(file_exists)
+                        .help("The private key of --tls-cert, in the PEM file at PATH"),
+                )
+                .arg(
+                    Arg::with_name("tls_name")
+                        .long("tls-name")
+                        .takes_value(true)
+                        .value_name("NAME")
+                        .requires("tls")
+                        .help(
+                            "Expect the listener's certificate to be for NAME, rather than the \
+                            host, which must be given when connecting by IP",
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("file")
+                .about("Archive records to a file for output, as they would be sent")
+                .arg(
+                    Arg::with_name("file_path")
+                        .value_name("PATH")
+                        .required(true)
+                        .help("Write to the file at PATH, replacing it if present"),
+                )
+                .arg(
+                    Arg::with_name("rotate_size")
+                        .long("rotate-size")
+                        .takes_value(true)
+                        .value_name("BYTES")
+                        .validator(|val| match val.parse::<u64>() {
+                            Ok(bytes) if bytes > 0 => Ok(()),
+                            _ => Err(format!("'{}' is not a valid number of bytes", &val)),
+                        })
+                        .help("Rotate the file once it holds at least BYTES"),
+                )
+                .arg(
+                    Arg::with_name("rotate_age")
+                        .long("rotate-age")
+                        .takes_value(true)
+                        .value_name("INTERVAL")
+                        .validator(|val| parse_interval(&val).map(|_| ()))
+                        .help(
+                            "Rotate the file once it has been written to for INTERVAL, \
+                            i.e 90s, 5m, 1h or 1d",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("gzip")
+                        .long("gzip")
+                        .takes_value(false)
+                        .help("Compress rotated files with gzip"),
+                ),
+        )
+}
+
+pub(crate) struct ProgramArgs {
+    exec_roots: Vec<PathBuf>,
+    con_type: ConOpts,
+    tees: Vec<ConOpts>,
+    format: Format,
+    checksum: Checksum,
+    binary: bool,
+    max_line: Option<usize>,
+    max_output: Option<u64>,
+    lines_per_sec: Option<u64>,
+    bytes_per_sec: Option<u64>,
+    dedup: bool,
+    encryption: Encryption,
+    compression: Compression,
+    heartbeat: Option<Duration>,
+    metrics: Option<Duration>,
+    reconnect: Option<Duration>,
+    spool: Option<(PathBuf, u64)>,
+    ack_window: Option<u64>,
+    batch: Option<usize>,
+    timeout: Option<Duration>,
+    max_runtime: Option<(Duration, Instant)>,
+    fail_fast: bool,
+    jobs: Option<usize>,
+    watch: bool,
+    every: Option<Duration>,
+    splay: Option<Duration>,
+    dry_run: bool,
+    id_strategy: IdStrategy,
+    id_collision: OnCollision,
+    summary: bool,
+    facts: bool,
+    pipeline: Option<String>,
+    timings: bool,
+    group_overlap: Option<u64>,
+    group_jobs: HashMap<Option<u64>, usize>,
+    retries: u32,
+    retry_backoff: Duration,
+    user: Option<String>,
+    group: Option<String>,
+    nice: Option<i32>,
+    ionice: Option<IoPriority>,
+    env: Option<Patterns>,
+    strict_discovery: bool,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    interpreters: HashMap<String, Vec<String>>,
+    ssh: Vec<String>,
+    meta: Arc<Meta>,
+}
+
+impl ProgramArgs {
+    /// Retains relevant user defined config settings gathered from the CLI
+    pub(crate) fn init(cli: App<'_, '_>) -> Self {
+        Self::from_matches(cli.get_matches())
+    }
+
+    /// As `init`, but from `args` rather than this program's command line, failing rather than
+    /// exiting if they are invalid
+    pub(crate) fn try_from_args<I, T>(cli: App<'_, '_>, args: I) -> clap::Result<Self>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        cli.get_matches_from_safe(args).map(Self::from_matches)
+    }
+
+    fn from_matches(store: ArgMatches<'_>) -> Self {
+        let exec_roots = store
+            .values_of("exec_root")
+            .unwrap()
+            .map(PathBuf::from)
+            .collect();
+
+        let checksum = match store.is_present("checksum") {
+            true => Checksum::Crc32,
+            false => Checksum::None,
+        };
+
+        let binary = store.is_present("binary");
+
+        let max_line = store
+            .value_of("max_line")
+            .map(|s| s.parse::<usize>().unwrap());
+
+        let max_output = store
+            .value_of("max_output")
+            .map(|s| s.parse::<u64>().unwrap());
+
+        let lines_per_sec = store
+            .value_of("lines_per_sec")
+            .map(|s| s.parse::<u64>().unwrap());
+
+        let bytes_per_sec = store
+            .value_of("bytes_per_sec")
+            .map(|s| s.parse::<u64>().unwrap());
+
+        let dedup = store.is_present("dedup");
+
+        let encryption = store
+            .value_of("key_file")
+            .map(|path| Encryption::XChaCha20Poly1305(Key::from_file(path).unwrap()))
+            .unwrap_or_default();
+
+        let compression = store
+            .value_of("compress")
+            .map(|s| parse_compression(s).unwrap())
+            .unwrap_or_default();
+
+        let heartbeat = store
+            .value_of("heartbeat")
+            .map(|s| Duration::from_secs(s.parse::<u64>().unwrap()));
+
+        let metrics = store
+            .value_of("metrics")
+            .map(|s| Duration::from_secs(s.parse::<u64>().unwrap()));
+
+        let reconnect = store
+            .value_of("reconnect")
+            .map(|s| Duration::from_secs(s.parse::<u64>().unwrap()));
+
+        let spool = store.value_of("spool").map(|dir| {
+            let size = store
+                .value_of("spool_size")
+                .map_or(DEFAULT_SPOOL_SIZE, |s| s.parse::<u64>().unwrap());
+            (PathBuf::from(dir), size)
+        });
+
+        let meta = store
+            .values_of("meta")
+            .into_iter()
+            .flatten()
+            .filter_map(|pair| {
+                let mut split = pair.splitn(2, '=');
+                Some((split.next()?.to_string(), split.next()?.to_string()))
+            })
+            .collect::<Meta>();
+
+        let ack_window = store
+            .value_of("ack_window")
+            .map(|s| s.parse::<u64>().unwrap());
+
+        let batch = store.value_of("batch").map(|s| s.parse::<usize>().unwrap());
+
+        let timeout = store
+            .value_of("timeout")
+            .map(|s| Duration::from_secs(s.parse::<u64>().unwrap()));
+
+        // Arguments are parsed as the program starts, so the run is timed from here
+        let max_runtime = store.value_of("max_runtime").map(|s| {
+            let runtime = parse_interval(s).unwrap();
+            (runtime, Instant::now() + runtime)
+        });
+
+        let fail_fast = store.is_present("fail_fast");
+
+        let jobs = store.value_of("jobs").map(|s| s.parse::<usize>().unwrap());
+
+        let watch = store.is_present("watch");
+
+        let dry_run = store.is_present("dry_run");
+        let id_strategy = store
+            .value_of("id_strategy")
+            .and_then(IdStrategy::parse)
+            .unwrap();
+        let id_collision = store
+            .value_of("id_collision")
+            .and_then(OnCollision::parse)
+            .unwrap();
+        let summary = store.is_present("summary");
+        let facts = store.is_present("facts");
+        let pipeline = store.value_of("pipeline").map(String::from);
+        let timings = store.is_present("timings");
+        let group_overlap = store
+            .value_of("group_policy")
+            .and_then(parse_group_policy)
+            .unwrap();
+
+        let group_jobs = store
+            .values_of("group_jobs")
+            .into_iter()
+            .flatten()
+            .filter_map(parse_group_jobs)
+            .collect();
+
+        let retries = store
+            .value_of("retries")
+            .map(|s| s.parse::<u32>().unwrap())
+            .unwrap_or_default();
+
+        let retry_backoff = store
+            .value_of("retry_backoff")
+            .map(|s| Duration::from_secs(s.parse::<u64>().unwrap()))
+            .unwrap();
+
+        let every = store.value_of("every").map(|s| parse_interval(s).unwrap());
+
+        let splay = store.value_of("splay").map(|s| parse_interval(s).unwrap());
+
+        let user = store.value_of("user").map(String::from);
+
+        let group = store.value_of("group").map(String::from);
+
+        let nice = store.value_of("nice").map(|s| s.parse::<i32>().unwrap());
+
+        let ionice = store
+            .value_of("ionice")
+            .map(|s| IoPriority::parse(s).unwrap());
+
+        let env_allow: Vec<_> = store.values_of("env_allow").into_iter().flatten().collect();
+        let env_strip: Vec<_> = store.values_of("env_strip").into_iter().flatten().collect();
+        let env = match env_allow.is_empty() && env_strip.is_empty() {
+            true => None,
+            false => Some(Patterns::new(env_allow, env_strip).unwrap()),
+        };
+
+        let strict_discovery = store.is_present("strict_discovery");
+
+        let include = store
+            .values_of("include")
+            .into_iter()
+            .flatten()
+            .map(String::from)
+            .collect();
+
+        let exclude = store
+            .values_of("exclude")
+            .into_iter()
+            .flatten()
+            .map(String::from)
+            .collect();
+
+        let ssh = store
+            .values_of("ssh")
+            .into_iter()
+            .flatten()
+            .map(String::from)
+            .collect();
+
+        let interpreters = store
+            .values_of("interpreter")
+            .into_iter()
+            .flatten()
+            .filter_map(parse_interpreter)
+            .collect();
+
+        let tees = store
+            .values_of("tee")
+            .into_iter()
+            .flatten()
+            .filter_map(parse_tee)
+            .collect();
+
+        let format = store.value_of("format").and_then(Format::parse).unwrap();
+
+        let con_type;
+        match store.subcommand() {
+            ("socket", Some(sub)) => {
+                con_type =
+                    ConOpts::UnixSocket(PathBuf::from(sub.value_of("socket_connect").unwrap()))
+            }
+            ("tcp", Some(sub)) => {
+                let bind = sub.value_of("tcp_addr").unwrap().into();
+                let port = sub
+                    .value_of("tcp_port")
+                    .map(|s| s.parse::<u16>().unwrap())
+                    .unwrap();
+                let tls = match sub.is_present("tls") {
+                    true => Some(Tls {
+                        ca: sub.value_of("tls_ca").map(PathBuf::from),
+                        identity: sub
+                            .value_of("tls_cert")
+                            .map(PathBuf::from)
+                            .zip(sub.value_of("tls_key").map(PathBuf::from)),
+                        name: sub.value_of("tls_name").map(String::from),
+                    }),
+                    false => None,
+                };
+                con_type = ConOpts::Tcp((bind, port), tls)
+            }
+            ("file", Some(sub)) => {
+                let path = PathBuf::from(sub.value_of("file_path").unwrap());
+                let rotation = Rotation {
+                    size: sub
+                        .value_of("rotate_size")
+                        .map(|s| s.parse::<u64>().unwrap()),
+                    age: sub
+                        .value_of("rotate_age")
+                        .map(|s| parse_interval(s).unwrap()),
+                    gzip: sub.is_present("gzip"),
+                };
+                con_type = ConOpts::File(path, rotation)
+            }
+            _ => con_type = ConOpts::default(),
+        }
+
+        Self {
+            exec_roots,
+            con_type,
+            tees,
+            format,
+            checksum,
+            binary,
+            max_line,
+            max_output,
+            lines_per_sec,
+            bytes_per_sec,
+            dedup,
+            encryption,
+            compression,
+            heartbeat,
+            metrics,
+            reconnect,
+            spool,
+            ack_window,
+            batch,
+            timeout,
+            max_runtime,
+            fail_fast,
+            jobs,
+            watch,
+            every,
+            splay,
+            dry_run,
+            id_strategy,
+            id_collision,
+            summary,
+            facts,
+            pipeline,
+            timings,
+            group_overlap,
+            group_jobs,
+            retries,
+            retry_backoff,
+            user,
+            group,
+            nice,
+            ionice,
+            env,
+            strict_discovery,
+            include,
+            exclude,
+            interpreters,
+            ssh,
+            meta: Arc::new(meta),
+        }
+    }
+
+    /// Return user's specified path roots, in the order given
+    pub(crate) fn exec_roots(&self) -> &[PathBuf] {
+        &self.exec_roots
+    }
+
+    /// Checksum to append to each serialized record
+    pub(crate) fn checksum(&self) -> Checksum {
+        self.checksum
+    }
+
+    /// Version stamped on each record, which decides whether Data records carry raw bytes
+    pub(crate) fn record_version(&self) -> u32 {
+        match self.binary {
+            true => BINARY_DATA_VERSION,
+            false => 1,
+        }
+    }
+
+    /// Longest child output line sent in a single record, if limited
+    pub(crate) fn max_line(&self) -> Option<usize> {
+        self.max_line
+    }
+
+    /// Most output read from a child before it is killed, if limited
+    pub(crate) fn max_output(&self) -> Option<u64> {
+        self.max_output
+    }
+
+    /// Most lines of a child's output sent a second, if limited
+    pub(crate) fn lines_per_sec(&self) -> Option<u64> {
+        self.lines_per_sec
+    }
+
+    /// Most bytes of a child's output sent a second, if limited
+    pub(crate) fn bytes_per_sec(&self) -> Option<u64> {
+        self.bytes_per_sec
+    }
+
+    /// Whether runs of identical lines of child output are collapsed
+    pub(crate) fn dedup(&self) -> bool {
+        self.dedup
+    }
+
+    /// Encryption to apply to each serialized record
+    pub(crate) fn encryption(&self) -> &Encryption {
+        &self.encryption
+    }
+
+    /// Compression to apply to each serialized record, before it is sealed
+    pub(crate) fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// How long the output may be idle before a heartbeat is sent, if at all
+    pub(crate) fn heartbeat(&self) -> Option<Duration> {
+        self.heartbeat
+    }
+
+    /// How often to report throughput in a metrics record, if at all
+    pub(crate) fn metrics(&self) -> Option<Duration> {
+        self.metrics
+    }
+
+    /// How long to keep trying to (re)connect to the listener for, if at all
+    pub(crate) fn reconnect(&self) -> Option<Duration> {
+        self.reconnect
+    }
+
+    /// Where to spool records while reconnecting, and the most bytes to keep there
+    pub(crate) fn spool(&self) -> Option<(&Path, u64)> {
+        self.spool
+            .as_ref()
+            .map(|(dir, size)| (dir.as_path(), *size))
+    }
+
+    /// How long an executable may run before it is killed, if at all
+    pub(crate) fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// How long the whole run may take, and when it must be over by, if limited
+    pub(crate) fn max_runtime(&self) -> Option<(Duration, Instant)> {
+        self.max_runtime
+    }
+
+    /// Whether to stop running anything more once an executable fails
+    pub(crate) fn fail_fast(&self) -> bool {
+        self.fail_fast
+    }
+
+    /// How many executables may run at once, if limited
+    pub(crate) fn jobs(&self) -> Option<usize> {
+        self.jobs
+    }
+
+    /// Whether to keep re-running executables as they change
+    pub(crate) fn watch(&self) -> bool {
+        self.watch
+    }
+
+    /// How often to re-run every executable, if at all
+    pub(crate) fn every(&self) -> Option<Duration> {
+        self.every
+    }
+
+    /// Longest random wait before each run, if any
+    pub(crate) fn splay(&self) -> Option<Duration> {
+        self.splay
+    }
+
+    /// Whether to describe what would run rather than running it
+    pub(crate) fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// The most executables of earlier priority groups that may still be running when the
+    /// next starts, if limited at all
+    pub(crate) fn group_overlap(&self) -> Option<u64> {
+        self.group_overlap
+    }
+
+    /// Most executables of the given priority that may run at once, if limited
+    pub(crate) fn group_jobs(&self, priority: Priority) -> Option<usize> {
+        let key = match priority {
+            Priority::Number(n) => Some(n),
+            Priority::None => None,
+        };
+
+        self.group_jobs.get(&key).copied()
+    }
+
+    /// How each executable's records are identified
+    pub(crate) fn id_strategy(&self) -> IdStrategy {
+        self.id_strategy
+    }
+
+    /// What to do when executables would share an id
+    pub(crate) fn id_collision(&self) -> OnCollision {
+        self.id_collision
+    }
+
+    /// Whether to summarize the run once it has finished
+    pub(crate) fn summary(&self) -> bool {
+        self.summary
+    }
+
+    /// Whether the stream starts with a facts record describing this host
+    pub(crate) fn facts(&self) -> bool {
+        self.facts
+    }
+
+    /// The listener's pipeline this connection's records should go through, if any
+    pub(crate) fn pipeline(&self) -> Option<&str> {
+        self.pipeline.as_deref()
+    }
+
+    /// Whether to label closing headers with how long each executable took
+    pub(crate) fn timings(&self) -> bool {
+        self.timings
+    }
+
+    /// How many times to retry an executable that fails
+    pub(crate) fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// How long to wait before the first retry of an executable
+    pub(crate) fn retry_backoff(&self) -> Duration {
+        self.retry_backoff
+    }
+
+    /// User to run executables as, if not this program's
+    pub(crate) fn user(&self) -> Option<&str> {
+        self.user.as_deref()
+    }
+
+    /// Group to run executables as, if not this program's
+    pub(crate) fn group(&self) -> Option<&str> {
+        self.group.as_deref()
+    }
+
+    /// Niceness to run executables at, if not this program's
+    pub(crate) fn nice(&self) -> Option<i32> {
+        self.nice
+    }
+
+    /// IO priority to run executables with, if not this program's
+    pub(crate) fn ionice(&self) -> Option<IoPriority> {
+        self.ionice
+    }
+
+    /// Which of this program's environment variables executables may inherit, if not all
+    pub(crate) fn env(&self) -> Option<&Patterns> {
+        self.env.as_ref()
+    }
+
+    /// Whether to check what each executable found resolves to before running it
+    pub(crate) fn strict_discovery(&self) -> bool {
+        self.strict_discovery
+    }
+
+    /// Patterns executables must match one of to run, if any
+    pub(crate) fn include(&self) -> &[String] {
+        &self.include
+    }
+
+    /// Patterns excluding any executable that matches
+    pub(crate) fn exclude(&self) -> &[String] {
+        &self.exclude
+    }
+
+    /// The interpreter, and any arguments it is given before the script, that runs the script
+    /// at `path`, if one is set for its extension
+    pub(crate) fn interpreter(&self, path: &Path) -> Option<(&str, &[String])> {
+        let ext = path.extension()?.to_str()?;
+
+        self.interpreters
+            .get(ext)
+            .and_then(|command| command.split_first())
+            .map(|(interpreter, args)| (interpreter.as_str(), args))
+    }
+
+    /// Hosts every executable runs on through ssh, rather than locally if there are any
+    pub(crate) fn ssh(&self) -> &[String] {
+        &self.ssh
+    }
+
+    /// Labels to attach to every Header and Data record
+    pub(crate) fn meta(&self) -> &Arc<Meta> {
+        &self.meta
+    }
+
+    /// Maximum number of unacknowledged records in flight, if ack mode was requested
+    pub(crate) fn ack_window(&self) -> Option<u64> {
+        self.ack_window
+    }
+
+    /// Maximum number of records packed into each frame, if batching was requested
+    pub(crate) fn batch(&self) -> Option<usize> {
+        self.batch
+    }
+
+    /// Every output selected, the first being the subcommand's (or stdout) followed by any
+    /// given with `--tee`
+    pub(crate) fn outputs(&self) -> impl Iterator<Item = &ConOpts> {
+        iter::once(&self.con_type).chain(self.tees.iter())
+    }
+
+    /// How records are printed when output to stdout
+    pub(crate) fn format(&self) -> Format {
+        self.format
+    }
+}
+
+/// Checks that `val` is the path of a file
+fn file_exists(val: String) -> Result<(), String> {
+    match PathBuf::from(&val).is_file() {
+        true => Ok(()),
+        false => Err(format!("'{}' does not exist or is not a file", &val)),
+    }
+}
+
+/// Parses a `--compress`, either `zstd` at the default level or `zstd:LEVEL`
+fn parse_compression(val: &str) -> Option<Compression> {
+    let mut split = val.splitn(2, ':');
+    if split.next()? != "zstd" {
+        return None;
+    }
+
+    match split.next().map(|level| level.parse::<i32>()) {
+        None => Some(Compression::Zstd(DEFAULT_ZSTD_LEVEL)),
+        Some(Ok(level)) if (1..=22).contains(&level) => Some(Compression::Zstd(level)),
+        Some(_) => None,
+    }
+}
+
+/// Parses a `--group-policy`, into the most executables of earlier groups that may still be
+/// running as the next starts, if limited
+fn parse_group_policy(val: &str) -> Option<Option<u64>> {
+    match val {
+        "serial" => Some(Some(0)),
+        "parallel" => Some(None),
+        _ => val
+            .strip_prefix("overlap=")
+            .and_then(|n| n.parse::<u64>().ok())
+            .map(Some),
+    }
+}
+
+/// Parses a priority, or `none` for no priority, and the most executables of it run at once,
+/// i.e `10=1`
+fn parse_group_jobs(val: &str) -> Option<(Option<u64>, usize)> {
+    let mut split = val.splitn(2, '=');
+    let priority = match split.next()? {
+        "none" => None,
+        n => Some(n.parse::<u64>().ok()?),
+    };
+
+    match split.next()?.parse::<usize>().ok()? {
+        0 => None,
+        jobs => Some((priority, jobs)),
+    }
+}
+
+/// Parses an extension and the command run for it, split on whitespace, i.e `py=python3 -u`.
+/// A leading dot on the extension is ignored
+fn parse_interpreter(val: &str) -> Option<(String, Vec<String>)> {
+    let mut split = val.splitn(2, '=');
+    let ext = split.next()?.trim_start_matches('.');
+    let command: Vec<_> = split.next()?.split_whitespace().map(String::from).collect();
+
+    match ext.is_empty() || command.is_empty() {
+        true => None,
+        false => Some((ext.to_string(), command)),
+    }
+}
+
+/// Parses an extra output, either `tcp:HOST:PORT`, `socket:PATH`, `file:PATH` or `stdout`
+fn parse_tee(val: &str) -> Option<ConOpts> {
+    let mut split = val.splitn(2, ':');
+    let kind = split.next()?;
+    let rest = split.next().filter(|rest| !rest.is_empty());
+
+    match (kind, rest) {
+        ("stdout", None) => Some(ConOpts::Stdout),
+        ("file", Some(path)) => Some(ConOpts::File(PathBuf::from(path), Rotation::default())),
+        #[cfg(unix)]
+        ("socket", Some(path)) => Some(ConOpts::UnixSocket(PathBuf::from(path))),
+        ("tcp", Some(addr)) => {
+            let idx = addr.rfind(':')?;
+            let (bind, port) = (&addr[..idx], addr[idx + 1..].parse::<u16>().ok()?);
+            match bind.is_empty() {
+                true => None,
+                false => Some(ConOpts::Tcp((bind.to_string(), port), None)),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Parses a whole number of seconds, minutes, hours or days, i.e `90s` or `5m`. A bare
+/// number is taken as seconds
+fn parse_interval(val: &str) -> Result<Duration, String> {
+    let split = val.find(|c: char| !c.is_ascii_digit()).unwrap_or(val.len());
+    let (num, unit) = val.split_at(split);
+
+    let scale = match unit {
+        "" | "s" => Some(1),
+        "m" => Some(60),
+        "h" => Some(60 * 60),
+        "d" => Some(24 * 60 * 60),
+        _ => None,
+    };
+
+    scale
+        .zip(num.parse::<u64>().ok())
+        .and_then(|(scale, num)| num.checked_mul(scale))
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("'{}' is not a valid interval, i.e 90s, 5m, 1h or 1d", val))
+}
+
+#[derive(Debug, Clone)]
+#[cfg(unix)]
+/// Possible output streams
+pub(crate) enum ConOpts {
+    Stdout,
+    Tcp((String, u16), Option<Tls>),
+    UnixSocket(PathBuf),
+    File(PathBuf, Rotation),
+}
+
+#[derive(Debug, Clone)]
+#[cfg(not(unix))]
+/// Possible output streams
+pub(crate) enum ConOpts {
+    Stdout,
+    Tcp(SocketAddr),
+    File(PathBuf, Rotation),
+}
+
+/// How records are printed to stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Format {
+    /// A one line summary of each record, for debugging
+    Debug,
+    /// One JSON object per line, see lib_transport::json
+    Ndjson,
+}
+
+impl Format {
+    fn parse(val: &str) -> Option<Self> {
+        match val {
+            "debug" => Some(Self::Debug),
+            "ndjson" => Some(Self::Ndjson),
+            _ => None,
+        }
+    }
+}
+
+impl Default for ConOpts {
+    fn default() -> Self {
+        Self::Stdout
+    }
+}
@@ -0,0 +1,112 @@
+use {
+    crate::{compare::Priority, error::Err, patterns::Patterns, prelude::*, unit::Unit},
+    serde::Deserialize,
+    serde_yaml::from_reader as read_yaml,
+    std::{
+        collections::HashSet,
+        ffi::OsStr,
+        fs::File,
+        path::{Path, PathBuf},
+        process::Command,
+        sync::Arc,
+    },
+};
+
+/// A command run as if it were an executable found under an exec root, for exec roots that
+/// are a YAML list of commands rather than a directory. Useful when what should run is
+/// already installed elsewhere, i.e in PATH.
+///
+/// Each is identified by its name, which gives it its sidecar in the list's directory, as
+/// an executable in it would have. Commands run in order of priority, those without one
+/// last, running at once with any of the same priority as they would as executables
+///
+/// ```yaml
+/// - name: disk
+///   path: df
+///   args: [-h]
+///   priority: 10
+/// - name: uptime
+///   path: /usr/bin/uptime
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Listed {
+    name: String,
+    /// Looked up in PATH if just a name, otherwise relative to the list's directory
+    path: PathBuf,
+    /// Passed before any its sidecar adds
+    #[serde(default)]
+    args: Vec<String>,
+    priority: Option<u64>,
+}
+
+impl Listed {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn priority(&self) -> Priority {
+        self.priority.map_or(Priority::None, Priority::Number)
+    }
+
+    /// The command running this, without anything its sidecar adds
+    pub fn command(&self) -> Command {
+        let mut command = Command::new(&self.path);
+        command.args(&self.args);
+
+        command
+    }
+}
+
+/// Reads the commands listed in the file at `list`, in the order they should run. Only those
+/// whose name is allowed by `patterns` are returned. Fails if the list can't be read, or any
+/// of its names is repeated or isn't fit for a file name
+pub fn read(list: &Path, patterns: &Patterns) -> Result<Vec<Result<(Priority, Unit)>>> {
+    debug!(list = %list.display(), "Loading command list");
+    let listed: Vec<Listed> = read_yaml(File::open(list)?).map_err(|source| Err::Commands {
+        path: list.display().to_string(),
+        source,
+    })?;
+    let dir = list.parent().unwrap_or_else(|| Path::new(""));
+    let invalid = |reason| -> CrateError {
+        Err::CommandsEntry {
+            path: list.display().to_string(),
+            reason,
+        }
+        .into()
+    };
+
+    let mut names = HashSet::with_capacity(listed.len());
+    let mut units = Vec::with_capacity(listed.len());
+    for mut command in listed {
+        let name = command.name.clone();
+        let name = name.as_str();
+        if Path::new(name).file_name() != Some(OsStr::new(name)) {
+            return Err(invalid(format!("'{}' isn't fit for a file name", name)));
+        }
+        if !names.insert(name.to_string()) {
+            return Err(invalid(format!("'{}' is repeated", name)));
+        }
+        if !patterns.allows(Path::new(name), false) {
+            continue;
+        }
+
+        // Only a bare name is looked up in PATH, anything else would be relative to
+        // wherever this program happens to run
+        if command.path.is_relative() && command.path.components().nth(1).is_some() {
+            command.path = dir.join(&command.path);
+        }
+        units.push((
+            command.priority(),
+            Unit::Listed {
+                path: dir.join(name),
+                list: list.to_path_buf(),
+                command: Arc::new(command),
+            },
+        ));
+    }
+    // Stable, so commands of the same priority keep the order listed
+    units.sort_by_key(|(priority, _)| *priority);
+
+    Ok(units.into_iter().map(Ok).collect())
+}
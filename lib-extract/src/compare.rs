@@ -1,22 +1,28 @@
 use {
     crate::prelude::*,
-    std::{cmp::Ordering, convert::TryFrom},
+    std::{
+        cmp::Ordering,
+        convert::TryFrom,
+        ffi::{OsStr, OsString},
+    },
     walkdir::DirEntry,
 };
 
-/// Helper function for WalkDir's .sort_by() function, encapsulates several fallible transformations
-/// and provides a sensible Ordering to WalkDir
-pub fn by_priority(a: &DirEntry, b: &DirEntry) -> Ordering {
-    let (first, second) = (Priority::try_from(a), Priority::try_from(b));
+/// Sort key of a single path component, see `priority_key`
+pub type PriorityKey = (Priority, bool, OsString);
 
-    // Note that this block should agree with Priority's Ord impl,
-    // assuming Ok => Priority::Number, Err => Priority::None
-    // so that the sorting outcome always follows: [has_priority,no_priority,invalid_str]
-    match (first, second) {
-        (Ok(a), Ok(b)) => a.cmp(&b),
-        (Ok(_), Err(_)) => Ordering::Less,
-        (Err(_), Ok(_)) => Ordering::Greater,
-        (Err(_), Err(_)) => Ordering::Equal,
+/// Encapsulates several fallible transformations of a file name into a sensible key to sort
+/// it by. Sorting by the keys of every component of a path under an exec root walks the
+/// root depth first, with each directory's entries in priority order
+pub fn priority_key(name: &OsStr) -> PriorityKey {
+    let priority = name.to_str().map(Priority::try_from_str);
+
+    // Note that this should agree with Priority's Ord impl, assuming Ok => Priority::Number,
+    // Err => Priority::None so that the sorting outcome always follows:
+    // [has_priority,no_priority,invalid_str], with the name itself breaking any tie
+    match priority {
+        Some(Ok(priority)) => (priority, false, name.to_os_string()),
+        _ => (Priority::None, true, name.to_os_string()),
     }
 }
 
@@ -29,7 +35,7 @@ pub enum Priority {
 }
 
 impl Priority {
-    fn try_from_str(s: &str) -> Result<Self> {
+    pub fn try_from_str(s: &str) -> Result<Self> {
         let numeric = str_take_while(s, |b| is_numeric(*b));
 
         if numeric.is_empty() {
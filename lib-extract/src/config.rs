@@ -0,0 +1,570 @@
+use {
+    crate::{error::Err, output::Killers, patterns::Patterns, prelude::*, sandbox::Sandbox, ARGS},
+    lib_transport::Meta,
+    serde::Deserialize,
+    serde_yaml::from_reader as read_yaml,
+    std::{
+        collections::BTreeMap,
+        convert::TryFrom,
+        ffi::CString,
+        fmt,
+        fs::File,
+        io::{self, Write},
+        os::unix::process::CommandExt,
+        path::{Path, PathBuf},
+        process::{Child, Command, Stdio},
+        thread,
+    },
+};
+
+/// Extension of the sidecar file configuring how an executable is spawned,
+/// i.e `10_some-script.sh.yaml` for `10_some-script.sh`
+pub const SIDECAR_EXTENSION: &str = "yaml";
+
+/// Space for the strings of a single passwd or group entry
+const LOOKUP_BUFFER_SIZE: usize = 16 * 1024;
+
+/// `ioprio_set` target for a single process, see ioprio_set(2)
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+
+/// Bits an IO priority's level is shifted past to make room for its class
+const IOPRIO_CLASS_SHIFT: u32 = 13;
+
+/// How a single executable is spawned, read from its sidecar file if it has one.
+/// Executables without one are run without arguments, inheriting this program's environment
+///
+/// ```yaml
+/// args: ["--verbose", "/var/log"]
+/// env:
+///   LANG: C
+/// inherit_env:
+///   only: [PATH, HOME]  # or except: ["*_TOKEN"]
+/// cwd: .
+/// stdin:
+///   text: "threshold: 10"
+/// labels:
+///   team: storage
+///   severity: high
+/// user: collector
+/// group: adm
+/// pty: true
+/// limits:
+///   cpu_seconds: 30
+///   memory_bytes: 268435456
+///   open_files: 64
+/// nice: 10
+/// ionice: best-effort:7
+/// expect_exit: [0, 1]
+/// max_output_bytes: 1048576
+/// sandbox:
+///   no_network: true
+///   seccomp: restricted
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpawnConfig {
+    /// Arguments passed to the executable, after its path
+    args: Vec<String>,
+    /// Variables set in the executable's environment, overriding any inherited
+    env: BTreeMap<String, String>,
+    inherit_env: Inherit,
+    /// Directory the executable runs in, relative to its own directory, so `.` runs it
+    /// where it is. Otherwise it runs in this program's working directory
+    cwd: Option<PathBuf>,
+    /// Fed to the executable's stdin, which is otherwise closed
+    stdin: Option<Stdin>,
+    /// Labels added to the executable's Header and Data records, overriding any `--meta`
+    labels: Meta,
+    limits: Limits,
+    sandbox: Sandbox,
+    /// CPU niceness, from -20 (most favourable) to 19, overriding `--nice`
+    nice: Option<i32>,
+    /// IO scheduling class and level, overriding `--ionice`
+    ionice: Option<IoPriority>,
+    /// User (name or uid) to run as, overriding `--user`
+    user: Option<String>,
+    /// Group (name or gid) to run as, overriding `--group`
+    group: Option<String>,
+    /// Run under a pseudo-terminal, for executables that only color or flush their output
+    /// as they go on one. What it writes to stdout and stderr is sent merged, as stdout
+    pty: bool,
+    /// Exit codes that count as success, in place of only 0. Any other exit, or being killed
+    /// by a signal, is reported as an error and fails this program once everything has run
+    expect_exit: Option<Vec<i32>>,
+    /// Most output read across stdout and stderr before the executable is killed, overriding
+    /// `--max-output`
+    max_output_bytes: Option<u64>,
+}
+
+impl SpawnConfig {
+    /// Reads the sidecar file of the executable at `path`, returning the default config if
+    /// there is none
+    pub fn for_executable(path: &Path) -> Result<Self> {
+        let sidecar = sidecar_path(path);
+
+        match File::open(&sidecar) {
+            Ok(file) => {
+                debug!(sidecar = %sidecar.display(), "Loading spawn config");
+                read_yaml(file).map_err(|source| {
+                    Err::Config {
+                        path: sidecar.display().to_string(),
+                        source,
+                    }
+                    .into()
+                })
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Applies this config's arguments, environment, limits and credentials to `command`.
+    /// Fails if the user or group to run as doesn't exist
+    pub fn apply<'c>(&self, command: &'c mut Command) -> Result<&'c mut Command> {
+        Ok(self.apply_local(command)?.args(&self.args).envs(&self.env))
+    }
+
+    /// As `apply`, but leaving out the arguments and variables this config sets, for a
+    /// `command` that passes them on to what it runs elsewhere
+    pub fn apply_local<'c>(&self, command: &'c mut Command) -> Result<&'c mut Command> {
+        let user = self.user.as_deref().or_else(|| ARGS.user());
+        let group = self.group.as_deref().or_else(|| ARGS.group());
+        let gid = group.map(lookup_group).transpose()?;
+        if let Some(gid) = gid {
+            command.gid(gid);
+        }
+        if let Some(user) = user {
+            // Without a group of its own the user runs under their primary group
+            match (lookup_user(user)?, gid) {
+                ((uid, Some(primary)), None) => command.uid(uid).gid(primary),
+                ((uid, _), Some(_)) => command.uid(uid),
+                ((_, None), None) => {
+                    return Err(run_as(user, "unknown uid, give a group to run it under"))
+                }
+            };
+        }
+
+        let (nice, ionice) = self.priority();
+        if nice.is_some() || ionice.is_some() {
+            // Safety: only makes the setpriority and ioprio_set syscalls, allocating nothing
+            unsafe {
+                command.pre_exec(move || set_priority(nice, ionice));
+            }
+        }
+
+        if self.limits.is_set() {
+            let limits = self.limits;
+            // Safety: only calls setrlimit, which is async-signal-safe, and allocates nothing
+            unsafe {
+                command.pre_exec(move || limits.set());
+            }
+        }
+        if self.sandbox.is_set() {
+            let sandbox = self.sandbox.prepare()?;
+            // Safety: only makes syscalls, everything they are given having been made here.
+            // Run after every other closure, so its seccomp filter can't forbid any of them
+            unsafe {
+                command.pre_exec(move || sandbox.enter());
+            }
+        }
+
+        // Variables are only ever inherited once they pass both --env-allow / --env-strip
+        // and the sidecar's own choice, any without a UTF-8 name are dropped then
+        let global = ARGS.env();
+        let except = match &self.inherit_env {
+            Inherit::Except(strip) => Some(Patterns::new(None, strip.iter().map(String::as_str))?),
+            _ => None,
+        };
+        let inherits = |key: &str| {
+            let chosen = match (&self.inherit_env, &except) {
+                (Inherit::Only(keep), _) => keep.iter().any(|kept| kept == key),
+                (_, Some(except)) => except.allows_var(key),
+                _ => true,
+            };
+            chosen && !matches!(global, Some(env) if !env.allows_var(key))
+        };
+        match (&self.inherit_env, global) {
+            (Inherit::All, None) => (),
+            (Inherit::None, _) => {
+                command.env_clear();
+            }
+            _ => {
+                let vars = std::env::vars_os()
+                    .filter(|(key, _)| matches!(key.to_str(), Some(key) if inherits(key)));
+                command.env_clear().envs(vars);
+            }
+        }
+
+        Ok(command)
+    }
+
+    /// Arguments passed to the executable
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// Variables set in the executable's environment
+    pub fn env(&self) -> &BTreeMap<String, String> {
+        &self.env
+    }
+
+    /// Whether the executable is fed anything on stdin
+    pub fn has_stdin(&self) -> bool {
+        self.stdin.is_some()
+    }
+
+    /// Labels to attach to the executable's records
+    pub fn labels(&self) -> &Meta {
+        &self.labels
+    }
+
+    /// The CPU niceness and IO priority the executable runs with, if not this program's
+    pub fn priority(&self) -> (Option<i32>, Option<IoPriority>) {
+        (
+            self.nice.or_else(|| ARGS.nice()),
+            self.ionice.or_else(|| ARGS.ionice()),
+        )
+    }
+
+    /// Whether the executable runs under a pseudo-terminal
+    pub fn pty(&self) -> bool {
+        self.pty
+    }
+
+    /// The exit codes the executable succeeds with, if the sidecar lists them
+    pub fn expect_exit(&self) -> Option<&[i32]> {
+        self.expect_exit.as_deref()
+    }
+
+    /// Most output read from the executable before it is killed, if limited
+    pub fn max_output(&self) -> Option<u64> {
+        self.max_output_bytes.or_else(|| ARGS.max_output())
+    }
+
+    /// Which of the executable's limits or sandbox kill it with a signal of their own
+    pub fn killers(&self) -> Killers {
+        Killers {
+            cpu_limit: self.limits.cpu_seconds.is_some(),
+            seccomp: self.sandbox.is_filtered(),
+        }
+    }
+
+    /// Whether the executable runs as some user or group other than this program's
+    pub fn changes_credentials(&self) -> bool {
+        self.user.is_some()
+            || self.group.is_some()
+            || ARGS.user().is_some()
+            || ARGS.group().is_some()
+    }
+
+    /// The working directory of the executable at `path`, if it has its own. Fails if it
+    /// isn't a directory
+    pub fn cwd(&self, path: &Path) -> Result<Option<PathBuf>> {
+        let cwd = match &self.cwd {
+            Some(cwd) => path.parent().unwrap_or_else(|| Path::new("")).join(cwd),
+            None => return Ok(None),
+        };
+
+        match std::fs::metadata(&cwd) {
+            Ok(meta) if meta.is_dir() => Ok(Some(cwd)),
+            Ok(_) => Err(bad_cwd(
+                &cwd,
+                io::Error::new(io::ErrorKind::Other, "not a directory"),
+            )),
+            Err(e) => Err(bad_cwd(&cwd, e)),
+        }
+    }
+
+    /// The stdin of the executable at `path`, see `SpawnConfig::feed`
+    pub fn stdin(&self, path: &Path) -> Result<Stdio> {
+        match &self.stdin {
+            None => Ok(Stdio::null()),
+            Some(Stdin::Text(_)) => Ok(Stdio::piped()),
+            Some(Stdin::File(file)) => {
+                let file = path.parent().unwrap_or_else(|| Path::new("")).join(file);
+                File::open(&file).map(Stdio::from).map_err(|source| {
+                    Err::Stdin {
+                        path: file.display().to_string(),
+                        source,
+                    }
+                    .into()
+                })
+            }
+        }
+    }
+
+    /// Writes any inline stdin to the spawned `child` from a separate thread, so a child that
+    /// reads slowly (or not at all) can't block anything else, closing stdin once done
+    pub fn feed(&self, child: &mut Child) {
+        match (&self.stdin, child.stdin.take()) {
+            (Some(Stdin::Text(text)), Some(mut stdin)) => {
+                let (pid, text) = (child.id(), text.clone());
+                thread::spawn(move || {
+                    stdin.write_all(text.as_bytes()).unwrap_or_else(|e| {
+                        debug!(pid, "Child closed stdin before reading all of it: {}", e)
+                    })
+                });
+            }
+            // Ensure stdin is closed
+            (_, stdin) => drop(stdin),
+        }
+    }
+}
+
+/// Resource limits set on an executable (and inherited by anything it starts) before it runs.
+/// Exceeding its CPU time kills it with SIGXCPU, which is reported as an error. Running out
+/// of memory or file descriptors instead fails whatever allocation or open went over the limit,
+/// which is up to the executable to report
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Limits {
+    /// Seconds of CPU time
+    cpu_seconds: Option<u64>,
+    /// Bytes of address space
+    memory_bytes: Option<u64>,
+    /// Open file descriptors, one more than the highest that can be opened
+    open_files: Option<u64>,
+}
+
+impl Limits {
+    fn is_set(&self) -> bool {
+        self.cpu_seconds.is_some() || self.memory_bytes.is_some() || self.open_files.is_some()
+    }
+
+    /// Sets each limit on the calling process
+    fn set(&self) -> io::Result<()> {
+        // The hard CPU limit is a second past the soft, so SIGXCPU is sent before SIGKILL
+        let limits = [
+            (
+                libc::RLIMIT_CPU,
+                self.cpu_seconds.map(|secs| (secs, secs + 1)),
+            ),
+            (
+                libc::RLIMIT_AS,
+                self.memory_bytes.map(|bytes| (bytes, bytes)),
+            ),
+            (
+                libc::RLIMIT_NOFILE,
+                self.open_files.map(|files| (files, files)),
+            ),
+        ];
+
+        for (resource, limit) in limits.iter() {
+            if let Some((soft, hard)) = limit {
+                let limit = libc::rlimit {
+                    rlim_cur: *soft as libc::rlim_t,
+                    rlim_max: *hard as libc::rlim_t,
+                };
+                if unsafe { libc::setrlimit(*resource, &limit) } != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// An IO scheduling class, and the level within it where it has them, as set by ionice(1).
+/// Written as the class followed by any level, i.e `best-effort:7`, `realtime:0` or `idle`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct IoPriority {
+    class: IoClass,
+    /// From 0 (highest) to 7
+    level: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IoClass {
+    Realtime = 1,
+    BestEffort = 2,
+    Idle = 3,
+}
+
+impl IoPriority {
+    /// Parses the class and level in `val`, the level defaulting to 4 as it does for ionice
+    pub fn parse(val: &str) -> std::result::Result<Self, String> {
+        let invalid = || {
+            format!(
+                "'{}' is not a valid IO priority, i.e realtime:N, best-effort:N or idle, \
+                where N is 0 to 7",
+                val
+            )
+        };
+        let mut parts = val.splitn(2, ':');
+        let class = match parts.next() {
+            Some("realtime") => IoClass::Realtime,
+            Some("best-effort") => IoClass::BestEffort,
+            Some("idle") => IoClass::Idle,
+            _ => return Err(invalid()),
+        };
+        let level = match (class, parts.next().map(str::parse::<u8>)) {
+            (IoClass::Idle, None) => 0,
+            (IoClass::Idle, Some(_)) => return Err(invalid()),
+            (_, None) => 4,
+            (_, Some(Ok(level))) if level <= 7 => level,
+            (_, Some(_)) => return Err(invalid()),
+        };
+
+        Ok(Self { class, level })
+    }
+
+    fn value(self) -> libc::c_int {
+        ((self.class as libc::c_int) << IOPRIO_CLASS_SHIFT) | self.level as libc::c_int
+    }
+}
+
+impl TryFrom<String> for IoPriority {
+    type Error = String;
+
+    fn try_from(val: String) -> std::result::Result<Self, Self::Error> {
+        Self::parse(&val)
+    }
+}
+
+impl fmt::Display for IoPriority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.class {
+            IoClass::Realtime => write!(f, "realtime:{}", self.level),
+            IoClass::BestEffort => write!(f, "best-effort:{}", self.level),
+            IoClass::Idle => write!(f, "idle"),
+        }
+    }
+}
+
+/// Sets the calling process' niceness and IO priority, whichever are given
+fn set_priority(nice: Option<i32>, ionice: Option<IoPriority>) -> io::Result<()> {
+    if let Some(nice) = nice {
+        if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    if let Some(ionice) = ionice {
+        let res =
+            unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ionice.value()) };
+        if res != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolves a user name or uid to its uid and primary gid. A uid without an entry in the user
+/// database has no primary gid
+fn lookup_user(name: &str) -> Result<(u32, Option<u32>)> {
+    let mut buf = vec![0; LOOKUP_BUFFER_SIZE];
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut found = std::ptr::null_mut();
+    let cname = CString::new(name).map_err(|e| run_as(name, e))?;
+
+    // Safety: every pointer outlives the call, and buf's length is passed along with it
+    let res = match name.parse::<libc::uid_t>() {
+        Ok(uid) => unsafe {
+            libc::getpwuid_r(uid, &mut passwd, buf.as_mut_ptr(), buf.len(), &mut found)
+        },
+        Err(_) => unsafe {
+            libc::getpwnam_r(
+                cname.as_ptr(),
+                &mut passwd,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut found,
+            )
+        },
+    };
+
+    match (res, found.is_null()) {
+        (0, false) => Ok((passwd.pw_uid, Some(passwd.pw_gid))),
+        (0, true) => match name.parse::<libc::uid_t>() {
+            Ok(uid) => Ok((uid, None)),
+            Err(_) => Err(run_as(name, "no such user")),
+        },
+        (errno, _) => Err(run_as(name, io::Error::from_raw_os_error(errno))),
+    }
+}
+
+/// Resolves a group name or gid to its gid
+fn lookup_group(name: &str) -> Result<u32> {
+    if let Ok(gid) = name.parse::<libc::gid_t>() {
+        return Ok(gid);
+    }
+
+    let mut buf = vec![0; LOOKUP_BUFFER_SIZE];
+    let mut group: libc::group = unsafe { std::mem::zeroed() };
+    let mut found = std::ptr::null_mut();
+    let cname = CString::new(name).map_err(|e| run_as(name, e))?;
+
+    // Safety: as lookup_user
+    let res = unsafe {
+        libc::getgrnam_r(
+            cname.as_ptr(),
+            &mut group,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut found,
+        )
+    };
+
+    match (res, found.is_null()) {
+        (0, false) => Ok(group.gr_gid),
+        (0, true) => Err(run_as(name, "no such group")),
+        (errno, _) => Err(run_as(name, io::Error::from_raw_os_error(errno))),
+    }
+}
+
+fn run_as<E>(name: &str, error: E) -> CrateError
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    Err::RunAs {
+        name: name.to_string(),
+        source: io::Error::new(io::ErrorKind::NotFound, error),
+    }
+    .into()
+}
+
+fn bad_cwd(cwd: &Path, error: io::Error) -> CrateError {
+    Err::Cwd {
+        path: cwd.display().to_string(),
+        source: error,
+    }
+    .into()
+}
+
+/// What an executable reads on stdin
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Stdin {
+    /// The contents of a file, relative to the executable's directory
+    File(PathBuf),
+    /// The given text
+    Text(String),
+}
+
+/// Which of this program's environment variables an executable inherits
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Inherit {
+    All,
+    None,
+    /// Only the variables named
+    Only(Vec<String>),
+    /// Every variable but those whose name matches one of these glob patterns
+    Except(Vec<String>),
+}
+
+impl Default for Inherit {
+    fn default() -> Self {
+        Self::All
+    }
+}
+
+fn sidecar_path(path: &Path) -> PathBuf {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".");
+    sidecar.push(SIDECAR_EXTENSION);
+
+    sidecar.into()
+}
@@ -0,0 +1,374 @@
+use {
+    crate::{models::SpanDisplay, prelude::*},
+    crossbeam_channel::SendError,
+    futures::channel::mpsc::SendError as AsyncSendError,
+    globset::Error as GlobError,
+    lib_transport::{BuildError, InterfaceError, InterfaceErrorKind},
+    serde_yaml::Error as YamlError,
+    std::{
+        ffi::OsString, fmt, io::Error as IoError, num::ParseIntError, process::ExitStatus,
+        str::Utf8Error, time::Duration,
+    },
+    thiserror::Error,
+    walkdir::Error as WalkdirError,
+};
+
+pub type CrateResult<T> = std::result::Result<T, CrateError>;
+
+#[derive(Debug)]
+pub struct CrateError {
+    inner: Box<Err>,
+}
+
+impl CrateError {
+    pub fn categorize(&self) -> Category {
+        self.inner.categorize()
+    }
+
+    /// Whether discovery skipped an entry, rather than something going wrong
+    pub fn is_skipped(&self) -> bool {
+        matches!(*self.inner, Err::Skipped { .. })
+    }
+}
+
+impl fmt::Display for CrateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.inner)
+    }
+}
+
+impl std::error::Error for CrateError {}
+
+/// Converts into the error carried by an Error record, so it can be reported downstream
+impl From<&CrateError> for InterfaceError {
+    fn from(err: &CrateError) -> Self {
+        let kind = match err.categorize() {
+            Category::Spawn => InterfaceErrorKind::Spawn,
+            Category::Timeout => InterfaceErrorKind::Timeout,
+            Category::FilePath => InterfaceErrorKind::Config,
+            Category::Io => InterfaceErrorKind::Io,
+            Category::Utf8 => InterfaceErrorKind::Decode,
+            Category::Exit | Category::ChannelError | Category::Record => {
+                InterfaceErrorKind::Generic
+            }
+        };
+
+        InterfaceError::from_error(kind, err.inner.as_ref())
+    }
+}
+
+impl<F> From<F> for CrateError
+where
+    F: Into<Err>,
+{
+    fn from(f: F) -> Self {
+        Self {
+            inner: Box::new(f.into()),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Err {
+    #[error("Non-UTF8 paths are not supported ({})", .0.to_string_lossy())]
+    PathInvalidUTF8(OsString),
+    #[error("{}", .source)]
+    PathError {
+        #[from]
+        source: WalkdirError,
+    },
+    #[error("Invalid priority order: {}", .source)]
+    PathPriorityParse {
+        #[from]
+        source: ParseIntError,
+    },
+    #[error("Unable to spawn '{}': {}", .path, .source)]
+    Spawn {
+        path: String,
+        #[source]
+        source: IoError,
+    },
+    #[error("Attempt {} of {} failed: {}", .attempt, .of, .source)]
+    Attempt {
+        attempt: u32,
+        of: u32,
+        #[source]
+        source: CrateError,
+    },
+    #[error(
+        "Unable to run '{}' as another user or group, this program must be root or have \
+        CAP_SETUID and CAP_SETGID: {}",
+        .path,
+        .source
+    )]
+    Privilege {
+        path: String,
+        #[source]
+        source: IoError,
+    },
+    #[error("Unable to run as '{}': {}", .name, .source)]
+    RunAs {
+        name: String,
+        #[source]
+        source: IoError,
+    },
+    #[error("Unable to run '{}' on '{}': {}", .path, .host, .reason)]
+    Remote {
+        path: String,
+        host: String,
+        reason: &'static str,
+    },
+    #[error("Unable to open '{}' for stdin: {}", .path, .source)]
+    Stdin {
+        path: String,
+        #[source]
+        source: IoError,
+    },
+    #[error("Unable to run in '{}': {}", .path, .source)]
+    Cwd {
+        path: String,
+        #[source]
+        source: IoError,
+    },
+    #[error("'{}' was killed for exceeding its CPU time limit", .path)]
+    CpuLimit { path: String },
+    #[error("'{}' was killed for making a syscall its sandbox forbids", .path)]
+    Sandboxed { path: String },
+    #[error("'{}' was killed after running for {}s", .path, .timeout.as_secs())]
+    Timeout { path: String, timeout: Duration },
+    #[error(
+        "'{}' was killed after writing more than {} bytes of output, the rest was dropped",
+        .path,
+        .limit
+    )]
+    OutputLimit { path: String, limit: u64 },
+    #[error(
+        "'{}' was killed as the run reached its maximum runtime of {}s",
+        .path,
+        .runtime.as_secs()
+    )]
+    Deadline { path: String, runtime: Duration },
+    #[error(
+        "'{}' was not run as the run reached its maximum runtime of {}s",
+        .path,
+        .runtime.as_secs()
+    )]
+    NotRun { path: String, runtime: Duration },
+    #[error("'{}' exited unexpectedly ({}), expected one of: {}", .path, .status, .expected)]
+    UnexpectedExit {
+        path: String,
+        status: ExitStatus,
+        expected: String,
+    },
+    #[error("{} executable(s) exited unexpectedly, see their expect_exit", .count)]
+    Failed { count: u64 },
+    #[error("Stopped running anything more as '{}' failed, see --fail-fast", .path)]
+    FailedFast { path: String },
+    #[error("Skipped '{}' as {}", .path, .reason)]
+    Skipped { path: String, reason: &'static str },
+    #[error("Invalid spawn config '{}': {}", .path, .source)]
+    Config {
+        path: String,
+        #[source]
+        source: YamlError,
+    },
+    #[error("Invalid manifest '{}': {}", .path, .source)]
+    Manifest {
+        path: String,
+        #[source]
+        source: YamlError,
+    },
+    #[error("Invalid manifest '{}': {}", .path, .reason)]
+    ManifestEntry { path: String, reason: String },
+    #[error("Invalid command list '{}': {}", .path, .source)]
+    Commands {
+        path: String,
+        #[source]
+        source: YamlError,
+    },
+    #[error("Invalid command list '{}': {}", .path, .reason)]
+    CommandsEntry { path: String, reason: String },
+    #[error(
+        "'{}' and '{}' would both be identified as '{}', see --id-strategy",
+        .first,
+        .second,
+        .id
+    )]
+    IdCollision {
+        id: String,
+        first: String,
+        second: String,
+    },
+    #[error("Invalid arguments: {}", .reason)]
+    Args { reason: String },
+    #[error("A runner has already been configured in this process")]
+    Configured,
+    #[error("Unable to set up TLS, {}", .reason)]
+    Tls { reason: String },
+    #[error("Invalid pattern: {}", .source)]
+    Pattern {
+        #[from]
+        source: GlobError,
+    },
+    #[error("{}", .source)]
+    Io {
+        #[from]
+        source: IoError,
+    },
+    #[error("Invalid output: {}", .source)]
+    Utf8 {
+        #[from]
+        source: Utf8Error,
+    },
+    #[error("Async channel send error: {}", .source)]
+    AsyncSendError {
+        #[from]
+        source: AsyncSendError,
+    },
+    #[error("Channel Receiver closed unexpectedly")]
+    SendError,
+    #[error("Stopped sending to output {} of {}, as it fell too far behind", .index + 1, .of)]
+    Behind { index: usize, of: usize },
+    #[error("{}", .source)]
+    Build {
+        #[from]
+        source: BuildError,
+    },
+}
+
+impl Err {
+    pub fn categorize(&self) -> Category {
+        match self {
+            Self::PathInvalidUTF8(_)
+            | Self::PathError { .. }
+            | Self::PathPriorityParse { .. }
+            | Self::Config { .. }
+            | Self::Manifest { .. }
+            | Self::ManifestEntry { .. }
+            | Self::Commands { .. }
+            | Self::CommandsEntry { .. }
+            | Self::Pattern { .. }
+            | Self::Tls { .. }
+            | Self::IdCollision { .. }
+            | Self::Args { .. }
+            | Self::Configured
+            | Self::Skipped { .. } => Category::FilePath,
+            Self::Spawn { .. }
+            | Self::Stdin { .. }
+            | Self::Cwd { .. }
+            | Self::Privilege { .. }
+            | Self::Remote { .. }
+            | Self::RunAs { .. } => Category::Spawn,
+            Self::Timeout { .. }
+            | Self::Deadline { .. }
+            | Self::NotRun { .. }
+            | Self::CpuLimit { .. } => Category::Timeout,
+            Self::UnexpectedExit { .. }
+            | Self::Failed { .. }
+            | Self::FailedFast { .. }
+            | Self::OutputLimit { .. }
+            | Self::Sandboxed { .. } => Category::Exit,
+            Self::Attempt { source, .. } => source.categorize(),
+            Self::Io { .. } => Category::Io,
+            Self::Utf8 { .. } => Category::Utf8,
+            Self::AsyncSendError { .. } | Self::SendError | Self::Behind { .. } => {
+                Category::ChannelError
+            }
+            Self::Build { .. } => Category::Record,
+        }
+    }
+}
+
+impl<T> From<SendError<T>> for Err {
+    fn from(_err: SendError<T>) -> Self {
+        Self::SendError
+    }
+}
+
+impl From<OsString> for Err {
+    fn from(name: OsString) -> Self {
+        Self::PathInvalidUTF8(name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Category {
+    FilePath,
+    Spawn,
+    Timeout,
+    Exit,
+    Io,
+    ChannelError,
+    Utf8,
+    Record,
+}
+
+impl SpanDisplay for Category {
+    fn span_print(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let output = match self {
+            Self::FilePath => "FilePath",
+            Self::Spawn => "Spawn",
+            Self::Timeout => "Timeout",
+            Self::Exit => "Exit",
+            Self::Io => "IO",
+            Self::ChannelError => "ChannelError",
+            Self::Utf8 => "UTF8",
+            Self::Record => "Record",
+        };
+
+        write!(f, "{}", output)
+    }
+}
+
+pub trait LogError {
+    fn ref_log(&self, level: tracing::Level);
+
+    fn log(self, level: tracing::Level) -> Self
+    where
+        Self: Sized,
+    {
+        (&self).ref_log(level);
+        self
+    }
+}
+
+impl<T> LogError for CrateResult<T> {
+    fn ref_log(&self, level: tracing::Level) {
+        match self {
+            Ok(_) => (),
+            Err(e) => e.ref_log(level),
+        }
+    }
+
+    fn log(self, level: tracing::Level) -> Self
+    where
+        Self: Sized,
+    {
+        match self {
+            ok @ Ok(_) => ok,
+            Err(e) => Err(e.log(level)),
+        }
+    }
+}
+
+impl LogError for CrateError {
+    fn ref_log(&self, level: tracing::Level) {
+        match level {
+            tracing::Level::ERROR => {
+                error!(kind = %self.categorize().span_display(), message = %self.inner)
+            }
+            tracing::Level::WARN => {
+                warn!(kind = %self.categorize().span_display(), message = %self.inner)
+            }
+            tracing::Level::INFO => {
+                info!(kind = %self.categorize().span_display(), message = %self.inner)
+            }
+            tracing::Level::DEBUG => {
+                debug!(kind = %self.categorize().span_display(), message = %self.inner)
+            }
+            tracing::Level::TRACE => {
+                trace!(kind = %self.categorize().span_display(), message = %self.inner)
+            }
+        }
+    }
+}
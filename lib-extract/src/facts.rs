@@ -0,0 +1,93 @@
+use {
+    crate::{ids::uuid, prelude::*},
+    lib_transport::{Facts, Meta},
+    std::{ffi::CStr, fs, mem::MaybeUninit, os::raw::c_char},
+};
+
+/// Where the machine id is kept, by systemd or otherwise by dbus
+const MACHINE_ID_PATHS: &[&str] = &["/etc/machine-id", "/var/lib/dbus/machine-id"];
+
+/// Describes the host this program is running on, along with the version of it, for the
+/// `--facts` record sent at the start of the stream. Anything that can't be found out is
+/// left out rather than failing the run
+pub fn gather() -> Facts {
+    let mut facts = Facts::now(1)
+        .fact("os", std::env::consts::OS)
+        .fact("arch", std::env::consts::ARCH)
+        .fact("extract_version", env!("CARGO_PKG_VERSION"));
+
+    if let Some(hostname) = hostname() {
+        facts = facts.fact("hostname", hostname);
+    }
+    if let Some(kernel) = kernel() {
+        facts = facts.fact("kernel", kernel);
+    }
+    // Safety: sysconf has no preconditions, and fails with -1
+    match unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) } {
+        cpus if cpus > 0 => facts.fact("cpus", cpus.to_string()),
+        _ => facts,
+    }
+}
+
+/// Labels every Header is sent with, so that the output of many hosts can be told apart once
+/// merged: the host's name, its machine id and an id unique to this run of the program, each
+/// left out if it can't be found out
+pub fn identity() -> Meta {
+    let instance_id = uuid()
+        .map_err(|e| warn!("Unable to generate an instance id: {}", e))
+        .ok();
+
+    vec![
+        ("hostname", hostname()),
+        ("machine_id", machine_id()),
+        ("instance_id", instance_id),
+    ]
+    .into_iter()
+    .filter_map(|(key, val)| Some((key.to_string(), val?)))
+    .collect()
+}
+
+fn hostname() -> Option<String> {
+    let mut buf = [0 as c_char; 256];
+    // Safety: the last byte is never written, so the name is always terminated
+    match unsafe { libc::gethostname(buf.as_mut_ptr(), buf.len() - 1) } {
+        0 => Some(to_string(&buf)),
+        _ => None,
+    }
+}
+
+/// The id systemd gives the host on install, stable across reboots unlike its boot id
+fn machine_id() -> Option<String> {
+    MACHINE_ID_PATHS.iter().find_map(|path| {
+        let id = fs::read_to_string(path).ok()?;
+        let id = id.trim();
+        match id.is_empty() {
+            true => None,
+            false => Some(id.to_string()),
+        }
+    })
+}
+
+/// The kernel's name and release, i.e "Linux 5.4.0"
+fn kernel() -> Option<String> {
+    let mut name = MaybeUninit::<libc::utsname>::zeroed();
+    // Safety: uname only writes the struct it is given, which is fully initialized on success
+    match unsafe { libc::uname(name.as_mut_ptr()) } {
+        0 => {
+            let name = unsafe { name.assume_init() };
+            Some(format!(
+                "{} {}",
+                to_string(&name.sysname),
+                to_string(&name.release)
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn to_string(buf: &[c_char]) -> String {
+    // Safety: every buffer passed is nul terminated
+    unsafe { CStr::from_ptr(buf.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
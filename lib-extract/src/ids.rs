@@ -0,0 +1,167 @@
+use {
+    crate::{compare::Priority, error::Err, prelude::*, unit::Unit, ARGS},
+    std::{
+        collections::HashMap,
+        io,
+        os::unix::ffi::OsStrExt,
+        path::{Path, PathBuf},
+    },
+};
+
+/// An executable found under an exec root, along with the id its records carry
+pub type Identified = Result<(Priority, Unit, String)>;
+
+/// How the records of each executable are identified
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdStrategy {
+    /// Its file name, which executables in different directories may share
+    Name,
+    /// Its path under its exec root
+    Path,
+    /// Its file name, followed by a hash of its path under its exec root
+    Hash,
+    /// A random UUID, new each time it runs
+    Uuid,
+}
+
+impl IdStrategy {
+    pub fn parse(val: &str) -> Option<Self> {
+        match val {
+            "name" => Some(Self::Name),
+            "path" => Some(Self::Path),
+            "hash" => Some(Self::Hash),
+            "uuid" => Some(Self::Uuid),
+            _ => None,
+        }
+    }
+
+    fn id(self, unit: &Unit) -> Result<String> {
+        let name = utf8(unit.path().file_name().unwrap_or_default().as_ref())?;
+
+        match self {
+            Self::Name => Ok(name.to_string()),
+            Self::Path => utf8(unit.relative()).map(String::from),
+            Self::Hash => Ok(format!("{}-{:08x}", name, fnv1a(unit.relative()))),
+            Self::Uuid => uuid(),
+        }
+    }
+}
+
+/// What happens when two executables of the same list would share an id
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnCollision {
+    /// Log a warning, leaving both with the same id
+    Warn,
+    /// Run nothing in the list, reporting an error instead
+    Fail,
+    /// Suffix every id after the first with `~N`, counting from 2
+    Suffix,
+}
+
+impl OnCollision {
+    pub fn parse(val: &str) -> Option<Self> {
+        match val {
+            "warn" => Some(Self::Warn),
+            "fail" => Some(Self::Fail),
+            "suffix" => Some(Self::Suffix),
+            _ => None,
+        }
+    }
+}
+
+/// Identifies every executable in `list` by `--id-strategy`, handling any that would share an
+/// id by `--id-collision`. Ids are only checked within a list, as each list is its own logical
+/// stream when there are several. Fails if any collide and they should fail, before anything
+/// is run
+pub fn identify<I>(list: I) -> Result<Vec<Identified>>
+where
+    I: Iterator<Item = Result<(Priority, Unit)>>,
+{
+    let (strategy, on_collision) = (ARGS.id_strategy(), ARGS.id_collision());
+    let mut seen: HashMap<String, PathBuf> = HashMap::new();
+    let mut identified = Vec::new();
+
+    for result in list {
+        let (priority, unit) = match result {
+            Ok(found) => found,
+            Err(e) => {
+                identified.push(Err(e));
+                continue;
+            }
+        };
+        let mut id = match strategy.id(&unit) {
+            Ok(id) => id,
+            Err(e) => {
+                identified.push(Err(e));
+                continue;
+            }
+        };
+
+        if let Some(first) = seen.get(&id) {
+            match on_collision {
+                OnCollision::Warn => warn!(
+                    id = %id,
+                    first = %first.display(),
+                    path = %unit.path().display(),
+                    "Executables share an id, see --id-strategy"
+                ),
+                OnCollision::Fail => {
+                    return Err(Err::IdCollision {
+                        id,
+                        first: first.display().to_string(),
+                        second: unit.path().display().to_string(),
+                    }
+                    .into())
+                }
+                OnCollision::Suffix => {
+                    id = (2..)
+                        .map(|n| format!("{}~{}", id, n))
+                        .find(|suffixed| !seen.contains_key(suffixed))
+                        .unwrap();
+                    debug!(id = %id, path = %unit.path().display(), "Suffixed shared id");
+                }
+            }
+        }
+        seen.entry(id.clone())
+            .or_insert_with(|| unit.path().to_path_buf());
+        identified.push(Ok((priority, unit, id)));
+    }
+
+    Ok(identified)
+}
+
+fn utf8(path: &Path) -> Result<&str> {
+    path.to_str()
+        .ok_or_else(|| Err::from(path.as_os_str().to_os_string()).into())
+}
+
+/// 32 bits of the FNV-1a hash of `path`, stable across runs and builds unlike std's hasher
+fn fnv1a(path: &Path) -> u32 {
+    let hash = path
+        .as_os_str()
+        .as_bytes()
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        });
+
+    (hash ^ (hash >> 32)) as u32
+}
+
+/// A random, version 4 UUID
+pub fn uuid() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    Ok(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    ))
+}
@@ -0,0 +1,217 @@
+#![allow(clippy::match_bool)]
+//! The engine behind the `extract` binary: discovering executables, running them in priority
+//! order and serializing their output as records. `Runner` runs it as the binary would, or
+//! returns the records for embedding and integration testing
+//!
+//! Only discovery is portable, finding executables on Windows by their PATHEXT extension
+//! rather than mode bits. Running them relies on unix process groups, signals and ptys, so
+//! the crate as a whole only builds for unix targets
+
+use {
+    crate::{
+        cli::{generate_cli, ProgramArgs},
+        error::Err,
+        models::{
+            decode_frame, failed_fast, frame_codec, get_executables_sorted, plan_roots,
+            process_roots, schedule_roots, splay_start, unexpected_exits, watch_roots, worker_wait,
+            write_select, ChildChannel, WriteChannel,
+        },
+        prelude::*,
+    },
+    crossbeam_channel::{bounded, Sender},
+    futures::{
+        channel::mpsc::{channel as async_bounded, Sender as AsyncSender},
+        prelude::*,
+    },
+    lazy_static::lazy_static,
+    lib_transport::{Meta, Record, Sequencer},
+    once_cell::sync::OnceCell,
+    process::{shutdown_on_signal, Groups, Jobs},
+    scan::ScanCache,
+    std::{
+        ffi::OsString,
+        io,
+        path::{Path, PathBuf},
+        sync::Arc,
+        thread,
+    },
+    summary::Summary,
+};
+
+pub use {
+    error::{CrateError, CrateResult},
+    models::init_logging,
+};
+
+mod cli;
+mod commands;
+mod compare;
+mod config;
+mod error;
+mod facts;
+mod ids;
+mod manifest;
+mod models;
+mod output;
+mod patterns;
+mod process;
+mod pty;
+mod remote;
+mod resume;
+mod rotate;
+mod sandbox;
+mod scan;
+mod spool;
+mod summary;
+mod tls;
+mod unit;
+mod watch;
+
+mod prelude {
+    pub use {
+        crate::{
+            enter,
+            error::{CrateError, CrateResult as Result, LogError as _},
+            models::SpanDisplay,
+        },
+        tracing::{
+            debug, debug_span, error, error_span as always_span, info, info_span, instrument,
+            trace, trace_span, warn, Level,
+        },
+        tracing_futures::Instrument as _,
+    };
+}
+
+#[macro_export]
+macro_rules! enter {
+    ($span:expr) => {
+        let span = $span;
+        let _grd = span.enter();
+    };
+    ($var:ident, $span:expr) => {
+        let $var = $span;
+        let _grd = $var.enter();
+    };
+}
+
+/// The run's configuration, set once by the first `Runner` configured
+static CONFIG: OnceCell<ProgramArgs> = OnceCell::new();
+
+lazy_static! {
+    static ref ARGS: &'static ProgramArgs = CONFIG
+        .get()
+        .expect("Nothing can run before a Runner has been configured");
+    static ref SEQUENCE: Sequencer = Sequencer::default();
+    static ref JOBS: Jobs = Jobs::new(ARGS.jobs());
+    static ref GROUPS: Groups = Groups::default();
+    static ref SUMMARY: Summary = Summary::default();
+    static ref SCANS: ScanCache = ScanCache::default();
+    static ref IDENTITY: Arc<Meta> = Arc::new(facts::identity());
+}
+
+/// Runs executables and serializes their output, exactly as the `extract` binary does, and is
+/// configured by the same arguments. Much of a run's state is process wide, so only one runner
+/// can be configured per process
+#[derive(Debug)]
+pub struct Runner {
+    _configured: (),
+}
+
+impl Runner {
+    /// Configures a runner from this program's command line, exiting with its usage if the
+    /// arguments are invalid
+    pub fn from_cli() -> Result<Self> {
+        Self::configure(ProgramArgs::init(generate_cli()))
+    }
+
+    /// Configures a runner from `args`, the first of which is the program's name, i.e
+    /// `["extract", "scripts/", "stdout"]`. Fails if they are invalid, or another runner
+    /// has already been configured
+    pub fn from_args<I, T>(args: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let args = ProgramArgs::try_from_args(generate_cli(), args)
+            .map_err(|e| Err::Args { reason: e.message })?;
+
+        Self::configure(args)
+    }
+
+    fn configure(args: ProgramArgs) -> Result<Self> {
+        CONFIG
+            .set(args)
+            .map_err(|_| CrateError::from(Err::Configured))?;
+
+        Ok(Self { _configured: () })
+    }
+
+    /// Runs everything, writing every record to the outputs given, and returns once the stream
+    /// has ended and every child has been reaped. Watching or scheduling runs never returns.
+    /// Being interrupted or terminated kills everything run, exiting this program. Any
+    /// `--splay` is waited out before anything is connected to.
+    /// Fails if any executable exited outside of its sidecar's `expect_exit`, or the run
+    /// failed fast
+    pub fn run(self) -> Result<()> {
+        splay_start();
+        let mut tokio = tokio::runtime::Runtime::new()?;
+        let (tx_write, rx_write) = async_bounded::<WriteChannel>(1024);
+        let (tx_child, rx_child) = bounded::<ChildChannel>(1024);
+
+        let child = worker_wait(rx_child, tx_write.clone());
+        let fut = tokio.spawn(write_select(rx_write).instrument(always_span!("tokio")));
+        tokio.spawn(async { shutdown_on_signal().await.log(Level::WARN) });
+
+        start(tx_write, tx_child);
+        tokio
+            .block_on(fut)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))??;
+
+        child.join().expect("Reaper thread panicked")?;
+
+        if let Some(path) = failed_fast() {
+            let path = path.to_string();
+            return Err(Err::FailedFast { path }.into());
+        }
+        match unexpected_exits() {
+            0 => Ok(()),
+            count => Err(Err::Failed { count }.into()),
+        }
+    }
+
+    /// The executables under `root` in the order they would run, without running anything
+    pub fn executables(&self, root: &Path) -> impl Iterator<Item = Result<PathBuf>> {
+        get_executables_sorted(root).map(|res| res.map(|(_, entry)| entry.into_path()))
+    }
+
+    /// Runs everything from a background thread, returning every record rather than writing
+    /// them to the outputs given. The stream ends after `StreamEnd`, once every child has
+    /// been reaped, and only fails if a record can't be read back, i.e with the wrong key
+    pub fn records(self) -> impl Stream<Item = Result<Record<'static, 'static>>> {
+        let (tx_write, rx_write) = async_bounded::<WriteChannel>(1024);
+        let (tx_child, rx_child) = bounded::<ChildChannel>(1024);
+
+        let child = worker_wait(rx_child, tx_write.clone());
+        thread::spawn(move || {
+            splay_start();
+            start(tx_write, tx_child);
+            child
+                .join()
+                .expect("Reaper thread panicked")
+                .log(Level::WARN)
+        });
+
+        let codec = frame_codec();
+        rx_write.map(move |frame| decode_frame(&codec, &frame))
+    }
+}
+
+/// Runs the exec roots as asked, a dry run, on a schedule, watched or just the once
+fn start(tx_write: AsyncSender<WriteChannel>, tx_child: Sender<ChildChannel>) {
+    match (ARGS.dry_run(), ARGS.every(), ARGS.watch()) {
+        (true, _, _) => plan_roots(ARGS.exec_roots(), tx_write, tx_child),
+        (false, Some(every), _) => schedule_roots(ARGS.exec_roots(), every, tx_write, tx_child),
+        (false, None, true) => watch_roots(ARGS.exec_roots(), tx_write, tx_child),
+        (false, None, false) => process_roots(ARGS.exec_roots(), tx_write, tx_child),
+    }
+}
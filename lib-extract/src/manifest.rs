@@ -0,0 +1,322 @@
+use {
+    crate::{
+        compare::{priority_key, Priority},
+        error::Err,
+        patterns::Patterns,
+        prelude::*,
+        scan::sort_key,
+        unit::{Container, Unit},
+    },
+    serde::Deserialize,
+    serde_yaml::from_reader as read_yaml,
+    std::{
+        collections::{BTreeMap, HashMap, HashSet},
+        convert::TryFrom,
+        ffi::OsStr,
+        fs::File,
+        io,
+        path::{Path, PathBuf},
+        sync::Arc,
+    },
+    walkdir::DirEntry,
+};
+
+/// Name of the file in an exec root that, if present, decides which of the root's
+/// executables run, and optionally the order they run in rather than the priorities in
+/// their filenames
+pub const MANIFEST_NAME: &str = "manifest.yaml";
+
+/// Which of an exec root's executables run, by the same include and exclude patterns as
+/// `--include` and `--exclude`, which add to these.
+///
+/// If given groups, the order they run in, as groups of executables that run at once.
+/// Each group runs once every group it comes `after` has finished, by default the group
+/// listed before it. Groups that become runnable at the same time run together.
+///
+/// A group may limit how many of its executables run at once with `jobs`, within
+/// `--jobs`. Groups that run together share a limit, the sum of theirs, unless any of them
+/// sets none. `--group-jobs` overrides it, with the level a group runs at, counting from 0,
+/// as its priority
+///
+/// Containers run alongside the root's executables as if they were executables named after
+/// them at the top of the root, see `Container`. Under `--watch` they re-run whenever the
+/// manifest is modified
+///
+/// ```yaml
+/// exclude: ["*.disabled"]
+/// containers:
+///   - name: scan
+///     image: alpine:3.12
+///     args: [df, -h]
+/// groups:
+///   - name: setup
+///     run: [init.sh]
+///   - name: collect
+///     run: [disk/usage.sh, net.sh, scan]
+///   - name: report
+///     run: [report.sh]
+///   # Runs alongside setup, as it comes after nothing
+///   - name: rotate
+///     run: [rotate-logs.sh]
+///     after: []
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    #[serde(default)]
+    include: Vec<String>,
+    #[serde(default)]
+    exclude: Vec<String>,
+    #[serde(default)]
+    containers: Vec<Arc<Container>>,
+    groups: Option<Vec<Group>>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Group {
+    name: String,
+    /// Executables in this group, relative to the exec root, or containers by name
+    run: Vec<PathBuf>,
+    /// Groups that must finish before this one runs, which must be listed before it
+    after: Option<Vec<String>>,
+    /// Most of this group's executables that run at once
+    jobs: Option<usize>,
+}
+
+impl Manifest {
+    /// Reads the manifest of the exec root at `root`, if it has one
+    pub fn for_root(root: &Path) -> Result<Option<Self>> {
+        let path = root.join(MANIFEST_NAME);
+
+        match File::open(&path) {
+            Ok(file) => {
+                debug!(manifest = %path.display(), "Loading manifest");
+                match read_yaml(file) {
+                    Ok(manifest) => Ok(Some(Self { path, ..manifest })),
+                    Err(source) => Err(Err::Manifest {
+                        path: path.display().to_string(),
+                        source,
+                    }
+                    .into()),
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Patterns executables must match to be included
+    pub fn include(&self) -> &[String] {
+        &self.include
+    }
+
+    /// Patterns excluding any executable that matches
+    pub fn exclude(&self) -> &[String] {
+        &self.exclude
+    }
+
+    /// Most executables that run at once at each level with a limit, by the `jobs` of the
+    /// groups at it. Without groups, nothing is limited
+    pub fn jobs(&self) -> Result<HashMap<u64, usize>> {
+        let groups = match &self.groups {
+            Some(groups) => groups,
+            None => return Ok(HashMap::new()),
+        };
+        let mut jobs: HashMap<u64, Option<usize>> = HashMap::new();
+
+        for (group, level) in groups.iter().zip(self.levels(groups)?) {
+            let limit = jobs.entry(level).or_insert(Some(0));
+            *limit = limit.zip(group.jobs).map(|(sum, jobs)| sum + jobs);
+        }
+
+        Ok(jobs
+            .into_iter()
+            .filter_map(|(level, limit)| limit.map(|limit| (level, limit)))
+            .collect())
+    }
+
+    /// Orders the executables `found` under `root` by this manifest's groups, if it has any,
+    /// each prioritized by the level its group runs at, along with its containers. Anything
+    /// found but not listed is skipped, and anything listed but not found is reported as an
+    /// error, unless `patterns` exclude it. Without groups, containers are ordered among what
+    /// was found as executables at the top of the root would be
+    pub fn order(
+        &self,
+        root: &Path,
+        found: impl Iterator<Item = Result<(Priority, DirEntry)>>,
+        patterns: &Patterns,
+    ) -> Result<Vec<Result<(Priority, Unit)>>> {
+        let containers = self.containers(root, patterns)?;
+        let groups = match &self.groups {
+            Some(groups) => groups,
+            None => {
+                let (mut ordered, mut keyed) = (Vec::new(), Vec::new());
+                for res in found {
+                    match res {
+                        Ok((priority, entry)) => {
+                            keyed.push((sort_key(&entry), Ok((priority, Unit::File(entry)))))
+                        }
+                        Err(e) => ordered.push(Err(e)),
+                    }
+                }
+                keyed.extend(containers.into_iter().map(|unit| {
+                    let key = vec![priority_key(unit.relative().as_os_str())];
+                    (
+                        key,
+                        Priority::try_from(&unit).map(|priority| (priority, unit)),
+                    )
+                }));
+                // Stable, so what was found keeps its order
+                keyed.sort_by(|(a, _), (b, _)| a.cmp(b));
+                ordered.extend(keyed.into_iter().map(|(_, res)| res));
+
+                return Ok(ordered);
+            }
+        };
+        let levels = self.levels(groups)?;
+        let mut ordered = Vec::new();
+        let mut unlisted = BTreeMap::new();
+
+        for res in found {
+            match res {
+                Ok((_, entry)) => {
+                    let listed = entry.path().strip_prefix(root).unwrap_or(entry.path());
+                    unlisted.insert(listed.to_path_buf(), Unit::File(entry));
+                }
+                Err(e) => ordered.push(Err(e)),
+            }
+        }
+        for unit in containers {
+            if unlisted.contains_key(unit.relative()) {
+                return Err(self.invalid(format!(
+                    "container '{}' shares its name with an executable in the exec root",
+                    unit.relative().display()
+                )));
+            }
+            unlisted.insert(unit.relative().to_path_buf(), unit);
+        }
+
+        let mut groups: Vec<_> = groups.iter().zip(levels).collect();
+        // Stable, so groups at the same level start in the order listed
+        groups.sort_by_key(|(_, level)| *level);
+
+        let mut runs = Vec::new();
+        for (group, level) in groups {
+            for listed in group.run.iter() {
+                match unlisted.remove(listed) {
+                    Some(unit) => runs.push(Ok((Priority::Number(level), unit))),
+                    None if patterns.allows(listed, false) => {
+                        runs.push(Err(self.invalid(format!(
+                            "'{}' in group '{}' isn't an executable in the exec root",
+                            listed.display(),
+                            group.name
+                        ))))
+                    }
+                    None => (),
+                }
+            }
+        }
+
+        ordered.extend(unlisted.into_values().map(|unit| {
+            Err(Err::Skipped {
+                path: unit.path().display().to_string(),
+                reason: "it isn't listed in the manifest",
+            }
+            .into())
+        }));
+        ordered.extend(runs);
+
+        Ok(ordered)
+    }
+
+    /// This manifest's containers allowed by `patterns`, as units of `root`. Every container
+    /// must have a name fit for a file at the top of the root, that no other shares
+    fn containers(&self, root: &Path, patterns: &Patterns) -> Result<Vec<Unit>> {
+        let mut names = HashSet::with_capacity(self.containers.len());
+
+        self.containers
+            .iter()
+            .filter_map(|container| {
+                let name = container.name();
+                let single = Path::new(name).file_name() == Some(OsStr::new(name));
+                if !single {
+                    return Some(Err(self.invalid(format!(
+                        "container '{}' must be named as a file at the top of the exec root",
+                        name
+                    ))));
+                }
+                if !names.insert(name) {
+                    return Some(Err(
+                        self.invalid(format!("container '{}' is repeated", name))
+                    ));
+                }
+
+                patterns.allows(Path::new(name), false).then(|| {
+                    Ok(Unit::Container {
+                        path: root.join(name),
+                        manifest: self.path.clone(),
+                        container: container.clone(),
+                    })
+                })
+            })
+            .collect()
+    }
+
+    /// The level each group runs at, one past the highest of the groups it comes after
+    fn levels(&self, groups: &[Group]) -> Result<Vec<u64>> {
+        let mut levels: HashMap<&str, u64> = HashMap::with_capacity(groups.len());
+        let mut listed: HashMap<&Path, &str> = HashMap::new();
+
+        groups
+            .iter()
+            .enumerate()
+            .map(|(idx, group)| {
+                if group.jobs == Some(0) {
+                    return Err(
+                        self.invalid(format!("group '{}' must allow at least 1 job", group.name))
+                    );
+                }
+                for path in group.run.iter() {
+                    if let Some(other) = listed.insert(path, &group.name) {
+                        return Err(self.invalid(format!(
+                            "'{}' is in both group '{}' and '{}'",
+                            path.display(),
+                            other,
+                            group.name
+                        )));
+                    }
+                }
+
+                let previous = idx.checked_sub(1).map(|prev| &groups[prev].name);
+                let level = match &group.after {
+                    Some(after) => after.iter().try_fold(0, |level, name| -> Result<u64> {
+                        let after = levels.get(name.as_str()).ok_or_else(|| {
+                            self.invalid(format!(
+                                "group '{}' comes after '{}', which isn't listed before it",
+                                group.name, name
+                            ))
+                        })?;
+                        Ok(level.max(after + 1))
+                    })?,
+                    None => previous.map_or(0, |name| levels[name.as_str()] + 1),
+                };
+
+                match levels.insert(&group.name, level) {
+                    Some(_) => Err(self.invalid(format!("group '{}' is repeated", group.name))),
+                    None => Ok(level),
+                }
+            })
+            .collect()
+    }
+
+    fn invalid(&self, reason: String) -> CrateError {
+        Err::ManifestEntry {
+            path: self.path.display().to_string(),
+            reason,
+        }
+        .into()
+    }
+}
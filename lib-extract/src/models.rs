@@ -0,0 +1,1646 @@
+use {
+    crate::{
+        cli::{ConOpts, Format},
+        commands,
+        compare::Priority,
+        config::{SpawnConfig, SIDECAR_EXTENSION},
+        error::Err,
+        facts,
+        ids::identify,
+        manifest::Manifest,
+        output::OutputContext,
+        patterns::Patterns,
+        prelude::*,
+        process::{
+            exit, past_deadline, planned, process_child, record_sink, report_error, report_log,
+            spawn_process, wait_exited, Jobs, RecordSink, Watchdog,
+        },
+        remote::{self, HOST_META},
+        resume::Preamble,
+        rotate::write_file,
+        scan,
+        spool::Spool,
+        unit::Unit,
+        watch::Watcher,
+        ARGS, GROUPS, IDENTITY, JOBS, SCANS, SUMMARY,
+    },
+    bytes::{BufMut, Bytes, BytesMut},
+    crossbeam_channel::{unbounded, Receiver, Sender},
+    futures::{
+        channel::mpsc::{
+            channel as async_bounded, Receiver as AsyncReceiver, Sender as AsyncSender,
+        },
+        future::{self, Either},
+        prelude::*,
+        stream,
+    },
+    lib_transport::{
+        ack::{self, AckWindow},
+        batch::{self, Batched},
+        handshake::{self, Negotiated, DEFAULT_HANDSHAKE_TIMEOUT},
+        json::JsonRecord,
+        metrics, new_trace_id, Channel, Facts, HeartbeatExt, Hello, Limits, Log, LogLevel,
+        MetricsExt, OwnedRecord, Record, RecordCodec, RecordFrame, RecordInterface,
+        RecordSerializer,
+    },
+    once_cell::sync::OnceCell,
+    rayon::{iter::ParallelBridge, prelude::*},
+    std::{
+        collections::{HashMap, VecDeque},
+        convert::TryFrom,
+        ffi::OsStr,
+        fmt,
+        fs::Metadata,
+        io::{self, Write},
+        iter,
+        marker::Unpin,
+        os::unix::process::ExitStatusExt,
+        path::{Path, PathBuf},
+        pin::Pin,
+        process::Child,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+        thread,
+        time::{Duration, Instant, SystemTime},
+    },
+    tokio::net::TcpStream,
+    tracing_subscriber::{EnvFilter, FmtSubscriber},
+    walkdir::DirEntry,
+};
+
+/// Alias for the type sent to the writer thread
+pub type WriteChannel = Bytes;
+
+/// How long nothing under a root must have changed for before `watch_roots` looks for
+/// changed executables, so that anything still being written is left alone
+const WATCH_SETTLE: Duration = Duration::from_secs(1);
+
+/// How long to wait before the first attempt at reconnecting, doubling with each after
+const RECONNECT_BACKOFF: Duration = Duration::from_millis(500);
+
+/// The longest wait between attempts at reconnecting
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Most frames sent without a flush, and so kept to be resent if the connection fails
+const MAX_UNFLUSHED: usize = 1024;
+
+/// Children that exited outside of their sidecar's `expect_exit`, failing the run
+static UNEXPECTED_EXITS: AtomicU64 = AtomicU64::new(0);
+
+/// The first executable to fail under `--fail-fast`, after which nothing more is run
+static FAILED_FAST: OnceCell<String> = OnceCell::new();
+
+/// The seed and length of the `--splay` waited out before the first run
+static SPLAYED: OnceCell<(u64, Duration)> = OnceCell::new();
+
+/// Sent to the reaper thread
+// Only one is sent per child, so boxing the larger variant would gain nothing
+#[allow(clippy::large_enum_variant)]
+pub enum ChildChannel {
+    /// A child the main program is finished with, the watchdog limiting how long it may
+    /// run if any, and where to send whether it succeeded
+    Reap(Child, OutputContext, Option<Watchdog>, Sender<bool>),
+    /// Every child of the given logical stream has been sent, so once they've been
+    /// reaped the stream can be closed
+    Close(u32),
+}
+
+/// Opens the stream, followed by the `--facts` describing this host if asked for, and the
+/// `--splay` the first run waited out if any
+fn start_stream(record_sink: &mut RecordSink) {
+    futures::executor::block_on(record_sink.send(Record::StreamStart)).unwrap();
+    if ARGS.facts() {
+        futures::executor::block_on(record_sink.send(Record::Facts(facts::gather()))).unwrap();
+    }
+    if let Some(splayed) = SPLAYED.get() {
+        send_splayed(record_sink, *splayed);
+    }
+}
+
+/// Waits out the `--splay` of the first run, before anything is connected to
+pub fn splay_start() {
+    if let Some(splayed) = splay() {
+        let _ = SPLAYED.set(splayed);
+    }
+}
+
+/// Waits a random time of up to `--splay`, returning the seed it was drawn from and how
+/// long it was, if there is a splay to wait out
+fn splay() -> Option<(u64, Duration)> {
+    let splay = ARGS.splay()?;
+    let mut bytes = [0u8; 8];
+    let seed = match getrandom::getrandom(&mut bytes) {
+        Ok(()) => u64::from_le_bytes(bytes),
+        // Anything that differs from host to host will do
+        Err(_) => SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64,
+    };
+    let wait = Duration::from_millis(seed % splay.as_millis().max(1) as u64);
+
+    info!(seed, wait_ms = wait.as_millis() as u64, "Splaying run");
+    thread::sleep(wait);
+    Some((seed, wait))
+}
+
+/// Sends the seed and length of a run's splay as a log, so that it can be traced back
+fn send_splayed(record_sink: &mut RecordSink, (seed, wait): (u64, Duration)) {
+    let message = format!("Run splayed by {}ms, seed {}", wait.as_millis(), seed);
+    let log = Log::new(message)
+        .level(LogLevel::Info)
+        .target(module_path!());
+    futures::executor::block_on(record_sink.send(Record::Log(log)))
+        .unwrap_or_else(|e| e.ref_log(Level::WARN));
+}
+
+/// Runs the executables found under each of `roots`. A single root is run exactly as it
+/// always has been, whereas several are run concurrently, each multiplexed over the one
+/// output as its own logical stream, numbered in the order the roots were given
+#[instrument(skip(roots, writer_tx, child_tx))]
+pub fn process_roots(
+    roots: &'static [PathBuf],
+    writer_tx: AsyncSender<WriteChannel>,
+    child_tx: Sender<ChildChannel>,
+) {
+    let mut record_sink = record_sink(writer_tx.clone());
+    start_stream(&mut record_sink);
+
+    let first_stream = match roots {
+        [_] => None,
+        _ => Some(0),
+    };
+    let lists = roots
+        .iter()
+        .map(|root| move || (get_executables_sorted(root), manifest_jobs(root)))
+        .collect();
+
+    process_lists(lists, first_stream, writer_tx, child_tx)
+}
+
+/// Walks and sorts each of `roots` as `process_roots` would, but rather than running anything
+/// sends a Header per executable describing the plan: the priority group each would run in,
+/// counting from 0, and its order overall. Anything that would be skipped or fail to be found
+/// is reported as it would be otherwise
+#[instrument(skip(roots, writer_tx, child_tx))]
+pub fn plan_roots(
+    roots: &'static [PathBuf],
+    writer_tx: AsyncSender<WriteChannel>,
+    child_tx: Sender<ChildChannel>,
+) {
+    let mut record_sink = record_sink(writer_tx.clone());
+    start_stream(&mut record_sink);
+
+    let streams = match roots {
+        [_] => vec![None],
+        _ => (0..roots.len() as u32).map(Some).collect(),
+    };
+
+    for (root, stream) in roots.iter().zip(streams) {
+        let mut cxt = OutputContext::new();
+        cxt.insert_version(ARGS.record_version());
+        if let Some(stream) = stream {
+            cxt.insert_stream(stream);
+            futures::executor::block_on(
+                record_sink.send(Record::Channel(Channel::open(1, stream))),
+            )
+            .unwrap();
+        }
+
+        let mut prev = None;
+        let (mut group, mut order) = (0u64, 0u64);
+        let list = identify(get_executables_sorted(root)).unwrap_or_else(|e| {
+            report_error(e, &cxt, &writer_tx).log(Level::ERROR);
+            Vec::new()
+        });
+        for result in list {
+            let (priority, unit, id) = match result {
+                Ok(found) => found,
+                Err(e) if e.is_skipped() => {
+                    report_log(&e, LogLevel::Warn, stream, &writer_tx);
+                    continue;
+                }
+                Err(e) => {
+                    report_error(e, &cxt, &writer_tx).log(Level::ERROR);
+                    continue;
+                }
+            };
+            if matches!(prev.replace(priority), Some(prev) if prev != priority) {
+                group += 1;
+            }
+
+            let path = unit.path().strip_prefix(root).unwrap_or(unit.path());
+            info!(stream = ?stream, group, order, path = %path.display(), "Would run");
+
+            let mut bld = cxt.clone();
+            bld.insert_id(&id);
+            bld.insert_trace_id(&new_trace_id());
+            bld.insert_meta(ARGS.meta().clone());
+            bld.insert_identity(IDENTITY.clone());
+            match SpawnConfig::for_executable(unit.path()) {
+                Ok(config) => bld.extend_meta(config.labels()),
+                Err(e) => {
+                    report_error(e, &bld, &writer_tx).log(Level::ERROR);
+                }
+            }
+            planned(&bld, group, order, path)
+                .build()
+                .map_err(CrateError::from)
+                .and_then(|record| futures::executor::block_on(record_sink.send(record)))
+                .unwrap_or_else(|e| {
+                    e.log(Level::ERROR);
+                });
+            order += 1;
+        }
+
+        if let Some(stream) = stream {
+            child_tx
+                .send(ChildChannel::Close(stream))
+                .expect("Reaper cannot close before every stream has been sent");
+        }
+    }
+}
+
+/// Runs every executable under `roots` each `every`, numbering the streams of each run on from
+/// the last, so every run is its own logical stream per root. A run that overruns the next
+/// skips it rather than piling up. Never returns, the stream only ends when this program
+/// is stopped
+#[instrument(skip(roots, writer_tx, child_tx))]
+pub fn schedule_roots(
+    roots: &'static [PathBuf],
+    every: Duration,
+    writer_tx: AsyncSender<WriteChannel>,
+    child_tx: Sender<ChildChannel>,
+) -> ! {
+    let mut record_sink = record_sink(writer_tx.clone());
+    start_stream(&mut record_sink);
+
+    let mut next_stream = 0u32;
+    let mut next_run = Instant::now();
+
+    loop {
+        debug!(first_stream = next_stream, "Starting scheduled run");
+        let lists = roots
+            .iter()
+            .map(|root| move || (get_executables_sorted(root), manifest_jobs(root)))
+            .collect();
+        process_lists(
+            lists,
+            Some(next_stream),
+            writer_tx.clone(),
+            child_tx.clone(),
+        );
+        next_stream += roots.len() as u32;
+
+        next_run += every;
+        let now = Instant::now();
+        if next_run < now {
+            let mut skipped = 0;
+            while next_run < now {
+                next_run += every;
+                skipped += 1;
+            }
+            warn!(
+                skipped,
+                "Run took longer than its interval, skipping missed runs"
+            );
+        }
+        thread::sleep(next_run - now);
+        // Each run is splayed from when it was due, so runs don't drift apart over time. Its
+        // streams aren't open yet, so the splay is logged outside of them
+        if let Some(splayed) = splay() {
+            send_splayed(&mut record_sink, splayed);
+        }
+    }
+}
+
+/// Runs `roots` as `process_roots` does, then watches them for executables that are added or
+/// modified, re-running each as it appears. Roots are only walked again once inotify reports
+/// a change under them and it has settled. Each round of re-runs is a fresh set of logical
+/// streams when there are several roots, as every stream of the last round has been closed.
+/// Never returns, the stream only ends when this program is stopped
+#[instrument(skip(roots, writer_tx, child_tx))]
+pub fn watch_roots(
+    roots: &'static [PathBuf],
+    writer_tx: AsyncSender<WriteChannel>,
+    child_tx: Sender<ChildChannel>,
+) -> ! {
+    let mut watcher = Watcher::new().expect("Unable to start watching roots");
+    watcher.watch(roots);
+    let mut seen = HashMap::new();
+    let _ = changed_executables(roots, &mut seen, SystemTime::now());
+
+    process_roots(roots, writer_tx.clone(), child_tx.clone());
+    let mut next_stream = roots.len() as u32;
+
+    loop {
+        if let Err(e) = watcher.wait(WATCH_SETTLE) {
+            CrateError::from(e).log(Level::WARN);
+            thread::sleep(WATCH_SETTLE);
+        }
+        watcher.watch(roots);
+
+        // Anything modified since the change settled may still be being written, so leave
+        // it for the next
+        let settled = SystemTime::now() - WATCH_SETTLE;
+        let lists: Vec<_> = changed_executables(roots, &mut seen, settled)
+            .into_iter()
+            .zip(roots)
+            .filter(|(list, _)| !list.is_empty())
+            .map(|(list, root)| move || (list.into_iter().map(Ok), manifest_jobs(root)))
+            .collect();
+        if lists.is_empty() {
+            continue;
+        }
+
+        info!(roots = lists.len(), "Executables changed, re-running them");
+        let first_stream = match roots {
+            [_] => None,
+            _ => Some(next_stream),
+        };
+        next_stream += lists.len() as u32;
+
+        process_lists(lists, first_stream, writer_tx.clone(), child_tx.clone());
+    }
+}
+
+/// Finds the executables under each root that are new or modified since they were `seen`,
+/// and were last modified before `settled`. Anything that has disappeared is forgotten
+fn changed_executables(
+    roots: &[PathBuf],
+    seen: &mut HashMap<PathBuf, Option<SystemTime>>,
+    settled: SystemTime,
+) -> Vec<Vec<(Priority, Unit)>> {
+    let mut found = HashMap::with_capacity(seen.len());
+
+    let lists = roots
+        .iter()
+        .map(|root| {
+            get_executables_sorted(root)
+                .filter_map(|res| res.log(Level::WARN).ok())
+                .filter(|(_, unit)| {
+                    let modified = unit.modified();
+                    let prev = seen.get(unit.path()).copied();
+                    let changed = prev != Some(modified);
+
+                    match changed && !matches!(modified, Some(m) if m <= settled) {
+                        // Still being written, check it again next time
+                        true => {
+                            if let Some(prev) = prev {
+                                found.insert(unit.path().to_path_buf(), prev);
+                            }
+                            false
+                        }
+                        false => {
+                            found.insert(unit.path().to_path_buf(), modified);
+                            changed
+                        }
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    *seen = found;
+
+    lists
+}
+
+/// Runs each of `lists` as its own logical stream, numbered from `first_stream`. Without a
+/// first stream there must be exactly one list, which is run without one
+fn process_lists<F, I>(
+    lists: Vec<F>,
+    first_stream: Option<u32>,
+    writer_tx: AsyncSender<WriteChannel>,
+    child_tx: Sender<ChildChannel>,
+) where
+    F: FnOnce() -> (I, HashMap<u64, usize>) + Send + 'static,
+    I: Iterator<Item = Result<(Priority, Unit)>> + Send,
+{
+    match first_stream {
+        None => lists
+            .into_iter()
+            .for_each(|f| process_list(f, None, writer_tx.clone(), child_tx.clone())),
+        Some(first) => lists
+            .into_iter()
+            .zip(first..)
+            .map(|(f, stream)| {
+                let (writer_tx, child_tx) = (writer_tx.clone(), child_tx.clone());
+                // Each stream blocks its pool's workers while waiting on a priority level to
+                // finish, so streams sharing a pool could deadlock by stealing each other's work
+                thread::spawn(move || {
+                    rayon::ThreadPoolBuilder::new()
+                        .build()
+                        .map(|pool| {
+                            pool.install(|| process_list(f, Some(stream), writer_tx, child_tx))
+                        })
+                        .unwrap_or_else(|e| error!(stream, "Failed to start stream: {}", e))
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .for_each(|handle| handle.join().expect("Stream thread panicked")),
+    }
+}
+
+/// A unit identified by `id` that is ready to run as part of its `group`, counting from 0,
+/// along with the jobs of that group if it limits them
+type Scheduled = Result<(u64, Unit, String, Option<Arc<Jobs>>)>;
+
+/// Responsible for running, processing and serializing the output of, the executable paths
+/// passed in. This function assumes that the given iterator's output is sorted by Priority,
+/// _and is already sorted_. It will attempt to run anything of the same Priority in parallel
+/// given there are system resources to do so, and by default only starts the next Priority
+/// once everything of the last has finished, see `--group-policy`. After serializing it sends the byte buffer to
+/// a channel whose receiver is responsible for writing the data out. If given a logical
+/// stream, every record is tagged with it, and the stream is opened before anything is run.
+/// Along with the list, `f` gives the jobs each priority's executables share by its root's
+/// manifest, unless `--group-jobs` overrides them
+#[instrument(skip(f, writer_tx, child_tx))]
+pub fn process_list<F, I>(
+    f: F,
+    stream: Option<u32>,
+    writer_tx: AsyncSender<WriteChannel>,
+    child_tx: Sender<ChildChannel>,
+) where
+    F: FnOnce() -> (I, HashMap<u64, usize>),
+    I: Iterator<Item = Result<(Priority, Unit)>> + Send,
+{
+    let (fctl_tx, fctl_rx): (Sender<()>, Receiver<()>) = unbounded();
+    let closer = stream.map(|stream| {
+        let mut record_sink = record_sink(writer_tx.clone());
+        futures::executor::block_on(record_sink.send(Record::Channel(Channel::open(1, stream))))
+            .unwrap();
+        (stream, child_tx.clone())
+    });
+
+    // The most executables of earlier groups that may still be running once a group starts
+    let overlap = ARGS.group_overlap().unwrap_or(u64::MAX);
+
+    // Nothing in the list runs if its ids can't be assigned
+    let (list, manifest_jobs) = f();
+    let list = identify(list).unwrap_or_else(|e| {
+        let mut cxt = OutputContext::new();
+        cxt.insert_version(ARGS.record_version());
+        if let Some(stream) = stream {
+            cxt.insert_stream(stream);
+        }
+        report_error(e, &cxt, &writer_tx).log(Level::ERROR);
+        Vec::new()
+    });
+
+    list.into_iter()
+        .flat_map(remote::on_hosts)
+        .scan(
+            (None, 0u64, 0u64, (None, None)),
+            |state, result| -> Option<Scheduled> {
+                let (prev, count, group, jobs) = state;
+                match result {
+                    Ok((priority, unit, id)) => {
+                        if priority != *prev.get_or_insert_with(|| priority) {
+                            *prev = Some(priority);
+                            *group += 1;
+                            // Every executable counted sends exactly one, so this can't underflow
+                            *count -= fctl_rx.try_iter().count() as u64;
+                            // Note that this can block
+                            while *count > overlap {
+                                fctl_rx
+                                    .recv()
+                                    .expect("Flow control tx cannot close before the rx");
+                                *count -= 1;
+                            }
+                        }
+                        *count += 1;
+                        // Each group's executables share the jobs its --group-jobs allows,
+                        // or its manifest does
+                        if jobs.0 != Some(priority) {
+                            let limit = ARGS.group_jobs(priority).or(match priority {
+                                Priority::Number(level) => manifest_jobs.get(&level).copied(),
+                                Priority::None => None,
+                            });
+                            *jobs = (Some(priority), limit.map(|n| Arc::new(Jobs::new(Some(n)))));
+                        }
+                        Some(Ok((*group, unit, id, jobs.1.clone())))
+                    }
+                    // Each is still counted off by the flow control once handled
+                    Err(e) => {
+                        *count += 1;
+                        Some(Err(e))
+                    }
+                }
+            },
+        )
+        .par_bridge()
+        .map(|result| {
+            result.map(|(group, unit, id, jobs)| {
+                let mut bld = OutputContext::new();
+                bld.insert_id(&id);
+                bld.insert_version(ARGS.record_version());
+                bld.insert_trace_id(&new_trace_id());
+                bld.insert_meta(ARGS.meta().clone());
+                bld.insert_identity(IDENTITY.clone());
+                if let Some(stream) = stream {
+                    bld.insert_stream(stream);
+                }
+                bld.insert_group(group);
+                (unit, bld, jobs)
+            })
+        })
+        .for_each_with(
+            (fctl_tx, writer_tx.clone(), child_tx),
+            |(fctl, writer, child), result| {
+                enter!(always_span!("rayon"));
+                // The group's own slot first, so that waiting on it holds up nothing else
+                let group_jobs = result.as_ref().ok().and_then(|(_, _, jobs)| jobs.clone());
+                let _group_slot = group_jobs.as_ref().map(|jobs| jobs.acquire());
+                let _slot = JOBS.acquire();
+                result
+                    .map(|(unit, bld, _)| run_with_retries(&unit, &bld, writer, child))
+                    .unwrap_or_else(|e| match e.is_skipped() {
+                        true => report_log(&e, LogLevel::Warn, stream, writer),
+                        false => {
+                            let mut cxt = OutputContext::new();
+                            cxt.insert_version(ARGS.record_version());
+                            if let Some(stream) = stream {
+                                cxt.insert_stream(stream);
+                            }
+                            report_error(e, &cxt, writer).log(Level::ERROR);
+                        }
+                    });
+
+                fctl.send(())
+                    .expect("Flow control rx cannot close before the tx");
+            },
+        );
+
+    if let Some((stream, child_tx)) = closer {
+        child_tx
+            .send(ChildChannel::Close(stream))
+            .expect("Reaper cannot close before every stream has been sent");
+    }
+    drop(writer_tx);
+}
+
+/// Runs a single executable, running it again up to `--retries` times while it fails to
+/// spawn or exits unsuccessfully, waiting twice as long as the last time before each retry.
+/// Once the run has reached its `--max-runtime`, it is reported as not run instead.
+/// With retries enabled, every attempt's header is labelled with its attempt number, as is
+/// any error reported during it
+fn run_with_retries(
+    unit: &Unit,
+    bld: &OutputContext,
+    writer: &mut AsyncSender<WriteChannel>,
+    child: &mut Sender<ChildChannel>,
+) {
+    let retries = ARGS.retries();
+    let mut backoff = ARGS.retry_backoff();
+
+    if let (true, Some((runtime, _))) = (past_deadline(), ARGS.max_runtime()) {
+        let path = unit.path().display().to_string();
+        report_error(Err::NotRun { path, runtime }.into(), bld, writer).log(Level::WARN);
+        return;
+    }
+    if let Some(first) = failed_fast() {
+        return warn!(path = %unit.path().display(), first, "Not run, as the run is failing fast");
+    }
+
+    let config = match SpawnConfig::for_executable(unit.path()) {
+        Ok(config) => config,
+        Err(e) => {
+            // Counted as run, as it would have been were its sidecar valid
+            SUMMARY.started(bld);
+            SUMMARY.finished(bld, false);
+            report_error(e, bld, writer).log(Level::ERROR);
+            return failed(unit);
+        }
+    };
+    let mut bld = bld.clone();
+    if !config.labels().is_empty() {
+        bld.extend_meta(config.labels());
+    }
+    if config.pty() {
+        bld.extend_meta(&iter::once(("pty".to_string(), "true".to_string())).collect());
+    }
+    if let Some(host) = unit.host() {
+        bld.extend_meta(&iter::once((HOST_META.to_string(), host.to_string())).collect());
+    }
+    if let Some(codes) = config.expect_exit() {
+        bld.insert_expect_exit(codes);
+    }
+    if let Some(limit) = config.max_output() {
+        bld.insert_max_output(limit);
+    }
+    bld.insert_killers(config.killers());
+    match config.priority() {
+        (None, None) => (),
+        (nice, ionice) => bld.extend_meta(
+            &nice
+                .map(|nice| ("nice".to_string(), nice.to_string()))
+                .into_iter()
+                .chain(ionice.map(|ionice| ("ionice".to_string(), ionice.to_string())))
+                .collect(),
+        ),
+    }
+    SUMMARY.started(&bld);
+
+    for attempt in 1..=retries + 1 {
+        // Only ever after an attempt has failed, so this too fails the executable
+        if attempt > 1 && past_deadline() {
+            warn!(path = %unit.path().display(), "Not retrying, the run reached its maximum runtime");
+            return failed(unit);
+        }
+        let mut bld = bld.clone();
+        if retries > 0 {
+            bld.insert_attempt(attempt, retries + 1);
+        }
+
+        let spawning = Instant::now();
+        let succeeded = spawn_process(unit, &config)
+            .map_err(|e| {
+                SUMMARY.finished(&bld, false);
+                report_error(e, &bld, writer)
+            })
+            .and_then(|(handle, pty)| {
+                enter!(always_span!("child.process", path = %unit.path().display(), pid = handle.id(), trace_id = bld.trace_id().unwrap_or_default()));
+                bld.insert_pid(handle.id());
+                if ARGS.timings() {
+                    bld.insert_spawned(spawning, spawning.elapsed());
+                }
+                process_child(handle, pty, &bld, writer, child)
+            })
+            // Waiting on the reaper only blocks this worker when a retry might follow, or a
+            // failure would stop the run
+            .map(|exited| (retries == 0 && !ARGS.fail_fast()) || exited.recv().unwrap_or(true))
+            .unwrap_or_else(|e| {
+                e.log(Level::ERROR);
+                false
+            });
+
+        if succeeded {
+            break;
+        }
+        if attempt > retries {
+            return failed(unit);
+        }
+        warn!(path = %unit.path().display(), attempt, "Failed, retrying in {:?}", backoff);
+        thread::sleep(backoff);
+        backoff *= 2;
+    }
+}
+
+/// Notes that `unit` failed, stopping the run if it should fail fast
+fn failed(unit: &Unit) {
+    if ARGS.fail_fast() && FAILED_FAST.set(unit.path().display().to_string()).is_ok() {
+        warn!(path = %unit.path().display(), "Failed, running nothing more");
+    }
+}
+
+/// The executable whose failure stopped the run under `--fail-fast`, if any has
+pub fn failed_fast() -> Option<&'static str> {
+    FAILED_FAST.get().map(String::as_str)
+}
+
+/// The jobs each level of `root`'s manifest allows, if it has one. Any error reading it is
+/// left to be reported as the root is discovered
+fn manifest_jobs(root: &Path) -> HashMap<u64, usize> {
+    match root.is_file() {
+        true => HashMap::new(),
+        false => Manifest::for_root(root)
+            .ok()
+            .flatten()
+            .and_then(|manifest| manifest.jobs().ok())
+            .unwrap_or_default(),
+    }
+}
+
+/// Alias for the executables found under an exec root, in the order they should run
+pub type Executables = Box<dyn Iterator<Item = Result<(Priority, Unit)>> + Send>;
+
+/// Returns the executables under `dir_root` in the order they should run, as
+/// `find_executables` does, unless the root has a manifest deciding the order instead.
+/// Only executables allowed by `--include`, `--exclude` and the manifest's own patterns
+/// are returned
+pub fn get_executables_sorted<T>(dir_root: T) -> Executables
+where
+    T: AsRef<Path>,
+{
+    discover(dir_root.as_ref()).unwrap_or_else(|e| Box::new(iter::once(Err(e))))
+}
+
+fn discover(root: &Path) -> Result<Executables> {
+    // A file rather than a directory lists the commands to run instead, see commands::Listed
+    if root.is_file() {
+        let patterns = Patterns::new(
+            ARGS.include().iter().map(String::as_str),
+            ARGS.exclude().iter().map(String::as_str),
+        )?;
+        return Ok(Box::new(commands::read(root, &patterns)?.into_iter()));
+    }
+
+    let manifest = Manifest::for_root(root)?;
+    let (include, exclude) = match &manifest {
+        Some(manifest) => (manifest.include(), manifest.exclude()),
+        None => (&[][..], &[][..]),
+    };
+    let patterns = Patterns::new(
+        ARGS.include().iter().chain(include).map(String::as_str),
+        ARGS.exclude().iter().chain(exclude).map(String::as_str),
+    )?;
+    let fingerprint = format!("{:?}", (include, exclude));
+    let found = find_executables(root.to_path_buf(), patterns.clone(), fingerprint);
+
+    Ok(match manifest {
+        Some(manifest) => Box::new(manifest.order(root, found, &patterns)?.into_iter()),
+        None => {
+            Box::new(found.map(|res| res.map(|(priority, entry)| (priority, Unit::File(entry)))))
+        }
+    })
+}
+
+/// Returns a iterator of Prioritized DirEntries that are guaranteed to be executable (or to have an
+/// --interpreter) and NOT a directory.
+/// In practice this is equivalent to a executable file, however evil use of symlinks could cause a non-file descriptor
+/// to pass through this filter.
+// I haven't bothered to fix this vulnerability because:
+// A. It would require multiple calls to stat
+// B. It is incredibly unlikely a user will stumble into a pathological case by accident
+// ...unless the user asks for --strict-discovery, see check_resolved
+//
+// Only reading the directories is done in order, stating and sorting what was found is spread
+// over the rayon pool as it dominates with tens of thousands of files. The result is still
+// that of a depth first walk with each directory's entries sorted by priority, see scan::sort_key
+fn find_executables(
+    dir_root: PathBuf,
+    patterns: Patterns,
+    fingerprint: String,
+) -> impl Iterator<Item = Result<(Priority, DirEntry)>> {
+    let strict = ARGS.strict_discovery();
+    let listed = |entry: &DirEntry| {
+        let relative = entry.path().strip_prefix(&dir_root).unwrap_or(entry.path());
+        // Matched before anything else, so excluded directories aren't walked at all
+        if entry.depth() > 0 && !patterns.allows(relative, entry.file_type().is_dir()) {
+            return false;
+        }
+
+        entry.file_type().is_dir()
+            || (entry.file_type().is_file() && !is_sidecar(entry))
+            // Anything but a plain file is only let through to be checked and reported
+            || (strict && !entry.file_type().is_file())
+    };
+    let examine = |entry: &DirEntry| examine(entry, strict);
+
+    // Repeated runs only rescan what changed since the last, see ScanCache
+    let (mut found, errors) = match ARGS.every().is_some() || ARGS.watch() {
+        true => SCANS.scan(&dir_root, fingerprint, listed, examine),
+        false => scan::walk(&dir_root, listed, examine),
+    };
+    found.par_sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    // Pass errors through, ahead of anything found
+    errors
+        .into_iter()
+        .map(|e| Err(e.into()))
+        .chain(found.into_iter().map(|(_, res)| res))
+}
+
+/// Returns the priority of anything listed that is an executable, or has an --interpreter
+fn examine(entry: &DirEntry, strict: bool) -> Option<Result<Priority>> {
+    if entry.file_type().is_file()
+        && ARGS.interpreter(entry.path()).is_none()
+        && !is_executable(entry).unwrap_or(false)
+    {
+        return None;
+    }
+
+    Some(
+        match strict {
+            true => check_resolved(entry),
+            false => Ok(()),
+        }
+        .and_then(|_| Priority::try_from(entry)),
+    )
+}
+
+/// Stats whatever `entry` finally resolves to, rejecting anything but a regular executable
+/// file, i.e symlinks to directories, devices or files that aren't executable
+fn check_resolved(entry: &DirEntry) -> Result<()> {
+    let skipped = |reason| {
+        Err::Skipped {
+            path: entry.path().display().to_string(),
+            reason,
+        }
+        .into()
+    };
+
+    match std::fs::metadata(entry.path()) {
+        Err(_) => Err(skipped("it, or what it links to, can't be read")),
+        Ok(meta) if !meta.is_file() => Err(skipped("it doesn't resolve to a regular file")),
+        Ok(meta)
+            if !executable(entry.path(), &meta) && ARGS.interpreter(entry.path()).is_none() =>
+        {
+            Err(skipped("it links to a file that isn't executable"))
+        }
+        Ok(_) => Ok(()),
+    }
+}
+
+/// Prints `record` to stdout as a single line of JSON
+fn print_json(record: Record<'_, '_>) -> Result<()> {
+    let record =
+        OwnedRecord::try_from(record).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let mut stdout = io::stdout();
+    serde_json::to_writer(&mut stdout, &JsonRecord::from(record)).map_err(io::Error::from)?;
+
+    writeln!(stdout).map_err(Into::into)
+}
+
+/// Initialize the global logger. This function must be called before ARGS is initialized,
+/// otherwise logs generated during CLI parsing will be silently ignored
+pub fn init_logging() {
+    let root_subscriber = FmtSubscriber::builder()
+        .with_writer(std::io::stderr)
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+            EnvFilter::default().add_directive(tracing_subscriber::filter::LevelFilter::INFO.into())
+        }))
+        .with_filter_reloading()
+        .finish();
+    tracing::subscriber::set_global_default(root_subscriber).expect("Failed to init logging");
+    info!("<== Logs Start ==>")
+}
+
+/// Receives all child processes that the main program is finished with and waits
+/// them. This is required on some architectures for the OS to release system resources.
+/// Waiting on a separate worker allows the rayon pool (which wants to be CPU bound)
+/// to avoid blocking. Each child's exit status is sent to the writer, followed by the
+/// closing of its logical stream if it has one, and once every child has been reaped
+/// the stream is closed, as the last exit is the last record besides any `--summary`
+pub fn worker_wait(
+    rx_child: Receiver<ChildChannel>,
+    writer_tx: AsyncSender<WriteChannel>,
+) -> thread::JoinHandle<Result<()>> {
+    thread::spawn(move || {
+        enter!(always_span!("child.cemetary"));
+        let mut record_sink = record_sink(writer_tx.clone());
+
+        for item in rx_child.iter() {
+            let (mut child, context, watchdog, exited) = match item {
+                ChildChannel::Reap(child, context, watchdog, exited) => {
+                    (child, context, watchdog, exited)
+                }
+                ChildChannel::Close(stream) => {
+                    debug!(stream, "All children reaped, closing stream");
+                    futures::executor::block_on(
+                        record_sink.send(Record::Channel(Channel::close(1, stream))),
+                    )
+                    .unwrap_or_else(|e| {
+                        e.log(Level::WARN);
+                    });
+                    continue;
+                }
+            };
+            let id = child.id();
+            if let Some(watchdog) = watchdog {
+                if let Err(e) = wait_exited(&child) {
+                    CrateError::from(e).log(Level::WARN);
+                }
+                watchdog.disarm(&context, &writer_tx);
+            }
+            match child.wait() {
+                Ok(status) => {
+                    GROUPS.reaped(id);
+                    let succeeded = match context.expect_exit() {
+                        Some(codes) => matches!(status.code(), Some(code) if codes.contains(&code)),
+                        None => status.success(),
+                    };
+                    SUMMARY.finished(&context, succeeded);
+                    match succeeded {
+                        true => debug!(pid = id, %status),
+                        false => warn!(pid = id, %status),
+                    }
+                    if let (false, Some(codes)) = (succeeded, context.expect_exit()) {
+                        UNEXPECTED_EXITS.fetch_add(1, Ordering::Relaxed);
+                        let err = Err::UnexpectedExit {
+                            path: context.id().unwrap_or_default().to_string(),
+                            status,
+                            expected: codes
+                                .iter()
+                                .map(ToString::to_string)
+                                .collect::<Vec<_>>()
+                                .join(", "),
+                        };
+                        report_error(err.into(), &context, &writer_tx).log(Level::ERROR);
+                    }
+                    // Only the CPU time limit ends a child with this signal, and only a sandbox's
+                    // seccomp filter this one, so either is left to the exit record without them
+                    let path = || context.id().unwrap_or_default().to_string();
+                    let killers = context.killers();
+                    let killed = match status.signal() {
+                        Some(libc::SIGXCPU) if killers.cpu_limit => {
+                            Some(Err::CpuLimit { path: path() })
+                        }
+                        Some(libc::SIGSYS) if killers.seccomp => {
+                            Some(Err::Sandboxed { path: path() })
+                        }
+                        _ => None,
+                    };
+                    if let Some(err) = killed {
+                        report_error(err.into(), &context, &writer_tx).log(Level::ERROR);
+                    }
+                    exit(&context, status)
+                        .build()
+                        .map_err(CrateError::from)
+                        .and_then(|record| futures::executor::block_on(record_sink.send(record)))
+                        .unwrap_or_else(|e| {
+                            e.log(Level::WARN);
+                        });
+                    // Only once the exit is on its way, so that it precedes any retry
+                    let _ = exited.send(succeeded);
+                }
+                Err(e) => {
+                    CrateError::from(e).log(Level::WARN);
+                }
+            }
+        }
+        match GROUPS.kill_all() {
+            0 => (),
+            groups => warn!(
+                groups,
+                "Killed what children left running once the run ended"
+            ),
+        }
+        if let Some(path) = failed_fast() {
+            let mut cxt = OutputContext::new();
+            cxt.insert_version(ARGS.record_version());
+            let err = Err::FailedFast {
+                path: path.to_string(),
+            };
+            report_error(err.into(), &cxt, &writer_tx).log(Level::ERROR);
+        }
+        if ARGS.summary() {
+            futures::executor::block_on(record_sink.send(Record::Metrics(SUMMARY.report())))
+                .unwrap_or_else(|e| {
+                    e.log(Level::WARN);
+                });
+        }
+        futures::executor::block_on(record_sink.send(Record::StreamEnd))
+    })
+}
+
+/// How many children have exited outside of their sidecar's `expect_exit` so far
+pub fn unexpected_exits() -> u64 {
+    UNEXPECTED_EXITS.load(Ordering::Relaxed)
+}
+
+/// Selects the output channels based on user input. With more than one, each is sent every
+/// record through a channel of its own, and any that fails is dropped without stopping the
+/// others. Only the first still open is waited on when its channel is full, so that output
+/// is slowed to its pace as it would be alone. Any other too far behind to take a record is
+/// dropped rather than holding up the rest, which its output shows by never ending the
+/// stream. The first failure is still returned once the rest have finished
+pub async fn write_select(mut rx_writer: AsyncReceiver<WriteChannel>) -> Result<()> {
+    let spool = ARGS
+        .spool()
+        .map(|(dir, size)| Spool::new(dir, size))
+        .transpose()?;
+    let mut outputs = ARGS.outputs().peekable();
+    let first = outputs.next().unwrap();
+    if outputs.peek().is_none() {
+        return write_output(rx_writer, first, spool).await;
+    }
+
+    let (mut senders, writers): (Vec<_>, Vec<_>) = iter::once((first, spool))
+        .chain(outputs.map(|output| (output, None)))
+        .enumerate()
+        .map(|(index, (output, spool))| {
+            let (tx, rx) = async_bounded::<WriteChannel>(1024);
+            ((index, tx), tokio::spawn(write_output(rx, output, spool)))
+        })
+        .unzip();
+    let of = senders.len();
+    let mut result = Ok(());
+
+    while let Some(frame) = rx_writer.next().await {
+        let mut open = Vec::with_capacity(senders.len());
+        for (index, mut tx) in senders.drain(..) {
+            let sent = match open.is_empty() {
+                true => tx.send(frame.clone()).await.map_err(|e| e.is_full()),
+                false => tx.try_send(frame.clone()).map_err(|e| e.is_full()),
+            };
+            match sent {
+                Ok(()) => open.push((index, tx)),
+                Err(true) => match CrateError::from(Err::Behind { index, of }) {
+                    e if result.is_ok() => result = Err(e),
+                    e => {
+                        e.log(Level::ERROR);
+                    }
+                },
+                // Its writer has failed, and will return why
+                Err(false) => (),
+            }
+        }
+        senders = open;
+
+        if senders.is_empty() {
+            break;
+        }
+    }
+    drop(senders);
+
+    for res in future::join_all(writers).await {
+        match res.map_err(|e| io::Error::new(io::ErrorKind::Other, e))? {
+            Err(e) if result.is_ok() => result = Err(e),
+            Err(e) => {
+                e.log(Level::ERROR);
+            }
+            Ok(()) => (),
+        }
+    }
+
+    result
+}
+
+/// Writes every record received to the given output, spooling them while reconnecting if
+/// given a spool
+async fn write_output(
+    rx_writer: AsyncReceiver<WriteChannel>,
+    output: &ConOpts,
+    spool: Option<Spool>,
+) -> Result<()> {
+    match output {
+        #[cfg(unix)]
+        ConOpts::UnixSocket(socket) => {
+            use tokio::net::UnixStream;
+            write_cbor(rx_writer, spool, || UnixStream::connect(socket))
+                .instrument(always_span!("unixstream", socket = %socket.display()))
+                .await
+        }
+        ConOpts::Tcp(addr, None) => {
+            let addr = (addr.0.as_str(), addr.1);
+            write_cbor(rx_writer, spool, || TcpStream::connect(addr))
+                .instrument(always_span!("tcp", bind = %addr.0, port = addr.1))
+                .await
+        }
+        ConOpts::Tcp(addr, Some(tls)) => {
+            let addr = (addr.0.as_str(), addr.1);
+            let (connector, name) = tls.connector(addr.0).log(Level::ERROR)?;
+            let connect = || {
+                let (connector, name) = (connector.clone(), name.clone());
+                async move {
+                    let stream = TcpStream::connect(addr).await?;
+                    connector.connect(name.as_ref(), stream).await
+                }
+            };
+
+            write_cbor(rx_writer, spool, connect)
+                .instrument(always_span!("tls", bind = %addr.0, port = addr.1))
+                .await
+        }
+        ConOpts::File(path, rotation) => {
+            write_file(rx_writer, path, rotation)
+                .instrument(always_span!("file", path = %path.display()))
+                .await
+        }
+        ConOpts::Stdout => {
+            write_debug(rx_writer)
+                .instrument(always_span!("debug", socket = "stdout"))
+                .await
+        }
+    }
+}
+
+/// Writes every record received to a connection made by `connect`. With `--reconnect`, a
+/// connection that can't be made, or is lost, is made again with exponential backoff, until
+/// it has been down for longer than allowed. Each new connection starts with a fresh
+/// handshake and the `Preamble` of the stream so far, after which any frames that hadn't yet
+/// been flushed to the last are sent again, so a listener may see a record twice (which it
+/// can tell by its sequence number). Records sent
+/// while the connection is down are moved to the `spool` if given, otherwise they wait in the
+/// writer channel, holding up children once it fills
+async fn write_cbor<C, F, W>(
+    rx_writer: AsyncReceiver<WriteChannel>,
+    mut spool: Option<Spool>,
+    mut connect: C,
+) -> Result<()>
+where
+    C: FnMut() -> F,
+    F: Future<Output = io::Result<W>>,
+    W: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let checksum = ARGS.checksum();
+    let encryption = ARGS.encryption();
+    let (mut writer, mut negotiated) = establish(&mut connect).await?;
+
+    // Older listeners would reject a record kind they don't know, so only report to those
+    // that asked for it. Decided by the first connection, as the stream is only built once
+    let period = ARGS
+        .metrics()
+        .filter(|_| negotiated.has_feature(metrics::FEATURE));
+    if ARGS.metrics().is_some() && period.is_none() {
+        warn!("Listener does not support metrics, none will be reported")
+    }
+
+    let rx_writer = match period {
+        Some(period) => {
+            let serializer = RecordSerializer::new()
+                .with_checksum(checksum)
+                .with_compression(ARGS.compression())
+                .with_encryption(encryption.clone());
+            rx_writer
+                .metrics("extract", period, serializer)
+                .left_stream()
+        }
+        None => rx_writer.right_stream(),
+    };
+
+    let rx_writer = match ARGS.heartbeat() {
+        Some(period) => {
+            let serializer = RecordSerializer::new()
+                .with_checksum(checksum)
+                .with_compression(ARGS.compression())
+                .with_encryption(encryption.clone());
+            rx_writer.heartbeat(period, serializer).left_stream()
+        }
+        None => rx_writer.right_stream(),
+    };
+
+    // Spooling may see the end of the records before they are all sent
+    let rx_writer = rx_writer.fuse();
+    futures::pin_mut!(rx_writer);
+    let mut unsent = VecDeque::new();
+    let mut preamble = ARGS.reconnect().map(|_| Preamble::new());
+    loop {
+        let res = {
+            // Anything spooled is older than what is still in the channel
+            let spooled = stream::iter(spool.iter_mut().flat_map(Spool::drain));
+            let mut frames = spooled.chain(&mut rx_writer);
+            let resent = Resent {
+                preamble: preamble.as_mut(),
+                unsent: &mut unsent,
+            };
+            write_frames(writer, &negotiated, &mut frames, resent).await
+        };
+
+        match res {
+            Ok(()) => break,
+            Err(e) if ARGS.reconnect().is_some() => {
+                warn!(
+                    unsent = unsent.len(),
+                    "Connection lost, reconnecting: {}", e
+                );
+                let (new_writer, new_negotiated) = match spool.as_mut() {
+                    Some(spool) => {
+                        spool_while(spool, &mut rx_writer, establish(&mut connect)).await?
+                    }
+                    None => establish(&mut connect).await?,
+                };
+                writer = new_writer;
+                negotiated = new_negotiated;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    info!("All data written successfully, closing the connection");
+
+    Ok(())
+}
+
+/// Connects and handshakes with the listener, retrying with exponential backoff for as long
+/// as `--reconnect` allows, if at all. A listener that doesn't reply to the handshake in
+/// time, as one that predates it never will, fails the attempt like any other. A listener
+/// that can't accept the records this program sends is never retried
+async fn establish<C, F, W>(connect: &mut C) -> Result<(W, Negotiated)>
+where
+    C: FnMut() -> F,
+    F: Future<Output = io::Result<W>>,
+    W: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let give_up = ARGS.reconnect().map(|limit| Instant::now() + limit);
+    let mut backoff = RECONNECT_BACKOFF;
+
+    loop {
+        debug!("Attempting connection...");
+        let attempt = async {
+            let mut writer = connect().await?;
+            let mut interface = RecordInterface::from_both(&mut writer);
+            let negotiated =
+                handshake::connect_within(&mut interface, hello(), DEFAULT_HANDSHAKE_TIMEOUT)
+                    .await?;
+            Ok::<_, CrateError>((writer, negotiated))
+        };
+
+        match (attempt.await, give_up) {
+            (Ok((writer, negotiated)), _) => {
+                info!("Connection established");
+                debug!(version = negotiated.proto_version, "Handshake complete");
+                check_negotiated(&negotiated)?;
+                return Ok((writer, negotiated));
+            }
+            (Err(e), Some(give_up)) if Instant::now() + backoff < give_up => {
+                warn!("Unable to connect, retrying in {:?}: {}", backoff, e);
+                tokio::time::delay_for(backoff).await;
+                backoff = (backoff * 2).min(MAX_RECONNECT_BACKOFF);
+            }
+            (Err(e), _) => return Err(e.log(Level::ERROR)),
+        }
+    }
+}
+
+/// Moves `frames` into `spool` until `establishing` completes
+async fn spool_while<St, Fut, T>(spool: &mut Spool, frames: &mut St, establishing: Fut) -> Result<T>
+where
+    St: Stream<Item = Bytes> + Unpin,
+    Fut: Future<Output = Result<T>>,
+{
+    let filling = spool.fill(frames);
+    futures::pin_mut!(establishing, filling);
+
+    match future::select(establishing, filling).await {
+        Either::Left((res, _)) => res,
+        Either::Right((res, establishing)) => {
+            res?;
+            establishing.await
+        }
+    }
+}
+
+/// The features this program asks the listener for, along with any pipeline
+fn hello() -> Hello {
+    let hello = Hello::new(
+        ARGS.checksum()
+            .feature()
+            .into_iter()
+            .chain(ARGS.encryption().feature())
+            .chain(ARGS.compression().feature())
+            .chain(ARGS.ack_window().map(|_| ack::FEATURE))
+            .chain(Some(metrics::FEATURE).filter(|_| ARGS.metrics().is_some() || ARGS.summary()))
+            .chain(Some(Facts::FEATURE).filter(|_| ARGS.facts()))
+            .chain(ARGS.batch().map(|_| batch::FEATURE)),
+    );
+
+    match ARGS.pipeline() {
+        Some(name) => hello.with_pipeline(name),
+        None => hello,
+    }
+}
+
+/// Records are sealed before the handshake takes place, so the listener must accept
+/// the checksum, encryption and compression, along with the version of any Data records
+fn check_negotiated(negotiated: &Negotiated) -> Result<()> {
+    if let Some(feature) = ARGS.checksum().feature() {
+        if !negotiated.has_feature(feature) {
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("listener does not support the '{}' checksum", feature),
+            )
+            .into());
+        }
+    }
+    if let Some(feature) = ARGS.encryption().feature() {
+        if !negotiated.has_feature(feature) {
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("listener does not support '{}' encryption", feature),
+            )
+            .into());
+        }
+    }
+    if let Some(feature) = ARGS.compression().feature() {
+        if !negotiated.has_feature(feature) {
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("listener does not support '{}' compression", feature),
+            )
+            .into());
+        }
+    }
+
+    // Unlike periodic metrics, the summary is sent by the reaper not the writer, so it can't
+    // be left out for a listener that doesn't support it
+    if ARGS.summary() && !negotiated.has_feature(metrics::FEATURE) {
+        return Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            "listener does not support metrics, which are required for the summary",
+        )
+        .into());
+    }
+
+    if ARGS.facts() && !negotiated.has_feature(Facts::FEATURE) {
+        return Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            "listener does not support facts records",
+        )
+        .into());
+    }
+
+    if negotiated.proto_version < ARGS.record_version() {
+        return Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!(
+                "listener does not support protocol version {}, which is required for binary data",
+                ARGS.record_version()
+            ),
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Writes `frames` to `writer` until they end, with whichever of acks and batches
+/// were negotiated
+async fn write_frames<W, St>(
+    writer: W,
+    negotiated: &Negotiated,
+    frames: &mut St,
+    resent: Resent<'_>,
+) -> Result<()>
+where
+    W: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+    St: Stream<Item = Bytes> + Unpin,
+{
+    let checksum = ARGS.checksum();
+    let encryption = ARGS.encryption();
+
+    // Unlike checksums, acks are optional so fall back to relying on the socket's buffers
+    let window = ARGS
+        .ack_window()
+        .filter(|_| negotiated.has_feature(ack::FEATURE));
+    if ARGS.ack_window().is_some() && window.is_none() {
+        warn!("Listener does not support acks, records will be sent unacknowledged")
+    }
+
+    let batch = ARGS
+        .batch()
+        .filter(|_| negotiated.has_feature(batch::FEATURE));
+    if ARGS.batch().is_some() && batch.is_none() {
+        warn!("Listener does not support batches, records will be sent one per frame")
+    }
+
+    match window {
+        Some(window) => {
+            let (read, write) = tokio::io::split(writer);
+            let acks = RecordInterface::from_read(read)
+                .with_checksum(checksum)
+                .with_compression(ARGS.compression())
+                .with_encryption(encryption.clone());
+            let buffer = tokio::io::BufWriter::new(write);
+            // Acks count records rather than frames, so batches are packed beneath the window
+            let sink = AckWindow::new(batched(RecordFrame::write(buffer), batch), acks, window);
+            forward_frames(frames, resent, sink).await
+        }
+        None => {
+            let buffer = tokio::io::BufWriter::new(writer);
+            forward_frames(frames, resent, batched(RecordFrame::write(buffer), batch)).await
+        }
+    }
+}
+
+/// What a connection is sent again of what the last was sent, if there was one
+struct Resent<'a> {
+    /// The stream so far, kept with `--reconnect`
+    preamble: Option<&'a mut Preamble>,
+    /// Frames not yet flushed to the last connection
+    unsent: &'a mut VecDeque<Bytes>,
+}
+
+impl Resent<'_> {
+    /// Drops the unsent frames once they have been flushed, noting them as delivered
+    fn flushed(&mut self) {
+        match self.preamble.as_mut() {
+            Some(preamble) => self
+                .unsent
+                .drain(..)
+                .for_each(|frame| preamble.delivered(&frame)),
+            None => self.unsent.clear(),
+        }
+    }
+}
+
+/// Forwards `frames` into `sink` as `StreamExt::forward` does, after first resending the
+/// preamble and any unsent frames over a connection that failed. Frames are only dropped
+/// from `unsent` once a flush has succeeded, so that they can be sent again if this fails
+async fn forward_frames<St, Si>(frames: &mut St, mut resent: Resent<'_>, sink: Si) -> Result<()>
+where
+    St: Stream<Item = Bytes> + Unpin,
+    Si: Sink<Bytes>,
+    CrateError: From<Si::Error>,
+{
+    futures::pin_mut!(sink);
+
+    let preamble = resent
+        .preamble
+        .iter()
+        .flat_map(|preamble| preamble.frames());
+    for frame in preamble.chain(resent.unsent.iter().cloned()) {
+        feed(sink.as_mut(), frame).await?;
+    }
+
+    loop {
+        let next = match frames.next().now_or_never() {
+            Some(next) if resent.unsent.len() < MAX_UNFLUSHED => next,
+            // Flush whenever there is nothing more to send yet, as forward does
+            ready => {
+                sink.flush().await?;
+                resent.flushed();
+                match ready {
+                    Some(next) => next,
+                    None => frames.next().await,
+                }
+            }
+        };
+
+        match next {
+            Some(frame) => {
+                resent.unsent.push_back(frame.clone());
+                feed(sink.as_mut(), frame).await?;
+            }
+            None => {
+                sink.close().await?;
+                resent.flushed();
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Sends `item` to `sink` without flushing it
+pub(crate) async fn feed<Si, T>(
+    mut sink: Pin<&mut Si>,
+    item: T,
+) -> std::result::Result<(), Si::Error>
+where
+    Si: Sink<T>,
+{
+    future::poll_fn(|cx| sink.as_mut().poll_ready(cx)).await?;
+    sink.as_mut().start_send(item)
+}
+
+/// Packs up to `batch` records into each frame sent to `sink`, if batching was negotiated
+fn batched<Si>(sink: Si, batch: Option<usize>) -> impl Sink<Bytes, Error = Si::Error>
+where
+    Si: Sink<Bytes>,
+{
+    match batch {
+        Some(records) => Batched::new(sink, records, batch::DEFAULT_FLUSH_INTERVAL).left_sink(),
+        None => sink.right_sink(),
+    }
+}
+
+/// Prints to stdout, but as a one line summary of each record not cbor, or a JSON object with
+/// `--format ndjson`. Each record is printed as it arrives, so a run that never ends is still
+/// printed. Should mostly be used for debugging purposes
+async fn write_debug(rx_writer: AsyncReceiver<WriteChannel>) -> Result<()> {
+    let mut rx_writer = match ARGS.metrics() {
+        Some(period) => {
+            let serializer = RecordSerializer::new()
+                .with_checksum(ARGS.checksum())
+                .with_compression(ARGS.compression())
+                .with_encryption(ARGS.encryption().clone());
+            rx_writer
+                .metrics("extract", period, serializer)
+                .left_stream()
+        }
+        None => rx_writer.right_stream(),
+    };
+
+    let codec = frame_codec();
+    let format = ARGS.format();
+    while let Some(frame) = rx_writer.next().await {
+        trace!("Writer received item, sized: {}", frame.len());
+        match (format, decode_frame(&codec, &frame)?) {
+            (Format::Debug, record) => println!("{}", record),
+            (Format::Ndjson, record) => print_json(record)?,
+        }
+    }
+
+    info!("All data written successfully");
+
+    Ok(())
+}
+
+/// Reads back the frames sent to the writer, as a listener would bar the framing
+pub fn frame_codec() -> RecordCodec {
+    RecordCodec::new()
+        .with_checksum(ARGS.checksum())
+        .with_compression(ARGS.compression())
+        .with_encryption(ARGS.encryption().clone())
+        .with_limits(Limits::new().with_max_frame_len(u32::MAX as usize))
+}
+
+/// Deserializes a single frame sent to the writer, which is missing only its length prefix
+pub fn decode_frame(codec: &RecordCodec, frame: &[u8]) -> Result<Record<'static, 'static>> {
+    let mut src = BytesMut::with_capacity(4 + frame.len());
+    src.put_u32(frame.len() as u32);
+    src.extend_from_slice(frame);
+
+    match codec.decode(&mut src)? {
+        Some(record) => Ok(record),
+        None => unreachable!("The buffer always holds a whole frame"),
+    }
+}
+
+fn is_executable(entry: &DirEntry) -> Result<bool> {
+    entry
+        .metadata()
+        .map(|meta| executable(entry.path(), &meta))
+        .map_err(|e| e.into())
+}
+
+/// Unix specific, checks file mode bits for executable status
+#[cfg(unix)]
+fn executable(_: &Path, meta: &Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    mode_exec(meta.permissions().mode())
+}
+
+/// Windows has no executable bit, so as with the shell, anything whose extension is listed
+/// in PATHEXT is executable
+#[cfg(windows)]
+fn executable(path: &Path, _: &Metadata) -> bool {
+    lazy_static::lazy_static! {
+        static ref PATHEXT: String =
+            std::env::var("PATHEXT").unwrap_or_else(|_| DEFAULT_PATHEXT.to_string());
+    }
+
+    path_ext_listed(path, &PATHEXT)
+}
+
+/// Whether the extension of `path` is one of those in `pathext`, a `;` separated list as
+/// PATHEXT is, i.e `.COM;.EXE`, ignoring case. Compiled everywhere but only used on Windows,
+/// so that it is tested
+#[cfg(any(windows, test))]
+fn path_ext_listed(path: &Path, pathext: &str) -> bool {
+    path.extension().and_then(OsStr::to_str).is_some_and(|ext| {
+        pathext
+            .split(';')
+            .map(|known| known.trim_start_matches('.'))
+            .any(|known| !known.is_empty() && known.eq_ignore_ascii_case(ext))
+    })
+}
+
+/// Used when PATHEXT isn't set, as it is by default
+#[cfg(windows)]
+const DEFAULT_PATHEXT: &str = ".COM;.EXE;.BAT;.CMD;.VBS;.VBE;.JS;.JSE;.WSF;.WSH;.MSC";
+
+/// Sidecar files configure an executable, they are never executed themselves
+fn is_sidecar(entry: &DirEntry) -> bool {
+    entry.path().extension() == Some(OsStr::new(SIDECAR_EXTENSION))
+}
+
+/// AND's exec bits
+#[cfg(unix)]
+fn mode_exec(mode: u32) -> bool {
+    mode & 0o111 != 0
+}
+
+pub trait SpanDisplay {
+    fn span_print(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+
+    fn span_display(&self) -> LocalDisplay<Self>
+    where
+        Self: Sized,
+    {
+        LocalDisplay::new(self)
+    }
+}
+
+pub struct LocalDisplay<'a, T> {
+    owner: &'a T,
+}
+
+impl<'a, T> LocalDisplay<'a, T> {
+    pub fn new(owner: &'a T) -> Self
+    where
+        T: SpanDisplay,
+    {
+        Self { owner }
+    }
+}
+
+impl<'a, T> fmt::Display for LocalDisplay<'a, T>
+where
+    T: SpanDisplay,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.owner.span_print(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_ext_listed_ignores_case_and_dots() {
+        let pathext = ".COM;.EXE;;bat";
+
+        assert!(path_ext_listed(Path::new("run.exe"), pathext));
+        assert!(path_ext_listed(Path::new("dir/run.BAT"), pathext));
+        assert!(!path_ext_listed(Path::new("run.ps1"), pathext));
+        assert!(!path_ext_listed(Path::new("exe"), pathext));
+        assert!(!path_ext_listed(Path::new("run."), pathext));
+    }
+}
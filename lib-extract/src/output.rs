@@ -0,0 +1,374 @@
+use {
+    crate::prelude::*,
+    arrayvec::ArrayVec,
+    lib_transport::{DataBuilder, DataContext, ExitBuilder, HeaderBuilder, Meta},
+    std::{
+        fmt,
+        sync::Arc,
+        time::{Duration, Instant},
+    },
+};
+
+/// Local representation of DataContext
+#[derive(Debug, Clone, Copy)]
+pub enum Directive {
+    Start,
+    Stdout,
+    Stderr,
+    End,
+}
+
+impl Into<DataContext> for Directive {
+    fn into(self) -> DataContext {
+        match self {
+            Self::Start => DataContext::Start,
+            Self::Stdout => DataContext::Stdout,
+            Self::Stderr => DataContext::Stderr,
+            Self::End => DataContext::End,
+        }
+    }
+}
+
+impl std::fmt::Display for Directive {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let x = match self {
+            Self::Start => "BEGIN",
+            Self::Stdout => "STDOUT",
+            Self::Stderr => "STDERR",
+            Self::End => "END",
+        };
+
+        write!(f, "{}", x)
+    }
+}
+
+impl SpanDisplay for Directive {
+    fn span_print(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let x = match self {
+            Self::Start => "Start",
+            Self::Stdout => "Stdout",
+            Self::Stderr => "Stderr",
+            Self::End => "End",
+        };
+
+        write!(f, "{}", x)
+    }
+}
+
+/// Container for various relevant data that should be passed to the parser
+#[derive(Debug, Default, Clone)]
+pub struct OutputContext {
+    inner: ArrayVec<[CxtItem; 12]>,
+}
+
+impl OutputContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert_id(&mut self, id: &str) {
+        self.inner.push(CxtItem::Id(Arc::from(id)))
+    }
+
+    pub fn insert_pid(&mut self, pid: u32) {
+        self.inner.push(CxtItem::Pid(pid))
+    }
+
+    pub fn insert_version(&mut self, version: u32) {
+        self.inner.push(CxtItem::Version(version))
+    }
+
+    pub fn insert_trace_id(&mut self, trace_id: &str) {
+        self.inner.push(CxtItem::TraceId(Arc::from(trace_id)))
+    }
+
+    pub fn insert_meta(&mut self, meta: Arc<Meta>) {
+        self.inner.push(CxtItem::Meta(meta))
+    }
+
+    /// Adds `labels` to any meta already inserted, overriding it where they share a key
+    pub fn extend_meta(&mut self, labels: &Meta) {
+        let meta = self.inner.iter_mut().find_map(|item| match item {
+            CxtItem::Meta(meta) => Some(meta),
+            _ => None,
+        });
+
+        match meta {
+            Some(meta) => Arc::make_mut(meta).extend(labels.clone()),
+            None => self.insert_meta(Arc::new(labels.clone())),
+        }
+    }
+
+    /// Labels identifying this host and run, see `facts::identity`. Only sent on headers, and
+    /// must follow any meta, which they take precedence over
+    pub fn insert_identity(&mut self, identity: Arc<Meta>) {
+        self.inner.push(CxtItem::Identity(identity))
+    }
+
+    pub fn insert_stream(&mut self, stream: u32) {
+        self.inner.push(CxtItem::Stream(stream))
+    }
+
+    /// The priority group the child runs in, counting from 0. Only used by `--summary`,
+    /// so never sent
+    pub fn insert_group(&mut self, group: u64) {
+        self.inner.push(CxtItem::Group(group))
+    }
+
+    /// When the child was spawned, and how long spawning it took. Only used by `--timings`,
+    /// which labels the closing header instead, so never sent
+    pub fn insert_spawned(&mut self, at: Instant, took: Duration) {
+        self.inner.push(CxtItem::Spawned(at, took))
+    }
+
+    /// The exit codes the child is expected to exit with, if not just success. Only used by
+    /// the reaper, so never sent
+    pub fn insert_expect_exit(&mut self, codes: &[i32]) {
+        self.inner.push(CxtItem::ExpectExit(Arc::from(codes)))
+    }
+
+    /// The most output read from the child before it is killed. Only used while reading it,
+    /// so never sent
+    pub fn insert_max_output(&mut self, limit: u64) {
+        self.inner.push(CxtItem::MaxOutput(limit))
+    }
+
+    /// Which of its own limits or sandbox may kill the child, so that its death by their
+    /// signal is reported as such. Only used by the reaper, so never sent
+    pub fn insert_killers(&mut self, killers: Killers) {
+        self.inner.push(CxtItem::Killers(killers))
+    }
+
+    /// Must follow any meta, as the attempt is added to it
+    pub fn insert_attempt(&mut self, attempt: u32, of: u32) {
+        self.inner.push(CxtItem::Attempt(attempt, of))
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.items().iter().find_map(|item| match item {
+            CxtItem::Id(i) => Some(i.as_ref()),
+            _ => None,
+        })
+    }
+
+    /// Which attempt at running the child this is, and out of how many
+    pub fn attempt(&self) -> Option<(u32, u32)> {
+        self.items().iter().find_map(|item| match item {
+            CxtItem::Attempt(attempt, of) => Some((*attempt, *of)),
+            _ => None,
+        })
+    }
+
+    pub fn version(&self) -> Option<u32> {
+        self.items().iter().find_map(|item| match item {
+            CxtItem::Version(i) => Some(*i),
+            _ => None,
+        })
+    }
+
+    pub fn stream(&self) -> Option<u32> {
+        self.items().iter().find_map(|item| match item {
+            CxtItem::Stream(i) => Some(*i),
+            _ => None,
+        })
+    }
+
+    pub fn group(&self) -> Option<u64> {
+        self.items().iter().find_map(|item| match item {
+            CxtItem::Group(i) => Some(*i),
+            _ => None,
+        })
+    }
+
+    pub fn spawned(&self) -> Option<(Instant, Duration)> {
+        self.items().iter().find_map(|item| match item {
+            CxtItem::Spawned(at, took) => Some((*at, *took)),
+            _ => None,
+        })
+    }
+
+    pub fn expect_exit(&self) -> Option<&[i32]> {
+        self.items().iter().find_map(|item| match item {
+            CxtItem::ExpectExit(codes) => Some(codes.as_ref()),
+            _ => None,
+        })
+    }
+
+    pub fn max_output(&self) -> Option<u64> {
+        self.items().iter().find_map(|item| match item {
+            CxtItem::MaxOutput(limit) => Some(*limit),
+            _ => None,
+        })
+    }
+
+    pub fn killers(&self) -> Killers {
+        self.items()
+            .iter()
+            .find_map(|item| match item {
+                CxtItem::Killers(killers) => Some(*killers),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn trace_id(&self) -> Option<&str> {
+        self.items().iter().find_map(|item| match item {
+            CxtItem::TraceId(i) => Some(i.as_ref()),
+            _ => None,
+        })
+    }
+
+    fn items(&self) -> &[CxtItem] {
+        &self.inner
+    }
+}
+
+/// What set on a child kills it with a signal of its own: its CPU time limit with SIGXCPU,
+/// and its sandbox's seccomp filter with SIGSYS
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Killers {
+    pub cpu_limit: bool,
+    pub seccomp: bool,
+}
+
+/// Local representation of context that is gathered early.
+// Currently using an enum due to the low number of variants.
+// If this enum gets larger than ~24 variants, should consider
+// moving to a trait based implementation
+#[derive(Debug, Clone)]
+enum CxtItem {
+    Version(u32),
+    Id(Arc<str>),
+    Pid(u32),
+    TraceId(Arc<str>),
+    Meta(Arc<Meta>),
+    Identity(Arc<Meta>),
+    Stream(u32),
+    Attempt(u32, u32),
+    Group(u64),
+    Spawned(Instant, Duration),
+    ExpectExit(Arc<[i32]>),
+    MaxOutput(u64),
+    Killers(Killers),
+}
+
+impl<'ctx> From<&'ctx OutputContext> for HeaderBuilder<'ctx> {
+    fn from(base: &'ctx OutputContext) -> Self {
+        base.items()
+            .iter()
+            .fold(Self::default(), |mut state, item| match item {
+                CxtItem::Version(i) => {
+                    state.version(*i);
+                    state
+                }
+                CxtItem::Id(i) => {
+                    state.id(i);
+                    state
+                }
+                CxtItem::Pid(i) => {
+                    state.pid(*i);
+                    state
+                }
+                CxtItem::Stream(i) => {
+                    state.stream(*i);
+                    state
+                }
+                CxtItem::TraceId(i) => {
+                    state.trace_id(i);
+                    state
+                }
+                CxtItem::Meta(i) => {
+                    state.labels(i);
+                    state
+                }
+                CxtItem::Identity(i) => {
+                    i.iter()
+                        .for_each(|(key, val)| state.meta(key.as_str(), val.as_str()));
+                    state
+                }
+                CxtItem::Attempt(i, _) => {
+                    state.meta("attempt", i.to_string());
+                    state
+                }
+                CxtItem::Group(_)
+                | CxtItem::Spawned(..)
+                | CxtItem::ExpectExit(_)
+                | CxtItem::MaxOutput(_)
+                | CxtItem::Killers(_) => state,
+            })
+    }
+}
+
+impl<'ctx> From<&'ctx OutputContext> for DataBuilder<'ctx, '_> {
+    fn from(base: &'ctx OutputContext) -> Self {
+        base.items()
+            .iter()
+            .fold(Self::default(), |mut state, item| match item {
+                CxtItem::Version(i) => {
+                    state.version(*i);
+                    state
+                }
+                CxtItem::Id(i) => {
+                    state.id(i);
+                    state
+                }
+                CxtItem::Pid(i) => {
+                    state.pid(*i);
+                    state
+                }
+                CxtItem::Stream(i) => {
+                    state.stream(*i);
+                    state
+                }
+                CxtItem::TraceId(i) => {
+                    state.trace_id(i);
+                    state
+                }
+                CxtItem::Meta(i) => {
+                    state.labels(i);
+                    state
+                }
+                CxtItem::Identity(_)
+                | CxtItem::Attempt(..)
+                | CxtItem::Group(_)
+                | CxtItem::Spawned(..)
+                | CxtItem::ExpectExit(_)
+                | CxtItem::MaxOutput(_)
+                | CxtItem::Killers(_) => state,
+            })
+    }
+}
+
+impl<'ctx> From<&'ctx OutputContext> for ExitBuilder<'ctx> {
+    fn from(base: &'ctx OutputContext) -> Self {
+        base.items()
+            .iter()
+            .fold(Self::default(), |mut state, item| match item {
+                CxtItem::Version(i) => {
+                    state.version(*i);
+                    state
+                }
+                CxtItem::Id(i) => {
+                    state.id(i);
+                    state
+                }
+                CxtItem::Pid(i) => {
+                    state.pid(*i);
+                    state
+                }
+                CxtItem::Stream(i) => {
+                    state.stream(*i);
+                    state
+                }
+                CxtItem::TraceId(_)
+                | CxtItem::Meta(_)
+                | CxtItem::Identity(_)
+                | CxtItem::Attempt(..)
+                | CxtItem::Group(_)
+                | CxtItem::Spawned(..)
+                | CxtItem::ExpectExit(_)
+                | CxtItem::MaxOutput(_)
+                | CxtItem::Killers(_) => state,
+            })
+    }
+}
@@ -0,0 +1,69 @@
+use {
+    crate::prelude::*,
+    globset::{Glob, GlobSet, GlobSetBuilder},
+    std::path::Path,
+};
+
+/// Include and exclude glob patterns deciding which executables discovery finds, matched
+/// against each path relative to its exec root. `*` also matches across directories, so
+/// `*.disabled` excludes a disabled executable at any depth. Also used to decide which
+/// environment variables executables inherit, by name
+#[derive(Debug, Clone)]
+pub struct Patterns {
+    /// Executables must match one of these to be found, if there are any
+    include: Option<GlobSet>,
+    /// Anything matching one of these is never found, nor is anything under it
+    exclude: GlobSet,
+}
+
+impl Patterns {
+    /// Compiles the given patterns, failing on the first that isn't a valid glob
+    pub fn new<'a, I, E>(include: I, exclude: E) -> Result<Self>
+    where
+        I: IntoIterator<Item = &'a str>,
+        E: IntoIterator<Item = &'a str>,
+    {
+        let mut include = include.into_iter().peekable();
+        let include = match include.peek() {
+            Some(_) => Some(build(include)?),
+            None => None,
+        };
+
+        Ok(Self {
+            include,
+            exclude: build(exclude)?,
+        })
+    }
+
+    /// Whether the entry at `path` (relative to its exec root) should be walked into, if a
+    /// directory, or run, if a file
+    pub fn allows(&self, path: &Path, is_dir: bool) -> bool {
+        match (self.exclude.is_match(path), &self.include) {
+            (true, _) => false,
+            (false, Some(include)) if !is_dir => include.is_match(path),
+            (false, _) => true,
+        }
+    }
+
+    /// Whether an environment variable called `name` is allowed, i.e matches an include
+    /// pattern, if there are any, and no exclude pattern
+    pub fn allows_var(&self, name: &str) -> bool {
+        self.allows(Path::new(name), false)
+    }
+}
+
+/// Checks that `pattern` is a valid glob
+pub fn validate(pattern: &str) -> std::result::Result<(), String> {
+    Glob::new(pattern)
+        .map(|_| ())
+        .map_err(|e| format!("'{}' is not a valid pattern: {}", pattern, e))
+}
+
+fn build<'a>(patterns: impl IntoIterator<Item = &'a str>) -> Result<GlobSet> {
+    let mut set = GlobSetBuilder::new();
+    for pattern in patterns {
+        set.add(Glob::new(pattern)?);
+    }
+
+    set.build().map_err(|e| e.into())
+}
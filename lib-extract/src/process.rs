@@ -0,0 +1,1059 @@
+use {
+    crate::{
+        config::SpawnConfig,
+        error::Err,
+        models::{ChildChannel, WriteChannel},
+        output::{Directive, OutputContext},
+        prelude::*,
+        pty, remote,
+        unit::Unit,
+        ARGS, GROUPS, SEQUENCE, SUMMARY,
+    },
+    crossbeam_channel::{bounded, Receiver, RecvTimeoutError, Sender},
+    futures::{
+        channel::mpsc::{SendError, Sender as AsyncSender},
+        executor::block_on,
+        prelude::*,
+        sink::SinkMapErr,
+        stream,
+    },
+    lib_transport::{
+        Common, DataBuilder, DataContext, Error as ErrorRecord, ExitBuilder, HeaderBuilder, Log,
+        LogLevel, Record, RecordInterface, RecordTime, Sequenced, BINARY_DATA_VERSION,
+        CHILD_SEQ_META, CONTINUED_META,
+    },
+    std::{
+        collections::HashSet,
+        fs::File,
+        io,
+        os::unix::{
+            io::AsRawFd,
+            process::{CommandExt, ExitStatusExt},
+        },
+        path::Path,
+        process::{Child, Command, ExitStatus, Stdio},
+        sync::{
+            atomic::{AtomicBool, AtomicU64, Ordering},
+            Mutex,
+        },
+        thread,
+        time::{Duration, Instant},
+    },
+    tokio::signal::unix::{signal, SignalKind},
+};
+
+/// Meta key labelling a `Data` record sent in place of a run of identical lines under
+/// `--dedup` with how many times it repeated, after the run's first line was sent as it was
+pub const REPEATED_META: &str = "repeated";
+
+/// Alias for the sink every record passes through on its way to the writer thread
+pub type RecordSink =
+    Sequenced<RecordInterface<SinkMapErr<AsyncSender<WriteChannel>, fn(SendError) -> CrateError>>>;
+
+/// Generates a sink that serializes records and sends them to the writer thread,
+/// stamping each with its position in the output stream
+pub fn record_sink(tx_write: AsyncSender<WriteChannel>) -> RecordSink {
+    Sequenced::new(
+        RecordInterface::new_sink(tx_write.sink_map_err(CrateError::from as _))
+            .with_checksum(ARGS.checksum())
+            .with_compression(ARGS.compression())
+            .with_encryption(ARGS.encryption().clone()),
+        SEQUENCE.clone(),
+    )
+}
+
+/// Execute a unit with the arguments, environment, working directory and stdin of its
+/// `SpawnConfig`, and return a process handle with stdout / stderr stored for use.
+/// Paths with an `--interpreter` for their extension are run as an argument to it, while
+/// containers and listed commands are run by their own command, and units of other hosts
+/// through ssh.
+/// Each leads its own process group, so that anything it started can be killed along with
+/// it, see `Groups`. Executables run under a pseudo-terminal lead their own session instead,
+/// with it as their controlling terminal, and are returned with its master end, which their
+/// output is read from
+pub fn spawn_process(unit: &Unit, config: &SpawnConfig) -> Result<(Child, Option<File>)> {
+    let path = unit.path();
+    let cwd = config.cwd(path)?;
+    // Whether a relative path is resolved before or after changing directory is platform
+    // specific, so don't leave it relative
+    let program = match cwd {
+        Some(_) => std::env::current_dir()?.join(path),
+        None => path.to_path_buf(),
+    };
+
+    let (mut command, script) = match (unit, unit.command(), ARGS.interpreter(&program)) {
+        (Unit::Remote { host, unit }, ..) => remote::command(host, unit, config)?,
+        (_, Some(command), _) => (command, None),
+        (_, None, Some((interpreter, args))) => {
+            let mut command = Command::new(interpreter);
+            command.args(args).arg(&program);
+            (command, None)
+        }
+        (_, None, None) => (Command::new(&program), None),
+    };
+    if let Some(cwd) = cwd {
+        command.current_dir(cwd);
+    }
+
+    let pty = match config.pty() {
+        true => Some(pty::open().map_err(|source| -> CrateError {
+            let path = path.display().to_string();
+            Err::Spawn { path, source }.into()
+        })?),
+        false => None,
+    };
+    match &pty {
+        // A new session is also a new process group, which setpgid would stop it from starting
+        Some((_, slave)) => {
+            let slave = slave.as_raw_fd();
+            // Safety: only makes the setsid and ioctl syscalls, allocating nothing. Added
+            // before the spawn config's closures, so a sandbox can't forbid either
+            unsafe {
+                command.pre_exec(move || pty::take_control(slave));
+            }
+        }
+        None => {
+            command.process_group(0);
+        }
+    }
+    let (stdout, stderr) = match &pty {
+        Some((_, slave)) => (
+            Stdio::from(slave.try_clone()?),
+            Stdio::from(slave.try_clone()?),
+        ),
+        None => (Stdio::piped(), Stdio::piped()),
+    };
+
+    match unit.host() {
+        // Its arguments and environment are passed on by ssh
+        Some(_) => config.apply_local(&mut command)?,
+        None => config.apply(&mut command)?,
+    }
+    .stdin(match script {
+        Some(_) => Stdio::piped(),
+        None => config.stdin(path)?,
+    })
+    .stdout(stdout)
+    .stderr(stderr)
+    .spawn()
+    .map(|mut child| {
+        GROUPS.insert(child.id());
+        match script {
+            Some(script) => remote::upload(&mut child, script),
+            None => config.feed(&mut child),
+        }
+        // Only the child may hold the slave open, so reading ends once it exits
+        (child, pty.map(|(master, _)| master))
+    })
+    .map_err(|source| {
+        let path = path.display().to_string();
+        // Only changing credentials fails with EPERM rather than EACCES
+        match source.raw_os_error() == Some(libc::EPERM) && config.changes_credentials() {
+            true => Err::Privilege { path, source },
+            false => Err::Spawn { path, source },
+        }
+        .into()
+    })
+}
+
+/// Bounds how many children may run at once, across every stream
+pub struct Jobs {
+    slots: Option<(Sender<()>, Receiver<()>)>,
+}
+
+impl Jobs {
+    /// Allows up to `jobs` children at once, or as many as the thread pool can run if None
+    pub fn new(jobs: Option<usize>) -> Self {
+        Self {
+            slots: jobs.map(bounded),
+        }
+    }
+
+    /// Blocks until another child may be run, returning a slot that frees itself once dropped
+    pub fn acquire(&self) -> JobSlot<'_> {
+        JobSlot(self.slots.as_ref().map(|(tx, rx)| {
+            tx.send(()).expect("Jobs hold both ends of their channel");
+            rx
+        }))
+    }
+}
+
+/// A running child's share of `Jobs`
+pub struct JobSlot<'j>(Option<&'j Receiver<()>>);
+
+impl Drop for JobSlot<'_> {
+    fn drop(&mut self) {
+        if let Some(rx) = self.0 {
+            let _ = rx.recv();
+        }
+    }
+}
+
+/// The process groups led by children, which outlive them for as long as anything they
+/// started does, i.e a daemon. Every group still around is killed when the run ends or this
+/// program is told to shut down, so that nothing it started is left running
+#[derive(Debug, Default)]
+pub struct Groups {
+    live: Mutex<HashSet<u32>>,
+}
+
+impl Groups {
+    /// Tracks the group led by the child just spawned as `pid`
+    pub fn insert(&self, pid: u32) {
+        self.live.lock().unwrap().insert(pid);
+    }
+
+    /// Stops tracking the group of the child `pid` once it has been reaped, unless something
+    /// it started is still in it
+    pub fn reaped(&self, pid: u32) {
+        // Safety: signal 0 only checks whether the group has any members left
+        match unsafe { libc::kill(-(pid as libc::pid_t), 0) } {
+            0 => debug!(
+                pid,
+                "Child exited, leaving processes it started in its group"
+            ),
+            _ => {
+                self.live.lock().unwrap().remove(&pid);
+            }
+        }
+    }
+
+    /// Kills every group still around, returning how many there were
+    pub fn kill_all(&self) -> usize {
+        let live: Vec<u32> = self.live.lock().unwrap().drain().collect();
+        for pid in live.iter() {
+            // A group's id can't be reused until all of its members are gone, so this can
+            // only miss, never kill something else
+            if unsafe { libc::kill(-(*pid as libc::pid_t), libc::SIGKILL) } != 0 {
+                let e = io::Error::last_os_error();
+                if e.raw_os_error() != Some(libc::ESRCH) {
+                    CrateError::from(e).log(Level::WARN);
+                }
+            }
+        }
+
+        live.len()
+    }
+}
+
+/// Kills every child's process group and exits once this program is interrupted, terminated
+/// or hung up on. Children lead groups of their own, so they are otherwise never sent the
+/// signals a terminal sends this program's
+pub async fn shutdown_on_signal() -> Result<()> {
+    let signals = [
+        (SignalKind::interrupt(), libc::SIGINT),
+        (SignalKind::terminate(), libc::SIGTERM),
+        (SignalKind::hangup(), libc::SIGHUP),
+    ]
+    .iter()
+    .map(|&(kind, number)| Ok(signal(kind)?.map(move |_| number)))
+    .collect::<Result<Vec<_>>>()?;
+
+    if let Some(number) = stream::select_all(signals).next().await {
+        let groups = GROUPS.kill_all();
+        warn!(
+            signal = number,
+            groups, "Shutting down, killed every child's process group"
+        );
+        std::process::exit(128 + number);
+    }
+
+    Ok(())
+}
+
+/// Sends `error` downstream as an Error record attributed to the given
+/// context, so the failure isn't only visible in this program's logs. It carries the
+/// context's id, so it can be tied to its executable even if that never ran
+pub fn report_error(
+    error: CrateError,
+    context: &OutputContext,
+    tx_write: &AsyncSender<WriteChannel>,
+) -> CrateError {
+    let error = match context.attempt() {
+        Some((attempt, of)) => Err::Attempt {
+            attempt,
+            of,
+            source: error,
+        }
+        .into(),
+        None => error,
+    };
+    let record = Record::Error(ErrorRecord {
+        required: Common {
+            stream: context.stream(),
+            ..Common::new(context.version().unwrap_or(1))
+        },
+        error: (&error).into(),
+        trace_id: context.trace_id().map(String::from),
+        id: context.id().map(String::from),
+    });
+
+    block_on(record_sink(tx_write.clone()).send(record)).unwrap_or_else(|e| e.ref_log(Level::WARN));
+
+    error
+}
+
+/// Sends `message` downstream as a Log record on the given logical stream, as well as
+/// logging it locally
+pub fn report_log<M>(
+    message: &M,
+    level: LogLevel,
+    stream: Option<u32>,
+    tx_write: &AsyncSender<WriteChannel>,
+) where
+    M: std::fmt::Display,
+{
+    let mut log = Log::new(message.to_string())
+        .level(level)
+        .target(module_path!());
+    log.required.stream = stream;
+    warn!(level = ?level, "{}", message);
+
+    block_on(record_sink(tx_write.clone()).send(Record::Log(log)))
+        .unwrap_or_else(|e| e.ref_log(Level::WARN));
+}
+
+/// Macro function for processing Child stdout and stderr.
+/// Attempts to parallelize output processing, if the underlying thread
+/// pool is not currently full. Returns where the reaper reports whether the child succeeded
+pub fn process_child(
+    mut handle: Child,
+    pty: Option<File>,
+    context: &OutputContext,
+    tx_write: &mut AsyncSender<WriteChannel>,
+    tx_child: &mut Sender<ChildChannel>,
+) -> Result<Receiver<bool>> {
+    trace!("Processing child {}", handle.id());
+    // Armed until the child is reaped, as it may close its output and carry on running
+    let watchdog = time_limit().map(|limit| Watchdog::arm(handle.id(), limit));
+
+    let body = || -> Result<()> {
+        let mut sink = record_sink(tx_write.clone());
+
+        block_on(sink.send(header(context, Directive::Start).build()?))?;
+        trace!("Sent opening header");
+
+        // Shared by both output streams, so their records can be put back in the order read
+        let order = AtomicU64::new(0);
+        let budget = context
+            .max_output()
+            .map(|limit| Budget::new(handle.id(), limit));
+        let budget = budget.as_ref();
+        let rate = RateLimit::new(ARGS.lines_per_sec(), ARGS.bytes_per_sec());
+        let rate = rate.as_ref();
+
+        let first_output = match (handle.stdout.take(), handle.stderr.take(), pty) {
+            // Output written to a terminal arrives merged, in the order it was written
+            (_, _, Some(master)) => process_child_output(
+                Directive::Stdout,
+                context,
+                &order,
+                budget,
+                rate,
+                pty::Reader::from(master),
+                tx_write.clone(),
+            )?,
+            // Attempt to parallelize output streams, if capacity in worker pool exists
+            (Some(ref mut stdout), Some(ref mut stderr), None) => {
+                let results = rayon::join(
+                    || {
+                        process_child_output(
+                            Directive::Stdout,
+                            &context,
+                            &order,
+                            budget,
+                            rate,
+                            stdout,
+                            tx_write.clone(),
+                        )
+                    },
+                    || {
+                        process_child_output(
+                            Directive::Stderr,
+                            &context,
+                            &order,
+                            budget,
+                            rate,
+                            stderr,
+                            tx_write.clone(),
+                        )
+                    },
+                );
+                let (stdout, stderr) = (results.0?, results.1?);
+                stdout.into_iter().chain(stderr).min()
+            }
+            (Some(ref mut stdout), None, None) => process_child_output(
+                Directive::Stdout,
+                &context,
+                &order,
+                budget,
+                rate,
+                stdout,
+                tx_write.clone(),
+            )?,
+            (None, Some(ref mut stderr), None) => process_child_output(
+                Directive::Stderr,
+                &context,
+                &order,
+                budget,
+                rate,
+                stderr,
+                tx_write.clone(),
+            )?,
+            (None, None, None) => None,
+        };
+
+        if let Some(budget) = budget.filter(|budget| budget.exceeded()) {
+            let path = context.id().unwrap_or_default().to_string();
+            let error = Err::OutputLimit {
+                path,
+                limit: budget.limit,
+            };
+            report_error(error.into(), context, tx_write).log(Level::ERROR);
+        }
+
+        let end = header(context, Directive::End).map(|this| {
+            if let Some((spawned, took)) = context.spawned() {
+                this.meta("spawn_us", took.as_micros().to_string());
+                if let Some(first) = first_output {
+                    let latency = first.saturating_duration_since(spawned);
+                    this.meta("first_output_us", latency.as_micros().to_string());
+                }
+                this.meta("duration_us", spawned.elapsed().as_micros().to_string());
+            }
+        });
+        block_on(sink.send(end.build()?))?;
+        trace!("Sent closing header");
+
+        Ok(())
+    };
+    let defer = body();
+    let (exited_tx, exited_rx) = bounded(1);
+
+    tx_child
+        .send(ChildChannel::Reap(
+            handle,
+            context.clone(),
+            watchdog,
+            exited_tx,
+        ))
+        .map_err(|e| e.into())
+        .and_then(|_| defer)
+        .map(|_| exited_rx)
+        .log(Level::ERROR)
+}
+
+/// How long a child starting now may run for, if limited, and whether it is limited by
+/// the run's `--max-runtime` rather than its own `--timeout`
+fn time_limit() -> Option<(Duration, bool)> {
+    let remaining = ARGS
+        .max_runtime()
+        .map(|(_, deadline)| deadline.saturating_duration_since(Instant::now()));
+
+    match (ARGS.timeout(), remaining) {
+        (Some(timeout), Some(remaining)) if remaining < timeout => Some((remaining, true)),
+        (Some(timeout), _) => Some((timeout, false)),
+        (None, Some(remaining)) => Some((remaining, true)),
+        (None, None) => None,
+    }
+}
+
+/// Whether the run has reached its `--max-runtime`, so nothing more should be run
+pub fn past_deadline() -> bool {
+    matches!(ARGS.max_runtime(), Some((_, deadline)) if Instant::now() >= deadline)
+}
+
+/// Kills a child's process group unless disarmed within its time limit. The reaper only
+/// disarms it once the child has exited, so a child that closes its output and hangs is
+/// still killed rather than holding up every exit after it
+pub struct Watchdog {
+    disarm: Sender<()>,
+    killed: thread::JoinHandle<bool>,
+    timeout: Duration,
+    deadline: bool,
+}
+
+impl Watchdog {
+    /// Arms a watchdog for the process group led by `pid`, given the child's `time_limit()`
+    fn arm(pid: u32, (timeout, deadline): (Duration, bool)) -> Self {
+        let (disarm, rx) = bounded::<()>(0);
+        let killed = thread::spawn(move || watch(pid, timeout, rx));
+
+        Self {
+            disarm,
+            killed,
+            timeout,
+            deadline,
+        }
+    }
+
+    /// Disarms the watchdog, reporting the child as timed out if it had to be killed. Must
+    /// be called after the child has exited but before it is reaped, while its pid still
+    /// leads its process group
+    pub fn disarm(self, context: &OutputContext, tx_write: &AsyncSender<WriteChannel>) {
+        drop(self.disarm);
+        if self.killed.join().unwrap_or(false) {
+            let path = context.id().unwrap_or_default().to_string();
+            let error = match (self.deadline, ARGS.max_runtime()) {
+                (true, Some((runtime, _))) => Err::Deadline { path, runtime },
+                _ => Err::Timeout {
+                    path,
+                    timeout: self.timeout,
+                },
+            };
+            report_error(error.into(), context, tx_write).log(Level::ERROR);
+        }
+    }
+}
+
+/// Kills the process group led by `pid` unless `rx` is disconnected within `timeout`,
+/// returning whether it had to kill anything
+fn watch(pid: u32, timeout: Duration, rx: Receiver<()>) -> bool {
+    match rx.recv_timeout(timeout) {
+        Err(RecvTimeoutError::Timeout) => {
+            warn!(
+                pid,
+                timeout = timeout.as_secs(),
+                "Child timed out, killing its process group"
+            );
+            // The child can't have been reaped yet, so its pid still leads the group
+            if unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGKILL) } != 0 {
+                CrateError::from(io::Error::last_os_error()).log(Level::WARN);
+            }
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Blocks until `child` has exited, without reaping it so that its pid can't be reused
+/// while a watchdog may still signal its process group
+pub fn wait_exited(child: &Child) -> io::Result<()> {
+    // Safety: waitid only writes to the siginfo_t given, which is valid zeroed
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    loop {
+        let flags = libc::WEXITED | libc::WNOWAIT;
+        match unsafe { libc::waitid(libc::P_PID, child.id(), &mut info, flags) } {
+            0 => return Ok(()),
+            _ => match io::Error::last_os_error() {
+                e if e.kind() == io::ErrorKind::Interrupted => continue,
+                e => return Err(e),
+            },
+        }
+    }
+}
+
+/// How much output a child may write across both its streams before it is killed, under
+/// `--max-output` or its sidecar's `max_output_bytes`
+struct Budget {
+    pid: u32,
+    limit: u64,
+    read: AtomicU64,
+    exceeded: AtomicBool,
+}
+
+impl Budget {
+    fn new(pid: u32, limit: u64) -> Self {
+        Self {
+            pid,
+            limit,
+            read: AtomicU64::new(0),
+            exceeded: AtomicBool::new(false),
+        }
+    }
+
+    /// Counts `bytes` more as read, returning whether they are within the limit. The first
+    /// stream to exceed it kills the child's process group, so the other soon stops too
+    fn take(&self, bytes: u64) -> bool {
+        if self.exceeded() {
+            return false;
+        }
+        if self.read.fetch_add(bytes, Ordering::Relaxed) + bytes <= self.limit {
+            return true;
+        }
+
+        if !self.exceeded.swap(true, Ordering::Relaxed) {
+            let (pid, limit) = (self.pid, self.limit);
+            warn!(
+                pid,
+                limit, "Child exceeded its output limit, killing its process group"
+            );
+            // The child isn't reaped until its output has been read, so its pid still
+            // leads the group
+            if unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGKILL) } != 0 {
+                CrateError::from(io::Error::last_os_error()).log(Level::WARN);
+            }
+        }
+        false
+    }
+
+    fn exceeded(&self) -> bool {
+        self.exceeded.load(Ordering::Relaxed)
+    }
+}
+
+/// Token buckets limiting how much of a child's output is sent a second across both its
+/// streams, under `--lines-per-sec` and `--bytes-per-sec`. Each holds up to a second's worth,
+/// so a child may burst after being quiet
+struct RateLimit {
+    buckets: Mutex<([Option<Bucket>; 2], Instant)>,
+}
+
+impl RateLimit {
+    /// None if neither rate is limited
+    fn new(lines: Option<u64>, bytes: Option<u64>) -> Option<Self> {
+        match (lines, bytes) {
+            (None, None) => None,
+            _ => Some(Self {
+                buckets: Mutex::new((
+                    [lines.map(Bucket::new), bytes.map(Bucket::new)],
+                    Instant::now(),
+                )),
+            }),
+        }
+    }
+
+    /// Takes `lines` and `bytes` from their buckets, returning whether both had enough for
+    /// them to be sent. Nothing is taken from either if they weren't
+    fn take(&self, lines: u64, bytes: u64) -> bool {
+        let mut guard = self.buckets.lock().unwrap();
+        let (buckets, last) = &mut *guard;
+        let elapsed = last.elapsed().as_secs_f64();
+        *last = Instant::now();
+
+        let wanted = [lines, bytes];
+        for bucket in buckets.iter_mut().flatten() {
+            bucket.refill(elapsed);
+        }
+        let allowed = buckets
+            .iter()
+            .zip(wanted.iter())
+            .all(|(bucket, n)| !matches!(bucket, Some(bucket) if !bucket.has(*n)));
+        if allowed {
+            for (bucket, n) in buckets.iter_mut().zip(wanted.iter()) {
+                if let Some(bucket) = bucket {
+                    bucket.take(*n);
+                }
+            }
+        }
+
+        allowed
+    }
+}
+
+struct Bucket {
+    rate: f64,
+    tokens: f64,
+}
+
+impl Bucket {
+    /// Starts full
+    fn new(rate: u64) -> Self {
+        let rate = rate as f64;
+        Self { rate, tokens: rate }
+    }
+
+    fn refill(&mut self, secs: f64) {
+        self.tokens = (self.tokens + secs * self.rate).min(self.rate);
+    }
+
+    /// Anything larger than the bucket can hold only needs it to be full
+    fn has(&self, n: u64) -> bool {
+        self.tokens >= (n as f64).min(self.rate)
+    }
+
+    fn take(&mut self, n: u64) {
+        self.tokens = (self.tokens - n as f64).max(0.0);
+    }
+}
+
+/// Serializes a child's output and sends it to
+/// the writer thread, with no intermediate allocations.
+/// Stops early once the child's output exceeds its `budget`, sending nothing past it,
+/// and drops whatever would exceed its `rate` limit, sending a log of how much once done.
+/// Returns when the first of it was read, if any was
+fn process_child_output<R>(
+    directive: Directive,
+    context: &OutputContext,
+    order: &AtomicU64,
+    budget: Option<&Budget>,
+    rate: Option<&RateLimit>,
+    read: R,
+    tx_write: AsyncSender<WriteChannel>,
+) -> Result<Option<Instant>>
+where
+    R: io::Read + Send,
+{
+    enter!(
+        span,
+        always_span!("child.stream", kind = %directive.span_display())
+    );
+    trace!("Processing child output stream");
+
+    let mut lines = 0u64;
+    let mut bytes = 0u64;
+    let mut first = None;
+
+    let buffer = io::BufReader::new(read);
+    let mut sink = record_sink(tx_write.clone());
+    let max = ARGS.max_line().unwrap_or(usize::MAX);
+    // Raw bytes are sent as is, whereas text is only split between whole characters
+    let whole_chars = ARGS.record_version() < BINARY_DATA_VERSION;
+    let dedup = ARGS.dedup();
+    let mut repeats = Repeats::default();
+    let (mut dropped_lines, mut dropped_bytes) = (0u64, 0u64);
+
+    for_capped_line(buffer, max, whole_chars, |line, truncated| {
+        // A line's terminator counts towards the limit, as it was written all the same
+        let written = line.len() as u64 + u64::from(!truncated);
+        if matches!(budget, Some(budget) if !budget.take(written)) {
+            return Ok(false);
+        }
+        first.get_or_insert_with(Instant::now);
+        if !truncated {
+            lines += 1;
+        }
+        bytes += line.len() as u64;
+
+        if dedup && repeats.is_repeat(line, truncated) {
+            return Ok(true);
+        }
+        // A run is sent once it is ended by a different line, even one dropped, or the end
+        // of the output
+        let run = repeats.ended(dedup.then_some(line), truncated);
+        if let Some((line, count, at)) = run {
+            send_line(
+                &mut sink,
+                context,
+                directive,
+                order,
+                &line,
+                repeated(count, at),
+            )?;
+        }
+        // Only a line's last chunk counts as a line, as only it ends one
+        if matches!(rate, Some(rate) if !rate.take(u64::from(!truncated), line.len() as u64)) {
+            dropped_lines += u64::from(!truncated);
+            dropped_bytes += line.len() as u64;
+            repeats.forget();
+            return Ok(true);
+        }
+        send_line(&mut sink, context, directive, order, line, |this| {
+            if truncated {
+                this.meta(CONTINUED_META, "true");
+            }
+        })
+        .map(|_| true)
+    })
+    .and_then(|_| match repeats.ended(None, false) {
+        Some((line, count, at)) => send_line(
+            &mut sink,
+            context,
+            directive,
+            order,
+            &line,
+            repeated(count, at),
+        ),
+        None => Ok(()),
+    })
+    .map(|_| {
+        if bytes > 0 {
+            debug!(lines, bytes, "Finished child stream")
+        }
+        SUMMARY.output(context, lines, bytes);
+        if dropped_bytes > 0 || dropped_lines > 0 {
+            let message = format!(
+                "Dropped {} lines ({} bytes) of '{}' {} over its rate limit",
+                dropped_lines,
+                dropped_bytes,
+                context.id().unwrap_or_default(),
+                directive,
+            );
+            report_log(&message, LogLevel::Warn, context.stream(), &tx_write);
+        }
+        first
+    })
+    .map_err(|e| e.into())
+}
+
+/// Sends `line` as a data record, labelled by `label` and numbered by the child's `order`
+fn send_line<F>(
+    sink: &mut RecordSink,
+    context: &OutputContext,
+    directive: Directive,
+    order: &AtomicU64,
+    line: &[u8],
+    label: F,
+) -> io::Result<()>
+where
+    F: FnOnce(&mut DataBuilder),
+{
+    data(context, directive, line)
+        .map(label)
+        .map(|this| {
+            let seq = order.fetch_add(1, Ordering::Relaxed);
+            this.meta(CHILD_SEQ_META, seq.to_string());
+        })
+        .build()
+        .map_err(CrateError::from)
+        .and_then(|record| block_on(sink.send(record)))
+        //Ugly workaround for closure's io::Error requirement,
+        //Round trips from our local error into io::Error and back
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+}
+
+/// The last whole line sent under `--dedup`, along with how many times it has repeated since
+/// and when it last did. Only lines read whole are ever compared, never any chunk of one
+/// split by `--max-line`
+#[derive(Debug, Default)]
+struct Repeats {
+    last: Option<Vec<u8>>,
+    count: u64,
+    at: i64,
+    /// Whether the last chunk read was cut short, so the next is part of the same line
+    continued: bool,
+}
+
+impl Repeats {
+    /// Whether the chunk `line` repeats the last line, counting it if so
+    fn is_repeat(&mut self, line: &[u8], truncated: bool) -> bool {
+        let repeat = !truncated && !self.continued && self.last.as_deref() == Some(line);
+        if repeat {
+            self.count += 1;
+            self.at = now();
+        }
+
+        repeat
+    }
+
+    /// Notes the chunk read wasn't a repeat, returning the run of the last line it ended, if
+    /// it repeated at all. A whole `line` given is the next to compare against
+    fn ended(&mut self, line: Option<&[u8]>, truncated: bool) -> Option<(Vec<u8>, u64, i64)> {
+        let whole = !truncated && !self.continued;
+        let count = std::mem::take(&mut self.count);
+        let run = self.last.take().filter(|_| count > 0);
+        self.last = line.filter(|_| whole).map(<[u8]>::to_vec);
+        self.continued = truncated;
+
+        run.map(|last| (last, count, self.at))
+    }
+
+    /// Forgets the last line, as one that wasn't sent came after it
+    fn forget(&mut self) {
+        self.last = None;
+    }
+}
+
+/// Labels the record sent for a run of identical lines, which is timed as its last repeat
+fn repeated(repeats: u64, at: i64) -> impl FnOnce(&mut DataBuilder) {
+    move |this| {
+        this.and(|this| this.time(at))
+            .and(|this| this.meta(REPEATED_META, repeats.to_string()));
+    }
+}
+
+/// Calls `for_each_line` with each line read, without its `\n` or `\r\n` terminator.
+/// Lines longer than `max` bytes are passed in chunks of at most `max`,
+/// every chunk but a line's last flagged as truncated, so that at most `max` bytes of any line
+/// are ever buffered. With `whole_chars`, a chunk never ends partway through a UTF-8 character
+fn for_capped_line<R, F>(
+    mut read: R,
+    max: usize,
+    whole_chars: bool,
+    mut for_each_line: F,
+) -> io::Result<()>
+where
+    R: io::BufRead,
+    F: FnMut(&[u8], bool) -> io::Result<bool>,
+{
+    let mut buf = Vec::new();
+
+    loop {
+        // The buffer may already hold the start of a character cut from the last chunk
+        let limit = max.saturating_sub(buf.len()).max(1) as u64;
+        // Spelled out, as the futures prelude's extension traits would otherwise be picked
+        io::BufRead::read_until(&mut io::Read::take(&mut read, limit), b'\n', &mut buf)?;
+        if buf.is_empty() {
+            return Ok(());
+        }
+
+        // A chunk cut exactly at the end of its line or of the output isn't truncated
+        let mut complete = buf.last() == Some(&b'\n');
+        if !complete {
+            match read.fill_buf()?.first() {
+                Some(b'\n') => {
+                    read.consume(1);
+                    buf.push(b'\n');
+                    complete = true;
+                }
+                Some(_) => (),
+                None => complete = true,
+            }
+        }
+
+        let more = match complete {
+            true => for_each_line(trim_line(&buf), false)?,
+            false => {
+                let cut = match whole_chars {
+                    true => char_boundary(&buf),
+                    false => buf.len(),
+                };
+                let more = for_each_line(&buf[..cut], true)?;
+                buf.drain(..cut);
+                more
+            }
+        };
+        if complete {
+            buf.clear();
+        }
+        if !more {
+            return Ok(());
+        }
+    }
+}
+
+/// Strips a trailing `\n` or `\r\n`
+fn trim_line(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+/// Where to cut `buf` so it doesn't end partway through a character, which is at its end
+/// unless it ends with an incomplete one
+fn char_boundary(buf: &[u8]) -> usize {
+    match std::str::from_utf8(buf) {
+        Err(e) if e.error_len().is_none() && e.valid_up_to() > 0 => e.valid_up_to(),
+        _ => buf.len(),
+    }
+}
+
+fn header<T>(cxt: &OutputContext, tag: T) -> HeaderBuilder<'_>
+where
+    T: Into<DataContext>,
+{
+    HeaderBuilder::from(cxt).map(|this| {
+        this.and(|this| this.time(now())).and(|this| this.tag(tag));
+    })
+}
+
+/// Describes a child that would have been run, as the `order`th executable of the `group`th
+/// priority group, without it having been spawned, so its pid is 0
+pub(crate) fn planned<'c>(
+    cxt: &'c OutputContext,
+    group: u64,
+    order: u64,
+    path: &Path,
+) -> HeaderBuilder<'c> {
+    header(cxt, Directive::Start).map(|this| {
+        this.and(|this| this.pid(0))
+            .and(|this| this.meta("group", group.to_string()))
+            .and(|this| this.meta("order", order.to_string()))
+            .and(|this| this.meta("path", path.display().to_string()));
+    })
+}
+
+pub(crate) fn exit(cxt: &OutputContext, status: ExitStatus) -> ExitBuilder<'_> {
+    ExitBuilder::from(cxt).map(|this| {
+        this.and(|this| this.time(now()))
+            .and(|this| this.status(status.code(), status.signal()));
+    })
+}
+
+fn data<'ctx, 'out, T>(
+    cxt: &'ctx OutputContext,
+    tag: T,
+    data: &'out [u8],
+) -> DataBuilder<'ctx, 'out>
+where
+    T: Into<DataContext>,
+{
+    DataBuilder::from(cxt).map(|this| {
+        this.and(|this| this.time(now()))
+            .and(|this| this.tag(tag))
+            .and(|this| this.bytes(data));
+    })
+}
+
+#[inline]
+fn now() -> i64 {
+    i64::now()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds `output` through `Repeats` as `for_capped_line` splits it by `max`, with every
+    /// line in `dropped` dropped, returning each line sent with how many times it repeated
+    fn dedup(output: &str, max: usize, dropped: &[&str]) -> Vec<(String, u64)> {
+        let mut repeats = Repeats::default();
+        let mut sent = Vec::new();
+        let text = |line: &[u8]| String::from_utf8_lossy(line).into_owned();
+
+        for_capped_line(output.as_bytes(), max, true, |line, truncated| {
+            if repeats.is_repeat(line, truncated) {
+                return Ok(true);
+            }
+            if let Some((line, count, _)) = repeats.ended(Some(line), truncated) {
+                sent.push((text(&line), count));
+            }
+            match dropped.contains(&text(line).as_str()) {
+                true => repeats.forget(),
+                false => sent.push((text(line), 0)),
+            }
+            Ok(true)
+        })
+        .unwrap();
+        if let Some((line, count, _)) = repeats.ended(None, false) {
+            sent.push((text(&line), count));
+        }
+
+        sent
+    }
+
+    fn lines(expected: &[(&str, u64)]) -> Vec<(String, u64)> {
+        expected
+            .iter()
+            .map(|(line, count)| (line.to_string(), *count))
+            .collect()
+    }
+
+    #[test]
+    fn whole_lines_repeat() {
+        let sent = dedup("a\na\na\nb\nb\n", 16, &[]);
+
+        assert_eq!(sent, lines(&[("a", 0), ("a", 2), ("b", 0), ("b", 1)]));
+    }
+
+    #[test]
+    fn tail_of_a_split_line_never_repeats() {
+        // 'abcdef' is split into 'abcd' and 'ef', which the next line must not repeat
+        let sent = dedup("abcdef\nef\nef\n", 4, &[]);
+
+        assert_eq!(sent, lines(&[("abcd", 0), ("ef", 0), ("ef", 0), ("ef", 1)]));
+    }
+
+    #[test]
+    fn split_line_ends_a_run() {
+        let sent = dedup("ab\nab\nabcdef\nab\n", 4, &[]);
+
+        assert_eq!(
+            sent,
+            lines(&[("ab", 0), ("ab", 1), ("abcd", 0), ("ef", 0), ("ab", 0)])
+        );
+    }
+
+    #[test]
+    fn dropped_line_ends_a_run() {
+        let sent = dedup("x\nx\nd\nx\n", 16, &["d"]);
+
+        assert_eq!(sent, lines(&[("x", 0), ("x", 1), ("x", 0)]));
+    }
+
+    #[test]
+    fn line_after_a_dropped_one_is_not_a_repeat() {
+        let sent = dedup("x\nd\nx\n", 16, &["d"]);
+
+        assert_eq!(sent, lines(&[("x", 0), ("x", 0)]));
+    }
+}
@@ -0,0 +1,98 @@
+use std::{
+    ffi::CStr,
+    fs::{File, OpenOptions},
+    io::{self, Read},
+    os::unix::{
+        fs::OpenOptionsExt,
+        io::{AsRawFd, FromRawFd, RawFd},
+    },
+};
+
+/// Size the terminal reports, for executables that lay their output out to fit it
+const COLUMNS: u16 = 80;
+const ROWS: u16 = 24;
+
+/// Opens a new pseudo-terminal, returning its master and slave ends. Neither is inherited
+/// by children unless passed as one of their standard streams
+pub fn open() -> io::Result<(File, File)> {
+    // Safety: the fd is owned by the File as soon as it is known to be valid
+    let master =
+        match unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC) } {
+            fd if fd < 0 => return Err(io::Error::last_os_error()),
+            fd => unsafe { File::from_raw_fd(fd) },
+        };
+    let fd = master.as_raw_fd();
+
+    check(unsafe { libc::grantpt(fd) })?;
+    check(unsafe { libc::unlockpt(fd) })?;
+    resize(fd)?;
+
+    let slave = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NOCTTY | libc::O_CLOEXEC)
+        .open(slave_path(fd)?)?;
+
+    Ok((master, slave))
+}
+
+/// Makes the calling process lead a new session, and so a new process group, with the
+/// pseudo-terminal `slave` as its controlling terminal. Meant for a child between fork and
+/// exec, where only async-signal-safe calls may be made
+pub fn take_control(slave: RawFd) -> io::Result<()> {
+    if unsafe { libc::setsid() } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    check(unsafe { libc::ioctl(slave, libc::TIOCSCTTY, 0) })
+}
+
+/// Reads everything written to a pseudo-terminal's slave end from its master. Once every
+/// slave is closed, Linux fails reads with EIO rather than returning end of file, which
+/// is taken as the end here
+#[derive(Debug)]
+pub struct Reader(File);
+
+impl From<File> for Reader {
+    fn from(master: File) -> Self {
+        Self(master)
+    }
+}
+
+impl Read for Reader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.read(buf) {
+            Err(e) if e.raw_os_error() == Some(libc::EIO) => Ok(0),
+            res => res,
+        }
+    }
+}
+
+fn slave_path(master: RawFd) -> io::Result<String> {
+    let mut buf = [0; 128];
+    // Safety: buf's length is passed along with it, and it is nul terminated on success
+    match unsafe { libc::ptsname_r(master, buf.as_mut_ptr(), buf.len()) } {
+        0 => Ok(unsafe { CStr::from_ptr(buf.as_ptr()) }
+            .to_string_lossy()
+            .into_owned()),
+        errno => Err(io::Error::from_raw_os_error(errno)),
+    }
+}
+
+fn resize(master: RawFd) -> io::Result<()> {
+    let size = libc::winsize {
+        ws_row: ROWS,
+        ws_col: COLUMNS,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+
+    check(unsafe { libc::ioctl(master, libc::TIOCSWINSZ, &size) })
+}
+
+fn check(res: libc::c_int) -> io::Result<()> {
+    match res {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
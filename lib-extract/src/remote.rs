@@ -0,0 +1,126 @@
+use {
+    crate::{config::SpawnConfig, error::Err, ids::Identified, prelude::*, unit::Unit, ARGS},
+    std::{
+        ffi::OsStr,
+        fs,
+        io::Write,
+        process::{Child, Command},
+        thread,
+    },
+};
+
+/// Label naming the host a unit ran on, for units run with `--ssh`
+pub const HOST_META: &str = "host";
+
+/// Copies the script fed on stdin to a temporary file, `$f`, for the command following it to
+/// run, removing it once that exits
+const UPLOAD: &str =
+    "f=$(mktemp) || exit 127; trap 'rm -f \"$f\"' EXIT; cat > \"$f\" && chmod 700 \"$f\" && ";
+
+/// Under `--ssh` each unit identified runs once on each host rather than here, as part of
+/// the same group so that a fleet is gathered from at once
+pub fn on_hosts(identified: Identified) -> Vec<Identified> {
+    match (identified, ARGS.ssh()) {
+        (Ok((priority, unit, id)), hosts) if !hosts.is_empty() => hosts
+            .iter()
+            .map(|host| {
+                let unit = Box::new(unit.clone());
+                Ok((
+                    priority,
+                    Unit::Remote {
+                        host: host.clone(),
+                        unit,
+                    },
+                    id.clone(),
+                ))
+            })
+            .collect(),
+        (identified, _) => vec![identified],
+    }
+}
+
+/// The ssh command running `unit` on `host`, along with the script to feed it on stdin if
+/// it must be copied over first. The sidecar's arguments and environment are passed on to
+/// the remote command, everything else applies to ssh itself here. An executable is copied
+/// over as it runs, and run by its `--interpreter` if it has one, which must then be found
+/// at the same path on `host`. Containers are run by the runtime on `host`, and listed
+/// commands as they are found there.
+///
+/// Hosts are connected to as ssh would be by hand, so keys, users and ports are configured
+/// as they would be for it, i.e in `~/.ssh/config`. It is never prompted for a password.
+/// A `--timeout` only kills ssh here, leaving sshd to end the remote command once the
+/// connection closes
+pub fn command(
+    host: &str,
+    unit: &Unit,
+    config: &SpawnConfig,
+) -> Result<(Command, Option<Vec<u8>>)> {
+    let mut remote: Vec<String> = match config.env().is_empty() {
+        true => Vec::new(),
+        false => env_prefix(config).collect(),
+    };
+
+    let script = match unit.command() {
+        Some(command) => {
+            remote.push(quote(command.get_program()));
+            remote.extend(command.get_args().map(quote));
+            None
+        }
+        // The script is fed on stdin, so nothing else can be
+        None if config.has_stdin() => {
+            return Err(Err::Remote {
+                path: unit.path().display().to_string(),
+                host: host.to_string(),
+                reason: "its sidecar's stdin can't be sent along with it",
+            }
+            .into())
+        }
+        None => {
+            if let Some((interpreter, args)) = ARGS.interpreter(unit.path()) {
+                remote.push(quote(interpreter.as_ref()));
+                remote.extend(args.iter().map(|arg| quote(arg.as_ref())));
+            }
+            remote.push("\"$f\"".to_string());
+            Some(fs::read(unit.path())?)
+        }
+    };
+    remote.extend(config.args().iter().map(|arg| quote(arg.as_ref())));
+
+    let remote = match script {
+        Some(_) => format!("{}{}", UPLOAD, remote.join(" ")),
+        None => remote.join(" "),
+    };
+    let mut command = Command::new("ssh");
+    command
+        .args(["-T", "-o", "BatchMode=yes", "--", host])
+        .arg(remote);
+
+    Ok((command, script))
+}
+
+/// Writes `script` to the spawned `child` from a separate thread, closing its stdin once done
+pub fn upload(child: &mut Child, script: Vec<u8>) {
+    if let Some(mut stdin) = child.stdin.take() {
+        let pid = child.id();
+        thread::spawn(move || {
+            stdin.write_all(&script).unwrap_or_else(|e| {
+                debug!(pid, "ssh closed stdin before the script was sent: {}", e)
+            })
+        });
+    }
+}
+
+/// The sidecar's variables, set through env(1) as the remote shell may not accept any
+fn env_prefix(config: &SpawnConfig) -> impl Iterator<Item = String> + '_ {
+    std::iter::once("env".to_string()).chain(
+        config
+            .env()
+            .iter()
+            .map(|(key, val)| quote(format!("{}={}", key, val).as_ref())),
+    )
+}
+
+/// Quotes `arg` for a POSIX shell, which the remote command is run by
+fn quote(arg: &OsStr) -> String {
+    format!("'{}'", arg.to_string_lossy().replace('\'', "'\\''"))
+}
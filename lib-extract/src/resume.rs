@@ -0,0 +1,92 @@
+use {
+    crate::{
+        models::{decode_frame, frame_codec, WriteChannel},
+        prelude::*,
+    },
+    lib_transport::{ChannelState, DataContext, Record, RecordCodec},
+};
+
+/// What a new connection must be sent before anything is resent to it, so that a listener
+/// validating the stream sees it opened just as the last connection left it: the
+/// `StreamStart`, then the `Channel` of each logical stream still open, then the start
+/// `Header` of each id still open. Kept as the frames first delivered, so the listener can
+/// tell them apart from new records by their sequence numbers as with any other resent.
+/// Every frame delivered is decoded to keep track, so this is only kept with `--reconnect`
+#[derive(Debug)]
+pub struct Preamble {
+    codec: RecordCodec,
+    start: Option<WriteChannel>,
+    /// Oldest first, so channels precede the headers opened in them
+    opened: Vec<(Opened, WriteChannel)>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Opened {
+    Channel(u32),
+    Header(Option<u32>, String),
+}
+
+impl Opened {
+    /// Whether this is the channel `stream`, or an id opened in it
+    fn in_stream(&self, stream: u32) -> bool {
+        match self {
+            Self::Channel(s) | Self::Header(Some(s), _) => *s == stream,
+            Self::Header(None, _) => false,
+        }
+    }
+}
+
+impl Preamble {
+    pub fn new() -> Self {
+        Self {
+            codec: frame_codec(),
+            start: None,
+            opened: Vec::new(),
+        }
+    }
+
+    /// Notes that `frame` has been flushed to the listener
+    pub fn delivered(&mut self, frame: &WriteChannel) {
+        let record = match decode_frame(&self.codec, frame) {
+            Ok(record) => record,
+            Err(e) => return e.ref_log(Level::WARN),
+        };
+        let stream = record.common().and_then(|common| common.stream);
+
+        match record {
+            Record::StreamStart => {
+                self.start = Some(frame.clone());
+                self.opened.clear();
+            }
+            Record::StreamEnd => {
+                self.start = None;
+                self.opened.clear();
+            }
+            Record::Channel(channel) => match channel.state {
+                ChannelState::Open => self
+                    .opened
+                    .push((Opened::Channel(channel.stream()), frame.clone())),
+                ChannelState::Close => {
+                    self.opened
+                        .retain(|(opened, _)| !opened.in_stream(channel.stream()));
+                }
+            },
+            Record::Header(header) => {
+                let opened = Opened::Header(stream, header.id.into_owned());
+                match header.cxt {
+                    DataContext::Start => self.opened.push((opened, frame.clone())),
+                    _ => self.opened.retain(|(o, _)| *o != opened),
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// The frames to send a new connection before any others
+    pub fn frames(&self) -> impl Iterator<Item = WriteChannel> + '_ {
+        self.start
+            .iter()
+            .chain(self.opened.iter().map(|(_, frame)| frame))
+            .cloned()
+    }
+}
@@ -0,0 +1,132 @@
+use {
+    crate::{
+        models::{feed, WriteChannel},
+        prelude::*,
+    },
+    flate2::{write::GzEncoder, Compression},
+    futures::{channel::mpsc::Receiver as AsyncReceiver, future, prelude::*},
+    lib_transport::RecordFrame,
+    std::{
+        ffi::OsString,
+        fs, io,
+        path::{Path, PathBuf},
+        pin::Pin,
+        time::{Duration, Instant},
+    },
+    tokio::{fs::File, io::BufWriter},
+};
+
+/// When a file output is moved aside for a new one, if ever
+#[derive(Debug, Clone, Default)]
+pub struct Rotation {
+    /// Rotate once the file holds at least this many bytes
+    pub size: Option<u64>,
+    /// Rotate once the file has been open this long, checked as each record arrives
+    pub age: Option<Duration>,
+    /// Compress each rotated file, replacing it with a `.gz`
+    pub gzip: bool,
+}
+
+impl Rotation {
+    fn due(&self, written: u64, opened: Instant) -> bool {
+        matches!(self.size, Some(size) if written >= size)
+            || matches!(self.age, Some(age) if opened.elapsed() >= age)
+    }
+}
+
+/// Archives every record to the file at `path`, framed and sealed as they would be sent
+/// to a listener. Rotated files are numbered in the order they were written, `PATH.1`,
+/// `PATH.2` and so on, skipping any already present
+pub async fn write_file(
+    mut rx_writer: AsyncReceiver<WriteChannel>,
+    path: &Path,
+    rotation: &Rotation,
+) -> Result<()> {
+    let mut next = 1;
+    let mut compressing = Vec::new();
+    let mut opened = Instant::now();
+    let mut written = 0;
+    let mut sink = open(path).await?;
+
+    loop {
+        // Flush whenever there is nothing more to write yet, as forward does
+        let frame = match rx_writer.next().now_or_never() {
+            Some(Some(frame)) => frame,
+            Some(None) => break,
+            None => {
+                sink.flush().await?;
+                match rx_writer.next().await {
+                    Some(frame) => frame,
+                    None => break,
+                }
+            }
+        };
+        trace!("Writer received item, sized: {}", frame.len());
+
+        if written > 0 && rotation.due(written, opened) {
+            sink.close().await?;
+            let rotated = rotate(path, &mut next).await?;
+            if rotation.gzip {
+                compressing.push(tokio::task::spawn_blocking(move || compress(&rotated)));
+            }
+
+            sink = open(path).await?;
+            opened = Instant::now();
+            written = 0;
+        }
+
+        written += frame.len() as u64;
+        feed(sink.as_mut(), frame).await?;
+    }
+    sink.close().await?;
+
+    for res in future::join_all(compressing).await {
+        res.map_err(|e| io::Error::new(io::ErrorKind::Other, e))??
+    }
+
+    info!("All data written successfully");
+
+    Ok(())
+}
+
+async fn open(path: &Path) -> Result<Pin<Box<impl Sink<WriteChannel, Error = io::Error>>>> {
+    let file = File::create(path).await?;
+
+    Ok(Box::pin(RecordFrame::write(BufWriter::new(file))))
+}
+
+/// Moves the file at `path` to the first free numbered name from `next`
+async fn rotate(path: &Path, next: &mut u64) -> Result<PathBuf> {
+    let rotated = loop {
+        let (rotated, gzipped) = (numbered(path, *next, ""), numbered(path, *next, ".gz"));
+        *next += 1;
+        if !rotated.exists() && !gzipped.exists() {
+            break rotated;
+        }
+    };
+    tokio::fs::rename(path, &rotated).await?;
+    debug!(rotated = %rotated.display(), "Rotated output file");
+
+    Ok(rotated)
+}
+
+/// Replaces the file at `path` with a gzipped copy
+fn compress(path: &Path) -> io::Result<()> {
+    let target = with_suffix(path, ".gz");
+    let mut encoder = GzEncoder::new(fs::File::create(&target)?, Compression::default());
+    io::copy(&mut fs::File::open(path)?, &mut encoder)?;
+    encoder.finish()?;
+
+    fs::remove_file(path)
+}
+
+fn numbered(path: &Path, number: u64, suffix: &str) -> PathBuf {
+    with_suffix(path, &format!(".{}{}", number, suffix))
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+
+    name.into()
+}
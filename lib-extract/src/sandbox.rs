@@ -0,0 +1,268 @@
+use {
+    serde::Deserialize,
+    std::{ffi::CStr, io},
+};
+
+/// `AUDIT_ARCH_*` of the architecture this program runs on, which its syscall numbers are
+/// only valid for
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: Option<u32> = Some(0xc000_003e);
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: Option<u32> = Some(0xc000_00b7);
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const AUDIT_ARCH: Option<u32> = None;
+
+/// Offsets into `seccomp_data` of the syscall number, the architecture and the low half of
+/// the first argument, which is first as both supported architectures are little endian
+const NR_OFFSET: u32 = 0;
+const ARCH_OFFSET: u32 = 4;
+const ARG0_OFFSET: u32 = 16;
+
+/// Set on every x32 syscall, which would otherwise get past the numbers checked
+#[cfg(target_arch = "x86_64")]
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+/// Syscalls that administer the system or other processes, killing anything that makes them
+/// under the `restricted` preset
+const RESTRICTED: &[libc::c_long] = &[
+    libc::SYS_ptrace,
+    libc::SYS_process_vm_readv,
+    libc::SYS_process_vm_writev,
+    libc::SYS_mount,
+    libc::SYS_umount2,
+    libc::SYS_pivot_root,
+    libc::SYS_chroot,
+    libc::SYS_unshare,
+    libc::SYS_setns,
+    libc::SYS_reboot,
+    libc::SYS_kexec_load,
+    libc::SYS_init_module,
+    libc::SYS_finit_module,
+    libc::SYS_delete_module,
+    libc::SYS_swapon,
+    libc::SYS_swapoff,
+    libc::SYS_bpf,
+    libc::SYS_perf_event_open,
+    libc::SYS_keyctl,
+    libc::SYS_add_key,
+    libc::SYS_request_key,
+];
+
+/// Confines an executable as it starts, set by its sidecar. Anything killed by its seccomp
+/// filter is reported as an error. Both apply to whatever runs here, so to the CLI of a
+/// container's runtime, or to ssh under `--ssh`, rather than what it runs
+///
+/// ```yaml
+/// sandbox:
+///   no_network: true
+///   seccomp: restricted
+/// ```
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sandbox {
+    /// Run in a network namespace of its own, with nothing but a loopback device that is down.
+    /// Without the privilege to make one, a user namespace is made for it too, mapping only
+    /// the user and group it runs as
+    no_network: bool,
+    seccomp: Option<Preset>,
+}
+
+/// Sets of syscalls a seccomp filter kills the executable for making
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preset {
+    /// Opening any socket but a unix socket, along with io_uring which could open one
+    NoNetwork,
+    /// As `no-network`, along with administering the system or tracing other processes
+    Restricted,
+}
+
+impl Sandbox {
+    pub fn is_set(&self) -> bool {
+        self.no_network || self.seccomp.is_some()
+    }
+
+    /// Whether a seccomp filter is installed, killing the executable with SIGSYS for any
+    /// syscall it forbids
+    pub fn is_filtered(&self) -> bool {
+        self.seccomp.is_some()
+    }
+
+    /// Everything needed to enter this sandbox, made before the executable is forked so
+    /// that entering it allocates nothing. Fails if seccomp isn't supported here
+    pub fn prepare(&self) -> io::Result<Prepared> {
+        let filter = match (self.seccomp, AUDIT_ARCH) {
+            (Some(preset), Some(arch)) => Some(filter(preset, arch)),
+            (Some(_), None) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "seccomp presets aren't supported on this architecture",
+                ))
+            }
+            (None, _) => None,
+        };
+
+        Ok(Prepared {
+            no_network: self.no_network,
+            filter,
+        })
+    }
+}
+
+/// A sandbox ready to be entered, see `Sandbox::prepare`
+pub struct Prepared {
+    no_network: bool,
+    filter: Option<Vec<libc::sock_filter>>,
+}
+
+impl Prepared {
+    /// Confines the calling process, installing its seccomp filter last as unshare may be
+    /// among the syscalls it forbids
+    pub fn enter(&self) -> io::Result<()> {
+        if self.no_network {
+            isolate_network()?;
+        }
+        if let Some(filter) = &self.filter {
+            let program = libc::sock_fprog {
+                len: filter.len() as libc::c_ushort,
+                filter: filter.as_ptr() as *mut libc::sock_filter,
+            };
+            // Safety: only makes the prctl and seccomp syscalls, the filter outliving both
+            check(unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) })?;
+            check(unsafe {
+                libc::syscall(
+                    libc::SYS_seccomp,
+                    libc::SECCOMP_SET_MODE_FILTER,
+                    0,
+                    &program as *const libc::sock_fprog,
+                )
+            } as libc::c_int)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Moves the calling process into a network namespace of its own, see `Sandbox::no_network`
+fn isolate_network() -> io::Result<()> {
+    // Safety: every call here only makes syscalls, formatting into buffers on the stack
+    if unsafe { libc::unshare(libc::CLONE_NEWNET) } == 0 {
+        return Ok(());
+    }
+
+    let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+    check(unsafe { libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET) })?;
+    // Groups can't be mapped by an unprivileged process until it gives up setgroups
+    write_proc(c_str(b"/proc/self/setgroups\0"), b"deny")?;
+    let mut buf = [0u8; 32];
+    write_proc(c_str(b"/proc/self/uid_map\0"), map_one(&mut buf, uid))?;
+    write_proc(c_str(b"/proc/self/gid_map\0"), map_one(&mut buf, gid))
+}
+
+/// Writes `id id 1` into `buf`, mapping `id` to itself in a user namespace
+fn map_one(buf: &mut [u8; 32], id: u32) -> &[u8] {
+    let mut digits = [0u8; 10];
+    let mut len = 0;
+    let mut rest = id;
+    loop {
+        digits[len] = b'0' + (rest % 10) as u8;
+        len += 1;
+        rest /= 10;
+        if rest == 0 {
+            break;
+        }
+    }
+
+    let mut at = 0;
+    for _ in 0..2 {
+        for digit in digits[..len].iter().rev() {
+            buf[at] = *digit;
+            at += 1;
+        }
+        buf[at] = b' ';
+        at += 1;
+    }
+    buf[at] = b'1';
+
+    &buf[..=at]
+}
+
+fn write_proc(path: &CStr, contents: &[u8]) -> io::Result<()> {
+    // Safety: the fd is closed whether or not the write succeeds
+    let fd = unsafe { libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC) };
+    check(fd)?;
+    let written =
+        unsafe { libc::write(fd, contents.as_ptr() as *const libc::c_void, contents.len()) };
+    let res = match written {
+        n if n < 0 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    };
+    unsafe { libc::close(fd) };
+
+    res
+}
+
+fn c_str(bytes: &'static [u8]) -> &'static CStr {
+    CStr::from_bytes_with_nul(bytes).expect("Path must be nul terminated")
+}
+
+/// A seccomp filter allowing everything but what `preset` forbids, killing the process
+/// for anything made from another architecture than `arch`
+fn filter(preset: Preset, arch: u32) -> Vec<libc::sock_filter> {
+    let kill = stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS);
+    let load = |offset| stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, offset);
+    let equals = |k, jt, jf| jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, k, jt, jf);
+
+    let mut filter = vec![load(ARCH_OFFSET), equals(arch, 1, 0), kill, load(NR_OFFSET)];
+    #[cfg(target_arch = "x86_64")]
+    filter.extend_from_slice(&[
+        jump(
+            libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K,
+            X32_SYSCALL_BIT,
+            0,
+            1,
+        ),
+        kill,
+    ]);
+
+    let forbidden = match preset {
+        Preset::NoNetwork => &[][..],
+        Preset::Restricted => RESTRICTED,
+    };
+    for nr in forbidden
+        .iter()
+        .chain(&[libc::SYS_io_uring_setup, libc::SYS_io_uring_enter])
+    {
+        filter.extend_from_slice(&[equals(*nr as u32, 0, 1), kill]);
+    }
+    // Only a socket of any family but AF_UNIX is killed for, anything else skips to the end
+    filter.extend_from_slice(&[
+        equals(libc::SYS_socket as u32, 0, 3),
+        load(ARG0_OFFSET),
+        equals(libc::AF_UNIX as u32, 1, 0),
+        kill,
+        stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW),
+    ]);
+
+    filter
+}
+
+fn stmt(code: u32, k: u32) -> libc::sock_filter {
+    jump(code, k, 0, 0)
+}
+
+fn jump(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter {
+        code: code as u16,
+        jt,
+        jf,
+        k,
+    }
+}
+
+fn check(res: libc::c_int) -> io::Result<()> {
+    match res {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
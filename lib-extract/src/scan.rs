@@ -0,0 +1,286 @@
+use {
+    crate::{
+        compare::{priority_key, Priority, PriorityKey},
+        prelude::*,
+    },
+    rayon::prelude::*,
+    std::{
+        collections::{HashMap, HashSet},
+        ffi::OsStr,
+        fs,
+        path::{Path, PathBuf},
+        sync::{Arc, Mutex},
+        time::SystemTime,
+    },
+    walkdir::{DirEntry, Error as WalkError, WalkDir},
+};
+
+/// Everything found under an exec root alongside its sort key, in no particular order.
+/// Entries that failed to be examined are passed through as errors
+pub type Scanned = Vec<Keyed>;
+
+/// Something found, alongside its sort key
+pub type Keyed = (Vec<PriorityKey>, Result<(Priority, DirEntry)>);
+
+/// Walks `root`, examining everything `listed` lets through (besides directories) in
+/// parallel. `examine` returns None for anything that isn't an executable
+pub fn walk<L, E>(root: &Path, listed: L, examine: E) -> (Scanned, Vec<WalkError>)
+where
+    L: FnMut(&DirEntry) -> bool,
+    E: Fn(&DirEntry) -> Option<Result<Priority>> + Sync,
+{
+    let (entries, errors): (Vec<_>, Vec<_>) = WalkDir::new(root)
+        .into_iter()
+        .filter_entry(listed)
+        .partition(|res| res.is_ok());
+
+    let found = entries
+        .into_par_iter()
+        .filter_map(|res| res.ok())
+        .filter(|entry| !entry.file_type().is_dir())
+        .filter_map(|entry| keyed(entry, &examine))
+        .collect();
+
+    (
+        found,
+        errors.into_iter().filter_map(|res| res.err()).collect(),
+    )
+}
+
+/// Sorting by this key walks an exec root depth first, with each directory's entries in
+/// priority order, see `priority_key`
+pub fn sort_key(entry: &DirEntry) -> Vec<PriorityKey> {
+    relative_components(entry).map(priority_key).collect()
+}
+
+/// The components of `entry`'s path under its exec root, which are as many as it is deep
+fn relative_components(entry: &DirEntry) -> impl Iterator<Item = &OsStr> {
+    let components = entry.path().components();
+    let skip = components.clone().count() - entry.depth();
+
+    components.skip(skip).map(|component| component.as_os_str())
+}
+
+/// Examines `entry`, keyed for sorting if it is an executable
+fn keyed<E>(entry: DirEntry, examine: &E) -> Option<Keyed>
+where
+    E: Fn(&DirEntry) -> Option<Result<Priority>>,
+{
+    examine(&entry).map(|res| (sort_key(&entry), res.map(|priority| (priority, entry))))
+}
+
+/// What was found in every directory under the exec roots, kept between the runs of `--every`
+/// and `--watch` so that only directories modified since (going by their mtime) are read and
+/// examined again. Every directory is still stat'd each run, so changes anywhere are noticed.
+/// A file changed in place, i.e made executable, doesn't modify its directory though, so
+/// isn't noticed until something else in the directory changes
+#[derive(Debug, Default)]
+pub struct ScanCache {
+    roots: Mutex<HashMap<PathBuf, Arc<Mutex<RootScan>>>>,
+}
+
+#[derive(Debug, Default)]
+struct RootScan {
+    /// Whatever else decided what the root's scan found, i.e its manifest's patterns
+    fingerprint: String,
+    dirs: HashMap<PathBuf, DirScan>,
+}
+
+#[derive(Debug)]
+struct DirScan {
+    /// When the directory was last modified as it was read, None if it couldn't be read in
+    /// full and must be read again
+    modified: Option<SystemTime>,
+    listed: Vec<Listed>,
+}
+
+#[derive(Debug)]
+enum Listed {
+    /// A directory, walked into
+    Dir(PathBuf),
+    /// An executable, with its sort key and priority
+    Found(Vec<PriorityKey>, Priority, DirEntry),
+    /// Anything that failed to be examined, which is examined again every scan so that the
+    /// failure is reported each time
+    Failed(DirEntry),
+}
+
+impl ScanCache {
+    /// Scans `root` as `walk` does, reading only the directories modified since the last
+    /// scan of `root`. Anything cached is dropped if the `fingerprint` has changed since
+    pub fn scan<L, E>(
+        &self,
+        root: &Path,
+        fingerprint: String,
+        listed: L,
+        examine: E,
+    ) -> (Scanned, Vec<WalkError>)
+    where
+        L: FnMut(&DirEntry) -> bool,
+        E: Fn(&DirEntry) -> Option<Result<Priority>> + Sync,
+    {
+        let cached = self
+            .roots
+            .lock()
+            .unwrap()
+            .entry(root.to_path_buf())
+            .or_default()
+            .clone();
+        let mut cached = cached.lock().unwrap();
+        if cached.fingerprint != fingerprint {
+            *cached = RootScan {
+                fingerprint,
+                dirs: HashMap::new(),
+            };
+        }
+
+        let stale: HashSet<_> = cached
+            .dirs
+            .par_iter()
+            .filter(|(dir, scan)| scan.modified.is_none() || modified(dir) != scan.modified)
+            .map(|(dir, _)| dir.clone())
+            .collect();
+        let errors = match cached.dirs.is_empty() || !stale.is_empty() {
+            true => cached.read(root, &stale, listed, &examine),
+            false => Vec::new(),
+        };
+        debug!(root = %root.display(), stale = stale.len(), "Scanned exec root");
+
+        (cached.collect(root, &examine), errors)
+    }
+}
+
+impl RootScan {
+    /// Lists every `stale` directory afresh, along with any that aren't cached yet, walking
+    /// only as much of the root as it takes to reach them
+    fn read<L, E>(
+        &mut self,
+        root: &Path,
+        stale: &HashSet<PathBuf>,
+        mut listed: L,
+        examine: &E,
+    ) -> Vec<WalkError>
+    where
+        L: FnMut(&DirEntry) -> bool,
+        E: Fn(&DirEntry) -> Option<Result<Priority>> + Sync,
+    {
+        let ancestors: HashSet<_> = stale
+            .iter()
+            .flat_map(|dir| dir.ancestors().skip(1))
+            .collect();
+        let mut reading: HashMap<PathBuf, (Option<SystemTime>, Vec<DirEntry>)> = HashMap::new();
+        let mut errors = Vec::new();
+
+        let mut walk = WalkDir::new(root).into_iter().filter_entry(|e| listed(e));
+        while let Some(res) = walk.next() {
+            let entry = match res {
+                Ok(entry) => entry,
+                Err(e) => {
+                    errors.push(e);
+                    continue;
+                }
+            };
+
+            let parent = match entry.depth() {
+                // A root that is a file is its own listing, read again every scan
+                0 if !entry.file_type().is_dir() => {
+                    Some(reading.entry(root.to_path_buf()).or_default())
+                }
+                0 => None,
+                _ => entry
+                    .path()
+                    .parent()
+                    .and_then(|parent| reading.get_mut(parent)),
+            };
+            if let Some((_, children)) = parent {
+                children.push(entry.clone());
+            }
+
+            if entry.file_type().is_dir() {
+                let path = entry.path();
+                let fresh = self.dirs.contains_key(path) && !stale.contains(path);
+                match (fresh, ancestors.contains(path)) {
+                    (false, _) => {
+                        reading.insert(path.to_path_buf(), (modified(path), Vec::new()));
+                    }
+                    (true, true) => (),
+                    (true, false) => walk.skip_current_dir(),
+                }
+            }
+        }
+
+        // Whatever couldn't be read must be read again next time, so the error is reported again
+        for e in errors.iter() {
+            let dir = e.path().and_then(|path| match reading.contains_key(path) {
+                true => Some(path),
+                false => path.parent(),
+            });
+            if let Some((modified, _)) = dir.and_then(|dir| reading.get_mut(dir)) {
+                *modified = None;
+            }
+        }
+
+        let read: Vec<_> = reading
+            .into_par_iter()
+            .map(|(dir, (modified, children))| {
+                let listed = children
+                    .into_par_iter()
+                    .filter_map(|entry| match entry.file_type().is_dir() {
+                        true => Some(Listed::Dir(entry.into_path())),
+                        false => examine(&entry).map(|res| match res {
+                            Ok(priority) => Listed::Found(sort_key(&entry), priority, entry),
+                            Err(_) => Listed::Failed(entry),
+                        }),
+                    })
+                    .collect();
+
+                (dir, DirScan { modified, listed })
+            })
+            .collect();
+        self.dirs.extend(read);
+
+        errors
+    }
+
+    /// Everything found under `root`, forgetting any directory that is no longer under it
+    fn collect<E>(&mut self, root: &Path, examine: &E) -> Scanned
+    where
+        E: Fn(&DirEntry) -> Option<Result<Priority>> + Sync,
+    {
+        let mut found = Vec::new();
+        let mut failed = Vec::new();
+        let mut reached = HashSet::new();
+        let mut dirs = vec![root.to_path_buf()];
+
+        while let Some(dir) = dirs.pop() {
+            let scan = match self.dirs.get(&dir) {
+                Some(scan) => scan,
+                None => continue,
+            };
+            for listed in scan.listed.iter() {
+                match listed {
+                    Listed::Dir(path) => dirs.push(path.clone()),
+                    Listed::Found(key, priority, entry) => {
+                        found.push((key.clone(), Ok((*priority, entry.clone()))))
+                    }
+                    Listed::Failed(entry) => failed.push(entry.clone()),
+                }
+            }
+            reached.insert(dir);
+        }
+        self.dirs.retain(|dir, _| reached.contains(dir));
+
+        found.par_extend(
+            failed
+                .into_par_iter()
+                .filter_map(|entry| keyed(entry, examine)),
+        );
+
+        found
+    }
+}
+
+/// When the directory at `path` was last modified, if it still exists
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
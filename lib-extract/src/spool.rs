@@ -0,0 +1,189 @@
+use {
+    crate::{models::WriteChannel, prelude::*},
+    futures::prelude::*,
+    std::{
+        collections::VecDeque,
+        convert::TryFrom,
+        ffi::OsStr,
+        fs::{self, File},
+        io::{self, BufReader, Read, Write},
+        iter,
+        path::{Path, PathBuf},
+    },
+};
+
+/// Extension of the segment files a spool is kept in
+const SPOOL_EXTENSION: &str = "spool";
+
+/// Most bytes written to a single segment, so that space is freed as the spool drains
+const SEGMENT_SIZE: u64 = 4 * 1024 * 1024;
+
+/// A bounded, first in first out queue of frames kept on disk, holding what couldn't be
+/// sent while the connection was down. Frames are kept in numbered segments, so any left
+/// by an earlier run are sent first
+#[derive(Debug)]
+pub struct Spool {
+    dir: PathBuf,
+    /// Most bytes kept on disk at once
+    max: u64,
+    size: u64,
+    /// Oldest first, with the bytes in each
+    segments: VecDeque<(PathBuf, u64)>,
+    /// The newest segment, while frames are being added to it
+    writer: Option<File>,
+    /// The oldest segment, while frames are being taken from it
+    reader: Option<BufReader<File>>,
+    /// A frame taken once the spool was full, sent after everything spooled
+    held: Option<WriteChannel>,
+    next: u64,
+}
+
+impl Spool {
+    /// Opens the spool in `dir`, creating it if needed
+    pub fn new(dir: &Path, max: u64) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+
+        let mut segments = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let number = path
+                .file_stem()
+                .filter(|_| path.extension() == Some(OsStr::new(SPOOL_EXTENSION)))
+                .and_then(OsStr::to_str)
+                .and_then(|stem| stem.parse::<u64>().ok());
+
+            if let Some(number) = number {
+                let size = fs::metadata(&path)?.len();
+                segments.push((number, path, size));
+            }
+        }
+        segments.sort_unstable_by_key(|(number, _, _)| *number);
+
+        if !segments.is_empty() {
+            info!(
+                spool = %dir.display(),
+                segments = segments.len(),
+                "Found frames spooled by an earlier run, these will be sent first"
+            );
+        }
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            max,
+            size: segments.iter().map(|(_, _, size)| size).sum(),
+            next: segments.last().map_or(0, |(number, _, _)| number + 1),
+            segments: segments
+                .into_iter()
+                .map(|(_, path, size)| (path, size))
+                .collect(),
+            writer: None,
+            reader: None,
+            held: None,
+        })
+    }
+
+    /// Moves `frames` into the spool until they end or it is full, after which it waits
+    /// for ever, so that any more are held up in the writer channel instead
+    pub async fn fill<St>(&mut self, frames: &mut St) -> Result<()>
+    where
+        St: Stream<Item = WriteChannel> + Unpin,
+    {
+        loop {
+            let frame = match self.held.take() {
+                Some(frame) => frame,
+                None => match frames.next().await {
+                    Some(frame) => frame,
+                    None => break,
+                },
+            };
+
+            let len = frame.len() as u64 + 4;
+            if self.size + len > self.max {
+                warn!(spool = %self.dir.display(), "Spool is full, holding back any more records");
+                self.held = Some(frame);
+                self.writer = None;
+                return future::pending().await;
+            }
+
+            self.push(&frame)?;
+            self.size += len;
+        }
+
+        self.writer = None;
+
+        Ok(())
+    }
+
+    /// Takes frames from the spool, oldest first, dropping each segment once read. Reading
+    /// picks up where it left off if stopped part way through
+    pub fn drain(&mut self) -> impl Iterator<Item = WriteChannel> + '_ {
+        // New frames are only added between calls to this, so start them in a new segment
+        self.writer = None;
+
+        iter::from_fn(move || loop {
+            match self.pop() {
+                Ok(Some(frame)) => return Some(frame),
+                Ok(None) if self.segments.is_empty() => return self.held.take(),
+                Ok(None) => self.drop_oldest(),
+                Err(e) => {
+                    error!(spool = %self.dir.display(), "Unable to read spool segment: {}", e);
+                    self.drop_oldest()
+                }
+            }
+        })
+    }
+
+    fn push(&mut self, frame: &[u8]) -> io::Result<()> {
+        let len = u32::try_from(frame.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "frame too large to spool"))?;
+
+        match (&self.writer, self.segments.back_mut()) {
+            (Some(_), Some((_, size))) if *size < SEGMENT_SIZE => *size += 4 + len as u64,
+            _ => {
+                let path = self
+                    .dir
+                    .join(format!("{:08}.{}", self.next, SPOOL_EXTENSION));
+                self.next += 1;
+                self.writer = Some(File::create(&path)?);
+                self.segments.push_back((path, 4 + len as u64));
+            }
+        }
+
+        let writer = self.writer.as_mut().unwrap();
+        writer.write_all(&len.to_be_bytes())?;
+        writer.write_all(frame)
+    }
+
+    /// Reads the next frame in the oldest segment, if there is one
+    fn pop(&mut self) -> io::Result<Option<WriteChannel>> {
+        if self.reader.is_none() {
+            match self.segments.front() {
+                Some((path, _)) => self.reader = Some(BufReader::new(File::open(path)?)),
+                None => return Ok(None),
+            }
+        }
+        let reader = self.reader.as_mut().unwrap();
+
+        let mut len = [0; 4];
+        match reader.read_exact(&mut len) {
+            Ok(()) => (),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+
+        let mut frame = vec![0; u32::from_be_bytes(len) as usize];
+        reader.read_exact(&mut frame)?;
+
+        Ok(Some(frame.into()))
+    }
+
+    fn drop_oldest(&mut self) {
+        self.reader = None;
+        if let Some((path, size)) = self.segments.pop_front() {
+            self.size -= size;
+            if let Err(e) = fs::remove_file(&path) {
+                warn!(segment = %path.display(), "Unable to remove spool segment: {}", e)
+            }
+        }
+    }
+}
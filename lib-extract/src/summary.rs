@@ -0,0 +1,118 @@
+use {
+    crate::{output::OutputContext, ARGS},
+    lib_transport::Metrics,
+    std::{
+        collections::BTreeMap,
+        sync::Mutex,
+        time::{Duration, Instant},
+    },
+};
+
+/// Name the summary is reported under, distinguishing it from `--metrics`' periodic reports
+pub const SUMMARY_SOURCE: &str = "extract.summary";
+
+/// Statistics on every priority group run, kept with `--summary` and reported once every
+/// child has been reaped. Groups are counted from 0 in the order they ran, within each
+/// logical stream if there are several
+#[derive(Debug, Default)]
+pub struct Summary {
+    groups: Mutex<BTreeMap<(Option<u32>, u64), Group>>,
+}
+
+#[derive(Debug, Default)]
+struct Group {
+    scripts: u64,
+    failures: u64,
+    lines: u64,
+    bytes: u64,
+    started: Option<Instant>,
+    finished: Option<Instant>,
+}
+
+impl Group {
+    fn wall_time(&self) -> Duration {
+        match (self.started, self.finished) {
+            (Some(started), Some(finished)) => finished.saturating_duration_since(started),
+            _ => Duration::default(),
+        }
+    }
+}
+
+impl Summary {
+    /// Counts an executable about to run for the first time
+    pub fn started(&self, cxt: &OutputContext) {
+        self.update(cxt, |group| {
+            group.scripts += 1;
+            group.started.get_or_insert_with(Instant::now);
+        })
+    }
+
+    /// Counts the lines and bytes one of a child's output streams produced
+    pub fn output(&self, cxt: &OutputContext, lines: u64, bytes: u64) {
+        self.update(cxt, |group| {
+            group.lines += lines;
+            group.bytes += bytes;
+        })
+    }
+
+    /// Counts an attempt at running an executable that has finished, or failed to start
+    pub fn finished(&self, cxt: &OutputContext, succeeded: bool) {
+        self.update(cxt, |group| {
+            group.failures += !succeeded as u64;
+            group.finished = Some(Instant::now());
+        })
+    }
+
+    /// Totals for the whole run, followed by the same for each group, i.e `group.0.lines`, or
+    /// `stream.1.group.0.lines` with several streams. Wall times are gauged in seconds, the
+    /// run's from the first group starting to the last finishing
+    pub fn report(&self) -> Metrics {
+        let groups = self.groups.lock().unwrap();
+        let started = groups.values().filter_map(|group| group.started).min();
+        let finished = groups.values().filter_map(|group| group.finished).max();
+        let wall_time = match (started, finished) {
+            (Some(started), Some(finished)) => finished.saturating_duration_since(started),
+            _ => Duration::default(),
+        };
+
+        let total = |count: fn(&Group) -> u64| groups.values().map(count).sum();
+        let mut report = Metrics::now(1, SUMMARY_SOURCE, wall_time)
+            .counter("scripts", total(|group| group.scripts))
+            .counter("failures", total(|group| group.failures))
+            .counter("lines", total(|group| group.lines))
+            .counter("bytes", total(|group| group.bytes))
+            .gauge("wall_secs", wall_time.as_secs_f64());
+
+        for ((stream, idx), group) in groups.iter() {
+            let prefix = match stream {
+                Some(stream) => format!("stream.{}.group.{}", stream, idx),
+                None => format!("group.{}", idx),
+            };
+            report = report
+                .counter(format!("{}.scripts", prefix), group.scripts)
+                .counter(format!("{}.failures", prefix), group.failures)
+                .counter(format!("{}.lines", prefix), group.lines)
+                .counter(format!("{}.bytes", prefix), group.bytes)
+                .gauge(
+                    format!("{}.wall_secs", prefix),
+                    group.wall_time().as_secs_f64(),
+                );
+        }
+
+        report
+    }
+
+    fn update<F>(&self, cxt: &OutputContext, f: F)
+    where
+        F: FnOnce(&mut Group),
+    {
+        if let (true, Some(group)) = (ARGS.summary(), cxt.group()) {
+            f(self
+                .groups
+                .lock()
+                .unwrap()
+                .entry((cxt.stream(), group))
+                .or_default())
+        }
+    }
+}
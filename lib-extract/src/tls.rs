@@ -0,0 +1,96 @@
+use {
+    crate::{error::Err, prelude::*},
+    std::{
+        fs::File,
+        io::BufReader,
+        path::{Path, PathBuf},
+        sync::Arc,
+    },
+    tokio_rustls::{
+        rustls::{
+            internal::pemfile::{certs, pkcs8_private_keys, rsa_private_keys},
+            ClientConfig, PrivateKey,
+        },
+        webpki::{DNSName, DNSNameRef},
+        TlsConnector,
+    },
+};
+
+/// How a tcp output is wrapped in TLS
+#[derive(Debug, Clone, Default)]
+pub struct Tls {
+    /// PEM file of the certificates the listener's is checked against, rather than the
+    /// usual web roots
+    pub ca: Option<PathBuf>,
+    /// PEM files of the certificate chain and private key this program identifies itself
+    /// with, if the listener asks
+    pub identity: Option<(PathBuf, PathBuf)>,
+    /// Name the listener's certificate must be for, rather than the host connected to
+    pub name: Option<String>,
+}
+
+impl Tls {
+    /// Builds a connector from the files given, along with the name the listener's
+    /// certificate is checked against when connecting to `host`
+    pub fn connector(&self, host: &str) -> Result<(TlsConnector, DNSName)> {
+        let mut config = ClientConfig::new();
+
+        match &self.ca {
+            Some(ca) => match config.root_store.add_pem_file(&mut open(ca)?) {
+                Ok((added, _)) if added > 0 => debug!(ca = %ca.display(), added, "Loaded CA"),
+                _ => return Err(invalid(ca, "no certificates could be read from it")),
+            },
+            None => config
+                .root_store
+                .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS),
+        }
+
+        if let Some((cert, key)) = &self.identity {
+            let chain = certs(&mut open(cert)?)
+                .ok()
+                .filter(|chain| !chain.is_empty())
+                .ok_or_else(|| invalid(cert, "no certificates could be read from it"))?;
+            config
+                .set_single_client_cert(chain, private_key(key)?)
+                .map_err(|e| invalid(cert, &e.to_string()))?;
+        }
+
+        let name = self.name.as_deref().unwrap_or(host);
+        let name = DNSNameRef::try_from_ascii_str(name).map_err(|_| {
+            CrateError::from(Err::Tls {
+                reason: format!(
+                    "'{}' is not a DNS name, use --tls-name to give the name on the \
+                    listener's certificate",
+                    name
+                ),
+            })
+        })?;
+
+        Ok((TlsConnector::from(Arc::new(config)), name.to_owned()))
+    }
+}
+
+/// Reads the first private key in `path`, either PKCS8 or RSA
+fn private_key(path: &Path) -> Result<PrivateKey> {
+    let pkcs8 = pkcs8_private_keys(&mut open(path)?).unwrap_or_default();
+    let rsa = rsa_private_keys(&mut open(path)?).unwrap_or_default();
+
+    pkcs8
+        .into_iter()
+        .chain(rsa)
+        .next()
+        .ok_or_else(|| invalid(path, "no private key could be read from it"))
+}
+
+fn open(path: &Path) -> Result<BufReader<File>> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| invalid(path, &e.to_string()))
+}
+
+fn invalid(path: &Path, reason: &str) -> CrateError {
+    Err::Tls {
+        reason: format!("'{}': {}", path.display(), reason),
+    }
+    .into()
+}
@@ -0,0 +1,172 @@
+use {
+    crate::{commands::Listed, compare::Priority, prelude::*},
+    serde::Deserialize,
+    std::{
+        convert::TryFrom,
+        fs, io,
+        path::{Path, PathBuf},
+        process::Command,
+        sync::Arc,
+        time::SystemTime,
+    },
+    walkdir::DirEntry,
+};
+
+/// Container runtime used when a container doesn't name one
+pub const DEFAULT_RUNTIME: &str = "docker";
+
+/// Something to run, either an executable found under an exec root, a container its
+/// manifest declares, or a command listed in place of one
+#[derive(Debug, Clone)]
+pub enum Unit {
+    File(DirEntry),
+    Container {
+        /// Where the container would be if it were an executable, named after it in the root
+        path: PathBuf,
+        /// The manifest declaring it, which it counts as modified along with
+        manifest: PathBuf,
+        container: Arc<Container>,
+    },
+    Listed {
+        /// Where the command would be if it were an executable, named after it beside its list
+        path: PathBuf,
+        /// The list naming it, which it counts as modified along with
+        list: PathBuf,
+        command: Arc<Listed>,
+    },
+    /// Another unit, run on `host` rather than here, see `remote`
+    Remote {
+        host: String,
+        unit: Box<Unit>,
+    },
+}
+
+impl Unit {
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::File(entry) => entry.path(),
+            Self::Container { path, .. } | Self::Listed { path, .. } => path,
+            Self::Remote { unit, .. } => unit.path(),
+        }
+    }
+
+    pub fn into_path(self) -> PathBuf {
+        match self {
+            Self::File(entry) => entry.into_path(),
+            Self::Container { path, .. } | Self::Listed { path, .. } => path,
+            Self::Remote { unit, .. } => unit.into_path(),
+        }
+    }
+
+    /// The path of this unit under its exec root
+    pub fn relative(&self) -> &Path {
+        match self {
+            Self::File(entry) => {
+                // As many components as the entry is deep
+                let path = entry.path();
+                let mut components = path.components();
+                for _ in entry.depth()..path.components().count() {
+                    components.next();
+                }
+
+                components.as_path()
+            }
+            Self::Container { container, .. } => Path::new(&container.name),
+            Self::Listed { command, .. } => Path::new(command.name()),
+            Self::Remote { unit, .. } => unit.relative(),
+        }
+    }
+
+    /// When this unit last changed, if that can be told
+    pub fn modified(&self) -> Option<SystemTime> {
+        let meta = match self {
+            Self::File(entry) => entry.metadata().map_err(io::Error::from),
+            Self::Container { manifest, .. } => fs::metadata(manifest),
+            Self::Listed { list, .. } => fs::metadata(list),
+            Self::Remote { unit, .. } => return unit.modified(),
+        };
+
+        meta.and_then(|meta| meta.modified()).ok()
+    }
+
+    /// The command running this unit, unless it is an executable run by its path
+    pub fn command(&self) -> Option<Command> {
+        match self {
+            Self::File(_) => None,
+            Self::Container { container, .. } => Some(container.command()),
+            Self::Listed { command, .. } => Some(command.command()),
+            Self::Remote { unit, .. } => unit.command(),
+        }
+    }
+
+    /// The host this unit runs on, if it isn't run here
+    pub fn host(&self) -> Option<&str> {
+        match self {
+            Self::Remote { host, .. } => Some(host),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<&Unit> for Priority {
+    type Error = CrateError;
+
+    fn try_from(unit: &Unit) -> std::result::Result<Self, Self::Error> {
+        match unit {
+            Unit::File(entry) => Self::try_from(entry),
+            Unit::Container { container, .. } => Self::try_from_str(&container.name),
+            Unit::Listed { command, .. } => Ok(command.priority()),
+            Unit::Remote { unit, .. } => Self::try_from(unit.as_ref()),
+        }
+    }
+}
+
+/// An image run in place of an executable, through the CLI of a runtime that accepts
+/// docker's `run` arguments, i.e docker or podman. The container is removed once it exits.
+///
+/// Its sidecar, named after it as an executable's would be, applies as it would to one,
+/// with the exception of its environment and credentials, which apply to the runtime's CLI
+/// rather than the container. The sidecar's arguments follow the container's own. A
+/// `--timeout` likewise only kills the CLI, so the container should be given a timeout of
+/// its own if it may hang
+///
+/// ```yaml
+/// containers:
+///   - name: 20_scan
+///     image: alpine:3.12
+///     args: [sh, -c, "df -h /host"]
+///     mounts: ["/:/host:ro"]
+///     runtime: podman
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Container {
+    /// Identifies the container as a file name would an executable, priority included
+    name: String,
+    image: String,
+    #[serde(default)]
+    args: Vec<String>,
+    /// Bind mounts, as `--volume` takes them
+    #[serde(default)]
+    mounts: Vec<String>,
+    runtime: Option<String>,
+}
+
+impl Container {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The command running this container, with its standard streams attached so that
+    /// its output is read as any executable's
+    pub fn command(&self) -> Command {
+        let mut command = Command::new(self.runtime.as_deref().unwrap_or(DEFAULT_RUNTIME));
+        command.args(["run", "--rm", "--interactive"]);
+        for mount in self.mounts.iter() {
+            command.arg("--volume").arg(mount);
+        }
+        command.arg(&self.image).args(&self.args);
+
+        command
+    }
+}
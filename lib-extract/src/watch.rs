@@ -0,0 +1,96 @@
+use {
+    crate::prelude::*,
+    std::{
+        ffi::CString,
+        fs::File,
+        io::{self, Read},
+        os::unix::{
+            ffi::OsStrExt,
+            io::{AsRawFd, FromRawFd},
+        },
+        path::{Path, PathBuf},
+        time::Duration,
+    },
+    walkdir::WalkDir,
+};
+
+/// Changes to a watched directory that may mean an executable was added or modified
+const EVENTS: u32 =
+    libc::IN_CREATE | libc::IN_MODIFY | libc::IN_ATTRIB | libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO;
+
+/// Wakes `--watch` when anything changes under its roots, with an inotify watch on every
+/// directory beneath them. Events are only used as a prompt to look again, so none are
+/// ever parsed, and the kernel drops the watch of any directory removed
+#[derive(Debug)]
+pub struct Watcher(File);
+
+impl Watcher {
+    pub fn new() -> io::Result<Self> {
+        // Safety: the fd is owned by the File as soon as it is known to be valid
+        match unsafe { libc::inotify_init1(libc::IN_CLOEXEC) } {
+            fd if fd < 0 => Err(io::Error::last_os_error()),
+            fd => Ok(Self(unsafe { File::from_raw_fd(fd) })),
+        }
+    }
+
+    /// Watches every directory under `roots`, or the directory holding a root that is a
+    /// command list. Directories already watched are left as they are, so this is called
+    /// again after every change to pick up any new ones
+    pub fn watch(&self, roots: &[PathBuf]) {
+        for root in roots {
+            match root.is_file() {
+                true => self.add(root.parent().unwrap_or_else(|| Path::new("."))),
+                false => WalkDir::new(root)
+                    .into_iter()
+                    .filter_map(|res| res.ok())
+                    .filter(|entry| entry.file_type().is_dir())
+                    .for_each(|entry| self.add(entry.path())),
+            }
+        }
+    }
+
+    fn add(&self, dir: &Path) {
+        let path = match CString::new(dir.as_os_str().as_bytes()) {
+            Ok(path) => path,
+            Err(_) => return,
+        };
+        // Safety: path is nul terminated, and outlives the call
+        if unsafe { libc::inotify_add_watch(self.0.as_raw_fd(), path.as_ptr(), EVENTS) } < 0 {
+            let e = io::Error::last_os_error();
+            warn!(dir = %dir.display(), "Unable to watch directory: {}", e);
+        }
+    }
+
+    /// Blocks until something under a watched directory changes, and then until nothing has
+    /// for `quiet`, so that anything still being written has settled
+    pub fn wait(&mut self, quiet: Duration) -> io::Result<()> {
+        self.wait_for(None)?;
+        while self.wait_for(Some(quiet))? {}
+
+        Ok(())
+    }
+
+    /// Waits up to `timeout` for events, or forever without one, discarding any that
+    /// arrive. Returns whether there were any
+    fn wait_for(&mut self, timeout: Option<Duration>) -> io::Result<bool> {
+        let mut fds = libc::pollfd {
+            fd: self.0.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = timeout.map_or(-1, |t| t.as_millis() as libc::c_int);
+
+        loop {
+            // Safety: fds is a single valid pollfd, as the count passed says
+            match unsafe { libc::poll(&mut fds, 1, timeout) } {
+                0 => return Ok(false),
+                n if n < 0 => match io::Error::last_os_error() {
+                    e if e.kind() == io::ErrorKind::Interrupted => continue,
+                    e => return Err(e),
+                },
+                // Room for many events, and more than any one can take with its name
+                _ => return self.0.read(&mut [0u8; 4096]).map(|_| true),
+            }
+        }
+    }
+}
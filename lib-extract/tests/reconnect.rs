@@ -0,0 +1,110 @@
+//! Reconnecting mid stream must leave every connection a valid stream on its own, as listeners
+//! like transform check each connection with a `StreamValidator` and drop any that isn't
+
+use {
+    futures::prelude::*,
+    lib_extract::Runner,
+    lib_transport::{handshake, Record, RecordInterface, ValidateExt, Violation},
+    std::{
+        env, fs,
+        os::unix::fs::PermissionsExt,
+        path::{Path, PathBuf},
+        process, thread,
+        time::Duration,
+    },
+    tokio::net::UnixListener,
+};
+
+/// Accepts the next connection and handshakes with it, returning its records as validated
+async fn accept(
+    listener: &mut UnixListener,
+) -> impl Stream<Item = Result<Record<'static, 'static>, Violation>> {
+    let (socket, _) = listener.accept().await.unwrap();
+    let mut interface = RecordInterface::from_both(socket);
+    let (_, first) = handshake::accept(&mut interface, &[]).await.unwrap();
+
+    stream::iter(first)
+        .chain(interface.map(Result::unwrap))
+        .validate()
+}
+
+fn scripts(dir: &Path) -> PathBuf {
+    let scripts = dir.join("scripts");
+    let script = scripts.join("slow.sh");
+    fs::create_dir_all(&scripts).unwrap();
+    fs::write(&script, "#!/bin/sh\necho before\nsleep 1\necho after\n").unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+    scripts
+}
+
+fn text(record: &Record) -> Option<String> {
+    match record {
+        Record::Data(data) => Some(data.text().into_owned()),
+        _ => None,
+    }
+}
+
+#[test]
+fn reconnecting_resends_the_stream_preamble() {
+    let dir = env::temp_dir().join(format!("extract-reconnect-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let scripts = scripts(&dir);
+    let (socket, spool) = (dir.join("listener.sock"), dir.join("spool"));
+
+    let mut rt = tokio::runtime::Runtime::new().unwrap();
+    let mut listener = rt.enter(|| UnixListener::bind(&socket)).unwrap();
+
+    let args = vec![
+        "extract".into(),
+        "--reconnect".into(),
+        "30".into(),
+        "--spool".into(),
+        spool.into_os_string(),
+        scripts.into_os_string(),
+        "socket".into(),
+        socket.into_os_string(),
+    ];
+    let runner = Runner::from_args(args).unwrap();
+    let extract = thread::spawn(move || runner.run());
+
+    let (first, second) = rt.block_on(async {
+        // Drop the first connection part way through the script's output
+        let mut records = accept(&mut listener).await;
+        let mut first = Vec::new();
+        while let Some(res) = records.next().await {
+            let record = res.expect("first connection is valid");
+            let written = text(&record);
+            first.push(record);
+            if written.is_some() {
+                break;
+            }
+        }
+        drop(records);
+
+        // Leave extract waiting on the handshake long enough for the rest to be spooled
+        tokio::time::delay_for(Duration::from_millis(1500)).await;
+
+        let second: Vec<_> = accept(&mut listener).await.collect().await;
+        (first, second)
+    });
+    extract.join().unwrap().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(matches!(first.first(), Some(Record::StreamStart)));
+    assert_eq!(text(first.last().unwrap()).as_deref(), Some("before"));
+
+    let second: Vec<_> = second
+        .into_iter()
+        .map(|res| res.expect("second connection is valid"))
+        .collect();
+    assert!(matches!(second.first(), Some(Record::StreamStart)));
+    assert!(matches!(second.get(1), Some(Record::Header(header)) if header.id == "slow.sh"));
+    assert!(second
+        .iter()
+        .any(|record| text(record).as_deref() == Some("after")));
+    assert!(second
+        .iter()
+        .any(|record| matches!(record, Record::Exit(_))));
+    assert!(matches!(second.last(), Some(Record::StreamEnd)));
+}
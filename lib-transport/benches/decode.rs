@@ -0,0 +1,63 @@
+use {
+    criterion::{criterion_group, criterion_main, Criterion, Throughput},
+    lib_transport::{Common, Data, DataContext, Encoding, Record},
+    std::borrow::Cow,
+};
+
+const RECORDS: usize = 1_000_000;
+
+/// Serializes a million Data records, roughly the shape of a chatty script's stdout
+fn frames() -> Vec<Vec<u8>> {
+    (0..RECORDS)
+        .map(|i| {
+            let record = Record::Data(Data {
+                required: Common::new(1),
+                time: i as i64,
+                id: Cow::Borrowed("10_some-script.sh"),
+                pid: 4242,
+                cxt: DataContext::Stdout,
+                data: Cow::Owned(
+                    format!("line {} of some reasonably sized output", i).into_bytes(),
+                ),
+                encoding: Encoding::Utf8,
+                trace_id: None,
+                meta: Cow::Owned(Default::default()),
+                unknown: Vec::new(),
+            });
+            serde_cbor::to_vec(&record).unwrap()
+        })
+        .collect()
+}
+
+fn decode(c: &mut Criterion) {
+    let frames = frames();
+    let mut group = c.benchmark_group("decode");
+    group
+        .sample_size(10)
+        .throughput(Throughput::Elements(RECORDS as u64));
+
+    group.bench_function("borrowed", |b| {
+        b.iter(|| {
+            frames
+                .iter()
+                .map(|frame| Record::from_frame(frame).unwrap())
+                .filter(|record| matches!(record, Record::Data(_)))
+                .count()
+        })
+    });
+
+    group.bench_function("owned", |b| {
+        b.iter(|| {
+            frames
+                .iter()
+                .map(|frame| Record::from_frame(frame).unwrap().into_owned())
+                .filter(|record| matches!(record, Record::Data(_)))
+                .count()
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
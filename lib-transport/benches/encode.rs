@@ -0,0 +1,72 @@
+use {
+    criterion::{criterion_group, criterion_main, Criterion, Throughput},
+    futures::pin_mut,
+    lib_transport::{
+        Common, Data, DataContext, Encoding, Record, RecordSerializer, SymmetricalCbor,
+    },
+    std::borrow::Cow,
+    tokio_serde::Serializer,
+};
+
+const RECORDS: usize = 1_000_000;
+
+/// A million Data records, roughly the shape of a chatty script's stdout
+fn records() -> Vec<Record<'static, 'static>> {
+    (0..RECORDS)
+        .map(|i| {
+            Record::Data(Data {
+                required: Common::new(1),
+                time: i as i64,
+                id: Cow::Borrowed("10_some-script.sh"),
+                pid: 4242,
+                cxt: DataContext::Stdout,
+                data: Cow::Owned(
+                    format!("line {} of some reasonably sized output", i).into_bytes(),
+                ),
+                encoding: Encoding::Utf8,
+                trace_id: None,
+                meta: Cow::Owned(Default::default()),
+                unknown: Vec::new(),
+            })
+        })
+        .collect()
+}
+
+fn encode(c: &mut Criterion) {
+    let records = records();
+    let mut group = c.benchmark_group("encode");
+    group
+        .sample_size(10)
+        .throughput(Throughput::Elements(RECORDS as u64));
+
+    // How records were serialized before RecordSerializer, a new serializer per record
+    group.bench_function("fresh", |b| {
+        b.iter(|| {
+            records
+                .iter()
+                .map(|record| {
+                    let mkr = SymmetricalCbor::<Record>::default();
+                    pin_mut!(mkr);
+                    mkr.serialize(record).unwrap()
+                })
+                .map(|bytes| bytes.len())
+                .sum::<usize>()
+        })
+    });
+
+    group.bench_function("reused", |b| {
+        b.iter(|| {
+            let mut serializer = RecordSerializer::new();
+            records
+                .iter()
+                .map(|record| serializer.serialize(record).unwrap())
+                .map(|bytes| bytes.len())
+                .sum::<usize>()
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, encode);
+criterion_main!(benches);
@@ -0,0 +1,180 @@
+use {
+    crate::record::{Ack, Record},
+    bytes::Bytes,
+    futures::{prelude::*, ready, stream::Fuse},
+    pin_project::pin_project,
+    std::{
+        io,
+        pin::Pin,
+        task::{Context, Poll},
+    },
+};
+
+/// Name of the handshake feature advertising acknowledgement support
+pub const FEATURE: &str = "ack";
+
+/// Default number of records a receiver will accept before acknowledging them
+pub const DEFAULT_INTERVAL: u64 = 32;
+
+/// Sender half of ack mode. Wraps a sink of serialized records, counting every record
+/// sent and refusing to accept more once `window` records are unacknowledged. Acks are
+/// read from `acks`, which should be the read half of the same connection
+#[pin_project]
+pub struct AckWindow<Si, St> {
+    #[pin]
+    sink: Si,
+    #[pin]
+    acks: Fuse<St>,
+    window: u64,
+    sent: u64,
+    acked: u64,
+}
+
+impl<Si, St> AckWindow<Si, St>
+where
+    St: Stream,
+{
+    pub fn new(sink: Si, acks: St, window: u64) -> Self {
+        Self {
+            sink,
+            acks: acks.fuse(),
+            window: window.max(1),
+            sent: 0,
+            acked: 0,
+        }
+    }
+}
+
+impl<Si, St, E, SE> Sink<Bytes> for AckWindow<Si, St>
+where
+    Si: Sink<Bytes, Error = E>,
+    St: Stream<Item = Result<Record<'static, 'static>, SE>>,
+    E: From<io::Error> + From<SE>,
+{
+    type Error = E;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let mut this = self.project();
+
+        loop {
+            let full = *this.sent - *this.acked >= *this.window;
+            match this.acks.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(Record::Ack(ack)))) => {
+                    *this.acked = ack.up_to_seq.max(*this.acked).min(*this.sent)
+                }
+                // The receiver should only ever send acks, ignore anything else
+                Poll::Ready(Some(Ok(_))) => {}
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e.into())),
+                Poll::Ready(None) | Poll::Pending if !full => break,
+                Poll::Ready(None) => {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::BrokenPipe,
+                        "receiver closed the connection with records still unacknowledged",
+                    )
+                    .into()))
+                }
+                Poll::Pending => {
+                    // Make sure the receiver can actually see what we're waiting on it to ack
+                    ready!(this.sink.as_mut().poll_flush(cx))?;
+                    return Poll::Pending;
+                }
+            }
+        }
+
+        this.sink.poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Bytes) -> Result<(), Self::Error> {
+        let this = self.project();
+        *this.sent += 1;
+        this.sink.start_send(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().sink.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().sink.poll_close(cx)
+    }
+}
+
+/// Receiver half of ack mode. Wraps a duplex record interface, acknowledging every
+/// `interval` records received, or whenever the underlying stream has caught up with
+/// the sender. This guarantees progress regardless of the sender's window size
+#[pin_project]
+pub struct Acknowledge<IO> {
+    #[pin]
+    io: IO,
+    interval: u64,
+    received: u64,
+    acked: u64,
+    pending: Option<u64>,
+    flushing: bool,
+}
+
+impl<IO> Acknowledge<IO> {
+    pub fn new(io: IO, interval: u64) -> Self {
+        Self {
+            io,
+            interval: interval.max(1),
+            received: 0,
+            acked: 0,
+            pending: None,
+            flushing: false,
+        }
+    }
+}
+
+impl<IO, E, SE> Stream for Acknowledge<IO>
+where
+    IO: Stream<Item = Result<Record<'static, 'static>, SE>>,
+    IO: Sink<Record<'static, 'static>, Error = E>,
+    SE: From<E>,
+{
+    type Item = Result<Record<'static, 'static>, SE>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            if let Some(seq) = *this.pending {
+                if let Err(e) = ready!(this.io.as_mut().poll_ready(cx)) {
+                    return Poll::Ready(Some(Err(e.into())));
+                }
+                if let Err(e) = this.io.as_mut().start_send(Record::Ack(Ack::new(1, seq))) {
+                    return Poll::Ready(Some(Err(e.into())));
+                }
+                *this.pending = None;
+                *this.flushing = true;
+            }
+
+            if *this.flushing {
+                match this.io.as_mut().poll_flush(cx) {
+                    Poll::Ready(Ok(())) => *this.flushing = false,
+                    Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+                    Poll::Pending => {}
+                }
+            }
+
+            match this.io.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    // Every frame counts, even those that failed to deserialize
+                    *this.received += 1;
+                    if *this.received - *this.acked >= *this.interval {
+                        *this.pending = Some(*this.received);
+                        *this.acked = *this.received;
+                    }
+                    return Poll::Ready(Some(item));
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                // Caught up with the sender, ack everything we have so it never stalls
+                Poll::Pending if *this.received > *this.acked => {
+                    *this.pending = Some(*this.received);
+                    *this.acked = *this.received;
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
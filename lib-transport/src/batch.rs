@@ -0,0 +1,190 @@
+use {
+    crate::{codec::LENGTH_LEN, interface::RecordInterface, limits::Limits},
+    bytes::{BufMut, Bytes, BytesMut},
+    futures::{prelude::*, ready},
+    pin_project::pin_project,
+    std::{
+        pin::Pin,
+        task::{Context, Poll},
+        time::Duration,
+    },
+    tokio::time::{delay_for, Delay, Instant},
+};
+
+/// Name of the handshake feature advertising support for batched frames. Once negotiated
+/// every frame the client sends is a batch, see `RecordInterface::with_batches`
+pub const FEATURE: &str = "batch";
+
+/// Default longest a record waits in a partial batch before it is sent anyway
+pub const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Sink adapter that packs several serialized records into each frame it sends to the
+/// underlying sink, cutting the per frame overhead of sending many small records. Each
+/// record is length delimited inside the batch exactly as `RecordFrame` delimits frames.
+///
+/// A batch is sent once it holds `records` records, would grow larger than `limits` allow,
+/// or its oldest record has waited `flush_interval`. Flushing only completes once the
+/// current batch has been sent, so flushes may take up to `flush_interval`. Closing sends
+/// whatever is left immediately
+#[pin_project]
+pub struct Batched<Si> {
+    #[pin]
+    inner: Si,
+    #[pin]
+    timer: Delay,
+    records: usize,
+    flush_interval: Duration,
+    limits: Limits,
+    batch: BytesMut,
+    batched: usize,
+    /// A complete batch the underlying sink has yet to accept
+    pending: Option<Bytes>,
+}
+
+impl<Si> Batched<Si> {
+    pub fn new(inner: Si, records: usize, flush_interval: Duration) -> Self {
+        Self::with_limits(inner, records, flush_interval, Limits::default())
+    }
+
+    /// As `Batched::new`, never sending a batch larger than `limits` allow
+    pub fn with_limits(
+        inner: Si,
+        records: usize,
+        flush_interval: Duration,
+        limits: Limits,
+    ) -> Self {
+        Self {
+            inner,
+            timer: delay_for(flush_interval),
+            records: records.max(1),
+            flush_interval,
+            limits,
+            batch: BytesMut::new(),
+            batched: 0,
+            pending: None,
+        }
+    }
+
+    pub fn get_ref(&self) -> &Si {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> Si {
+        self.inner
+    }
+
+    /// Completes the current batch, leaving it for the underlying sink
+    fn complete(self: Pin<&mut Self>) {
+        let this = self.project();
+        if *this.batched > 0 {
+            *this.pending = Some(this.batch.split().freeze());
+            *this.batched = 0;
+        }
+    }
+}
+
+impl<Si> Batched<Si>
+where
+    Si: Sink<Bytes>,
+{
+    /// Hands any complete batch to the underlying sink
+    fn poll_send(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Si::Error>> {
+        let mut this = self.project();
+        if this.pending.is_some() {
+            ready!(this.inner.as_mut().poll_ready(cx))?;
+            let batch = this.pending.take().unwrap();
+            this.inner.start_send(batch)?;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<Si> Sink<Bytes> for Batched<Si>
+where
+    Si: Sink<Bytes>,
+{
+    type Error = Si::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_send(cx))?;
+        if self.batched > 0 && self.as_mut().project().timer.poll(cx).is_ready() {
+            self.as_mut().complete();
+            ready!(self.as_mut().poll_send(cx))?;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Bytes) -> Result<(), Self::Error> {
+        // Records that would push the batch over the limit start the next one
+        if self.batch.len() + LENGTH_LEN + item.len() > self.limits.max_frame_len() {
+            self.as_mut().complete();
+        }
+
+        let mut this = self.as_mut().project();
+        if *this.batched == 0 {
+            this.timer
+                .as_mut()
+                .reset(Instant::now() + *this.flush_interval);
+        }
+        this.batch.reserve(LENGTH_LEN + item.len());
+        this.batch.put_u32(item.len() as u32);
+        this.batch.extend_from_slice(&item);
+        *this.batched += 1;
+
+        if *this.batched >= *this.records {
+            self.complete();
+        }
+
+        Ok(())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Get any earlier batches on their way before waiting on the current one
+        ready!(self.as_mut().poll_send(cx))?;
+        ready!(self.as_mut().project().inner.poll_flush(cx))?;
+        if self.batched == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        ready!(self.as_mut().project().timer.poll(cx));
+        self.as_mut().complete();
+        ready!(self.as_mut().poll_send(cx))?;
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_send(cx))?;
+        self.as_mut().complete();
+        ready!(self.as_mut().poll_send(cx))?;
+        self.project().inner.poll_close(cx)
+    }
+}
+
+/// Records are only batched on their way out, anything received passes straight through
+impl<Si> Stream for Batched<Si>
+where
+    Si: Stream,
+{
+    type Item = Si::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().inner.poll_next(cx)
+    }
+}
+
+impl<IF> RecordInterface<IF> {
+    /// Packs up to `records` records into each frame sent, see `Batched`. Only use this once
+    /// the other end has negotiated `batch::FEATURE`, as it must unpack them with
+    /// `RecordInterface::with_batches`
+    pub fn buffered(
+        self,
+        records: usize,
+        flush_interval: Duration,
+    ) -> RecordInterface<Batched<IF>> {
+        self.map_inner(|inner, limits| {
+            Batched::with_limits(inner, records, flush_interval, *limits)
+        })
+    }
+}
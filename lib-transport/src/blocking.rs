@@ -0,0 +1,193 @@
+use {
+    crate::{
+        checksum::Checksum,
+        codec::{frame_length, RecordCodec, LENGTH_LEN},
+        compression::Compression,
+        encryption::Encryption,
+        limits::Limits,
+        record::Record,
+    },
+    bytes::BytesMut,
+    serde::Serialize,
+    std::io::{self, Read, Write},
+};
+
+/// Blocking counterpart to a read only `RecordInterface`, for tools without an async runtime.
+/// Frames are delimited exactly as `RecordFrame` delimits them, so either end of a connection
+/// can be blocking or async. Iterating over a reader yields every record until EOF
+pub struct RecordReader<R> {
+    inner: R,
+    checksum: Checksum,
+    compression: Compression,
+    encryption: Encryption,
+    limits: Limits,
+    buffer: BytesMut,
+}
+
+impl<R> RecordReader<R>
+where
+    R: Read,
+{
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            checksum: Checksum::default(),
+            compression: Compression::default(),
+            encryption: Encryption::default(),
+            limits: Limits::default(),
+            buffer: BytesMut::new(),
+        }
+    }
+
+    /// Sets the checksum verified on every record read. Defaults to `Checksum::None`
+    pub fn with_checksum(mut self, checksum: Checksum) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    /// Sets the compression removed from every record read. Defaults to `Compression::None`
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets the encryption removed from every record read. Defaults to `Encryption::None`
+    pub fn with_encryption(mut self, encryption: Encryption) -> Self {
+        self.encryption = encryption;
+        self
+    }
+
+    /// Sets the limits enforced on every record read. Defaults to `Limits::default()`
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Reads the next record, borrowing any strings from this reader's buffer.
+    /// Returns None if the underlying reader was at EOF before the next frame began
+    pub fn read_record(&mut self) -> io::Result<Option<Record<'_, '_>>> {
+        let mut length = [0u8; LENGTH_LEN];
+        if !read_exact_or_eof(&mut self.inner, &mut length)? {
+            return Ok(None);
+        }
+
+        let length = frame_length(&length, &self.limits)?;
+        self.buffer.clear();
+        self.buffer.resize(length, 0);
+        self.inner.read_exact(&mut self.buffer)?;
+        self.encryption.open(&mut self.buffer)?;
+        self.checksum.open(&mut self.buffer)?;
+        self.compression.open(&mut self.buffer, &self.limits)?;
+        self.limits.check_depth(&self.buffer)?;
+
+        Record::from_frame(&self.buffer).map(Some)
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R> Iterator for RecordReader<R>
+where
+    R: Read,
+{
+    type Item = io::Result<Record<'static, 'static>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record()
+            .map(|opt| opt.map(Record::into_owned))
+            .transpose()
+    }
+}
+
+/// Blocking counterpart to a write only `RecordInterface`. Like any other writer,
+/// records may be buffered until `flush` is called
+pub struct RecordWriter<W> {
+    inner: W,
+    codec: RecordCodec,
+    buffer: BytesMut,
+}
+
+impl<W> RecordWriter<W>
+where
+    W: Write,
+{
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            codec: RecordCodec::default(),
+            buffer: BytesMut::new(),
+        }
+    }
+
+    /// Sets the checksum appended to every record written. Defaults to `Checksum::None`
+    pub fn with_checksum(mut self, checksum: Checksum) -> Self {
+        self.codec = self.codec.with_checksum(checksum);
+        self
+    }
+
+    /// Sets the compression applied to every record written. Defaults to `Compression::None`
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.codec = self.codec.with_compression(compression);
+        self
+    }
+
+    /// Sets the encryption applied to every record written. Defaults to `Encryption::None`
+    pub fn with_encryption(mut self, encryption: Encryption) -> Self {
+        self.codec = self.codec.with_encryption(encryption);
+        self
+    }
+
+    /// Sets the limits enforced on the size of every record written. Defaults to `Limits::default()`
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.codec = self.codec.with_limits(limits);
+        self
+    }
+
+    /// Serializes and writes a single record
+    pub fn write_record<T>(&mut self, record: &T) -> io::Result<()>
+    where
+        T: Serialize,
+    {
+        self.buffer.clear();
+        self.codec.encode(record, &mut self.buffer)?;
+        self.inner.write_all(&self.buffer)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+/// Fills `buf` from `reader`, returning false if the reader was at EOF before
+/// any bytes were read. EOF part way through is an error
+fn read_exact_or_eof<R>(reader: &mut R, buf: &mut [u8]) -> io::Result<bool>
+where
+    R: Read,
+{
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(true)
+}
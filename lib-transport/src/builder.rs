@@ -0,0 +1,409 @@
+use {
+    crate::{
+        markers::{DataContext, Encoding},
+        record::{Common, Data, Exit, Header, Meta, Record, BINARY_DATA_VERSION},
+    },
+    std::{borrow::Cow, error, fmt},
+};
+
+/// Returned when a builder is missing one or more required fields
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildError {
+    record: &'static str,
+    missing: Vec<&'static str>,
+}
+
+impl BuildError {
+    /// The kind of record that failed to build
+    pub fn record(&self) -> &'static str {
+        self.record
+    }
+
+    /// The required fields that were never set
+    pub fn missing(&self) -> &[&'static str] {
+        &self.missing
+    }
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "incomplete {} record, missing: {}",
+            self.record,
+            self.missing.join(", ")
+        )
+    }
+}
+
+impl error::Error for BuildError {}
+
+/// Collects the names of any unset required fields, erroring if there were any
+macro_rules! check_required {
+    ($record:expr, $( $field:ident ),+) => {{
+        let missing: Vec<&'static str> = vec![$( (stringify!($field), $field.is_none()) ),+]
+            .into_iter()
+            .filter_map(|(name, missing)| match missing {
+                true => Some(name),
+                false => None,
+            })
+            .collect();
+
+        if !missing.is_empty() {
+            return Err(BuildError {
+                record: $record,
+                missing,
+            });
+        }
+    }};
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct HeaderBuilder<'i> {
+    version: Option<u32>,
+    tag: Option<DataContext>,
+    time: Option<i64>,
+    id: Option<&'i str>,
+    pid: Option<u32>,
+    stream: Option<u32>,
+    trace_id: Option<&'i str>,
+    meta: Cow<'i, Meta>,
+}
+
+impl<'i> HeaderBuilder<'i> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn map<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut Self),
+    {
+        f(&mut self);
+        self
+    }
+
+    pub fn and<F>(&mut self, f: F) -> &mut Self
+    where
+        F: FnOnce(&mut Self),
+    {
+        f(self);
+        self
+    }
+
+    pub fn version(&mut self, version: u32) {
+        self.version.replace(version);
+    }
+
+    pub fn tag<T>(&mut self, tag: T)
+    where
+        T: Into<DataContext>,
+    {
+        self.tag.replace(tag.into());
+    }
+
+    pub fn time(&mut self, time: i64) {
+        self.time.replace(time);
+    }
+
+    pub fn id(&mut self, id: &'i str) {
+        self.id.replace(id);
+    }
+
+    pub fn pid(&mut self, pid: u32) {
+        self.pid.replace(pid);
+    }
+
+    /// The logical stream this record belongs to, when multiplexing several over one connection
+    pub fn stream(&mut self, stream: u32) {
+        self.stream.replace(stream);
+    }
+
+    pub fn trace_id(&mut self, trace_id: &'i str) {
+        self.trace_id.replace(trace_id);
+    }
+
+    /// Replaces any labels set so far with `meta`
+    pub fn labels(&mut self, meta: &'i Meta) {
+        self.meta = Cow::Borrowed(meta);
+    }
+
+    /// Adds a single label, on top of any set so far
+    pub fn meta<K, V>(&mut self, key: K, value: V)
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.meta.to_mut().insert(key.into(), value.into());
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.version.is_some()
+            && self.tag.is_some()
+            && self.time.is_some()
+            && self.id.is_some()
+            && self.pid.is_some()
+    }
+
+    /// Generates a `Record::Header`, erroring with every missing field if the builder is incomplete
+    pub fn build(self) -> Result<Record<'i, 'static>, BuildError> {
+        let Self {
+            version,
+            tag,
+            time,
+            id,
+            pid,
+            stream,
+            trace_id,
+            meta,
+        } = self;
+        check_required!("Header", version, tag, time, id, pid);
+
+        Ok(Record::Header(Header {
+            required: Common {
+                stream,
+                ..Common::new(version.unwrap())
+            },
+            time: time.unwrap(),
+            id: id.map(|id| id.into()).unwrap(),
+            pid: pid.unwrap(),
+            cxt: tag.unwrap(),
+            trace_id: trace_id.map(|id| id.into()),
+            meta,
+            unknown: Vec::new(),
+        }))
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct DataBuilder<'i, 'd> {
+    version: Option<u32>,
+    tag: Option<DataContext>,
+    time: Option<i64>,
+    id: Option<&'i str>,
+    pid: Option<u32>,
+    stream: Option<u32>,
+    data: Option<(&'d [u8], Encoding)>,
+    trace_id: Option<&'i str>,
+    meta: Cow<'i, Meta>,
+}
+
+impl<'i, 'd> DataBuilder<'i, 'd> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn map<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut Self),
+    {
+        f(&mut self);
+        self
+    }
+
+    pub fn and<F>(&mut self, f: F) -> &mut Self
+    where
+        F: FnOnce(&mut Self),
+    {
+        f(self);
+        self
+    }
+
+    pub fn version(&mut self, version: u32) {
+        self.version.replace(version);
+    }
+
+    pub fn tag<T>(&mut self, tag: T)
+    where
+        T: Into<DataContext>,
+    {
+        self.tag.replace(tag.into());
+    }
+
+    pub fn time(&mut self, time: i64) {
+        self.time.replace(time);
+    }
+
+    pub fn id(&mut self, id: &'i str) {
+        self.id.replace(id);
+    }
+
+    pub fn pid(&mut self, pid: u32) {
+        self.pid.replace(pid);
+    }
+
+    /// The logical stream this record belongs to, when multiplexing several over one connection
+    pub fn stream(&mut self, stream: u32) {
+        self.stream.replace(stream);
+    }
+
+    pub fn data(&mut self, data: &'d str) {
+        self.data.replace((data.as_bytes(), Encoding::Utf8));
+    }
+
+    /// Sets a raw byte payload, which is only sent losslessly by records of at least
+    /// `BINARY_DATA_VERSION`. Older records replace any invalid UTF-8 in it
+    pub fn bytes(&mut self, bytes: &'d [u8]) {
+        self.data.replace((bytes, Encoding::detect(bytes)));
+    }
+
+    pub fn trace_id(&mut self, trace_id: &'i str) {
+        self.trace_id.replace(trace_id);
+    }
+
+    /// Replaces any labels set so far with `meta`
+    pub fn labels(&mut self, meta: &'i Meta) {
+        self.meta = Cow::Borrowed(meta);
+    }
+
+    /// Adds a single label, on top of any set so far
+    pub fn meta<K, V>(&mut self, key: K, value: V)
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.meta.to_mut().insert(key.into(), value.into());
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.version.is_some()
+            && self.tag.is_some()
+            && self.time.is_some()
+            && self.id.is_some()
+            && self.pid.is_some()
+            && self.data.is_some()
+    }
+
+    /// Generates a `Record::Data`, erroring with every missing field if the builder is incomplete
+    pub fn build(self) -> Result<Record<'i, 'd>, BuildError> {
+        let Self {
+            version,
+            tag,
+            time,
+            id,
+            pid,
+            stream,
+            data,
+            trace_id,
+            meta,
+        } = self;
+        check_required!("Data", version, tag, time, id, pid, data);
+
+        let version = version.unwrap();
+        let (data, encoding) = match (data.unwrap(), version < BINARY_DATA_VERSION) {
+            ((bytes, Encoding::Binary), true) => (
+                Cow::Owned(String::from_utf8_lossy(bytes).into_owned().into_bytes()),
+                Encoding::Utf8,
+            ),
+            ((bytes, encoding), _) => (Cow::Borrowed(bytes), encoding),
+        };
+
+        Ok(Record::Data(Data {
+            required: Common {
+                stream,
+                ..Common::new(version)
+            },
+            time: time.unwrap(),
+            id: id.map(|id| id.into()).unwrap(),
+            pid: pid.unwrap(),
+            cxt: tag.unwrap(),
+            data,
+            encoding,
+            trace_id: trace_id.map(|id| id.into()),
+            meta,
+            unknown: Vec::new(),
+        }))
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ExitBuilder<'i> {
+    version: Option<u32>,
+    time: Option<i64>,
+    id: Option<&'i str>,
+    pid: Option<u32>,
+    stream: Option<u32>,
+    status: Option<(Option<i32>, Option<i32>)>,
+}
+
+impl<'i> ExitBuilder<'i> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn map<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut Self),
+    {
+        f(&mut self);
+        self
+    }
+
+    pub fn and<F>(&mut self, f: F) -> &mut Self
+    where
+        F: FnOnce(&mut Self),
+    {
+        f(self);
+        self
+    }
+
+    pub fn version(&mut self, version: u32) {
+        self.version.replace(version);
+    }
+
+    pub fn time(&mut self, time: i64) {
+        self.time.replace(time);
+    }
+
+    pub fn id(&mut self, id: &'i str) {
+        self.id.replace(id);
+    }
+
+    pub fn pid(&mut self, pid: u32) {
+        self.pid.replace(pid);
+    }
+
+    /// The logical stream this record belongs to, when multiplexing several over one connection
+    pub fn stream(&mut self, stream: u32) {
+        self.stream.replace(stream);
+    }
+
+    /// The process' exit code, or the signal that terminated it
+    pub fn status(&mut self, code: Option<i32>, signal: Option<i32>) {
+        self.status.replace((code, signal));
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.version.is_some()
+            && self.time.is_some()
+            && self.id.is_some()
+            && self.pid.is_some()
+            && self.status.is_some()
+    }
+
+    /// Generates a `Record::Exit`, erroring with every missing field if the builder is incomplete
+    pub fn build(self) -> Result<Record<'i, 'static>, BuildError> {
+        let Self {
+            version,
+            time,
+            id,
+            pid,
+            stream,
+            status,
+        } = self;
+        check_required!("Exit", version, time, id, pid, status);
+        let (code, signal) = status.unwrap();
+
+        Ok(Record::Exit(Exit {
+            required: Common {
+                stream,
+                ..Common::new(version.unwrap())
+            },
+            time: time.unwrap(),
+            id: id.map(|id| id.into()).unwrap(),
+            pid: pid.unwrap(),
+            code,
+            signal,
+        }))
+    }
+}
@@ -0,0 +1,84 @@
+use {
+    crate::error::{CrateError, Kind},
+    bytes::{Buf, BufMut, BytesMut},
+    crc32fast::Hasher,
+    std::io,
+};
+
+/// Size in bytes of a CRC32 trailer
+const CRC32_LEN: usize = 4;
+
+/// Integrity check appended to the end of every serialized record.
+/// Both ends of a connection must agree on the checksum in use, as
+/// there is no way to detect the presence of a trailer from the frame alone
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Checksum {
+    #[default]
+    None,
+    Crc32,
+}
+
+impl Checksum {
+    /// Name of the handshake feature advertising CRC32 checksums
+    pub const FEATURE_CRC32: &'static str = "crc32";
+
+    /// The handshake feature this checksum corresponds to, if any
+    pub fn feature(self) -> Option<&'static str> {
+        match self {
+            Self::None => None,
+            Self::Crc32 => Some(Self::FEATURE_CRC32),
+        }
+    }
+
+    /// Appends this checksum's trailer to the serialized record occupying `buffer[start..]`
+    pub(crate) fn seal(self, buffer: &mut BytesMut, start: usize) {
+        match self {
+            Self::None => {}
+            Self::Crc32 => {
+                let crc = crc32(&buffer[start..]);
+                buffer.put_u32(crc);
+            }
+        }
+    }
+
+    /// Verifies and strips this checksum's trailer from the given frame,
+    /// erroring if the frame is too short or the checksum does not match
+    pub(crate) fn open(self, frame: &mut BytesMut) -> Result<(), io::Error> {
+        match self {
+            Self::None => Ok(()),
+            Self::Crc32 => {
+                if frame.len() < CRC32_LEN {
+                    return Err(mismatch(format!(
+                        "frame of {} bytes is too short to contain a checksum",
+                        frame.len()
+                    )));
+                }
+                let mut trailer = frame.split_off(frame.len() - CRC32_LEN);
+                let expected = trailer.get_u32();
+                let actual = crc32(frame);
+
+                if expected == actual {
+                    Ok(())
+                } else {
+                    Err(mismatch(format!(
+                        "expected crc32 {:#010x}, calculated {:#010x}",
+                        expected, actual
+                    )))
+                }
+            }
+        }
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut hasher = Hasher::new();
+    hasher.update(data);
+    hasher.finalize()
+}
+
+fn mismatch(msg: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        CrateError::now(Kind::Checksum, msg),
+    )
+}
@@ -0,0 +1,242 @@
+use {
+    crate::{
+        checksum::Checksum, compression::Compression, encryption::Encryption, limits::Limits,
+        record::Record,
+    },
+    bytes::{buf::BufMutExt, Buf, BufMut, Bytes, BytesMut},
+    serde::Serialize,
+    std::io,
+};
+
+/// Size in bytes of the big endian length prefixing every frame
+pub(crate) const LENGTH_LEN: usize = 4;
+
+/// Default size of the buffer a `RecordSerializer` carves serialized records from
+pub const DEFAULT_BUFFER_SIZE: usize = 8 * 1024;
+
+/// Frames and serializes `record` onto the end of `dst`, exactly as `RecordInterface` would
+/// send it, without a checksum. See `RecordCodec` for the checksum aware equivalent
+pub fn encode_record<T>(record: &T, dst: &mut BytesMut) -> io::Result<()>
+where
+    T: Serialize,
+{
+    RecordCodec::default().encode(record, dst)
+}
+
+/// Removes and deserializes the first complete frame in `src`, returning None if `src` does
+/// not yet hold an entire frame. Expects frames without a checksum, see `RecordCodec` for
+/// the checksum aware equivalent
+pub fn decode_record(src: &mut BytesMut) -> io::Result<Option<Record<'static, 'static>>> {
+    RecordCodec::default().decode(src)
+}
+
+/// The protocol's framing and serialization, without any IO. Useful for embedding the
+/// protocol in other runtimes or event loops: read bytes into a buffer, and call `decode`
+/// until it returns None. Frames are delimited exactly as `RecordFrame` delimits them
+#[derive(Debug, Default, Clone)]
+pub struct RecordCodec {
+    checksum: Checksum,
+    compression: Compression,
+    encryption: Encryption,
+    limits: Limits,
+    canonical: bool,
+}
+
+impl RecordCodec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the checksum appended to every record encoded,
+    /// and verified on every record decoded. Defaults to `Checksum::None`
+    pub fn with_checksum(mut self, checksum: Checksum) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    /// Sets the compression applied to every record encoded,
+    /// and removed from every record decoded. Defaults to `Compression::None`
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets the encryption applied to every record encoded,
+    /// and removed from every record decoded. Defaults to `Encryption::None`
+    pub fn with_encryption(mut self, encryption: Encryption) -> Self {
+        self.encryption = encryption;
+        self
+    }
+
+    /// Sets the limits enforced on every record decoded, and on the size of every
+    /// record encoded. Defaults to `Limits::default()`
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets whether every record is encoded canonically, see
+    /// `RecordSerializer::with_canonical`. Defaults to false
+    pub fn with_canonical(mut self, canonical: bool) -> Self {
+        self.canonical = canonical;
+        self
+    }
+
+    /// Frames and serializes `record` onto the end of `dst`
+    pub fn encode<T>(&self, record: &T, dst: &mut BytesMut) -> io::Result<()>
+    where
+        T: Serialize,
+    {
+        let start = dst.len();
+        dst.put_u32(0);
+
+        let length = serialize_into(dst, record, self.canonical)
+            .and_then(|_| self.compression.seal(dst, start + LENGTH_LEN))
+            .map(|_| self.checksum.seal(dst, start + LENGTH_LEN))
+            .and_then(|_| self.encryption.seal(dst, start + LENGTH_LEN))
+            .and_then(|_| {
+                let length = dst.len() - start - LENGTH_LEN;
+                self.limits.check_frame_len(length).map(|_| length)
+            })
+            .inspect_err(|_| dst.truncate(start))?;
+        dst[start..start + LENGTH_LEN].copy_from_slice(&(length as u32).to_be_bytes());
+
+        Ok(())
+    }
+
+    /// Removes and deserializes the first complete frame in `src`, returning None if
+    /// `src` does not yet hold an entire frame. Nothing is removed from `src` unless
+    /// a whole frame is available, even if that frame then fails to deserialize
+    pub fn decode(&self, src: &mut BytesMut) -> io::Result<Option<Record<'static, 'static>>> {
+        if src.len() < LENGTH_LEN {
+            return Ok(None);
+        }
+
+        let length = frame_length(&src[..LENGTH_LEN], &self.limits)?;
+        if src.len() < LENGTH_LEN + length {
+            src.reserve(LENGTH_LEN + length - src.len());
+            return Ok(None);
+        }
+
+        src.advance(LENGTH_LEN);
+        let mut frame = src.split_to(length);
+        self.encryption.open(&mut frame)?;
+        self.checksum.open(&mut frame)?;
+        self.compression.open(&mut frame, &self.limits)?;
+        self.limits.check_depth(&frame)?;
+
+        Record::from_frame(&frame).map(|record| Some(record.into_owned()))
+    }
+}
+
+/// Parses a frame's length prefix, erroring if it exceeds the maximum frame size
+pub(crate) fn frame_length(prefix: &[u8], limits: &Limits) -> io::Result<usize> {
+    let mut length = [0u8; LENGTH_LEN];
+    length.copy_from_slice(&prefix[..LENGTH_LEN]);
+    let length = u32::from_be_bytes(length) as usize;
+
+    limits.check_frame_len(length).map(|_| length)
+}
+
+/// Serializes records into a reusable buffer. Each record is split off the front of the
+/// buffer, and once the buffer runs low it is reclaimed if every record carved from it has
+/// been dropped, so a steady stream of records only allocates while records are held up
+/// downstream. Each allocation is `buffer_size` bytes, amortized over every record it holds
+#[derive(Debug)]
+pub struct RecordSerializer {
+    checksum: Checksum,
+    compression: Compression,
+    encryption: Encryption,
+    scratch: BytesMut,
+    buffer_size: usize,
+    canonical: bool,
+}
+
+impl RecordSerializer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the size of the buffer records are serialized into. Larger buffers allocate less
+    /// often when records are held downstream, at the cost of holding more memory while any
+    /// record carved from them is still alive. Defaults to `DEFAULT_BUFFER_SIZE`
+    pub fn with_buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    /// Sets the checksum appended to every record serialized. Defaults to `Checksum::None`
+    pub fn with_checksum(mut self, checksum: Checksum) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    /// Sets the compression applied to every record serialized. Defaults to `Compression::None`
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets the encryption applied to every record serialized. Defaults to `Encryption::None`
+    pub fn with_encryption(mut self, encryption: Encryption) -> Self {
+        self.encryption = encryption;
+        self
+    }
+
+    /// Sets whether every record is serialized canonically: each map's keys sorted in the
+    /// canonical order of RFC 7049, with definite lengths. Logically identical records then
+    /// always serialize to identical bytes (i.e for signing or deduplication), regardless of
+    /// the iteration order of their `Meta`. Unknown fields are sorted by tag along with every
+    /// other field. Slower, as each record is first built up as a `Value`. Defaults to false
+    pub fn with_canonical(mut self, canonical: bool) -> Self {
+        self.canonical = canonical;
+        self
+    }
+
+    /// Serializes a single record, without framing
+    pub fn serialize<T>(&mut self, record: &T) -> io::Result<Bytes>
+    where
+        T: Serialize,
+    {
+        // Reserving either reclaims the existing buffer, or abandons it to whatever records
+        // are still using it and allocates a new one, which is why this asks for the whole
+        // buffer rather than just enough for this record
+        if self.scratch.capacity() < self.buffer_size / 2 {
+            self.scratch.reserve(self.buffer_size);
+        }
+
+        serialize_into(&mut self.scratch, record, self.canonical)
+            .and_then(|_| self.compression.seal(&mut self.scratch, 0))
+            .map(|_| self.checksum.seal(&mut self.scratch, 0))
+            .and_then(|_| self.encryption.seal(&mut self.scratch, 0))
+            .map(|_| self.scratch.split().freeze())
+            .inspect_err(|_| self.scratch.clear())
+    }
+}
+
+impl Default for RecordSerializer {
+    fn default() -> Self {
+        Self {
+            checksum: Checksum::default(),
+            compression: Compression::default(),
+            encryption: Encryption::default(),
+            scratch: BytesMut::new(),
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            canonical: false,
+        }
+    }
+}
+
+/// Appends the CBOR serialization of `record` to `dst`. A `Value`'s maps are always in
+/// canonical order, so a canonical serialization goes via one
+fn serialize_into<T>(dst: &mut BytesMut, record: &T, canonical: bool) -> io::Result<()>
+where
+    T: Serialize,
+{
+    match canonical {
+        true => serde_cbor::value::to_value(record)
+            .and_then(|value| serde_cbor::to_writer(dst.writer(), &value)),
+        false => serde_cbor::to_writer(dst.writer(), record),
+    }
+    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
@@ -0,0 +1,112 @@
+use {
+    crate::{
+        error::{CrateError, Kind},
+        handshake::Negotiated,
+        limits::Limits,
+    },
+    bytes::BytesMut,
+    std::io,
+};
+
+#[cfg(feature = "zstd")]
+use std::io::Read;
+
+/// Compression level used when none is given, zstd's own default
+pub const DEFAULT_ZSTD_LEVEL: i32 = 3;
+
+/// Compression applied to every serialized record, before any checksum is appended or
+/// encryption applied. Worth enabling on slow links, as records are compressed one at a
+/// time the savings are greatest when each carries plenty of data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    /// zstd at the given level, between 1 and 22. The level only affects records
+    /// compressed, any level decompresses records compressed at any other
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+}
+
+impl Compression {
+    /// Name of the handshake feature advertising zstd compression
+    pub const FEATURE_ZSTD: &'static str = "zstd";
+
+    /// The handshake feature this compression corresponds to, if any
+    pub fn feature(self) -> Option<&'static str> {
+        match self {
+            Self::None => None,
+            #[cfg(feature = "zstd")]
+            Self::Zstd(_) => Some(Self::FEATURE_ZSTD),
+        }
+    }
+
+    /// What a listener reads a client's records with, once `negotiated` with it. The level only
+    /// matters when compressing, records compressed at any level are decompressed alike
+    pub fn negotiated(negotiated: &Negotiated) -> Self {
+        match negotiated.has_feature(Self::FEATURE_ZSTD) {
+            #[cfg(feature = "zstd")]
+            true => Self::Zstd(DEFAULT_ZSTD_LEVEL),
+            _ => Self::None,
+        }
+    }
+
+    /// Compresses the serialized record occupying `buffer[start..]` in place
+    #[cfg_attr(not(feature = "zstd"), allow(unused_variables))]
+    pub(crate) fn seal(self, buffer: &mut BytesMut, start: usize) -> io::Result<()> {
+        match self {
+            Self::None => Ok(()),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(level) => {
+                let compressed = zstd::block::compress(&buffer[start..], level).map_err(|e| {
+                    failed(Kind::Generic, format!("unable to compress record: {}", e))
+                })?;
+                buffer.truncate(start);
+                buffer.extend_from_slice(&compressed);
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Decompresses the given frame in place. Errors if the frame is not a compressed record,
+    /// or would decompress to more than the largest frame `limits` accepts
+    #[cfg_attr(not(feature = "zstd"), allow(unused_variables))]
+    pub(crate) fn open(self, frame: &mut BytesMut, limits: &Limits) -> io::Result<()> {
+        match self {
+            Self::None => Ok(()),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(_) => {
+                let max = limits.max_frame_len();
+                let mut record = Vec::with_capacity(frame.len() * 4);
+                zstd::Decoder::with_buffer(&frame[..])
+                    .and_then(|decoder| decoder.take(max as u64 + 1).read_to_end(&mut record))
+                    .map_err(|e| {
+                        failed(Kind::Decode, format!("unable to decompress record: {}", e))
+                    })?;
+
+                // Reading stops a byte past the limit, so a frame can't inflate without bound
+                if record.len() > max {
+                    return Err(failed(
+                        Kind::Limit,
+                        format!(
+                            "frame decompresses to more than the maximum of {} bytes",
+                            max
+                        ),
+                    ));
+                }
+                frame.clear();
+                frame.extend_from_slice(&record);
+
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "zstd"), allow(dead_code))]
+fn failed<S>(kind: Kind, msg: S) -> io::Error
+where
+    S: Into<String>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, CrateError::now(kind, msg))
+}
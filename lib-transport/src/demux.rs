@@ -0,0 +1,154 @@
+use {
+    crate::owned::{HeaderContext, OwnedRecord},
+    futures::{channel::mpsc, prelude::*, ready},
+    pin_project::pin_project,
+    std::{
+        collections::HashMap,
+        pin::Pin,
+        task::{Context, Poll},
+    },
+};
+
+/// Number of records buffered for each id before the demultiplexer waits on its consumer
+const ID_BUFFER: usize = 256;
+
+/// An id is only unique within the logical stream it was opened on
+type IdKey = (Option<u32>, String);
+
+/// Extension trait for streams of owned records
+pub trait DemuxExt: Stream<Item = OwnedRecord> + Sized {
+    /// Splits this stream into a sub-stream per id, each yielding its `Header` start, every
+    /// `Data` record with that id, and finally its `Header` end before completing. Records that
+    /// don't belong to any id are passed through as they arrive.
+    ///
+    /// Sub-streams are fed from this stream, so each must be polled concurrently with it
+    /// (i.e in its own task), otherwise a full sub-stream will stall every other id
+    fn demux_by_id(self) -> DemuxById<Self>;
+}
+
+impl<St> DemuxExt for St
+where
+    St: Stream<Item = OwnedRecord>,
+{
+    fn demux_by_id(self) -> DemuxById<Self> {
+        DemuxById {
+            inner: self,
+            open: HashMap::new(),
+            pending: None,
+        }
+    }
+}
+
+/// An item yielded by `DemuxById`
+#[derive(Debug)]
+pub enum Demuxed {
+    /// A `Header` start opened a new id, the rest of its records arrive on this sub-stream
+    Id(IdStream),
+    /// A record that doesn't belong to any id, i.e a `Channel` record
+    Record(OwnedRecord),
+    /// A `Header` or `Data` record that doesn't fit the state of its id, i.e a duplicate start
+    /// or `Data` for an id that was never opened
+    Unmatched(OwnedRecord),
+}
+
+#[pin_project]
+pub struct DemuxById<St> {
+    #[pin]
+    inner: St,
+    open: HashMap<IdKey, mpsc::Sender<OwnedRecord>>,
+    pending: Option<(IdKey, OwnedRecord)>,
+}
+
+impl<St> Stream for DemuxById<St>
+where
+    St: Stream<Item = OwnedRecord>,
+{
+    type Item = Demuxed;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            // Finish handing off the last record before reading another
+            if let Some((key, record)) = this.pending.take() {
+                // Only a Header end is ever pending, as a start opens a new id
+                let end = matches!(record, OwnedRecord::Header(_));
+                if let Some(tx) = this.open.get_mut(&key) {
+                    match tx.poll_ready(cx) {
+                        Poll::Pending => {
+                            *this.pending = Some((key, record));
+                            return Poll::Pending;
+                        }
+                        // A dropped sub-stream has no further interest in its records
+                        Poll::Ready(Err(_)) => (),
+                        Poll::Ready(Ok(())) => {
+                            let _ = tx.start_send(record);
+                        }
+                    }
+                }
+                // Dropping its sender completes the id's sub-stream
+                if end {
+                    this.open.remove(&key);
+                }
+            }
+
+            let record = match ready!(this.inner.as_mut().poll_next(cx)) {
+                Some(record) => record,
+                None => {
+                    // Complete any ids that will now never see their end
+                    this.open.clear();
+                    return Poll::Ready(None);
+                }
+            };
+
+            let (key, start) = match record {
+                OwnedRecord::Header(ref header) => (
+                    (header.required.stream, header.id.clone()),
+                    header.cxt == HeaderContext::Start,
+                ),
+                OwnedRecord::Data(ref data) => ((data.required.stream, data.id.clone()), false),
+                record => return Poll::Ready(Some(Demuxed::Record(record))),
+            };
+
+            match (start, this.open.contains_key(&key)) {
+                (true, false) => {
+                    let (mut tx, rx) = mpsc::channel(ID_BUFFER);
+                    // A new channel always has room for its first record
+                    let _ = tx.try_send(record);
+                    this.open.insert(key.clone(), tx);
+
+                    return Poll::Ready(Some(Demuxed::Id(IdStream { key, rx })));
+                }
+                (false, true) => *this.pending = Some((key, record)),
+                _ => return Poll::Ready(Some(Demuxed::Unmatched(record))),
+            }
+        }
+    }
+}
+
+/// The records of a single id, completing after its `Header` end
+#[derive(Debug)]
+pub struct IdStream {
+    key: IdKey,
+    rx: mpsc::Receiver<OwnedRecord>,
+}
+
+impl IdStream {
+    /// The id shared by every record in this stream
+    pub fn id(&self) -> &str {
+        &self.key.1
+    }
+
+    /// The logical stream this id was opened on, if the connection is multiplexed
+    pub fn stream(&self) -> Option<u32> {
+        self.key.0
+    }
+}
+
+impl Stream for IdStream {
+    type Item = OwnedRecord;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_next_unpin(cx)
+    }
+}
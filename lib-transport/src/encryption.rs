@@ -0,0 +1,169 @@
+use {
+    crate::error::{CrateError, Kind},
+    bytes::{BufMut, BytesMut},
+    chacha20poly1305::{
+        aead::{AeadInPlace, NewAead},
+        XChaCha20Poly1305, XNonce,
+    },
+    std::{fmt, fs, io, path::Path},
+};
+
+/// Size in bytes of a symmetric key
+pub const KEY_LEN: usize = 32;
+
+/// Size in bytes of an XChaCha20-Poly1305 authentication tag
+const TAG_LEN: usize = 16;
+
+/// Size in bytes of an XChaCha20-Poly1305 nonce
+const NONCE_LEN: usize = 24;
+
+/// A symmetric key, shared out of band by both ends of a connection
+#[derive(Clone, PartialEq, Eq)]
+pub struct Key([u8; KEY_LEN]);
+
+impl Key {
+    pub fn from_bytes(bytes: [u8; KEY_LEN]) -> Self {
+        Self(bytes)
+    }
+
+    /// Parses a key from its hex encoding, returning None
+    /// unless `hex` is exactly `KEY_LEN * 2` hex digits
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        if hex.len() != KEY_LEN * 2 || !hex.is_ascii() {
+            return None;
+        }
+
+        let mut key = [0u8; KEY_LEN];
+        for (byte, pair) in key.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            *byte = std::str::from_utf8(pair)
+                .ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())?;
+        }
+
+        Some(Self(key))
+    }
+
+    /// Reads a key from the hex encoding in the file at `path`, ignoring any surrounding
+    /// whitespace. Errors if the file can't be read, or doesn't hold a key
+    pub fn from_file<P>(path: P) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let hex = fs::read_to_string(path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("unable to read key file '{}': {}", path.display(), e),
+            )
+        })?;
+
+        Self::from_hex(hex.trim()).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "'{}' does not contain a key, expected {} hex digits",
+                    path.display(),
+                    KEY_LEN * 2
+                ),
+            )
+        })
+    }
+
+    /// Generates a new random key
+    pub fn generate() -> io::Result<Self> {
+        let mut key = [0u8; KEY_LEN];
+        getrandom::getrandom(&mut key)?;
+
+        Ok(Self(key))
+    }
+
+    /// The key's hex encoding, as accepted by `Key::from_hex`
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
+
+// Keep key material out of any logs
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Key(..)")
+    }
+}
+
+/// Encryption applied to every serialized record, after any checksum has been appended.
+/// Keys are symmetric and shared out of band, so both ends of a connection must be
+/// configured with the same key. Unlike TLS this protects record payloads only, the
+/// handshake and the length of every frame are still visible on the wire
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Encryption {
+    #[default]
+    None,
+    XChaCha20Poly1305(Key),
+}
+
+impl Encryption {
+    /// Name of the handshake feature advertising XChaCha20-Poly1305 encryption
+    pub const FEATURE_XCHACHA20POLY1305: &'static str = "xchacha20poly1305";
+
+    /// The handshake feature this encryption corresponds to, if any
+    pub fn feature(&self) -> Option<&'static str> {
+        match self {
+            Self::None => None,
+            Self::XChaCha20Poly1305(_) => Some(Self::FEATURE_XCHACHA20POLY1305),
+        }
+    }
+
+    /// Encrypts the serialized record occupying `buffer[start..]` in place,
+    /// appending the authentication tag and a random nonce
+    pub(crate) fn seal(&self, buffer: &mut BytesMut, start: usize) -> io::Result<()> {
+        match self {
+            Self::None => Ok(()),
+            Self::XChaCha20Poly1305(key) => {
+                let mut nonce = XNonce::default();
+                getrandom::getrandom(&mut nonce)?;
+
+                let tag = XChaCha20Poly1305::new(&key.0.into())
+                    .encrypt_in_place_detached(&nonce, &[], &mut buffer[start..])
+                    .map_err(|_| failed("unable to encrypt record"))?;
+                buffer.put_slice(&tag);
+                buffer.put_slice(&nonce);
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Verifies and decrypts the given frame in place, stripping its tag and nonce.
+    /// Errors if the frame is too short, or was not encrypted with this key
+    pub(crate) fn open(&self, frame: &mut BytesMut) -> io::Result<()> {
+        match self {
+            Self::None => Ok(()),
+            Self::XChaCha20Poly1305(key) => {
+                if frame.len() < TAG_LEN + NONCE_LEN {
+                    return Err(failed(format!(
+                        "frame of {} bytes is too short to be encrypted",
+                        frame.len()
+                    )));
+                }
+                let trailer = frame.split_off(frame.len() - TAG_LEN - NONCE_LEN);
+                let (mut tag, mut nonce) = ([0u8; TAG_LEN], [0u8; NONCE_LEN]);
+                tag.copy_from_slice(&trailer[..TAG_LEN]);
+                nonce.copy_from_slice(&trailer[TAG_LEN..]);
+
+                XChaCha20Poly1305::new(&key.0.into())
+                    .decrypt_in_place_detached(&nonce.into(), &[], frame, &tag.into())
+                    .map_err(|_| failed("unable to decrypt record, the keys may not match"))
+            }
+        }
+    }
+}
+
+fn failed<S>(msg: S) -> io::Error
+where
+    S: Into<String>,
+{
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        CrateError::now(Kind::Encryption, msg),
+    )
+}
@@ -1,20 +1,24 @@
 use std::fmt::Display;
 use {
     serde::{Deserialize, Serialize},
-    std::{error, fmt},
+    std::{error, fmt, io},
 };
 
 /// Simple error struct that contains an approximate time
-/// at which the error occurred, an error kind, and the
-/// textual message of the original error.
-#[derive(Debug, Serialize, Deserialize)]
+/// at which the error occurred, an error kind, the
+/// textual message of the original error and the messages
+/// of whatever caused it, outermost first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CrateError {
     time: i64,
     kind: Kind,
     msg: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    causes: Vec<String>,
 }
 
 impl CrateError {
+    /// Records `msg`'s chain of sources as its causes
     pub fn new<E>(time: i64, kind: Option<Kind>, msg: E) -> Self
     where
         E: error::Error,
@@ -23,16 +27,67 @@ impl CrateError {
             time,
             kind: kind.unwrap_or_default(),
             msg: msg.to_string(),
+            causes: source_chain(&msg),
         }
     }
 
+    /// Generates an error timestamped with the current system time
+    /// from `error`, recording its chain of sources as its causes
+    pub fn from_error<E>(kind: Kind, error: &E) -> Self
+    where
+        E: error::Error + ?Sized,
+    {
+        Self {
+            time: now_nanos(),
+            kind,
+            msg: error.to_string(),
+            causes: source_chain(error),
+        }
+    }
+
+    /// Generates an error timestamped with the current system time
+    pub(crate) fn now<S>(kind: Kind, msg: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            time: now_nanos(),
+            kind,
+            msg: msg.into(),
+            causes: Vec::new(),
+        }
+    }
+
+    /// Appends a cause, below any recorded so far
+    pub fn with_cause<S>(mut self, cause: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.causes.push(cause.into());
+        self
+    }
+
     pub fn kind(&self) -> Kind {
         self.kind
     }
 
+    pub fn message(&self) -> &str {
+        &self.msg
+    }
+
+    /// Messages of whatever caused this error, outermost first
+    pub fn causes(&self) -> &[String] {
+        &self.causes
+    }
+
     pub fn timestamp_nanos(&self) -> i64 {
         self.time
     }
+
+    /// Retrieves the error wrapped by an `io::Error` returned from this crate, if any
+    pub fn from_io(error: &io::Error) -> Option<&Self> {
+        error.get_ref().and_then(|inner| inner.downcast_ref())
+    }
 }
 
 impl Display for CrateError {
@@ -41,32 +96,110 @@ impl Display for CrateError {
             f,
             "{} error as occurred at nano-second epoch {} with the message: {}",
             self.kind, self.time, self.msg
-        )
+        )?;
+        for cause in self.causes.iter() {
+            write!(f, ", caused by: {}", cause)?;
+        }
+
+        Ok(())
     }
 }
 
 impl error::Error for CrateError {}
 
+/// Categorizes an `io::Error`, unwrapping it if it was returned by this crate
+impl From<io::Error> for CrateError {
+    fn from(error: io::Error) -> Self {
+        if let Some(inner) = Self::from_io(&error) {
+            return inner.clone();
+        }
+
+        let kind = match error.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => Kind::Timeout,
+            io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => Kind::Decode,
+            _ => Kind::Io,
+        };
+
+        Self::from_error(kind, &error)
+    }
+}
+
+/// The messages of every error in `error`'s chain of sources. Errors often include their
+/// source's message in their own, in which case it isn't repeated
+fn source_chain<E>(error: &E) -> Vec<String>
+where
+    E: error::Error + ?Sized,
+{
+    let mut last = error.to_string();
+    let mut causes = Vec::new();
+    for cause in std::iter::successors(error.source(), |cause| cause.source()) {
+        let msg = cause.to_string();
+        if !last.contains(&msg) {
+            causes.push(msg.clone());
+        }
+        last = msg;
+    }
+
+    causes
+}
+
+/// Current system time as nanoseconds since the unix epoch
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub(crate) fn now_nanos() -> i64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as i64)
+        .unwrap_or_default()
+}
+
+/// wasm32-unknown-unknown has no clock (`SystemTime::now` panics there), so anything
+/// generated with the current time is stamped with the epoch instead. Callers should set
+/// the time themselves, i.e from their host
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+pub(crate) fn now_nanos() -> i64 {
+    0
+}
+
 /// Catagories of error
 // Expand when needed
 // TODO: make #[non-exhaustive] once rust > 1.40
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
 pub enum Kind {
+    #[default]
     Generic,
+    Checksum,
+    Handshake,
+    Limit,
+    Encryption,
+    /// Reading or writing failed
+    Io,
+    /// A child process could not be started
+    Spawn,
+    /// Something took too long, i.e a peer stopped responding
+    Timeout,
+    /// Received data could not be deserialized or was otherwise invalid
+    Decode,
+    /// A program was misconfigured
+    Config,
 }
 
 impl Display for Kind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let s = match self {
             Self::Generic => "Generic",
+            Self::Checksum => "Checksum",
+            Self::Handshake => "Handshake",
+            Self::Limit => "Limit",
+            Self::Encryption => "Encryption",
+            Self::Io => "IO",
+            Self::Spawn => "Spawn",
+            Self::Timeout => "Timeout",
+            Self::Decode => "Decode",
+            Self::Config => "Config",
         };
 
         write!(f, "{}", s)
     }
 }
-
-impl Default for Kind {
-    fn default() -> Self {
-        Self::Generic
-    }
-}
@@ -0,0 +1,204 @@
+use {
+    crate::{
+        codec::{frame_length, DEFAULT_BUFFER_SIZE, LENGTH_LEN},
+        interface::RecordInterface,
+        limits::Limits,
+    },
+    bytes::{Buf, BufMut, Bytes, BytesMut},
+    futures::{
+        io::{AsyncRead, AsyncWrite},
+        prelude::*,
+        ready,
+    },
+    pin_project::pin_project,
+    std::{
+        io,
+        pin::Pin,
+        task::{Context, Poll},
+    },
+};
+
+/// Amount read from the underlying io at a time
+const READ_CHUNK: usize = 8 * 1024;
+
+/// Length delimited framing over the `futures::io` traits, for runtimes other than tokio
+/// (i.e async-std). Frames are delimited exactly as `RecordFrame` delimits them, so either
+/// end of a connection can use either runtime. Implements `TryStream<Ok = BytesMut>` if the
+/// underlying io is `AsyncRead`, and `Sink<Bytes>` if it is `AsyncWrite`
+#[pin_project]
+#[derive(Debug)]
+pub struct FuturesFrame<T> {
+    #[pin]
+    io: T,
+    limits: Limits,
+    read_buf: BytesMut,
+    write_buf: BytesMut,
+    eof: bool,
+}
+
+impl<T> FuturesFrame<T> {
+    pub fn new(io: T) -> Self {
+        Self::with_limits(io, Limits::default())
+    }
+
+    /// As `FuturesFrame::new`, rejecting any frame larger than `limits` allow before it is
+    /// buffered
+    pub fn with_limits(io: T, limits: Limits) -> Self {
+        Self {
+            io,
+            limits,
+            read_buf: BytesMut::new(),
+            write_buf: BytesMut::new(),
+            eof: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.io
+    }
+
+    pub fn into_inner(self) -> T {
+        self.io
+    }
+}
+
+impl<T> Stream for FuturesFrame<T>
+where
+    T: AsyncRead,
+{
+    type Item = io::Result<BytesMut>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            if this.read_buf.len() >= LENGTH_LEN {
+                let length = frame_length(&this.read_buf[..LENGTH_LEN], this.limits)?;
+                if this.read_buf.len() >= LENGTH_LEN + length {
+                    this.read_buf.advance(LENGTH_LEN);
+                    return Poll::Ready(Some(Ok(this.read_buf.split_to(length))));
+                }
+            }
+
+            if *this.eof {
+                if this.read_buf.is_empty() {
+                    return Poll::Ready(None);
+                }
+                // Report the partial frame once, ending the stream on the next poll
+                this.read_buf.clear();
+                return Poll::Ready(Some(Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "connection closed part way through a frame",
+                ))));
+            }
+
+            let start = this.read_buf.len();
+            this.read_buf.resize(start + READ_CHUNK, 0);
+            let read = this.io.as_mut().poll_read(cx, &mut this.read_buf[start..]);
+            let read = match read {
+                Poll::Ready(Ok(n)) => n,
+                Poll::Ready(Err(e)) => {
+                    this.read_buf.truncate(start);
+                    return Poll::Ready(Some(Err(e)));
+                }
+                Poll::Pending => {
+                    this.read_buf.truncate(start);
+                    return Poll::Pending;
+                }
+            };
+            this.read_buf.truncate(start + read);
+            *this.eof = read == 0;
+        }
+    }
+}
+
+impl<T> Sink<Bytes> for FuturesFrame<T>
+where
+    T: AsyncWrite,
+{
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Only write out buffered frames once there are enough of them to be worth it
+        match self.write_buf.len() >= DEFAULT_BUFFER_SIZE {
+            true => self.poll_flush(cx),
+            false => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Bytes) -> Result<(), Self::Error> {
+        let this = self.project();
+        this.limits.check_frame_len(item.len())?;
+
+        this.write_buf.reserve(LENGTH_LEN + item.len());
+        this.write_buf.put_u32(item.len() as u32);
+        this.write_buf.extend_from_slice(&item);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let mut this = self.project();
+
+        while !this.write_buf.is_empty() {
+            match ready!(this.io.as_mut().poll_write(cx, this.write_buf))? {
+                0 => {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        "failed to write frame to the underlying io",
+                    )))
+                }
+                n => this.write_buf.advance(n),
+            }
+        }
+
+        this.io.poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        self.project().io.poll_close(cx)
+    }
+}
+
+impl<T> RecordInterface<FuturesFrame<T>>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    /// Generates an Interface that implements both `Sink<T: Serialize>` and `TryStream<Ok = Record>`
+    /// from a `futures::io` type, see `RecordInterface::from_both` for the tokio equivalent
+    pub fn from_futures_both(io: T) -> Self {
+        Self::from_futures_both_limited(io, Limits::default())
+    }
+
+    /// As `RecordInterface::from_futures_both`, rejecting any received record that breaches `limits`
+    pub fn from_futures_both_limited(io: T, limits: Limits) -> Self {
+        Self::new_both(FuturesFrame::with_limits(io, limits)).with_limits(limits)
+    }
+}
+
+impl<T> RecordInterface<FuturesFrame<T>>
+where
+    T: AsyncWrite,
+{
+    /// Generates a write only Interface that implements `Sink<T: Serialize>`
+    /// from a `futures::io` type, see `RecordInterface::from_write` for the tokio equivalent
+    pub fn from_futures_write(io: T) -> Self {
+        Self::new_sink(FuturesFrame::new(io))
+    }
+}
+
+impl<T> RecordInterface<FuturesFrame<T>>
+where
+    T: AsyncRead,
+{
+    /// Generates a read only Interface that implements `TryStream<Ok = Record>`
+    /// from a `futures::io` type, see `RecordInterface::from_read` for the tokio equivalent
+    pub fn from_futures_read(io: T) -> Self {
+        Self::from_futures_read_limited(io, Limits::default())
+    }
+
+    /// As `RecordInterface::from_futures_read`, rejecting any received record that breaches `limits`
+    pub fn from_futures_read_limited(io: T, limits: Limits) -> Self {
+        Self::new_stream(FuturesFrame::with_limits(io, limits)).with_limits(limits)
+    }
+}
@@ -0,0 +1,193 @@
+use {
+    crate::{
+        error::{CrateError, Kind},
+        record::{Hello, Record, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION},
+    },
+    futures::prelude::*,
+    std::{io, time::Duration},
+};
+
+/// Prefix of the handshake feature naming the pipeline a client's records should go through
+/// on the listener, i.e `pipeline=network`. Listeners that don't know of pipelines ignore it
+/// as they would any other feature, and it is never in their reply
+pub const PIPELINE_PREFIX: &str = "pipeline=";
+
+/// How long a client should wait for the listener's reply to its Hello, see `connect_within`
+pub const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The outcome of a successful handshake, i.e the protocol version and
+/// features both sides of the connection have agreed to use
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Negotiated {
+    pub proto_version: u32,
+    pub features: Vec<String>,
+    /// The pipeline the client asked for, only ever set on the listener's side
+    pub pipeline: Option<String>,
+    legacy: bool,
+}
+
+impl Negotiated {
+    /// The result of a handshake with a peer that predates `Record::Hello`
+    pub fn legacy() -> Self {
+        Self {
+            proto_version: 0,
+            features: Vec::new(),
+            pipeline: None,
+            legacy: true,
+        }
+    }
+
+    /// Whether the peer skipped the handshake entirely
+    pub fn is_legacy(&self) -> bool {
+        self.legacy
+    }
+
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+}
+
+/// Client half of the handshake. Sends `hello` and waits for the listener's
+/// reply, which contains the negotiated version and the subset of features
+/// the listener accepted. Errors if the listener rejects the connection or
+/// replies with a version outside of what this library supports
+pub async fn connect<S, E, SE>(io: &mut S, hello: Hello) -> Result<Negotiated, E>
+where
+    S: Stream<Item = Result<Record<'static, 'static>, SE>>,
+    S: Sink<Record<'static, 'static>, Error = E> + Unpin,
+    E: From<io::Error> + From<SE>,
+{
+    io.send(Record::Hello(hello)).await?;
+
+    match io.next().await {
+        Some(Ok(Record::Hello(reply))) => {
+            if reply.proto_version < MIN_PROTOCOL_VERSION || reply.proto_version > PROTOCOL_VERSION
+            {
+                Err(rejected(format!(
+                    "listener negotiated unsupported protocol version {}",
+                    reply.proto_version
+                )))
+            } else {
+                Ok(Negotiated {
+                    proto_version: reply.proto_version,
+                    features: reply.features,
+                    pipeline: None,
+                    legacy: false,
+                })
+            }
+        }
+        Some(Ok(Record::Error(e))) => Err(rejected(format!(
+            "listener rejected the connection: {}",
+            e.error
+        ))),
+        Some(Ok(_)) => Err(rejected("listener replied with an unexpected record")),
+        Some(Err(e)) => Err(e.into()),
+        None => Err(rejected(
+            "listener closed the connection during the handshake",
+        )),
+    }
+}
+
+/// As `connect`, but erroring if the handshake hasn't completed within `timeout`. A listener
+/// that predates the handshake never replies, so without one a client would wait forever
+#[cfg(feature = "tokio")]
+pub async fn connect_within<S, E, SE>(
+    io: &mut S,
+    hello: Hello,
+    timeout: Duration,
+) -> Result<Negotiated, E>
+where
+    S: Stream<Item = Result<Record<'static, 'static>, SE>>,
+    S: Sink<Record<'static, 'static>, Error = E> + Unpin,
+    E: From<io::Error> + From<SE>,
+{
+    tokio::time::timeout(timeout, connect(io, hello))
+        .await
+        .unwrap_or_else(|_| {
+            Err(failed(
+                io::ErrorKind::TimedOut,
+                format!(
+                    "listener didn't reply to the handshake within {:?}",
+                    timeout
+                ),
+            ))
+        })
+}
+
+/// Listener half of the handshake. Reads the client's first record, if it is a
+/// `Record::Hello` a reply is sent containing the highest common protocol version and
+/// the intersection of the client's features with `supported`, along with any pipeline it
+/// named in `Negotiated::pipeline`. Clients that predate
+/// the handshake are accepted as `Negotiated::legacy()`, in which case the first record
+/// they sent is returned so the caller can process it as normal.
+///
+/// If the client's version is too old an Error record is sent back before erroring
+pub async fn accept<S, E, SE>(
+    io: &mut S,
+    supported: &[&str],
+) -> Result<(Negotiated, Option<Record<'static, 'static>>), E>
+where
+    S: Stream<Item = Result<Record<'static, 'static>, SE>>,
+    S: Sink<Record<'static, 'static>, Error = E> + Unpin,
+    E: From<io::Error> + From<SE>,
+{
+    match io.next().await {
+        Some(Ok(Record::Hello(hello))) => {
+            let proto_version = hello.proto_version.min(PROTOCOL_VERSION);
+            if proto_version < MIN_PROTOCOL_VERSION {
+                let err = CrateError::now(
+                    Kind::Handshake,
+                    format!(
+                        "protocol version {} is not supported, minimum is {}",
+                        hello.proto_version, MIN_PROTOCOL_VERSION
+                    ),
+                );
+                io.send(Record::new_error(PROTOCOL_VERSION, err)).await?;
+
+                return Err(rejected(format!(
+                    "client protocol version {} is too old",
+                    hello.proto_version
+                )));
+            }
+
+            let pipeline = hello.pipeline().map(str::to_string);
+            let features: Vec<String> = hello
+                .features
+                .into_iter()
+                .filter(|f| supported.contains(&f.as_str()))
+                .collect();
+
+            let mut reply = Hello::new(features.iter().cloned());
+            reply.proto_version = proto_version;
+            io.send(Record::Hello(reply)).await?;
+
+            let negotiated = Negotiated {
+                proto_version,
+                features,
+                pipeline,
+                legacy: false,
+            };
+
+            Ok((negotiated, None))
+        }
+        Some(Ok(record)) => Ok((Negotiated::legacy(), Some(record))),
+        Some(Err(e)) => Err(e.into()),
+        None => Ok((Negotiated::legacy(), None)),
+    }
+}
+
+fn rejected<S, E>(msg: S) -> E
+where
+    S: Into<String>,
+    E: From<io::Error>,
+{
+    failed(io::ErrorKind::ConnectionRefused, msg)
+}
+
+fn failed<S, E>(kind: io::ErrorKind, msg: S) -> E
+where
+    S: Into<String>,
+    E: From<io::Error>,
+{
+    E::from(io::Error::new(kind, CrateError::now(Kind::Handshake, msg)))
+}
@@ -0,0 +1,80 @@
+use {
+    crate::{
+        codec::RecordSerializer,
+        record::{Heartbeat, Record},
+    },
+    bytes::Bytes,
+    futures::{prelude::*, ready},
+    pin_project::pin_project,
+    std::{
+        pin::Pin,
+        task::{Context, Poll},
+        time::Duration,
+    },
+    tokio::time::{delay_for, Delay, Instant},
+};
+
+/// Extension trait for streams of serialized records heading for the wire
+pub trait HeartbeatExt: Stream<Item = Bytes> + Sized {
+    /// Interleaves a serialized `Record::Heartbeat` into this stream every time `period`
+    /// elapses without the underlying stream yielding a record. Heartbeats are serialized
+    /// by `serializer`, which should share the checksum and encryption of every other record
+    fn heartbeat(self, period: Duration, serializer: RecordSerializer) -> WithHeartbeat<Self>;
+}
+
+impl<St> HeartbeatExt for St
+where
+    St: Stream<Item = Bytes>,
+{
+    fn heartbeat(self, period: Duration, serializer: RecordSerializer) -> WithHeartbeat<Self> {
+        WithHeartbeat {
+            inner: self,
+            timer: delay_for(period),
+            period,
+            serializer,
+        }
+    }
+}
+
+#[pin_project]
+pub struct WithHeartbeat<St> {
+    #[pin]
+    inner: St,
+    #[pin]
+    timer: Delay,
+    period: Duration,
+    serializer: RecordSerializer,
+}
+
+impl<St> Stream for WithHeartbeat<St>
+where
+    St: Stream<Item = Bytes>,
+{
+    type Item = Bytes;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        if let Poll::Ready(item) = this.inner.poll_next(cx) {
+            this.timer.reset(Instant::now() + *this.period);
+            return Poll::Ready(item);
+        }
+
+        // Nothing to send, check if we've been idle long enough to need a heartbeat
+        ready!(this.timer.as_mut().poll(cx));
+        this.timer.reset(Instant::now() + *this.period);
+
+        match this
+            .serializer
+            .serialize(&Record::Heartbeat(Heartbeat::now(1)))
+        {
+            Ok(bytes) => Poll::Ready(Some(bytes)),
+            // A heartbeat is always serializable, but if it somehow isn't just
+            // skip this one and wait for the next period
+            Err(_) => {
+                let _ = this.timer.poll(cx);
+                Poll::Pending
+            }
+        }
+    }
+}
@@ -0,0 +1,344 @@
+use {
+    crate::{
+        checksum::Checksum,
+        codec::{frame_length, RecordSerializer, LENGTH_LEN},
+        compression::Compression,
+        encryption::Encryption,
+        error::CrateError,
+        limits::Limits,
+        record::Record,
+    },
+    bytes::{Buf, Bytes, BytesMut},
+    futures::{prelude::*, ready},
+    pin_project::pin_project,
+    serde::Serialize,
+    std::{
+        error, fmt, io,
+        pin::Pin,
+        task::{Context, Poll},
+    },
+};
+
+/// Provides an interface for moving from deserialized Records to serialized
+/// byte buffers and vice versa.
+#[pin_project]
+pub struct RecordInterface<IF> {
+    #[pin]
+    inner: IF,
+    checksum: Checksum,
+    compression: Compression,
+    encryption: Encryption,
+    limits: Limits,
+    serializer: RecordSerializer,
+    /// Whether each frame received is a batch of records, and what remains of the current one
+    batches: Option<BytesMut>,
+}
+
+impl<IF> RecordInterface<IF> {
+    /// Sets the checksum appended to every record sent by this Interface,
+    /// and verified on every record received. Defaults to `Checksum::None`
+    pub fn with_checksum(mut self, checksum: Checksum) -> Self {
+        self.checksum = checksum;
+        self.serializer = self.serializer.with_checksum(checksum);
+        self
+    }
+
+    /// Sets the compression applied to every record sent by this Interface,
+    /// and removed from every record received. Defaults to `Compression::None`
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self.serializer = self.serializer.with_compression(compression);
+        self
+    }
+
+    /// Sets the encryption applied to every record sent by this Interface,
+    /// and removed from every record received. Defaults to `Encryption::None`
+    pub fn with_encryption(mut self, encryption: Encryption) -> Self {
+        self.serializer = self.serializer.with_encryption(encryption.clone());
+        self.encryption = encryption;
+        self
+    }
+
+    /// Sets the size of the buffer outgoing records are serialized into.
+    /// Defaults to `DEFAULT_BUFFER_SIZE`, see `RecordSerializer` for details
+    pub fn with_buffer_size(mut self, buffer_size: usize) -> Self {
+        self.serializer = self.serializer.with_buffer_size(buffer_size);
+        self
+    }
+
+    /// Sets the limits enforced on every record received. Defaults to `Limits::default()`.
+    /// Note that these are checked once a frame has been read, use `RecordInterface::from_both_limited`
+    /// or `RecordInterface::from_read_limited` to also enforce the frame length while reading
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets whether every frame received is a batch of records packed by
+    /// `RecordInterface::buffered`, which should be the case once `batch::FEATURE`
+    /// has been negotiated. Defaults to false
+    pub fn with_batches(mut self, batches: bool) -> Self {
+        self.batches = match batches {
+            true => Some(BytesMut::new()),
+            false => None,
+        };
+        self
+    }
+
+    /// Swaps out the underlying object, keeping this Interface's configuration
+    #[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+    pub(crate) fn map_inner<F, T>(self, f: F) -> RecordInterface<T>
+    where
+        F: FnOnce(IF, &Limits) -> T,
+    {
+        RecordInterface {
+            inner: f(self.inner, &self.limits),
+            checksum: self.checksum,
+            compression: self.compression,
+            encryption: self.encryption,
+            limits: self.limits,
+            serializer: self.serializer,
+            batches: self.batches,
+        }
+    }
+}
+
+impl<IF> RecordInterface<IF>
+where
+    IF: TryStream<Ok = BytesMut>,
+    IF: Sink<Bytes>,
+    <IF as TryStream>::Error: From<io::Error>,
+    <IF as Sink<Bytes>>::Error: From<io::Error>,
+{
+    /// Generates an Interface that implements both `Sink<T: Serialize>` and `TryStream<Ok = Record>`
+    /// from an underlying object. Most commonly, a
+    /// `RecordFrame` instance.
+    /// It is useful for situations when you need to both deserialize and serialize `Record`s.
+    /// If you only have the async IO stream (i.e a type that is `AsyncRead + AsyncWrite`)
+    /// prefer using `RecordInterface::from_both`
+    pub fn new_both(inner: IF) -> Self {
+        Self {
+            inner,
+            checksum: Checksum::default(),
+            compression: Compression::default(),
+            encryption: Encryption::default(),
+            limits: Limits::default(),
+            serializer: RecordSerializer::default(),
+            batches: None,
+        }
+    }
+}
+
+impl<IF> RecordInterface<IF>
+where
+    IF: TryStream<Ok = BytesMut>,
+    IF::Error: From<io::Error>,
+{
+    /// Generates an Interface that implements `TryStream<Ok = Record>`
+    /// This function is useful when the IO stream is being handled further
+    /// up the data stream, for example, if your data stream looks like this: TCP Socket -> `RecordFrameRead` -> `map()` -> `channel` -> ... -> `RecordInterface`
+    ///
+    /// If you only have the async IO stream (i.e a type that is at least `AsyncRead`)
+    /// prefer using `RecordInterface::from_write`
+    pub fn new_stream(inner: IF) -> Self {
+        Self {
+            inner,
+            checksum: Checksum::default(),
+            compression: Compression::default(),
+            encryption: Encryption::default(),
+            limits: Limits::default(),
+            serializer: RecordSerializer::default(),
+            batches: None,
+        }
+    }
+}
+
+impl<IF> RecordInterface<IF>
+where
+    IF: Sink<Bytes>,
+    IF::Error: From<io::Error>,
+{
+    /// Generates an Interface that implements `Sink<T: Serialize>` from an underlying
+    /// sink, most commonly a `RecordFrameWrite`
+    /// This function is useful when the IO stream is further down the data stream, i.e `RecordInterface` -> `channel` -> `inspect()` -> TCP Socket.
+    ///
+    /// If you only have the async IO stream (i.e a type that is at least `AsyncWrite`)
+    /// prefer using `RecordInterface::from_read`
+    pub fn new_sink(inner: IF) -> Self {
+        Self {
+            inner,
+            checksum: Checksum::default(),
+            compression: Compression::default(),
+            encryption: Encryption::default(),
+            limits: Limits::default(),
+            serializer: RecordSerializer::default(),
+            batches: None,
+        }
+    }
+}
+
+impl<IF> RecordInterface<IF> {
+    /// Turns a single frame back into the record it was serialized from
+    fn open(&self, mut bytes: BytesMut) -> Result<Record<'static, 'static>, io::Error> {
+        self.limits.check_frame_len(bytes.len())?;
+        self.encryption.open(&mut bytes)?;
+        self.checksum.open(&mut bytes)?;
+        self.compression.open(&mut bytes, &self.limits)?;
+        self.limits.check_depth(&bytes)?;
+
+        Ok(Record::from_frame(&bytes)?.into_owned())
+    }
+}
+
+impl<IF, E> Stream for RecordInterface<IF>
+where
+    IF: Stream<Item = Result<BytesMut, E>>,
+    IF: TryStream<Ok = BytesMut, Error = E>,
+{
+    type Item = Result<Record<'static, 'static>, RecvError<IF::Error>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let this = self.as_mut().project();
+            let frame = match next_batched(this.batches, this.limits) {
+                Some(frame) => frame,
+                None => match ready!(this.inner.poll_next(cx)) {
+                    Some(Ok(bytes)) if this.batches.is_some() => {
+                        *this.batches = Some(bytes);
+                        continue;
+                    }
+                    Some(Ok(bytes)) => Ok(bytes),
+                    Some(Err(e)) => return Poll::Ready(Some(Err(RecvError::Io(e)))),
+                    None => return Poll::Ready(None),
+                },
+            };
+
+            return Poll::Ready(Some(
+                frame
+                    .and_then(|bytes| self.open(bytes))
+                    .map_err(|e| RecvError::Decode(CrateError::from(e))),
+            ));
+        }
+    }
+}
+
+/// Removes the next record's frame from the current batch, if there is one
+fn next_batched(
+    batches: &mut Option<BytesMut>,
+    limits: &Limits,
+) -> Option<Result<BytesMut, io::Error>> {
+    let batch = batches.as_mut().filter(|batch| !batch.is_empty())?;
+    let frame = match batch.len() >= LENGTH_LEN {
+        true => frame_length(&batch[..LENGTH_LEN], limits).map(|length| LENGTH_LEN + length),
+        false => Err(truncated()),
+    }
+    .and_then(|end| match batch.len() >= end {
+        true => Ok(end),
+        false => Err(truncated()),
+    })
+    .map(|end| {
+        let mut frame = batch.split_to(end);
+        frame.advance(LENGTH_LEN);
+        frame
+    });
+
+    // Nothing after a bad length can be found, so give up on the rest of the batch
+    if frame.is_err() {
+        batch.clear();
+    }
+
+    Some(frame)
+}
+
+/// A batch ended part way through one of its records
+fn truncated() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "batch ended part way through a record",
+    )
+}
+
+/// Returned by the stream half of a `RecordInterface`, separating frames that couldn't be
+/// turned into a record from failures of the underlying io
+#[derive(Debug)]
+pub enum RecvError<E> {
+    /// A frame was read, but failed its checksum, decryption, decompression, limits or
+    /// deserialization.
+    /// The frame has been consumed, so the stream can carry on with the next one
+    Decode(CrateError),
+    /// The underlying io failed, nothing further in the stream can be trusted
+    Io(E),
+}
+
+impl<E> RecvError<E> {
+    /// Whether only the current frame was lost, see `RecvError::Decode`
+    pub fn is_decode(&self) -> bool {
+        match self {
+            Self::Decode(_) => true,
+            Self::Io(_) => false,
+        }
+    }
+}
+
+impl<E> fmt::Display for RecvError<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Decode(e) => write!(f, "{}", e),
+            Self::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl<E> error::Error for RecvError<E> where E: fmt::Debug + fmt::Display {}
+
+/// Errors from the underlying io, i.e when sending acks over the same connection
+impl<E> From<E> for RecvError<E> {
+    fn from(error: E) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl<E> From<RecvError<E>> for io::Error
+where
+    E: Into<io::Error>,
+{
+    fn from(error: RecvError<E>) -> Self {
+        match error {
+            RecvError::Decode(e) => io::Error::new(io::ErrorKind::InvalidData, e),
+            RecvError::Io(e) => e.into(),
+        }
+    }
+}
+
+impl<IF, T> Sink<T> for RecordInterface<IF>
+where
+    IF: Sink<Bytes>,
+    IF::Error: From<io::Error>,
+    T: Serialize,
+{
+    type Error = IF::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        let this = self.project();
+        let bytes = this.serializer.serialize(&item)?;
+
+        this.inner.start_send(bytes)?;
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().project().inner.poll_flush(cx))?;
+        self.project().inner.poll_close(cx)
+    }
+}
@@ -0,0 +1,346 @@
+//! A plain JSON representation of records, with every field spelled out, for printing
+//! records where they are read by people or tools like jq rather than decoded again
+
+use {
+    crate::{
+        Ack as RecordAck, Channel as RecordChannel, ChannelState, Common as RecordCommon,
+        Encoding as RecordEncoding, Error as RecordError, Exit as RecordExit, Facts as RecordFacts,
+        HeaderContext, Heartbeat as RecordHeartbeat, Hello as RecordHello, InterfaceError,
+        Log as RecordLog, LogLevel, Meta, Metrics as RecordMetrics, OutputStream, OwnedData,
+        OwnedHeader, OwnedRecord,
+    },
+    serde::{Deserialize, Serialize},
+    std::collections::BTreeMap,
+};
+
+/// An `OwnedRecord` as JSON, externally tagged by its kind, i.e `{"Data":{...}}`
+#[derive(Debug, Serialize, Deserialize)]
+pub enum JsonRecord {
+    StreamStart,
+    StreamEnd,
+    Header(Header),
+    Data(Data),
+    Log(Log),
+    Error(Error),
+    Hello(Hello),
+    Heartbeat(Heartbeat),
+    Ack(Ack),
+    Exit(Exit),
+    Channel(Channel),
+    Metrics(Metrics),
+    Facts(Facts),
+}
+
+impl From<OwnedRecord> for JsonRecord {
+    fn from(record: OwnedRecord) -> Self {
+        match record {
+            OwnedRecord::StreamStart => Self::StreamStart,
+            OwnedRecord::StreamEnd => Self::StreamEnd,
+            OwnedRecord::Header(r) => Self::Header(r.into()),
+            OwnedRecord::Data(r) => Self::Data(r.into()),
+            OwnedRecord::Log(r) => Self::Log(r.into()),
+            OwnedRecord::Error(r) => Self::Error(r.into()),
+            OwnedRecord::Hello(r) => Self::Hello(r.into()),
+            OwnedRecord::Heartbeat(r) => Self::Heartbeat(r.into()),
+            OwnedRecord::Ack(r) => Self::Ack(r.into()),
+            OwnedRecord::Exit(r) => Self::Exit(r.into()),
+            OwnedRecord::Channel(r) => Self::Channel(r.into()),
+            OwnedRecord::Metrics(r) => Self::Metrics(r.into()),
+            OwnedRecord::Facts(r) => Self::Facts(r.into()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Data {
+    required: Common,
+    time: i64,
+    id: String,
+    pid: u32,
+    cxt: Context,
+    data: String,
+    encoding: Encoding,
+    trace_id: Option<String>,
+    meta: Meta,
+}
+
+impl From<OwnedData> for Data {
+    fn from(r: OwnedData) -> Self {
+        Self {
+            required: r.required.into(),
+            time: r.time,
+            id: r.id,
+            pid: r.pid,
+            cxt: r.cxt.into(),
+            data: String::from_utf8_lossy(&r.data).into_owned(),
+            encoding: r.encoding.into(),
+            trace_id: r.trace_id,
+            meta: r.meta,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Header {
+    required: Common,
+    time: i64,
+    id: String,
+    pid: u32,
+    cxt: Context,
+    trace_id: Option<String>,
+    meta: Meta,
+}
+
+impl From<OwnedHeader> for Header {
+    fn from(r: OwnedHeader) -> Self {
+        Self {
+            required: r.required.into(),
+            time: r.time,
+            id: r.id,
+            pid: r.pid,
+            cxt: r.cxt.into(),
+            trace_id: r.trace_id,
+            meta: r.meta,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Error {
+    required: Common,
+    error: InterfaceError,
+    trace_id: Option<String>,
+    id: Option<String>,
+}
+
+impl From<RecordError> for Error {
+    fn from(r: RecordError) -> Self {
+        Self {
+            required: r.required.into(),
+            error: r.error,
+            trace_id: r.trace_id,
+            id: r.id,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Log {
+    required: Common,
+    log: String,
+    level: LogLevel,
+    target: Option<String>,
+    time: i64,
+}
+
+impl From<RecordLog> for Log {
+    fn from(r: RecordLog) -> Self {
+        Self {
+            required: r.required.into(),
+            log: r.log,
+            level: r.level,
+            target: r.target,
+            time: r.time,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Hello {
+    required: Common,
+    proto_version: u32,
+    features: Vec<String>,
+}
+
+impl From<RecordHello> for Hello {
+    fn from(r: RecordHello) -> Self {
+        Self {
+            required: r.required.into(),
+            proto_version: r.proto_version,
+            features: r.features,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Heartbeat {
+    required: Common,
+    time: i64,
+}
+
+impl From<RecordHeartbeat> for Heartbeat {
+    fn from(r: RecordHeartbeat) -> Self {
+        Self {
+            required: r.required.into(),
+            time: r.time,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Ack {
+    required: Common,
+    up_to_seq: u64,
+}
+
+impl From<RecordAck> for Ack {
+    fn from(r: RecordAck) -> Self {
+        Self {
+            required: r.required.into(),
+            up_to_seq: r.up_to_seq,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Exit {
+    required: Common,
+    time: i64,
+    id: String,
+    pid: u32,
+    code: Option<i32>,
+    signal: Option<i32>,
+}
+
+impl From<RecordExit<'_>> for Exit {
+    fn from(r: RecordExit) -> Self {
+        Self {
+            required: r.required.into(),
+            time: r.time,
+            id: r.id.into(),
+            pid: r.pid,
+            code: r.code,
+            signal: r.signal,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Channel {
+    required: Common,
+    state: State,
+}
+
+impl From<RecordChannel> for Channel {
+    fn from(r: RecordChannel) -> Self {
+        Self {
+            required: r.required.into(),
+            state: r.state.into(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Metrics {
+    required: Common,
+    time: i64,
+    source: String,
+    period_ms: u64,
+    counters: BTreeMap<String, u64>,
+    gauges: BTreeMap<String, f64>,
+}
+
+impl From<RecordMetrics> for Metrics {
+    fn from(r: RecordMetrics) -> Self {
+        Self {
+            required: r.required.into(),
+            time: r.time,
+            source: r.source,
+            period_ms: r.period.as_millis() as u64,
+            counters: r.counters,
+            gauges: r.gauges,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Facts {
+    required: Common,
+    time: i64,
+    facts: BTreeMap<String, String>,
+}
+
+impl From<RecordFacts> for Facts {
+    fn from(r: RecordFacts) -> Self {
+        Self {
+            required: r.required.into(),
+            time: r.time,
+            facts: r.facts,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Common {
+    version: u32,
+    seq: Option<u64>,
+    stream: Option<u32>,
+}
+
+impl From<RecordCommon> for Common {
+    fn from(r: RecordCommon) -> Self {
+        Self {
+            version: r.version,
+            seq: r.seq,
+            stream: r.stream,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Context {
+    Start,
+    End,
+    Stdout,
+    Stderr,
+}
+
+impl From<HeaderContext> for Context {
+    fn from(cxt: HeaderContext) -> Self {
+        match cxt {
+            HeaderContext::Start => Self::Start,
+            HeaderContext::End => Self::End,
+        }
+    }
+}
+
+impl From<OutputStream> for Context {
+    fn from(cxt: OutputStream) -> Self {
+        match cxt {
+            OutputStream::Stdout => Self::Stdout,
+            OutputStream::Stderr => Self::Stderr,
+        }
+    }
+}
+
+/// Binary data can't be represented losslessly in JSON, so it is printed as text with any
+/// invalid UTF-8 replaced. This lets consumers know when that has happened
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Encoding {
+    Utf8,
+    Binary,
+}
+
+impl From<RecordEncoding> for Encoding {
+    fn from(encoding: RecordEncoding) -> Self {
+        match encoding {
+            RecordEncoding::Utf8 => Self::Utf8,
+            RecordEncoding::Binary => Self::Binary,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum State {
+    Open,
+    Close,
+}
+
+impl From<ChannelState> for State {
+    fn from(state: ChannelState) -> Self {
+        match state {
+            ChannelState::Open => Self::Open,
+            ChannelState::Close => Self::Close,
+        }
+    }
+}
@@ -1,13 +1,74 @@
+pub mod ack;
+#[cfg(feature = "tokio")]
+pub mod batch;
+mod blocking;
+mod builder;
+mod checksum;
+mod codec;
+mod compression;
+mod demux;
+mod encryption;
 mod error;
+#[cfg(feature = "futures-io")]
+mod futures_io;
+pub mod handshake;
+#[cfg(feature = "tokio")]
+mod heartbeat;
+mod interface;
+pub mod json;
+mod limits;
 mod markers;
+#[cfg(feature = "tokio")]
+pub mod metrics;
+mod owned;
+mod reassemble;
 mod record;
+mod sequence;
+#[cfg(feature = "chrono")]
+mod timestamp;
+#[cfg(feature = "tokio")]
 mod tokio_cbor;
 mod traits;
+mod validate;
 
 pub use crate::{
-    error::CrateError as InterfaceError,
-    markers::{DataContext, KindMarker, TagMarker},
+    blocking::{RecordReader, RecordWriter},
+    builder::{BuildError, DataBuilder, ExitBuilder, HeaderBuilder},
+    checksum::Checksum,
+    codec::{decode_record, encode_record, RecordCodec, RecordSerializer, DEFAULT_BUFFER_SIZE},
+    compression::{Compression, DEFAULT_ZSTD_LEVEL},
+    demux::{DemuxById, DemuxExt, Demuxed, IdStream},
+    encryption::{Encryption, Key, KEY_LEN},
+    error::{CrateError as InterfaceError, Kind as InterfaceErrorKind},
+    interface::{RecordInterface, RecvError},
+    limits::{Limits, DEFAULT_MAX_DEPTH, DEFAULT_MAX_FRAME_LEN},
+    markers::{ChannelState, DataContext, Encoding, KindMarker, LogLevel, TagMarker},
+    owned::{
+        ContextError, HeaderContext, OutputStream, OwnedData, OwnedHeader, OwnedRecord,
+        CHILD_SEQ_META,
+    },
+    reassemble::{ReassembleExt, Reassembled, CONTINUED_META},
     record::*,
-    tokio_cbor::{Bytes, BytesMut, Cbor, RecordFrame, RecordInterface, SymmetricalCbor},
+    sequence::{Anomaly, CheckSequence, SequenceExt, Sequenced, Sequencer},
     traits::{Marker, Repr},
+    validate::{StreamValidator, Validate, ValidateExt, Violation},
 };
+
+pub use {
+    bytes::{Bytes, BytesMut},
+    serde_cbor::Value,
+};
+
+#[cfg(feature = "tokio")]
+pub use crate::{
+    batch::Batched,
+    heartbeat::{HeartbeatExt, WithHeartbeat},
+    metrics::{MetricsExt, WithMetrics},
+    tokio_cbor::{Cbor, FrameCodec, RecordFrame, RecordFrameBuilder, SymmetricalCbor},
+};
+
+#[cfg(feature = "futures-io")]
+pub use crate::futures_io::FuturesFrame;
+
+#[cfg(feature = "chrono")]
+pub use crate::timestamp::RecordTime;
@@ -0,0 +1,156 @@
+use {
+    crate::error::{CrateError, Kind},
+    std::io,
+};
+
+/// Default largest frame accepted, matching `LengthDelimitedCodec`'s default
+pub const DEFAULT_MAX_FRAME_LEN: usize = 8 * 1024 * 1024;
+
+/// Default deepest nesting accepted in a record. Well formed records never nest
+/// more than a few levels deep, so this leaves plenty of room for new record kinds
+pub const DEFAULT_MAX_DEPTH: usize = 16;
+
+/// Bounds on what a receiver will accept from the other end of a connection.
+/// A frame breaching either limit is rejected with an error of kind `Kind::Limit`,
+/// before any attempt is made to deserialize it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    max_frame_len: usize,
+    max_depth: usize,
+}
+
+impl Limits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the largest frame accepted, in bytes, including any checksum
+    pub fn with_max_frame_len(mut self, max_frame_len: usize) -> Self {
+        self.max_frame_len = max_frame_len;
+        self
+    }
+
+    /// Sets the deepest nesting of arrays, maps and tags accepted in a record
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn max_frame_len(&self) -> usize {
+        self.max_frame_len
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// Errors if a frame of `len` bytes is larger than allowed
+    pub(crate) fn check_frame_len(&self, len: usize) -> io::Result<()> {
+        match len > self.max_frame_len {
+            true => Err(exceeded(format!(
+                "frame of {} bytes exceeds the maximum of {} bytes",
+                len, self.max_frame_len
+            ))),
+            false => Ok(()),
+        }
+    }
+
+    /// Walks the CBOR item at the start of `frame` without deserializing it, erroring if its
+    /// nesting is deeper than allowed. Malformed or truncated items are left for the
+    /// deserializer to reject
+    pub(crate) fn check_depth(&self, frame: &[u8]) -> io::Result<()> {
+        // Items remaining in each open container, None for indefinite length containers
+        let mut open: Vec<Option<u64>> = Vec::new();
+        let mut pos = 0;
+
+        while let Some(&initial) = frame.get(pos) {
+            pos += 1;
+            let (major, info) = (initial >> 5, initial & 0x1f);
+
+            let arg = match info {
+                0..=23 => Some(u64::from(info)),
+                24..=27 => {
+                    let len = 1 << (info - 24);
+                    match frame.get(pos..pos + len) {
+                        Some(bytes) => {
+                            pos += len;
+                            Some(bytes.iter().fold(0u64, |acc, b| acc << 8 | u64::from(*b)))
+                        }
+                        None => return Ok(()),
+                    }
+                }
+                31 => None,
+                _ => return Ok(()),
+            };
+
+            let container = match (major, arg) {
+                // Break, closing the innermost indefinite length container
+                (7, None) => {
+                    match open.pop() {
+                        Some(None) => {}
+                        _ => return Ok(()),
+                    }
+                    None
+                }
+                // Byte and text strings, indefinite ones are a container of chunks
+                (2, Some(len)) | (3, Some(len)) => {
+                    pos = pos.saturating_add(len as usize);
+                    None
+                }
+                (2, None) | (3, None) | (4, None) | (5, None) => Some(None),
+                (4, Some(len)) => Some(Some(len)),
+                (5, Some(len)) => Some(Some(len.saturating_mul(2))),
+                (6, Some(_)) => Some(Some(1)),
+                _ => None,
+            };
+
+            if let Some(items) = container {
+                if open.len() >= self.max_depth {
+                    return Err(exceeded(format!(
+                        "record nesting exceeds the maximum depth of {}",
+                        self.max_depth
+                    )));
+                }
+                // An empty container is a complete item in itself
+                if items != Some(0) {
+                    open.push(items);
+                    continue;
+                }
+            }
+
+            // An item (or the container a break just closed) completed,
+            // which may in turn complete its enclosing containers
+            loop {
+                match open.last_mut() {
+                    None => return Ok(()),
+                    Some(Some(remaining)) => {
+                        *remaining -= 1;
+                        match *remaining {
+                            0 => open.pop(),
+                            _ => break,
+                        };
+                    }
+                    Some(None) => break,
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+}
+
+fn exceeded(msg: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        CrateError::now(Kind::Limit, msg),
+    )
+}
@@ -16,6 +16,63 @@ pub enum TagMarker {
     Data = 5,
     Utf8Data = 6,
     Error = 7,
+    ProtoVersion = 8,
+    Features = 9,
+    Sequence = 10,
+    Code = 11,
+    Signal = 12,
+    Level = 13,
+    Target = 14,
+    Seq = 15,
+    TraceId = 16,
+    Meta = 17,
+    Stream = 18,
+    State = 19,
+    Bytes = 20,
+    Encoding = 21,
+    Source = 22,
+    Period = 23,
+    Counters = 24,
+    Gauges = 25,
+    Facts = 26,
+}
+
+impl TagMarker {
+    /// The marker with the given repr, None if the tag was added by a newer version of this library
+    pub fn from_repr(repr: u16) -> Option<Self> {
+        let marker = match repr {
+            0 => Self::DataContext,
+            1 => Self::Version,
+            2 => Self::Time,
+            3 => Self::Id,
+            4 => Self::Pid,
+            5 => Self::Data,
+            6 => Self::Utf8Data,
+            7 => Self::Error,
+            8 => Self::ProtoVersion,
+            9 => Self::Features,
+            10 => Self::Sequence,
+            11 => Self::Code,
+            12 => Self::Signal,
+            13 => Self::Level,
+            14 => Self::Target,
+            15 => Self::Seq,
+            16 => Self::TraceId,
+            17 => Self::Meta,
+            18 => Self::Stream,
+            19 => Self::State,
+            20 => Self::Bytes,
+            21 => Self::Encoding,
+            22 => Self::Source,
+            23 => Self::Period,
+            24 => Self::Counters,
+            25 => Self::Gauges,
+            26 => Self::Facts,
+            _ => return None,
+        };
+
+        Some(marker)
+    }
 }
 
 impl Marker for TagMarker {
@@ -45,6 +102,13 @@ pub enum KindMarker {
     Data = 3,
     Log = 4,
     Error = 5,
+    Hello = 6,
+    Heartbeat = 7,
+    Ack = 8,
+    Exit = 9,
+    Channel = 10,
+    Metrics = 11,
+    Facts = 12,
 }
 
 impl Marker for KindMarker {
@@ -62,7 +126,7 @@ impl Repr for KindMarker {
 }
 
 /// Marker for a context field that is present in some record objects
-#[derive(Debug, Clone, Copy, Serialize_repr, Deserialize_repr)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize_repr, Deserialize_repr)]
 #[repr(u16)]
 pub enum DataContext {
     Start = 0,
@@ -84,3 +148,87 @@ impl Repr for DataContext {
         *self as u32
     }
 }
+
+/// Hint for how the payload of a `Data` record should be interpreted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize_repr, Deserialize_repr)]
+#[repr(u16)]
+pub enum Encoding {
+    /// Valid UTF-8 text
+    Utf8 = 0,
+    /// Arbitrary bytes, which may or may not be valid UTF-8
+    Binary = 1,
+}
+
+impl Encoding {
+    /// The most specific hint that fits `bytes`
+    pub fn detect(bytes: &[u8]) -> Self {
+        match std::str::from_utf8(bytes).is_ok() {
+            true => Self::Utf8,
+            false => Self::Binary,
+        }
+    }
+}
+
+impl Marker for Encoding {
+    type Marker = Encoding;
+
+    fn as_marker(&self) -> Self::Marker {
+        *self
+    }
+}
+
+impl Repr for Encoding {
+    fn repr_u8(&self) -> u32 {
+        *self as u32
+    }
+}
+
+/// Severity of a `Log` record, ordered from least to most severe
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize_repr, Deserialize_repr, Default,
+)]
+#[repr(u16)]
+pub enum LogLevel {
+    Trace = 0,
+    Debug = 1,
+    #[default]
+    Info = 2,
+    Warn = 3,
+    Error = 4,
+}
+
+impl Marker for LogLevel {
+    type Marker = LogLevel;
+
+    fn as_marker(&self) -> Self::Marker {
+        *self
+    }
+}
+
+impl Repr for LogLevel {
+    fn repr_u8(&self) -> u32 {
+        *self as u32
+    }
+}
+
+/// Whether a `Channel` record opens or closes its logical stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize_repr, Deserialize_repr)]
+#[repr(u16)]
+pub enum ChannelState {
+    Open = 0,
+    Close = 1,
+}
+
+impl Marker for ChannelState {
+    type Marker = ChannelState;
+
+    fn as_marker(&self) -> Self::Marker {
+        *self
+    }
+}
+
+impl Repr for ChannelState {
+    fn repr_u8(&self) -> u32 {
+        *self as u32
+    }
+}
@@ -0,0 +1,127 @@
+use {
+    crate::{
+        codec::RecordSerializer,
+        record::{Metrics, Record},
+    },
+    bytes::Bytes,
+    futures::{prelude::*, ready},
+    pin_project::pin_project,
+    std::{
+        pin::Pin,
+        task::{Context, Poll},
+        time::Duration,
+    },
+    tokio::time::{delay_for, Delay, Instant},
+};
+
+/// Name of the handshake feature advertising support for `Record::Metrics`
+pub const FEATURE: &str = "metrics";
+
+/// Extension trait for streams of serialized records heading for the wire
+pub trait MetricsExt: Stream<Item = Bytes> + Sized {
+    /// Counts the records and bytes passing through this stream, interleaving a serialized
+    /// `Record::Metrics` reported by `source` every `period`, and once more after the
+    /// underlying stream ends. Metrics are serialized by `serializer`, which should share
+    /// the checksum and encryption of every other record
+    fn metrics<S>(
+        self,
+        source: S,
+        period: Duration,
+        serializer: RecordSerializer,
+    ) -> WithMetrics<Self>
+    where
+        S: Into<String>;
+}
+
+impl<St> MetricsExt for St
+where
+    St: Stream<Item = Bytes>,
+{
+    fn metrics<S>(
+        self,
+        source: S,
+        period: Duration,
+        serializer: RecordSerializer,
+    ) -> WithMetrics<Self>
+    where
+        S: Into<String>,
+    {
+        WithMetrics {
+            inner: self,
+            timer: delay_for(period),
+            source: source.into(),
+            period,
+            serializer,
+            records: 0,
+            bytes: 0,
+            reported: (0, 0, Instant::now()),
+            done: false,
+        }
+    }
+}
+
+#[pin_project]
+pub struct WithMetrics<St> {
+    #[pin]
+    inner: St,
+    #[pin]
+    timer: Delay,
+    source: String,
+    period: Duration,
+    serializer: RecordSerializer,
+    records: u64,
+    bytes: u64,
+    /// Records and bytes as of the last report, and when it was made
+    reported: (u64, u64, Instant),
+    done: bool,
+}
+
+impl<St> Stream for WithMetrics<St>
+where
+    St: Stream<Item = Bytes>,
+{
+    type Item = Bytes;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            if *this.done {
+                return Poll::Ready(None);
+            }
+
+            // Check the timer first, a busy stream would otherwise never report
+            match this.timer.as_mut().poll(cx) {
+                Poll::Ready(_) => this.timer.reset(Instant::now() + *this.period),
+                Poll::Pending => match ready!(this.inner.as_mut().poll_next(cx)) {
+                    Some(item) => {
+                        *this.records += 1;
+                        *this.bytes += item.len() as u64;
+                        return Poll::Ready(Some(item));
+                    }
+                    None => *this.done = true,
+                },
+            }
+
+            let now = Instant::now();
+            let (records, bytes, since) = *this.reported;
+            let elapsed = now.duration_since(since).as_secs_f64().max(f64::EPSILON);
+            *this.reported = (*this.records, *this.bytes, now);
+
+            let metrics = Metrics::now(1, this.source.as_str(), *this.period)
+                .counter("records", *this.records)
+                .counter("bytes", *this.bytes)
+                .gauge(
+                    "records_per_sec",
+                    (*this.records - records) as f64 / elapsed,
+                )
+                .gauge("bytes_per_sec", (*this.bytes - bytes) as f64 / elapsed);
+
+            // Metrics are always serializable, but if they somehow aren't just
+            // skip this report and carry on with the next
+            if let Ok(bytes) = this.serializer.serialize(&Record::Metrics(metrics)) {
+                return Poll::Ready(Some(bytes));
+            }
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use {
+    crate::{
+        markers::{DataContext, Encoding},
+        record::{
+            Ack, Channel, Common, Data, Error, Exit, Facts, Header, Heartbeat, Hello, Log, Meta,
+            Metrics, Record,
+        },
+    },
+    serde_cbor::Value,
+    std::{borrow::Cow, convert::TryFrom, error, fmt},
+};
+
+/// Returned when a `Header` or `Data` record carries a context that isn't valid for its kind
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContextError {
+    found: DataContext,
+    expected: &'static [DataContext],
+}
+
+impl ContextError {
+    /// The context the record carried
+    pub fn found(&self) -> DataContext {
+        self.found
+    }
+
+    /// The contexts valid for the record's kind
+    pub fn expected(&self) -> &'static [DataContext] {
+        self.expected
+    }
+}
+
+impl fmt::Display for ContextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid record context {:?}, expected one of: {:?}",
+            self.found, self.expected
+        )
+    }
+}
+
+impl error::Error for ContextError {}
+
+/// An owned `Record`, free of any lifetimes so it can be held across tasks and channels.
+/// Converting a `Record` into one validates the contexts of its `Header` and `Data`
+/// records, so consumers never need to handle a `Data` record that claims to be `Start`
+#[derive(Debug)]
+pub enum OwnedRecord {
+    StreamStart,
+    StreamEnd,
+    Header(OwnedHeader),
+    Data(OwnedData),
+    Log(Log),
+    Error(Error),
+    Hello(Hello),
+    Heartbeat(Heartbeat),
+    Ack(Ack),
+    Exit(Exit<'static>),
+    Channel(Channel),
+    Metrics(Metrics),
+    Facts(Facts),
+}
+
+impl OwnedRecord {
+    /// Fields common to every record kind, None for the stream markers
+    pub fn common(&self) -> Option<&Common> {
+        match self {
+            Self::StreamStart | Self::StreamEnd => None,
+            Self::Header(r) => Some(&r.required),
+            Self::Data(r) => Some(&r.required),
+            Self::Log(r) => Some(&r.required),
+            Self::Error(r) => Some(&r.required),
+            Self::Hello(r) => Some(&r.required),
+            Self::Heartbeat(r) => Some(&r.required),
+            Self::Ack(r) => Some(&r.required),
+            Self::Exit(r) => Some(&r.required),
+            Self::Channel(r) => Some(&r.required),
+            Self::Metrics(r) => Some(&r.required),
+            Self::Facts(r) => Some(&r.required),
+        }
+    }
+}
+
+impl TryFrom<Record<'_, '_>> for OwnedRecord {
+    type Error = ContextError;
+
+    fn try_from(record: Record<'_, '_>) -> Result<Self, ContextError> {
+        let owned = match record {
+            Record::StreamStart => Self::StreamStart,
+            Record::StreamEnd => Self::StreamEnd,
+            Record::Header(r) => Self::Header(OwnedHeader::try_from(r)?),
+            Record::Data(r) => Self::Data(OwnedData::try_from(r)?),
+            Record::Log(r) => Self::Log(r),
+            Record::Error(r) => Self::Error(r),
+            Record::Hello(r) => Self::Hello(r),
+            Record::Heartbeat(r) => Self::Heartbeat(r),
+            Record::Ack(r) => Self::Ack(r),
+            Record::Exit(r) => Self::Exit(r.into_owned()),
+            Record::Channel(r) => Self::Channel(r),
+            Record::Metrics(r) => Self::Metrics(r),
+            Record::Facts(r) => Self::Facts(r),
+        };
+
+        Ok(owned)
+    }
+}
+
+impl From<OwnedRecord> for Record<'static, 'static> {
+    fn from(owned: OwnedRecord) -> Self {
+        match owned {
+            OwnedRecord::StreamStart => Record::StreamStart,
+            OwnedRecord::StreamEnd => Record::StreamEnd,
+            OwnedRecord::Header(r) => r.into(),
+            OwnedRecord::Data(r) => r.into(),
+            OwnedRecord::Log(r) => Record::Log(r),
+            OwnedRecord::Error(r) => Record::Error(r),
+            OwnedRecord::Hello(r) => Record::Hello(r),
+            OwnedRecord::Heartbeat(r) => Record::Heartbeat(r),
+            OwnedRecord::Ack(r) => Record::Ack(r),
+            OwnedRecord::Exit(r) => Record::Exit(r),
+            OwnedRecord::Channel(r) => Record::Channel(r),
+            OwnedRecord::Metrics(r) => Record::Metrics(r),
+            OwnedRecord::Facts(r) => Record::Facts(r),
+        }
+    }
+}
+
+impl From<OwnedHeader> for OwnedRecord {
+    fn from(header: OwnedHeader) -> Self {
+        Self::Header(header)
+    }
+}
+
+impl From<OwnedData> for OwnedRecord {
+    fn from(data: OwnedData) -> Self {
+        Self::Data(data)
+    }
+}
+
+/// Owned equivalent of `Header`, whose context can only be `Start` or `End`
+#[derive(Debug, Clone)]
+pub struct OwnedHeader {
+    pub required: Common,
+    pub time: i64,
+    pub id: String,
+    pub pid: u32,
+    pub cxt: HeaderContext,
+    pub trace_id: Option<String>,
+    pub meta: Meta,
+    /// Fields this version doesn't understand, see `Header::unknown`
+    pub unknown: Vec<(u16, Value)>,
+}
+
+impl TryFrom<Header<'_>> for OwnedHeader {
+    type Error = ContextError;
+
+    fn try_from(header: Header<'_>) -> Result<Self, Self::Error> {
+        Ok(Self {
+            cxt: HeaderContext::try_from(header.cxt)?,
+            required: header.required,
+            time: header.time,
+            id: header.id.into_owned(),
+            pid: header.pid,
+            trace_id: header.trace_id.map(Cow::into_owned),
+            meta: header.meta.into_owned(),
+            unknown: header.unknown,
+        })
+    }
+}
+
+impl From<OwnedHeader> for Record<'static, 'static> {
+    fn from(header: OwnedHeader) -> Self {
+        Record::Header(Header {
+            required: header.required,
+            time: header.time,
+            id: Cow::Owned(header.id),
+            pid: header.pid,
+            cxt: header.cxt.into(),
+            trace_id: header.trace_id.map(Cow::Owned),
+            meta: Cow::Owned(header.meta),
+            unknown: header.unknown,
+        })
+    }
+}
+
+/// Meta key numbering each `Data` record of a child in the order its output was read, across
+/// both stdout and stderr, from 0. Records of one child interleave in the order of this
+/// number, whatever order they were sent in
+pub const CHILD_SEQ_META: &str = "child_seq";
+
+/// Owned equivalent of `Data`, whose context can only be `Stdout` or `Stderr`
+#[derive(Debug, Clone)]
+pub struct OwnedData {
+    pub required: Common,
+    pub time: i64,
+    pub id: String,
+    pub pid: u32,
+    pub cxt: OutputStream,
+    pub data: Vec<u8>,
+    pub encoding: Encoding,
+    pub trace_id: Option<String>,
+    pub meta: Meta,
+    /// Fields this version doesn't understand, see `Header::unknown`
+    pub unknown: Vec<(u16, Value)>,
+}
+
+impl OwnedData {
+    /// The payload as text, replacing any invalid UTF-8
+    pub fn text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.data)
+    }
+
+    /// Where this record falls among its child's output, see `CHILD_SEQ_META`
+    pub fn child_seq(&self) -> Option<u64> {
+        self.meta
+            .get(CHILD_SEQ_META)
+            .and_then(|seq| seq.parse().ok())
+    }
+}
+
+impl TryFrom<Data<'_, '_>> for OwnedData {
+    type Error = ContextError;
+
+    fn try_from(data: Data<'_, '_>) -> Result<Self, Self::Error> {
+        Ok(Self {
+            cxt: OutputStream::try_from(data.cxt)?,
+            required: data.required,
+            time: data.time,
+            id: data.id.into_owned(),
+            pid: data.pid,
+            data: data.data.into_owned(),
+            encoding: data.encoding,
+            trace_id: data.trace_id.map(Cow::into_owned),
+            meta: data.meta.into_owned(),
+            unknown: data.unknown,
+        })
+    }
+}
+
+impl From<OwnedData> for Record<'static, 'static> {
+    fn from(data: OwnedData) -> Self {
+        Record::Data(Data {
+            required: data.required,
+            time: data.time,
+            id: Cow::Owned(data.id),
+            pid: data.pid,
+            cxt: data.cxt.into(),
+            data: Cow::Owned(data.data),
+            encoding: data.encoding,
+            trace_id: data.trace_id.map(Cow::Owned),
+            meta: Cow::Owned(data.meta),
+            unknown: data.unknown,
+        })
+    }
+}
+
+/// The subset of `DataContext` valid for a `Header` record
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderContext {
+    Start,
+    End,
+}
+
+impl HeaderContext {
+    const VALID: &'static [DataContext] = &[DataContext::Start, DataContext::End];
+}
+
+impl From<HeaderContext> for DataContext {
+    fn from(cxt: HeaderContext) -> Self {
+        match cxt {
+            HeaderContext::Start => DataContext::Start,
+            HeaderContext::End => DataContext::End,
+        }
+    }
+}
+
+impl TryFrom<DataContext> for HeaderContext {
+    type Error = ContextError;
+
+    fn try_from(cxt: DataContext) -> Result<Self, Self::Error> {
+        match cxt {
+            DataContext::Start => Ok(Self::Start),
+            DataContext::End => Ok(Self::End),
+            found => Err(ContextError {
+                found,
+                expected: Self::VALID,
+            }),
+        }
+    }
+}
+
+/// The subset of `DataContext` valid for a `Data` record, i.e which of a process'
+/// output streams the data was read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+impl OutputStream {
+    const VALID: &'static [DataContext] = &[DataContext::Stdout, DataContext::Stderr];
+}
+
+impl From<OutputStream> for DataContext {
+    fn from(cxt: OutputStream) -> Self {
+        match cxt {
+            OutputStream::Stdout => DataContext::Stdout,
+            OutputStream::Stderr => DataContext::Stderr,
+        }
+    }
+}
+
+impl TryFrom<DataContext> for OutputStream {
+    type Error = ContextError;
+
+    fn try_from(cxt: DataContext) -> Result<Self, Self::Error> {
+        match cxt {
+            DataContext::Stdout => Ok(Self::Stdout),
+            DataContext::Stderr => Ok(Self::Stderr),
+            found => Err(ContextError {
+                found,
+                expected: Self::VALID,
+            }),
+        }
+    }
+}
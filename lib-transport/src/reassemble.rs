@@ -0,0 +1,140 @@
+use {
+    crate::owned::{HeaderContext, OutputStream, OwnedData, OwnedRecord},
+    futures::{prelude::*, ready},
+    pin_project::pin_project,
+    std::{
+        collections::{HashMap, VecDeque},
+        pin::Pin,
+        task::{Context, Poll},
+    },
+};
+
+/// Meta key flagging a `Data` record as part of a line continued by the next `Data` record
+/// with the same id and output stream, i.e when a producer splits lines longer than it is
+/// willing to buffer
+pub const CONTINUED_META: &str = "truncated";
+
+/// A line is only continued on the logical stream, id and output it was started on
+type LineKey = (Option<u32>, String, OutputStream);
+
+/// Extension trait for streams of owned records
+pub trait ReassembleExt: Stream<Item = OwnedRecord> + Sized {
+    /// Joins lines that were split across several `Data` records back into one, yielded in
+    /// place of their last part. Parts are held until the line ends, so lines are only ever
+    /// joined up to `max` bytes, beyond which what has been joined so far is yielded still
+    /// flagged as continued. A line cut short by its id's `Header` end, or the end of the
+    /// stream, is likewise yielded as it is.
+    ///
+    /// The joined record keeps the first part's time and sequence number, so any sequence
+    /// checking should happen before reassembly
+    fn reassemble_lines(self, max: usize) -> Reassembled<Self>;
+}
+
+impl<St> ReassembleExt for St
+where
+    St: Stream<Item = OwnedRecord>,
+{
+    fn reassemble_lines(self, max: usize) -> Reassembled<Self> {
+        Reassembled {
+            inner: self,
+            max,
+            partial: HashMap::new(),
+            ready: VecDeque::new(),
+        }
+    }
+}
+
+#[pin_project]
+pub struct Reassembled<St> {
+    #[pin]
+    inner: St,
+    max: usize,
+    partial: HashMap<LineKey, OwnedData>,
+    /// Records to yield before reading another, i.e lines flushed by a `Header` end
+    ready: VecDeque<OwnedRecord>,
+}
+
+impl<St> Stream for Reassembled<St>
+where
+    St: Stream<Item = OwnedRecord>,
+{
+    type Item = OwnedRecord;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            if let Some(record) = this.ready.pop_front() {
+                return Poll::Ready(Some(record));
+            }
+
+            let record = match ready!(this.inner.as_mut().poll_next(cx)) {
+                Some(record) => record,
+                None if this.partial.is_empty() => return Poll::Ready(None),
+                None => {
+                    flush(this.partial, this.ready, |_| true);
+                    continue;
+                }
+            };
+
+            match record {
+                OwnedRecord::Data(data) => {
+                    let key = (data.required.stream, data.id.clone(), data.cxt);
+                    let continued = is_continued(&data);
+
+                    let line = match this.partial.remove(&key) {
+                        Some(mut line) => {
+                            line.data.extend_from_slice(&data.data);
+                            line
+                        }
+                        None => data,
+                    };
+
+                    match continued && line.data.len() < *this.max {
+                        true => {
+                            this.partial.insert(key, line);
+                        }
+                        false => return Poll::Ready(Some(finish(line, continued))),
+                    }
+                }
+                OwnedRecord::Header(header) if header.cxt == HeaderContext::End => {
+                    let (stream, id) = (header.required.stream, &header.id);
+                    flush(this.partial, this.ready, |key| {
+                        key.0 == stream && &key.1 == id
+                    });
+                    this.ready.push_back(header.into());
+                }
+                OwnedRecord::StreamEnd => {
+                    flush(this.partial, this.ready, |_| true);
+                    this.ready.push_back(record);
+                }
+                record => return Poll::Ready(Some(record)),
+            }
+        }
+    }
+}
+
+fn is_continued(data: &OwnedData) -> bool {
+    data.meta.get(CONTINUED_META).map(String::as_str) == Some("true")
+}
+
+/// Drops the continued flag from a line that has ended
+fn finish(mut line: OwnedData, continued: bool) -> OwnedRecord {
+    if !continued {
+        line.meta.remove(CONTINUED_META);
+    }
+
+    line.into()
+}
+
+/// Queues every partial line matching `filter` as it is, in the order they were started
+fn flush<F>(partial: &mut HashMap<LineKey, OwnedData>, ready: &mut VecDeque<OwnedRecord>, filter: F)
+where
+    F: Fn(&LineKey) -> bool,
+{
+    let keys: Vec<_> = partial.keys().filter(|key| filter(key)).cloned().collect();
+    let mut lines: Vec<_> = keys.iter().filter_map(|key| partial.remove(key)).collect();
+    lines.sort_by_key(|line| (line.required.seq, line.time));
+
+    ready.extend(lines.into_iter().map(OwnedRecord::from));
+}
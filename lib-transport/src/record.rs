@@ -1,133 +1,1352 @@
 use {
     crate::{
-        error::CrateError,
-        markers::{DataContext, TagMarker},
+        error::{now_nanos, CrateError},
+        handshake::PIPELINE_PREFIX,
+        markers::{ChannelState, DataContext, Encoding, LogLevel, TagMarker},
     },
     serde::{
         de::{self, Deserializer, IgnoredAny, MapAccess, Visitor},
         ser::{SerializeMap, Serializer},
         {Deserialize, Serialize},
     },
-    std::{borrow::Cow, fmt},
+    serde_cbor::{ser::SliceWrite, Value},
+    std::{
+        borrow::Cow,
+        collections::{BTreeMap, HashMap},
+        fmt, io,
+        marker::PhantomData,
+        sync::atomic::{AtomicU32, Ordering},
+        time::Duration,
+    },
 };
 
-/// The in-memory representation of a Record. This is the mechanism by which the
-/// binaries transmit information across the wire. This struct has an intentionally
-/// minimalistic API. Any manipulation should be done via some local representation,
-/// making use of From/Into (or some similar interface) for moving into and out of
-/// said representation.
-///
-/// As an aside, this structure's Serde impl is optimized for size and _highly_ unlikely
-/// to de/serialize into a valid Record if the data is not serialized *and* deserialized as this struct.
-/// Do not attempt to de/serialize into some intermediary struct. It will end badly.
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(tag = "t", content = "c")]
-pub enum Record<'i, 'd> {
-    #[serde(rename = "ss")]
-    StreamStart,
-    #[serde(rename = "se")]
-    StreamEnd,
-    #[serde(rename = "h")]
-    Header(Header<'i>),
-    #[serde(rename = "d")]
-    Data(Data<'i, 'd>),
-    #[serde(rename = "l")]
-    Log(Log),
-    #[serde(rename = "e")]
-    Error(Error),
+/// The highest protocol version this library understands
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// The lowest protocol version this library will negotiate down to
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// The first record version whose `Data` records carry raw bytes and an `Encoding` hint,
+/// rather than UTF-8 text
+pub const BINARY_DATA_VERSION: u32 = 2;
+
+/// Version of the encoding a record of any given version is written in. Unlike
+/// `PROTOCOL_VERSION` this only changes if an existing record starts encoding to different
+/// bytes, i.e a `TagMarker` is renumbered, which the checked in corpus of encoded frames
+/// (lib-transport/tests/corpus) exists to catch
+pub const fn wire_format_version() -> u32 {
+    1
+}
+
+/// The in-memory representation of a Record. This is the mechanism by which the
+/// binaries transmit information across the wire. This struct has an intentionally
+/// minimalistic API. Any manipulation should be done via some local representation,
+/// making use of From/Into (or some similar interface) for moving into and out of
+/// said representation.
+///
+/// As an aside, this structure's Serde impl is optimized for size and _highly_ unlikely
+/// to de/serialize into a valid Record if the data is not serialized *and* deserialized as this struct.
+/// Do not attempt to de/serialize into some intermediary struct. It will end badly.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "t", content = "c")]
+pub enum Record<'i, 'd> {
+    #[serde(rename = "ss")]
+    StreamStart,
+    #[serde(rename = "se")]
+    StreamEnd,
+    #[serde(rename = "h")]
+    Header(#[serde(borrow)] Header<'i>),
+    #[serde(rename = "d")]
+    Data(#[serde(borrow)] Data<'i, 'd>),
+    #[serde(rename = "l")]
+    Log(Log),
+    #[serde(rename = "e")]
+    Error(Error),
+    #[serde(rename = "hi")]
+    Hello(Hello),
+    #[serde(rename = "hb")]
+    Heartbeat(Heartbeat),
+    #[serde(rename = "a")]
+    Ack(Ack),
+    #[serde(rename = "x")]
+    Exit(Exit<'i>),
+    #[serde(rename = "ch")]
+    Channel(Channel),
+    #[serde(rename = "m")]
+    Metrics(Metrics),
+    #[serde(rename = "f")]
+    Facts(Facts),
+}
+
+impl<'i, 'd> Record<'i, 'd> {
+    /// Deserializes a single (checksum free) frame, borrowing any strings in `Header` and `Data`
+    /// records from it rather than allocating. Use `Record::into_owned` to detach the result from
+    /// the frame if it needs to outlive it
+    pub fn from_frame(frame: &'i [u8]) -> Result<Self, io::Error>
+    where
+        'i: 'd,
+    {
+        serde_cbor::from_slice(frame).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Serializes this record into the start of `buf` as a single (checksum free) frame body,
+    /// returning the number of bytes written. Never allocates, so callers without a runtime
+    /// (i.e a WASM plugin) can hand the frame off to their host themselves. Errors with
+    /// `io::ErrorKind::WriteZero` if `buf` is too small to hold the record
+    pub fn to_frame(&self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        let mut serializer = serde_cbor::Serializer::new(SliceWrite::new(buf));
+        self.serialize(&mut serializer)
+            .map_err(|e| match e.is_scratch_too_small() {
+                true => io::Error::new(io::ErrorKind::WriteZero, e),
+                false => io::Error::new(io::ErrorKind::InvalidData, e),
+            })?;
+
+        Ok(serializer.into_inner().bytes_written())
+    }
+
+    /// Clones any borrowed data, detaching this record from the frame it was deserialized from
+    pub fn into_owned(self) -> Record<'static, 'static> {
+        match self {
+            Self::StreamStart => Record::StreamStart,
+            Self::StreamEnd => Record::StreamEnd,
+            Self::Header(r) => Record::Header(r.into_owned()),
+            Self::Data(r) => Record::Data(r.into_owned()),
+            Self::Log(r) => Record::Log(r),
+            Self::Error(r) => Record::Error(r),
+            Self::Hello(r) => Record::Hello(r),
+            Self::Heartbeat(r) => Record::Heartbeat(r),
+            Self::Channel(r) => Record::Channel(r),
+            Self::Ack(r) => Record::Ack(r),
+            Self::Exit(r) => Record::Exit(r.into_owned()),
+            Self::Metrics(r) => Record::Metrics(r),
+            Self::Facts(r) => Record::Facts(r),
+        }
+    }
+
+    /// Fields common to every record kind, None for the stream delimiters
+    pub fn common(&self) -> Option<&Common> {
+        match self {
+            Self::StreamStart | Self::StreamEnd => None,
+            Self::Header(r) => Some(&r.required),
+            Self::Data(r) => Some(&r.required),
+            Self::Log(r) => Some(&r.required),
+            Self::Error(r) => Some(&r.required),
+            Self::Hello(r) => Some(&r.required),
+            Self::Heartbeat(r) => Some(&r.required),
+            Self::Channel(r) => Some(&r.required),
+            Self::Ack(r) => Some(&r.required),
+            Self::Exit(r) => Some(&r.required),
+            Self::Metrics(r) => Some(&r.required),
+            Self::Facts(r) => Some(&r.required),
+        }
+    }
+
+    /// Mutable variant of `Record::common`
+    pub fn common_mut(&mut self) -> Option<&mut Common> {
+        match self {
+            Self::StreamStart | Self::StreamEnd => None,
+            Self::Header(r) => Some(&mut r.required),
+            Self::Data(r) => Some(&mut r.required),
+            Self::Log(r) => Some(&mut r.required),
+            Self::Error(r) => Some(&mut r.required),
+            Self::Hello(r) => Some(&mut r.required),
+            Self::Heartbeat(r) => Some(&mut r.required),
+            Self::Channel(r) => Some(&mut r.required),
+            Self::Ack(r) => Some(&mut r.required),
+            Self::Exit(r) => Some(&mut r.required),
+            Self::Metrics(r) => Some(&mut r.required),
+            Self::Facts(r) => Some(&mut r.required),
+        }
+    }
+
+    /// Convenience function for generating Record errors
+    pub fn new_error<E>(version: u32, err: E) -> Self
+    where
+        E: Into<CrateError>,
+    {
+        Self::Error(Error {
+            required: Common::new(version),
+            error: err.into(),
+            trace_id: None,
+            id: None,
+        })
+    }
+}
+
+/// Number of characters of a `Data` record's data shown by `Record`'s Display impl
+const DATA_PREVIEW_LEN: usize = 64;
+
+/// Renders a one line summary of the record, i.e for debug output or operator tooling.
+/// Data longer than `DATA_PREVIEW_LEN` characters is truncated unless the alternate flag
+/// (`{:#}`) is set
+impl fmt::Display for Record<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::StreamStart => return write!(f, "stream-start"),
+            Self::StreamEnd => return write!(f, "stream-end"),
+            Self::Header(r) => write!(
+                f,
+                "header id={} pid={} cxt={:?} time={}",
+                r.id, r.pid, r.cxt, r.time
+            )?,
+            Self::Data(r) => {
+                write!(
+                    f,
+                    "data id={} pid={} cxt={:?} time={} ",
+                    r.id, r.pid, r.cxt, r.time
+                )?;
+                let text = r.text();
+                match (f.alternate(), text.char_indices().nth(DATA_PREVIEW_LEN)) {
+                    (false, Some((end, _))) => write!(f, "{:?}...", &text[..end])?,
+                    _ => write!(f, "{:?}", text)?,
+                }
+                if r.encoding == Encoding::Binary {
+                    write!(f, " binary")?
+                }
+            }
+            Self::Log(r) => write!(f, "log level={:?} time={} {:?}", r.level, r.time, r.log)?,
+            Self::Error(r) => match &r.id {
+                Some(id) => write!(f, "error id={} {}", id, r.error)?,
+                None => write!(f, "error {}", r.error)?,
+            },
+            Self::Hello(r) => write!(
+                f,
+                "hello version={} features={:?}",
+                r.proto_version, r.features
+            )?,
+            Self::Heartbeat(r) => write!(f, "heartbeat time={}", r.time)?,
+            Self::Ack(r) => write!(f, "ack up_to_seq={}", r.up_to_seq)?,
+            Self::Exit(r) => write!(
+                f,
+                "exit id={} pid={} code={:?} signal={:?} time={}",
+                r.id, r.pid, r.code, r.signal, r.time
+            )?,
+            Self::Channel(r) => write!(f, "channel state={:?}", r.state)?,
+            Self::Metrics(r) => write!(
+                f,
+                "metrics source={} period={:?} counters={:?} gauges={:?}",
+                r.source, r.period, r.counters, r.gauges
+            )?,
+            Self::Facts(r) => write!(f, "facts {:?}", r.facts)?,
+        }
+
+        let common = self
+            .common()
+            .expect("stream delimiters have returned already");
+        if let Some(seq) = common.seq {
+            write!(f, " seq={}", seq)?
+        }
+        if let Some(stream) = common.stream {
+            write!(f, " stream={}", stream)?
+        }
+
+        Ok(())
+    }
+}
+
+/// Contains a byte slice and related context. This slice contains some unit of data that is conceptually
+/// whole or 'one' for its intended destination. It should be preceded by _one_ header record, `Context::Start`
+/// and any number of other `Data` records. It should be followed by any number of `Data` records and a single Header `Context::End`
+#[derive(Debug)]
+pub struct Data<'i, 'd> {
+    pub required: Common,
+    pub time: i64,
+    pub id: Cow<'i, str>,
+    pub pid: u32,
+    pub cxt: DataContext,
+    /// The payload, which is only ever UTF-8 text for records older than `BINARY_DATA_VERSION`
+    pub data: Cow<'d, [u8]>,
+    pub encoding: Encoding,
+    pub trace_id: Option<Cow<'i, str>>,
+    pub meta: Cow<'i, Meta>,
+    /// Fields this version doesn't understand, i.e added by a newer producer. They are
+    /// written back out as is, so they survive passing through this version
+    pub unknown: Vec<(u16, Value)>,
+}
+
+impl Data<'_, '_> {
+    pub fn into_owned(self) -> Data<'static, 'static> {
+        Data {
+            required: self.required,
+            time: self.time,
+            id: Cow::Owned(self.id.into_owned()),
+            pid: self.pid,
+            cxt: self.cxt,
+            data: Cow::Owned(self.data.into_owned()),
+            encoding: self.encoding,
+            trace_id: self.trace_id.map(|id| Cow::Owned(id.into_owned())),
+            meta: Cow::Owned(self.meta.into_owned()),
+            unknown: self.unknown,
+        }
+    }
+
+    /// The payload as text, replacing any invalid UTF-8
+    pub fn text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.data)
+    }
+}
+
+/// A header / tail record for gracefully terminating a stream of Data records. Conceptually, it is responsible for starting
+/// and terminating a stream of `Data` records
+#[derive(Debug)]
+pub struct Header<'i> {
+    pub required: Common,
+    pub time: i64,
+    pub id: Cow<'i, str>,
+    pub pid: u32,
+    pub cxt: DataContext,
+    pub trace_id: Option<Cow<'i, str>>,
+    pub meta: Cow<'i, Meta>,
+    /// Fields this version doesn't understand, i.e added by a newer producer. They are
+    /// written back out as is, so they survive passing through this version
+    pub unknown: Vec<(u16, Value)>,
+}
+
+impl Header<'_> {
+    pub fn into_owned(self) -> Header<'static> {
+        Header {
+            required: self.required,
+            time: self.time,
+            id: Cow::Owned(self.id.into_owned()),
+            pid: self.pid,
+            cxt: self.cxt,
+            trace_id: self.trace_id.map(|id| Cow::Owned(id.into_owned())),
+            meta: Cow::Owned(self.meta.into_owned()),
+            unknown: self.unknown,
+        }
+    }
+}
+
+/// Sent once a process has been reaped, after its closing `Header`. `code` is None if
+/// the process was terminated by a signal, in which case `signal` should be set
+#[derive(Debug)]
+pub struct Exit<'i> {
+    pub required: Common,
+    pub time: i64,
+    pub id: Cow<'i, str>,
+    pub pid: u32,
+    pub code: Option<i32>,
+    pub signal: Option<i32>,
+}
+
+impl Exit<'_> {
+    pub fn into_owned(self) -> Exit<'static> {
+        Exit {
+            required: self.required,
+            time: self.time,
+            id: Cow::Owned(self.id.into_owned()),
+            pid: self.pid,
+            code: self.code,
+            signal: self.signal,
+        }
+    }
+
+    /// Whether the process exited normally with a code of 0
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+/// Contains any error messages that were caused by an unexpected / non-graceful termination of a project binary
+#[derive(Debug)]
+pub struct Error {
+    pub required: Common,
+    pub error: CrateError,
+    pub trace_id: Option<String>,
+    /// Id of the executable the error concerns, if it concerns one. Set for errors about an
+    /// executable that may never have sent a header, i.e one that failed to spawn
+    pub id: Option<String>,
+}
+
+/// Contains any log messages that were produced by a project binary up the data stream.
+/// Version 1 records only carry the message, `level`, `target` and `time` are defaulted
+/// when deserializing them
+#[derive(Debug)]
+pub struct Log {
+    pub required: Common,
+    pub log: String,
+    pub level: LogLevel,
+    pub target: Option<String>,
+    pub time: i64,
+}
+
+impl Log {
+    /// Record version that introduced the level, target and time fields
+    pub const STRUCTURED_VERSION: u32 = 2;
+
+    /// Generates an `Info` log timestamped with the current system time,
+    /// see the other methods for changing its level and target
+    pub fn new<S>(log: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            required: Common::new(Self::STRUCTURED_VERSION),
+            log: log.into(),
+            level: LogLevel::default(),
+            target: None,
+            time: now_nanos(),
+        }
+    }
+
+    pub fn level(mut self, level: LogLevel) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// The module or component that produced this log
+    pub fn target<S>(mut self, target: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.target = Some(target.into());
+        self
+    }
+
+    pub fn time(mut self, time: i64) -> Self {
+        self.time = time;
+        self
+    }
+}
+
+/// Announces the sender's protocol version and optional features. It is exchanged once,
+/// before `StreamStart`, by the helpers in `handshake`
+#[derive(Debug, Clone)]
+pub struct Hello {
+    pub required: Common,
+    pub proto_version: u32,
+    pub features: Vec<String>,
+}
+
+impl Hello {
+    pub fn new<I, S>(features: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            required: Common::new(PROTOCOL_VERSION),
+            proto_version: PROTOCOL_VERSION,
+            features: features.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+
+    /// Asks the listener to send this connection's records through the pipeline `name`,
+    /// see `handshake::PIPELINE_PREFIX`
+    pub fn with_pipeline(mut self, name: &str) -> Self {
+        self.features.push(format!("{}{}", PIPELINE_PREFIX, name));
+        self
+    }
+
+    /// The pipeline this asks for, if any
+    pub fn pipeline(&self) -> Option<&str> {
+        self.features
+            .iter()
+            .find_map(|f| f.strip_prefix(PIPELINE_PREFIX))
+    }
+}
+
+/// Sent by producers while they have nothing else to send, proving the connection
+/// is still alive. Consumers should never forward these records
+#[derive(Debug, Clone)]
+pub struct Heartbeat {
+    pub required: Common,
+    pub time: i64,
+}
+
+impl Heartbeat {
+    /// Generates a heartbeat timestamped with the current system time
+    pub fn now(version: u32) -> Self {
+        Self {
+            required: Common::new(version),
+            time: now_nanos(),
+        }
+    }
+}
+
+/// Statistics a stage periodically reports about itself, i.e its throughput, so they travel
+/// in-band with the records they describe. Only sent to peers that negotiated the `metrics`
+/// feature, see `metrics::WithMetrics`
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    pub required: Common,
+    pub time: i64,
+    /// The stage reporting, i.e "extract"
+    pub source: String,
+    /// How long the gauges were measured over
+    pub period: Duration,
+    /// Running totals since the stage started
+    pub counters: BTreeMap<String, u64>,
+    /// Values measured over the last `period`, i.e rates
+    pub gauges: BTreeMap<String, f64>,
+}
+
+impl Metrics {
+    /// Generates an empty set of metrics timestamped with the current system time
+    pub fn now<S>(version: u32, source: S, period: Duration) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            required: Common::new(version),
+            time: now_nanos(),
+            source: source.into(),
+            period,
+            counters: BTreeMap::new(),
+            gauges: BTreeMap::new(),
+        }
+    }
+
+    pub fn counter<S>(mut self, name: S, value: u64) -> Self
+    where
+        S: Into<String>,
+    {
+        self.counters.insert(name.into(), value);
+        self
+    }
+
+    pub fn gauge<S>(mut self, name: S, value: f64) -> Self
+    where
+        S: Into<String>,
+    {
+        self.gauges.insert(name.into(), value);
+        self
+    }
+}
+
+/// Describes the host a stream was collected on, i.e its hostname and OS, sent right after
+/// `StreamStart` so the stream describes itself. Only sent to peers that negotiated the
+/// `facts` feature
+#[derive(Debug, Clone)]
+pub struct Facts {
+    pub required: Common,
+    pub time: i64,
+    pub facts: BTreeMap<String, String>,
+}
+
+impl Facts {
+    /// Name of the handshake feature advertising support for `Record::Facts`
+    pub const FEATURE: &'static str = "facts";
+
+    /// Generates an empty set of facts timestamped with the current system time
+    pub fn now(version: u32) -> Self {
+        Self {
+            required: Common::new(version),
+            time: now_nanos(),
+            facts: BTreeMap::new(),
+        }
+    }
+
+    pub fn fact<K, V>(mut self, name: K, value: V) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.facts.insert(name.into(), value.into());
+        self
+    }
+}
+
+/// Sent by consumers back to the producer, acknowledging that every record up to and
+/// including `up_to_seq` has been received. Only exchanged when both sides negotiated
+/// the `ack` feature, see `ack::AckWindow`
+#[derive(Debug, Clone)]
+pub struct Ack {
+    pub required: Common,
+    pub up_to_seq: u64,
+}
+
+impl Ack {
+    pub fn new(version: u32, up_to_seq: u64) -> Self {
+        Self {
+            required: Common::new(version),
+            up_to_seq,
+        }
+    }
+}
+
+/// Opens or closes a logical stream on a connection multiplexing several of them. Every
+/// record sent between the two with the same `Common::stream` belongs to that stream
+#[derive(Debug, Clone)]
+pub struct Channel {
+    pub required: Common,
+    pub state: ChannelState,
+}
+
+impl Channel {
+    pub fn open(version: u32, stream: u32) -> Self {
+        Self::new(version, stream, ChannelState::Open)
+    }
+
+    pub fn close(version: u32, stream: u32) -> Self {
+        Self::new(version, stream, ChannelState::Close)
+    }
+
+    fn new(version: u32, stream: u32, state: ChannelState) -> Self {
+        let mut required = Common::new(version);
+        required.stream = Some(stream);
+
+        Self { required, state }
+    }
+
+    /// The logical stream this record opens or closes
+    pub fn stream(&self) -> u32 {
+        self.required.stream.unwrap_or_default()
+    }
+}
+
+/// Free-form labels attached by a producer, i.e host, environment or tenant
+pub type Meta = HashMap<String, String>;
+
+/// Deserializes a string, borrowing it from the input if the deserializer allows
+struct CowStr<'a>(Cow<'a, str>);
+
+impl<'de> Deserialize<'de> for CowStr<'de> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct CowStrVisitor;
+
+        impl<'de> Visitor<'de> for CowStrVisitor {
+            type Value = CowStr<'de>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a string")
+            }
+
+            fn visit_borrowed_str<E>(self, v: &'de str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(CowStr(Cow::Borrowed(v)))
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(CowStr(Cow::Owned(v.to_owned())))
+            }
+
+            fn visit_string<E>(self, v: String) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(CowStr(Cow::Owned(v)))
+            }
+        }
+
+        deserializer.deserialize_str(CowStrVisitor)
+    }
+}
+
+/// Serializes a byte slice as bytes, rather than as a sequence of integers
+struct RawBytes<'a>(&'a [u8]);
+
+impl Serialize for RawBytes<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+/// The byte equivalent of `CowStr`
+struct CowBytes<'a>(Cow<'a, [u8]>);
+
+impl<'de> Deserialize<'de> for CowBytes<'de> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct CowBytesVisitor;
+
+        impl<'de> Visitor<'de> for CowBytesVisitor {
+            type Value = CowBytes<'de>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a byte string")
+            }
+
+            fn visit_borrowed_bytes<E>(self, v: &'de [u8]) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(CowBytes(Cow::Borrowed(v)))
+            }
+
+            fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(CowBytes(Cow::Owned(v.to_owned())))
+            }
+
+            fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(CowBytes(Cow::Owned(v)))
+            }
+        }
+
+        deserializer.deserialize_bytes(CowBytesVisitor)
+    }
+}
+
+/// Generates a new id for correlating a single script run across every binary it passes
+/// through. Ids are unique per process and are made up of the current time, the generating
+/// process' id and a counter, as 32 hex characters
+pub fn new_trace_id() -> String {
+    static COUNTER: AtomicU32 = AtomicU32::new(0);
+
+    format!(
+        "{:016x}{:08x}{:08x}",
+        now_nanos(),
+        process_id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// The current process' id
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+fn process_id() -> u32 {
+    std::process::id()
+}
+
+/// wasm32-unknown-unknown has no processes, so ids generated there only differ by time
+/// and counter
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+fn process_id() -> u32 {
+    0
+}
+
+/// Contains any fields that are common to every record kind
+#[derive(Debug, Clone)]
+pub struct Common {
+    pub version: u32,
+    /// Position of this record in the sender's stream, if the sender is sequencing
+    /// its records. See `sequence::Sequenced`
+    pub seq: Option<u64>,
+    /// The logical stream this record belongs to, if the sender is multiplexing several
+    /// streams over one connection. Each is opened and closed by a `Record::Channel`
+    pub stream: Option<u32>,
+}
+
+impl Common {
+    pub fn new(version: u32) -> Self {
+        Self {
+            version,
+            seq: None,
+            stream: None,
+        }
+    }
+
+    fn serialize_entries<M>(&self, map: &mut M) -> Result<(), M::Error>
+    where
+        M: SerializeMap,
+    {
+        map.serialize_entry(&TagMarker::Version, &self.version)?;
+        if let Some(seq) = self.seq {
+            map.serialize_entry(&TagMarker::Seq, &seq)?;
+        }
+        if let Some(stream) = self.stream {
+            map.serialize_entry(&TagMarker::Stream, &stream)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'i, 'd> Serialize for Data<'i, 'd> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+        self.required.serialize_entries(&mut map)?;
+        map.serialize_entry(&TagMarker::Time, &self.time)?;
+        map.serialize_entry(&TagMarker::Id, &self.id)?;
+        map.serialize_entry(&TagMarker::Pid, &self.pid)?;
+        map.serialize_entry(&TagMarker::DataContext, &self.cxt)?;
+        match self.required.version < BINARY_DATA_VERSION {
+            // Older peers only understand text
+            true => map.serialize_entry(&TagMarker::Data, self.text().as_ref())?,
+            false => {
+                map.serialize_entry(&TagMarker::Bytes, &RawBytes(&self.data))?;
+                map.serialize_entry(&TagMarker::Encoding, &self.encoding)?;
+            }
+        }
+        if let Some(ref trace_id) = self.trace_id {
+            map.serialize_entry(&TagMarker::TraceId, trace_id)?;
+        }
+        if !self.meta.is_empty() {
+            map.serialize_entry(&TagMarker::Meta, self.meta.as_ref())?;
+        }
+        for (tag, value) in self.unknown.iter() {
+            map.serialize_entry(tag, value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de: 'i + 'd, 'i, 'd> Deserialize<'de> for Data<'i, 'd> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct DataVisitor<'i, 'd>(PhantomData<Data<'i, 'd>>);
+
+        impl<'de: 'i + 'd, 'i, 'd> Visitor<'de> for DataVisitor<'i, 'd> {
+            type Value = Data<'i, 'd>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("Expecting a valid 'Data' record")
+            }
+
+            fn visit_map<V>(self, mut map: V) -> Result<Self::Value, V::Error>
+            where
+                V: MapAccess<'de>,
+            {
+                macro_rules! checked_set {
+                    ($var:ident) => {{
+                        if $var.is_some() {
+                            return Err(de::Error::duplicate_field("$var"));
+                        }
+                        $var = Some(map.next_value()?);
+                    }};
+                }
+                let mut version = None;
+                let mut seq = None;
+                let mut stream = None;
+                let mut time = None;
+                let mut id = None;
+                let mut pid = None;
+                let mut cxt = None;
+                let mut data = None;
+                let mut bytes = None;
+                let mut encoding = None;
+                let mut trace_id = None;
+                let mut meta = None;
+                let mut unknown = Vec::new();
+
+                while let Some(tag) = map.next_key()? {
+                    match TagMarker::from_repr(tag) {
+                        Some(TagMarker::Version) => checked_set!(version),
+                        Some(TagMarker::Seq) => checked_set!(seq),
+                        Some(TagMarker::Stream) => checked_set!(stream),
+                        Some(TagMarker::Time) => checked_set!(time),
+                        Some(TagMarker::Id) => checked_set!(id),
+                        Some(TagMarker::Pid) => checked_set!(pid),
+                        Some(TagMarker::DataContext) => checked_set!(cxt),
+                        Some(TagMarker::Data) => checked_set!(data),
+                        Some(TagMarker::Bytes) => checked_set!(bytes),
+                        Some(TagMarker::Encoding) => checked_set!(encoding),
+                        Some(TagMarker::TraceId) => checked_set!(trace_id),
+                        Some(TagMarker::Meta) => checked_set!(meta),
+                        // Anything else, including known tags that belong to other records
+                        _ => unknown.push((tag, map.next_value()?)),
+                    }
+                }
+                let version = version.ok_or_else(|| de::Error::missing_field("version"))?;
+                let (data, encoding) = match version < BINARY_DATA_VERSION {
+                    true => (
+                        data.map(|cow: CowStr| match cow.0 {
+                            Cow::Borrowed(text) => Cow::Borrowed(text.as_bytes()),
+                            Cow::Owned(text) => Cow::Owned(text.into_bytes()),
+                        })
+                        .ok_or_else(|| de::Error::missing_field("data"))?,
+                        Encoding::Utf8,
+                    ),
+                    false => (
+                        bytes
+                            .map(|cow: CowBytes| cow.0)
+                            .ok_or_else(|| de::Error::missing_field("bytes"))?,
+                        encoding.ok_or_else(|| de::Error::missing_field("encoding"))?,
+                    ),
+                };
+                Ok(Self::Value {
+                    required: Common {
+                        version,
+                        seq,
+                        stream,
+                    },
+                    time: time.ok_or_else(|| de::Error::missing_field("time"))?,
+                    id: id
+                        .map(|cow: CowStr| cow.0)
+                        .ok_or_else(|| de::Error::missing_field("id"))?,
+                    pid: pid.ok_or_else(|| de::Error::missing_field("pid"))?,
+                    cxt: cxt.ok_or_else(|| de::Error::missing_field("cxt"))?,
+                    data,
+                    encoding,
+                    trace_id: trace_id.map(|cow: CowStr| cow.0),
+                    meta: Cow::Owned(meta.unwrap_or_default()),
+                    unknown,
+                })
+            }
+        }
+
+        const FIELDS: &[&str] = &[
+            "required", "time", "id", "pid", "data", "encoding", "trace_id", "meta",
+        ];
+        deserializer.deserialize_struct("Data", FIELDS, DataVisitor(PhantomData))
+    }
+}
+
+impl<'i> Serialize for Header<'i> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+        self.required.serialize_entries(&mut map)?;
+        map.serialize_entry(&TagMarker::Time, &self.time)?;
+        map.serialize_entry(&TagMarker::Id, &self.id)?;
+        map.serialize_entry(&TagMarker::DataContext, &self.cxt)?;
+        map.serialize_entry(&TagMarker::Pid, &self.pid)?;
+        if let Some(ref trace_id) = self.trace_id {
+            map.serialize_entry(&TagMarker::TraceId, trace_id)?;
+        }
+        if !self.meta.is_empty() {
+            map.serialize_entry(&TagMarker::Meta, self.meta.as_ref())?;
+        }
+        for (tag, value) in self.unknown.iter() {
+            map.serialize_entry(tag, value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de: 'i, 'i> Deserialize<'de> for Header<'i> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct HeaderVisitor<'i>(PhantomData<Header<'i>>);
+
+        impl<'de: 'i, 'i> Visitor<'de> for HeaderVisitor<'i> {
+            type Value = Header<'i>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("Expecting a valid 'Header' record")
+            }
+
+            fn visit_map<V>(self, mut map: V) -> Result<Self::Value, V::Error>
+            where
+                V: MapAccess<'de>,
+            {
+                macro_rules! checked_set {
+                    ($var:ident) => {{
+                        if $var.is_some() {
+                            return Err(de::Error::duplicate_field("$var"));
+                        }
+                        $var = Some(map.next_value()?);
+                    }};
+                }
+                let mut version = None;
+                let mut seq = None;
+                let mut stream = None;
+                let mut time = None;
+                let mut id = None;
+                let mut pid = None;
+                let mut cxt = None;
+                let mut trace_id = None;
+                let mut meta = None;
+                let mut unknown = Vec::new();
+
+                while let Some(tag) = map.next_key()? {
+                    match TagMarker::from_repr(tag) {
+                        Some(TagMarker::Version) => checked_set!(version),
+                        Some(TagMarker::Seq) => checked_set!(seq),
+                        Some(TagMarker::Stream) => checked_set!(stream),
+                        Some(TagMarker::Time) => checked_set!(time),
+                        Some(TagMarker::Id) => checked_set!(id),
+                        Some(TagMarker::DataContext) => checked_set!(cxt),
+                        Some(TagMarker::Pid) => checked_set!(pid),
+                        Some(TagMarker::TraceId) => checked_set!(trace_id),
+                        Some(TagMarker::Meta) => checked_set!(meta),
+                        // Anything else, including known tags that belong to other records
+                        _ => unknown.push((tag, map.next_value()?)),
+                    }
+                }
+                Ok(Self::Value {
+                    required: Common {
+                        version: version.ok_or_else(|| de::Error::missing_field("version"))?,
+                        seq,
+                        stream,
+                    },
+                    time: time.ok_or_else(|| de::Error::missing_field("time"))?,
+                    id: id
+                        .map(|cow: CowStr| cow.0)
+                        .ok_or_else(|| de::Error::missing_field("id"))?,
+                    pid: pid.ok_or_else(|| de::Error::missing_field("pid"))?,
+                    cxt: cxt.ok_or_else(|| de::Error::missing_field("cxt"))?,
+                    trace_id: trace_id.map(|cow: CowStr| cow.0),
+                    meta: Cow::Owned(meta.unwrap_or_default()),
+                    unknown,
+                })
+            }
+        }
+
+        const FIELDS: &[&str] = &["required", "time", "id", "pid", "trace_id", "meta"];
+        deserializer.deserialize_struct("Header", FIELDS, HeaderVisitor(PhantomData))
+    }
+}
+
+impl Serialize for Error {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+        self.required.serialize_entries(&mut map)?;
+        map.serialize_entry(&TagMarker::Error, &self.error)?;
+        if let Some(ref trace_id) = self.trace_id {
+            map.serialize_entry(&TagMarker::TraceId, trace_id)?;
+        }
+        if let Some(ref id) = self.id {
+            map.serialize_entry(&TagMarker::Id, id)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Error {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct ErrorVisitor;
+
+        impl<'de> Visitor<'de> for ErrorVisitor {
+            type Value = Error;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("Expecting a valid 'Error' record")
+            }
+
+            fn visit_map<V>(self, mut map: V) -> Result<Self::Value, V::Error>
+            where
+                V: MapAccess<'de>,
+            {
+                macro_rules! checked_set {
+                    ($var:ident) => {{
+                        if $var.is_some() {
+                            return Err(de::Error::duplicate_field("$var"));
+                        }
+                        $var = Some(map.next_value()?);
+                    }};
+                }
+                let mut version = None;
+                let mut seq = None;
+                let mut stream = None;
+                let mut error = None;
+                let mut trace_id = None;
+                let mut id = None;
+
+                while let Some(key) = map.next_key()? {
+                    match key {
+                        TagMarker::Version => checked_set!(version),
+                        TagMarker::Seq => checked_set!(seq),
+                        TagMarker::Stream => checked_set!(stream),
+                        // Utf8Data is still accepted from peers that wrote errors under it
+                        TagMarker::Error | TagMarker::Utf8Data => checked_set!(error),
+                        TagMarker::TraceId => checked_set!(trace_id),
+                        TagMarker::Id => checked_set!(id),
+                        _ => {
+                            let _ignored: IgnoredAny = map.next_value()?;
+                        }
+                    }
+                }
+                Ok(Self::Value {
+                    required: Common {
+                        version: version.ok_or_else(|| de::Error::missing_field("version"))?,
+                        seq,
+                        stream,
+                    },
+                    error: error.ok_or_else(|| de::Error::missing_field("error"))?,
+                    trace_id,
+                    id,
+                })
+            }
+        }
+
+        const FIELDS: &[&str] = &["required", "error", "trace_id", "id"];
+        deserializer.deserialize_struct("Error", FIELDS, ErrorVisitor)
+    }
+}
+
+impl Serialize for Log {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+        self.required.serialize_entries(&mut map)?;
+        map.serialize_entry(&TagMarker::Utf8Data, &self.log)?;
+        if self.required.version >= Self::STRUCTURED_VERSION {
+            map.serialize_entry(&TagMarker::Level, &self.level)?;
+            if let Some(ref target) = self.target {
+                map.serialize_entry(&TagMarker::Target, target)?;
+            }
+            map.serialize_entry(&TagMarker::Time, &self.time)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Log {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct LogVisitor;
+
+        impl<'de> Visitor<'de> for LogVisitor {
+            type Value = Log;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("Expecting a valid 'Log' record")
+            }
+
+            fn visit_map<V>(self, mut map: V) -> Result<Self::Value, V::Error>
+            where
+                V: MapAccess<'de>,
+            {
+                macro_rules! checked_set {
+                    ($var:ident) => {{
+                        if $var.is_some() {
+                            return Err(de::Error::duplicate_field("$var"));
+                        }
+                        $var = Some(map.next_value()?);
+                    }};
+                }
+
+                let mut version = None;
+                let mut seq = None;
+                let mut stream = None;
+                let mut log = None;
+                let mut level = None;
+                let mut target = None;
+                let mut time = None;
+
+                while let Some(key) = map.next_key()? {
+                    match key {
+                        TagMarker::Version => checked_set!(version),
+                        TagMarker::Seq => checked_set!(seq),
+                        TagMarker::Stream => checked_set!(stream),
+                        TagMarker::Utf8Data => checked_set!(log),
+                        TagMarker::Level => checked_set!(level),
+                        TagMarker::Target => checked_set!(target),
+                        TagMarker::Time => checked_set!(time),
+                        _ => {
+                            let _ignored: IgnoredAny = map.next_value()?;
+                        }
+                    }
+                }
+                Ok(Self::Value {
+                    required: Common {
+                        version: version.ok_or_else(|| de::Error::missing_field("version"))?,
+                        seq,
+                        stream,
+                    },
+                    log: log.ok_or_else(|| de::Error::missing_field("log"))?,
+                    level: level.unwrap_or_default(),
+                    target,
+                    time: time.unwrap_or_default(),
+                })
+            }
+        }
+
+        const FIELDS: &[&str] = &["required", "log", "level", "target", "time"];
+        deserializer.deserialize_struct("Log", FIELDS, LogVisitor)
+    }
+}
+
+impl Serialize for Hello {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+        self.required.serialize_entries(&mut map)?;
+        map.serialize_entry(&TagMarker::ProtoVersion, &self.proto_version)?;
+        map.serialize_entry(&TagMarker::Features, &self.features)?;
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Hello {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct HelloVisitor;
+
+        impl<'de> Visitor<'de> for HelloVisitor {
+            type Value = Hello;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("Expecting a valid 'Hello' record")
+            }
+
+            fn visit_map<V>(self, mut map: V) -> Result<Self::Value, V::Error>
+            where
+                V: MapAccess<'de>,
+            {
+                macro_rules! checked_set {
+                    ($var:ident) => {{
+                        if $var.is_some() {
+                            return Err(de::Error::duplicate_field("$var"));
+                        }
+                        $var = Some(map.next_value()?);
+                    }};
+                }
+
+                let mut version = None;
+                let mut seq = None;
+                let mut stream = None;
+                let mut proto_version = None;
+                let mut features = None;
+
+                while let Some(key) = map.next_key()? {
+                    match key {
+                        TagMarker::Version => checked_set!(version),
+                        TagMarker::Seq => checked_set!(seq),
+                        TagMarker::Stream => checked_set!(stream),
+                        TagMarker::ProtoVersion => checked_set!(proto_version),
+                        TagMarker::Features => checked_set!(features),
+                        _ => {
+                            let _ignored: IgnoredAny = map.next_value()?;
+                        }
+                    }
+                }
+                Ok(Self::Value {
+                    required: Common {
+                        version: version.ok_or_else(|| de::Error::missing_field("version"))?,
+                        seq,
+                        stream,
+                    },
+                    proto_version: proto_version
+                        .ok_or_else(|| de::Error::missing_field("proto_version"))?,
+                    features: features.unwrap_or_default(),
+                })
+            }
+        }
+
+        const FIELDS: &[&str] = &["required", "proto_version", "features"];
+        deserializer.deserialize_struct("Hello", FIELDS, HelloVisitor)
+    }
 }
 
-impl<'i, 'd> Record<'i, 'd> {
-    /// Convenience function for generating Record errors
-    pub fn new_error<E>(version: u32, err: E) -> Self
+impl Serialize for Heartbeat {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
-        E: Into<CrateError>,
+        S: Serializer,
     {
-        Self::Error(Error {
-            required: Common::new(version),
-            error: err.into(),
-        })
+        let mut map = serializer.serialize_map(None)?;
+        self.required.serialize_entries(&mut map)?;
+        map.serialize_entry(&TagMarker::Time, &self.time)?;
+        map.end()
     }
 }
 
-/// Contains a byte slice and related context. This slice contains some unit of data that is conceptually
-/// whole or 'one' for its intended destination. It should be preceded by _one_ header record, `Context::Start`
-/// and any number of other `Data` records. It should be followed by any number of `Data` records and a single Header `Context::End`
-#[derive(Debug)]
-pub struct Data<'i, 'd> {
-    pub required: Common,
-    pub time: i64,
-    pub id: Cow<'i, str>,
-    pub pid: u32,
-    pub cxt: DataContext,
-    pub data: Cow<'d, str>,
-}
+impl<'de> Deserialize<'de> for Heartbeat {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct HeartbeatVisitor;
 
-/// A header / tail record for gracefully terminating a stream of Data records. Conceptually, it is responsible for starting
-/// and terminating a stream of `Data` records
-#[derive(Debug)]
-pub struct Header<'i> {
-    pub required: Common,
-    pub time: i64,
-    pub id: Cow<'i, str>,
-    pub pid: u32,
-    pub cxt: DataContext,
-}
+        impl<'de> Visitor<'de> for HeartbeatVisitor {
+            type Value = Heartbeat;
 
-/// Contains any error messages that were caused by an unexpected / non-graceful termination of a project binary
-#[derive(Debug)]
-pub struct Error {
-    pub required: Common,
-    pub error: CrateError,
-}
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("Expecting a valid 'Heartbeat' record")
+            }
 
-/// Contains any log messages that were produced by a project binary up the data stream.
-#[derive(Debug)]
-pub struct Log {
-    pub required: Common,
-    pub log: String,
-}
+            fn visit_map<V>(self, mut map: V) -> Result<Self::Value, V::Error>
+            where
+                V: MapAccess<'de>,
+            {
+                macro_rules! checked_set {
+                    ($var:ident) => {{
+                        if $var.is_some() {
+                            return Err(de::Error::duplicate_field("$var"));
+                        }
+                        $var = Some(map.next_value()?);
+                    }};
+                }
 
-/// Contains any fields that are common to every record kind
-#[derive(Debug)]
-pub struct Common {
-    pub version: u32,
-}
+                let mut version = None;
+                let mut seq = None;
+                let mut stream = None;
+                let mut time = None;
 
-impl Common {
-    pub fn new(version: u32) -> Self {
-        Self { version }
+                while let Some(key) = map.next_key()? {
+                    match key {
+                        TagMarker::Version => checked_set!(version),
+                        TagMarker::Seq => checked_set!(seq),
+                        TagMarker::Stream => checked_set!(stream),
+                        TagMarker::Time => checked_set!(time),
+                        _ => {
+                            let _ignored: IgnoredAny = map.next_value()?;
+                        }
+                    }
+                }
+                Ok(Self::Value {
+                    required: Common {
+                        version: version.ok_or_else(|| de::Error::missing_field("version"))?,
+                        seq,
+                        stream,
+                    },
+                    time: time.ok_or_else(|| de::Error::missing_field("time"))?,
+                })
+            }
+        }
+
+        const FIELDS: &[&str] = &["required", "time"];
+        deserializer.deserialize_struct("Heartbeat", FIELDS, HeartbeatVisitor)
     }
 }
 
-impl<'i, 'd> Serialize for Data<'i, 'd> {
+impl Serialize for Metrics {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
         let mut map = serializer.serialize_map(None)?;
-        map.serialize_entry(&TagMarker::Version, &self.required.version)?;
+        self.required.serialize_entries(&mut map)?;
         map.serialize_entry(&TagMarker::Time, &self.time)?;
-        map.serialize_entry(&TagMarker::Id, &self.id)?;
-        map.serialize_entry(&TagMarker::Pid, &self.pid)?;
-        map.serialize_entry(&TagMarker::DataContext, &self.cxt)?;
-        map.serialize_entry(&TagMarker::Data, self.data.as_ref())?;
+        map.serialize_entry(&TagMarker::Source, &self.source)?;
+        map.serialize_entry(&TagMarker::Period, &(self.period.as_millis() as u64))?;
+        if !self.counters.is_empty() {
+            map.serialize_entry(&TagMarker::Counters, &self.counters)?;
+        }
+        if !self.gauges.is_empty() {
+            map.serialize_entry(&TagMarker::Gauges, &self.gauges)?;
+        }
         map.end()
     }
 }
 
-impl<'de> Deserialize<'de> for Data<'_, '_> {
+impl<'de> Deserialize<'de> for Metrics {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        struct DataVisitor;
+        struct MetricsVisitor;
 
-        impl<'de> Visitor<'de> for DataVisitor {
-            type Value = Data<'static, 'static>;
+        impl<'de> Visitor<'de> for MetricsVisitor {
+            type Value = Metrics;
 
             fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-                formatter.write_str("Expecting a valid 'Data' record")
+                formatter.write_str("Expecting a valid 'Metrics' record")
             }
 
             fn visit_map<V>(self, mut map: V) -> Result<Self::Value, V::Error>
@@ -142,21 +1361,26 @@ impl<'de> Deserialize<'de> for Data<'_, '_> {
                         $var = Some(map.next_value()?);
                     }};
                 }
+
                 let mut version = None;
+                let mut seq = None;
+                let mut stream = None;
                 let mut time = None;
-                let mut id = None;
-                let mut pid = None;
-                let mut cxt = None;
-                let mut data = None;
+                let mut source = None;
+                let mut period = None;
+                let mut counters = None;
+                let mut gauges = None;
 
                 while let Some(key) = map.next_key()? {
                     match key {
                         TagMarker::Version => checked_set!(version),
+                        TagMarker::Seq => checked_set!(seq),
+                        TagMarker::Stream => checked_set!(stream),
                         TagMarker::Time => checked_set!(time),
-                        TagMarker::Id => checked_set!(id),
-                        TagMarker::Pid => checked_set!(pid),
-                        TagMarker::DataContext => checked_set!(cxt),
-                        TagMarker::Data => checked_set!(data),
+                        TagMarker::Source => checked_set!(source),
+                        TagMarker::Period => checked_set!(period),
+                        TagMarker::Counters => checked_set!(counters),
+                        TagMarker::Gauges => checked_set!(gauges),
                         _ => {
                             let _ignored: IgnoredAny = map.next_value()?;
                         }
@@ -165,52 +1389,52 @@ impl<'de> Deserialize<'de> for Data<'_, '_> {
                 Ok(Self::Value {
                     required: Common {
                         version: version.ok_or_else(|| de::Error::missing_field("version"))?,
+                        seq,
+                        stream,
                     },
                     time: time.ok_or_else(|| de::Error::missing_field("time"))?,
-                    id: id
-                        .map(|cow: String| cow.into())
-                        .ok_or_else(|| de::Error::missing_field("id"))?,
-                    pid: pid.ok_or_else(|| de::Error::missing_field("pid"))?,
-                    cxt: cxt.ok_or_else(|| de::Error::missing_field("cxt"))?,
-                    data: data
-                        .map(|cow: String| cow.into())
-                        .ok_or_else(|| de::Error::missing_field("data"))?,
+                    source: source.ok_or_else(|| de::Error::missing_field("source"))?,
+                    period: period
+                        .map(Duration::from_millis)
+                        .ok_or_else(|| de::Error::missing_field("period"))?,
+                    counters: counters.unwrap_or_default(),
+                    gauges: gauges.unwrap_or_default(),
                 })
             }
         }
 
-        const FIELDS: &[&str] = &["required", "time", "id", "pid", "data"];
-        deserializer.deserialize_struct("Data", FIELDS, DataVisitor)
+        const FIELDS: &[&str] = &["required", "time", "source", "period", "counters", "gauges"];
+        deserializer.deserialize_struct("Metrics", FIELDS, MetricsVisitor)
     }
 }
 
-impl<'i> Serialize for Header<'i> {
+impl Serialize for Facts {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
         let mut map = serializer.serialize_map(None)?;
-        map.serialize_entry(&TagMarker::Version, &self.required.version)?;
+        self.required.serialize_entries(&mut map)?;
         map.serialize_entry(&TagMarker::Time, &self.time)?;
-        map.serialize_entry(&TagMarker::Id, &self.id)?;
-        map.serialize_entry(&TagMarker::DataContext, &self.cxt)?;
-        map.serialize_entry(&TagMarker::Pid, &self.pid)?;
+        if !self.facts.is_empty() {
+            map.serialize_entry(&TagMarker::Facts, &self.facts)?;
+        }
         map.end()
     }
 }
 
-impl<'de> Deserialize<'de> for Header<'_> {
+impl<'de> Deserialize<'de> for Facts {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        struct HeaderVisitor;
+        struct FactsVisitor;
 
-        impl<'de> Visitor<'de> for HeaderVisitor {
-            type Value = Header<'static>;
+        impl<'de> Visitor<'de> for FactsVisitor {
+            type Value = Facts;
 
             fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-                formatter.write_str("Expecting a valid 'Header' record")
+                formatter.write_str("Expecting a valid 'Facts' record")
             }
 
             fn visit_map<V>(self, mut map: V) -> Result<Self::Value, V::Error>
@@ -225,19 +1449,20 @@ impl<'de> Deserialize<'de> for Header<'_> {
                         $var = Some(map.next_value()?);
                     }};
                 }
+
                 let mut version = None;
+                let mut seq = None;
+                let mut stream = None;
                 let mut time = None;
-                let mut id = None;
-                let mut pid = None;
-                let mut cxt = None;
+                let mut facts = None;
 
                 while let Some(key) = map.next_key()? {
                     match key {
                         TagMarker::Version => checked_set!(version),
+                        TagMarker::Seq => checked_set!(seq),
+                        TagMarker::Stream => checked_set!(stream),
                         TagMarker::Time => checked_set!(time),
-                        TagMarker::Id => checked_set!(id),
-                        TagMarker::DataContext => checked_set!(cxt),
-                        TagMarker::Pid => checked_set!(pid),
+                        TagMarker::Facts => checked_set!(facts),
                         _ => {
                             let _ignored: IgnoredAny = map.next_value()?;
                         }
@@ -246,46 +1471,44 @@ impl<'de> Deserialize<'de> for Header<'_> {
                 Ok(Self::Value {
                     required: Common {
                         version: version.ok_or_else(|| de::Error::missing_field("version"))?,
+                        seq,
+                        stream,
                     },
                     time: time.ok_or_else(|| de::Error::missing_field("time"))?,
-                    id: id
-                        .map(|cow: String| cow.into())
-                        .ok_or_else(|| de::Error::missing_field("id"))?,
-                    pid: pid.ok_or_else(|| de::Error::missing_field("pid"))?,
-                    cxt: cxt.ok_or_else(|| de::Error::missing_field("cxt"))?,
+                    facts: facts.unwrap_or_default(),
                 })
             }
         }
 
-        const FIELDS: &[&str] = &["required", "time", "id", "pid"];
-        deserializer.deserialize_struct("Header", FIELDS, HeaderVisitor)
+        const FIELDS: &[&str] = &["required", "time", "facts"];
+        deserializer.deserialize_struct("Facts", FIELDS, FactsVisitor)
     }
 }
 
-impl Serialize for Error {
+impl Serialize for Ack {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
         let mut map = serializer.serialize_map(None)?;
-        map.serialize_entry(&TagMarker::Version, &self.required.version)?;
-        map.serialize_entry(&TagMarker::Error, &self.error)?;
+        self.required.serialize_entries(&mut map)?;
+        map.serialize_entry(&TagMarker::Sequence, &self.up_to_seq)?;
         map.end()
     }
 }
 
-impl<'de> Deserialize<'de> for Error {
+impl<'de> Deserialize<'de> for Ack {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        struct ErrorVisitor;
+        struct AckVisitor;
 
-        impl<'de> Visitor<'de> for ErrorVisitor {
-            type Value = Error;
+        impl<'de> Visitor<'de> for AckVisitor {
+            type Value = Ack;
 
             fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-                formatter.write_str("Expecting a valid 'Error' record")
+                formatter.write_str("Expecting a valid 'Ack' record")
             }
 
             fn visit_map<V>(self, mut map: V) -> Result<Self::Value, V::Error>
@@ -300,13 +1523,18 @@ impl<'de> Deserialize<'de> for Error {
                         $var = Some(map.next_value()?);
                     }};
                 }
+
                 let mut version = None;
-                let mut error = None;
+                let mut seq = None;
+                let mut stream = None;
+                let mut up_to_seq = None;
 
                 while let Some(key) = map.next_key()? {
                     match key {
                         TagMarker::Version => checked_set!(version),
-                        TagMarker::Utf8Data => checked_set!(error),
+                        TagMarker::Seq => checked_set!(seq),
+                        TagMarker::Stream => checked_set!(stream),
+                        TagMarker::Sequence => checked_set!(up_to_seq),
                         _ => {
                             let _ignored: IgnoredAny = map.next_value()?;
                         }
@@ -315,41 +1543,135 @@ impl<'de> Deserialize<'de> for Error {
                 Ok(Self::Value {
                     required: Common {
                         version: version.ok_or_else(|| de::Error::missing_field("version"))?,
+                        seq,
+                        stream,
                     },
-                    error: error.ok_or_else(|| de::Error::missing_field("error"))?,
+                    up_to_seq: up_to_seq.ok_or_else(|| de::Error::missing_field("up_to_seq"))?,
                 })
             }
         }
 
-        const FIELDS: &[&str] = &["required", "error"];
-        deserializer.deserialize_struct("Error", FIELDS, ErrorVisitor)
+        const FIELDS: &[&str] = &["required", "up_to_seq"];
+        deserializer.deserialize_struct("Ack", FIELDS, AckVisitor)
     }
 }
 
-impl Serialize for Log {
+impl<'i> Serialize for Exit<'i> {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
         let mut map = serializer.serialize_map(None)?;
-        map.serialize_entry(&TagMarker::Version, &self.required.version)?;
-        map.serialize_entry(&TagMarker::Utf8Data, &self.log)?;
+        self.required.serialize_entries(&mut map)?;
+        map.serialize_entry(&TagMarker::Time, &self.time)?;
+        map.serialize_entry(&TagMarker::Id, &self.id)?;
+        map.serialize_entry(&TagMarker::Pid, &self.pid)?;
+        if let Some(code) = self.code {
+            map.serialize_entry(&TagMarker::Code, &code)?;
+        }
+        if let Some(signal) = self.signal {
+            map.serialize_entry(&TagMarker::Signal, &signal)?;
+        }
         map.end()
     }
 }
 
-impl<'de> Deserialize<'de> for Log {
+impl<'de> Deserialize<'de> for Exit<'_> {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        struct LogVisitor;
+        struct ExitVisitor;
 
-        impl<'de> Visitor<'de> for LogVisitor {
-            type Value = Log;
+        impl<'de> Visitor<'de> for ExitVisitor {
+            type Value = Exit<'static>;
 
             fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-                formatter.write_str("Expecting a valid 'Log' record")
+                formatter.write_str("Expecting a valid 'Exit' record")
+            }
+
+            fn visit_map<V>(self, mut map: V) -> Result<Self::Value, V::Error>
+            where
+                V: MapAccess<'de>,
+            {
+                macro_rules! checked_set {
+                    ($var:ident) => {{
+                        if $var.is_some() {
+                            return Err(de::Error::duplicate_field("$var"));
+                        }
+                        $var = Some(map.next_value()?);
+                    }};
+                }
+                let mut version = None;
+                let mut seq = None;
+                let mut stream = None;
+                let mut time = None;
+                let mut id = None;
+                let mut pid = None;
+                let mut code = None;
+                let mut signal = None;
+
+                while let Some(key) = map.next_key()? {
+                    match key {
+                        TagMarker::Version => checked_set!(version),
+                        TagMarker::Seq => checked_set!(seq),
+                        TagMarker::Stream => checked_set!(stream),
+                        TagMarker::Time => checked_set!(time),
+                        TagMarker::Id => checked_set!(id),
+                        TagMarker::Pid => checked_set!(pid),
+                        TagMarker::Code => checked_set!(code),
+                        TagMarker::Signal => checked_set!(signal),
+                        _ => {
+                            let _ignored: IgnoredAny = map.next_value()?;
+                        }
+                    }
+                }
+                Ok(Self::Value {
+                    required: Common {
+                        version: version.ok_or_else(|| de::Error::missing_field("version"))?,
+                        seq,
+                        stream,
+                    },
+                    time: time.ok_or_else(|| de::Error::missing_field("time"))?,
+                    id: id
+                        .map(|cow: String| cow.into())
+                        .ok_or_else(|| de::Error::missing_field("id"))?,
+                    pid: pid.ok_or_else(|| de::Error::missing_field("pid"))?,
+                    code,
+                    signal,
+                })
+            }
+        }
+
+        const FIELDS: &[&str] = &["required", "time", "id", "pid", "code", "signal"];
+        deserializer.deserialize_struct("Exit", FIELDS, ExitVisitor)
+    }
+}
+
+impl Serialize for Channel {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+        self.required.serialize_entries(&mut map)?;
+        map.serialize_entry(&TagMarker::State, &self.state)?;
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Channel {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct ChannelVisitor;
+
+        impl<'de> Visitor<'de> for ChannelVisitor {
+            type Value = Channel;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("Expecting a valid 'Channel' record")
             }
 
             fn visit_map<V>(self, mut map: V) -> Result<Self::Value, V::Error>
@@ -366,12 +1688,16 @@ impl<'de> Deserialize<'de> for Log {
                 }
 
                 let mut version = None;
-                let mut log = None;
+                let mut seq = None;
+                let mut stream = None;
+                let mut state = None;
 
                 while let Some(key) = map.next_key()? {
                     match key {
                         TagMarker::Version => checked_set!(version),
-                        TagMarker::Utf8Data => checked_set!(log),
+                        TagMarker::Seq => checked_set!(seq),
+                        TagMarker::Stream => checked_set!(stream),
+                        TagMarker::State => checked_set!(state),
                         _ => {
                             let _ignored: IgnoredAny = map.next_value()?;
                         }
@@ -380,13 +1706,15 @@ impl<'de> Deserialize<'de> for Log {
                 Ok(Self::Value {
                     required: Common {
                         version: version.ok_or_else(|| de::Error::missing_field("version"))?,
+                        seq,
+                        stream: Some(stream.ok_or_else(|| de::Error::missing_field("stream"))?),
                     },
-                    log: log.ok_or_else(|| de::Error::missing_field("log"))?,
+                    state: state.ok_or_else(|| de::Error::missing_field("state"))?,
                 })
             }
         }
 
-        const FIELDS: &[&str] = &["required", "log"];
-        deserializer.deserialize_struct("Log", FIELDS, LogVisitor)
+        const FIELDS: &[&str] = &["required", "state"];
+        deserializer.deserialize_struct("Channel", FIELDS, ChannelVisitor)
     }
 }
@@ -0,0 +1,164 @@
+use {
+    crate::record::Record,
+    futures::{prelude::*, ready},
+    pin_project::pin_project,
+    std::{
+        fmt,
+        pin::Pin,
+        sync::{Arc, Mutex},
+        task::{Context, Poll},
+    },
+};
+
+/// Shared counter handing out sequence numbers. Clones share the same counter,
+/// so every sink stamped by a clone produces a single, gapless sequence
+#[derive(Debug, Clone, Default)]
+pub struct Sequencer {
+    next: Arc<Mutex<u64>>,
+}
+
+/// Sink adapter that stamps every record passing through it with the next sequence
+/// number from its `Sequencer`. The number is assigned and the record handed to the
+/// underlying sink under the same lock, so records enter any shared channel further
+/// down in sequence order. The stream delimiters have no `Common` and are not stamped
+#[pin_project]
+pub struct Sequenced<Si> {
+    #[pin]
+    inner: Si,
+    sequencer: Sequencer,
+}
+
+impl<Si> Sequenced<Si> {
+    pub fn new(inner: Si, sequencer: Sequencer) -> Self {
+        Self { inner, sequencer }
+    }
+}
+
+impl<'i, 'd, Si> Sink<Record<'i, 'd>> for Sequenced<Si>
+where
+    Si: Sink<Record<'i, 'd>>,
+{
+    type Error = Si::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, mut item: Record<'i, 'd>) -> Result<(), Self::Error> {
+        let this = self.project();
+        let mut next = this
+            .sequencer
+            .next
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        match item.common_mut() {
+            Some(common) => {
+                common.seq = Some(*next);
+                this.inner.start_send(item)?;
+                *next += 1;
+                Ok(())
+            }
+            None => this.inner.start_send(item),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_close(cx)
+    }
+}
+
+/// An inconsistency in a sequenced stream of records
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anomaly {
+    /// Records `expected` up to (but not including) `received` never arrived
+    Gap { expected: u64, received: u64 },
+    /// A record arrived with a sequence number that has already been seen
+    Duplicate { seq: u64 },
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Gap { expected, received } => write!(
+                f,
+                "{} record(s) missing, expected sequence {} but received {}",
+                received - expected,
+                expected,
+                received
+            ),
+            Self::Duplicate { seq } => write!(f, "duplicate record with sequence {}", seq),
+        }
+    }
+}
+
+/// Extension trait for streams of deserialized records
+pub trait SequenceExt: Sized {
+    /// Calls `report` for every gap or duplicate detected in this stream's sequence numbers,
+    /// which are expected to start at 0. Records are passed through untouched, unsequenced
+    /// records are ignored
+    fn check_sequence<F>(self, report: F) -> CheckSequence<Self, F>
+    where
+        F: FnMut(Anomaly);
+}
+
+impl<'i, 'd, St, E> SequenceExt for St
+where
+    St: Stream<Item = Result<Record<'i, 'd>, E>>,
+{
+    fn check_sequence<F>(self, report: F) -> CheckSequence<Self, F>
+    where
+        F: FnMut(Anomaly),
+    {
+        CheckSequence {
+            inner: self,
+            report,
+            expected: 0,
+        }
+    }
+}
+
+#[pin_project]
+pub struct CheckSequence<St, F> {
+    #[pin]
+    inner: St,
+    report: F,
+    expected: u64,
+}
+
+impl<'i, 'd, St, E, F> Stream for CheckSequence<St, F>
+where
+    St: Stream<Item = Result<Record<'i, 'd>, E>>,
+    F: FnMut(Anomaly),
+{
+    type Item = St::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let item = ready!(this.inner.poll_next(cx));
+
+        if let Some(seq) = item
+            .as_ref()
+            .and_then(|res| res.as_ref().ok())
+            .and_then(|record| record.common())
+            .and_then(|common| common.seq)
+        {
+            let expected = *this.expected;
+            if seq > expected {
+                (this.report)(Anomaly::Gap {
+                    expected,
+                    received: seq,
+                })
+            } else if seq < expected {
+                (this.report)(Anomaly::Duplicate { seq })
+            }
+            *this.expected = expected.max(seq + 1);
+        }
+
+        Poll::Ready(item)
+    }
+}
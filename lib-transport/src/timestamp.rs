@@ -0,0 +1,80 @@
+use {
+    chrono::{DateTime, TimeZone, Utc},
+    std::{cmp::Ordering, time::Duration},
+};
+
+const NANOS_PER_SEC: i64 = 1_000_000_000;
+
+/// Extension trait for record times, the nanoseconds since the unix epoch stored in the
+/// `time` field of every timestamped record
+pub trait RecordTime: Sized {
+    /// The current time, as a record time
+    fn now() -> Self;
+
+    /// Converts a `DateTime` into a record time. Saturates outside of the ~584 years a
+    /// record time can represent, centered on 1970
+    fn from_datetime<Tz>(time: &DateTime<Tz>) -> Self
+    where
+        Tz: TimeZone;
+
+    /// Converts this record time back into a `DateTime`
+    fn to_datetime(&self) -> DateTime<Utc>;
+
+    /// Whether this and `other` are no further than `skew` apart, i.e if two
+    /// producers whose clocks disagree by up to `skew` could have stamped them at once
+    fn within(&self, other: &Self, skew: Duration) -> bool;
+
+    /// Orders this against `other`, treating any times within `skew` of each other as
+    /// equal. Use this when ordering records from different hosts, so that clock skew isn't
+    /// mistaken for ordering. Note that this is not transitive, sort by `Ord` first
+    fn cmp_skewed(&self, other: &Self, skew: Duration) -> Ordering;
+
+    /// Whether this is before `other` by more than `skew`
+    fn before(&self, other: &Self, skew: Duration) -> bool {
+        self.cmp_skewed(other, skew) == Ordering::Less
+    }
+
+    /// Whether this is after `other` by more than `skew`
+    fn after(&self, other: &Self, skew: Duration) -> bool {
+        self.cmp_skewed(other, skew) == Ordering::Greater
+    }
+}
+
+impl RecordTime for i64 {
+    fn now() -> Self {
+        crate::error::now_nanos()
+    }
+
+    fn from_datetime<Tz>(time: &DateTime<Tz>) -> Self
+    where
+        Tz: TimeZone,
+    {
+        time.timestamp()
+            .checked_mul(NANOS_PER_SEC)
+            .and_then(|secs| secs.checked_add(time.timestamp_subsec_nanos() as i64))
+            .unwrap_or_else(|| match time.timestamp() < 0 {
+                true => i64::MIN,
+                false => i64::MAX,
+            })
+    }
+
+    fn to_datetime(&self) -> DateTime<Utc> {
+        Utc.timestamp(
+            self.div_euclid(NANOS_PER_SEC),
+            self.rem_euclid(NANOS_PER_SEC) as u32,
+        )
+    }
+
+    fn within(&self, other: &Self, skew: Duration) -> bool {
+        self.cmp_skewed(other, skew) == Ordering::Equal
+    }
+
+    fn cmp_skewed(&self, other: &Self, skew: Duration) -> Ordering {
+        // Widened, as the difference between any two i64s always fits in an i128
+        let diff = *self as i128 - *other as i128;
+        match diff.unsigned_abs() <= skew.as_nanos() {
+            true => Ordering::Equal,
+            false => diff.cmp(&0),
+        }
+    }
+}
@@ -1,222 +1,319 @@
 use {
-    crate::record::Record,
-    futures::{pin_mut, prelude::*, ready},
-    pin_project::pin_project,
-    serde::Serialize,
-    std::{
-        io,
-        pin::Pin,
-        task::{Context, Poll},
-    },
+    crate::{codec::LENGTH_LEN, interface::RecordInterface, limits::Limits},
+    bytes::{Buf, Bytes, BytesMut},
+    std::{convert::TryFrom, io},
     tokio::io::{AsyncRead, AsyncWrite},
-    tokio_serde::{Deserializer, Serializer},
-    tokio_util::codec::{Framed, FramedRead, FramedWrite, LengthDelimitedCodec},
+    tokio_util::codec::{Decoder, Encoder, Framed, FramedRead, FramedWrite, LengthDelimitedCodec},
 };
 
-pub use {
-    bytes::{Bytes, BytesMut},
-    tokio_serde::formats::{Cbor, SymmetricalCbor},
-};
+pub use tokio_serde::formats::{Cbor, SymmetricalCbor};
 
 /// Contains convenience methods for generating framed readers/writers
 pub struct RecordFrame;
 
 impl RecordFrame {
+    /// Configures the framing before generating a reader/writer, see `RecordFrameBuilder`
+    pub fn builder() -> RecordFrameBuilder {
+        RecordFrameBuilder::new()
+    }
+
     /// Framed variant that is read and write
-    pub fn read_write<T>(io: T) -> Framed<T, LengthDelimitedCodec>
+    pub fn read_write<T>(io: T) -> Framed<T, FrameCodec>
     where
         T: AsyncRead + AsyncWrite,
     {
-        Framed::new(io, LengthDelimitedCodec::default())
+        Framed::new(io, FrameCodec::default())
     }
 
     /// Read only variant
-    pub fn read<T>(io: T) -> FramedRead<T, LengthDelimitedCodec>
+    pub fn read<T>(io: T) -> FramedRead<T, FrameCodec>
     where
         T: AsyncRead,
     {
-        FramedRead::new(io, LengthDelimitedCodec::default())
+        FramedRead::new(io, FrameCodec::default())
     }
 
     /// Write only variant
-    pub fn write<T>(io: T) -> FramedWrite<T, LengthDelimitedCodec>
+    pub fn write<T>(io: T) -> FramedWrite<T, FrameCodec>
     where
         T: AsyncWrite,
     {
-        FramedWrite::new(io, LengthDelimitedCodec::default())
+        FramedWrite::new(io, FrameCodec::default())
     }
 }
 
-/// Provides an interface for moving from deserialized Records to serialized
-/// byte buffers and vice versa.
-#[pin_project]
-pub struct RecordInterface<IF> {
-    #[pin]
-    inner: IF,
+/// Configures how records are framed, for interop with producers that don't frame them the
+/// way this library does. Defaults to the library's own framing: each frame is preceded by
+/// a 4 byte big endian length of its body
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RecordFrameBuilder {
+    limits: Limits,
+    field: LengthField,
 }
 
-impl<IF> RecordInterface<IF>
-where
-    IF: TryStream<Ok = BytesMut>,
-    IF: Sink<Bytes>,
-    <IF as TryStream>::Error: From<io::Error>,
-    <IF as Sink<Bytes>>::Error: From<io::Error>,
-{
-    /// Generates an Interface that implements both `Sink<T: Serialize>` and `TryStream<Ok = Record>`
-    /// from an underlying object. Most commonly, a
-    /// `RecordFrame` instance.
-    /// It is useful for situations when you need to both deserialize and serialize `Record`s.
-    /// If you only have the async IO stream (i.e a type that is `AsyncRead + AsyncWrite`)
-    /// prefer using `RecordInterface::from_both`
-    pub fn new_both(inner: IF) -> Self {
-        Self { inner }
+impl RecordFrameBuilder {
+    pub fn new() -> Self {
+        Self::default()
     }
-}
 
-impl<IF> RecordInterface<IF>
-where
-    IF: TryStream<Ok = BytesMut>,
-    IF::Error: From<io::Error>,
-{
-    /// Generates an Interface that implements `TryStream<Ok = Record>`
-    /// This function is useful when the IO stream is being handled further
-    /// up the data stream, for example, if your data stream looks like this: TCP Socket -> `RecordFrameRead` -> `map()` -> `channel` -> ... -> `RecordInterface`
+    /// Rejects any frame that breaches `limits`, see `Limits`
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Shorthand for setting only the max frame length of this builder's `Limits`
+    pub fn max_frame_length(mut self, max_frame_len: usize) -> Self {
+        self.limits = self.limits.with_max_frame_len(max_frame_len);
+        self
+    }
+
+    /// Sets the number of bytes in each frame's length field
+    ///
+    /// # Panics
     ///
-    /// If you only have the async IO stream (i.e a type that is at least `AsyncRead`)
-    /// prefer using `RecordInterface::from_write`
-    pub fn new_stream(inner: IF) -> Self {
-        Self { inner }
+    /// If `len` is not between 1 and 8 inclusive
+    pub fn length_field_length(mut self, len: usize) -> Self {
+        assert!(
+            (1..=8).contains(&len),
+            "length field must be 1 to 8 bytes long, not {}",
+            len
+        );
+        self.field.len = len;
+        self
+    }
+
+    /// Sets a delta added to the value of each frame's length field to get the length of its
+    /// body, i.e `-4` for a producer whose length field counts the field itself
+    pub fn length_adjustment(mut self, adjustment: isize) -> Self {
+        self.field.adjustment = adjustment;
+        self
+    }
+
+    /// Reads and writes the length field most significant byte first, the default
+    pub fn big_endian(mut self) -> Self {
+        self.field.big_endian = true;
+        self
+    }
+
+    /// Reads and writes the length field least significant byte first
+    pub fn little_endian(mut self) -> Self {
+        self.field.big_endian = false;
+        self
+    }
+
+    /// Generates a codec with this builder's framing
+    pub fn new_codec(&self) -> FrameCodec {
+        FrameCodec::with_field(self.limits, self.field)
+    }
+
+    /// Framed variant that is read and write
+    pub fn read_write<T>(&self, io: T) -> Framed<T, FrameCodec>
+    where
+        T: AsyncRead + AsyncWrite,
+    {
+        Framed::new(io, self.new_codec())
+    }
+
+    /// Read only variant
+    pub fn read<T>(&self, io: T) -> FramedRead<T, FrameCodec>
+    where
+        T: AsyncRead,
+    {
+        FramedRead::new(io, self.new_codec())
+    }
+
+    /// Write only variant
+    pub fn write<T>(&self, io: T) -> FramedWrite<T, FrameCodec>
+    where
+        T: AsyncWrite,
+    {
+        FramedWrite::new(io, self.new_codec())
     }
 }
 
-impl<IF> RecordInterface<IF>
-where
-    IF: Sink<Bytes>,
-    IF::Error: From<io::Error>,
-{
-    /// Generates an Interface that implements `Sink<T: Serialize>` from an underlying
-    /// sink, most commonly a `RecordFrameWrite`
-    /// This function is useful when the IO stream is further down the data stream, i.e `RecordInterface` -> `channel` -> `inspect()` -> TCP Socket.
-    ///
-    /// If you only have the async IO stream (i.e a type that is at least `AsyncWrite`)
-    /// prefer using `RecordInterface::from_read`
-    pub fn new_sink(inner: IF) -> Self {
-        Self { inner }
+/// Shape of the length field preceding each frame
+#[derive(Debug, Clone, Copy)]
+struct LengthField {
+    len: usize,
+    adjustment: isize,
+    big_endian: bool,
+}
+
+impl LengthField {
+    /// Length of the body following `prefix`, erroring if it is larger than `limits` allow
+    fn frame_length(&self, mut prefix: &[u8], limits: &Limits) -> io::Result<usize> {
+        let length = match self.big_endian {
+            true => prefix.get_uint(self.len),
+            false => prefix.get_uint_le(self.len),
+        };
+        let length = usize::try_from(length).unwrap_or(usize::MAX);
+        let length = match self.adjustment < 0 {
+            true => length.checked_sub(self.adjustment.unsigned_abs()),
+            false => length.checked_add(self.adjustment.unsigned_abs()),
+        }
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "frame length would overflow after adjustment",
+            )
+        })?;
+
+        limits.check_frame_len(length).map(|_| length)
+    }
+}
+
+impl Default for LengthField {
+    fn default() -> Self {
+        Self {
+            len: LENGTH_LEN,
+            adjustment: 0,
+            big_endian: true,
+        }
+    }
+}
+
+/// Length delimited codec that rejects any frame larger than its `Limits` allow
+/// with an error of kind `Kind::Limit`, before buffering the frame
+#[derive(Debug)]
+pub struct FrameCodec {
+    inner: LengthDelimitedCodec,
+    limits: Limits,
+    field: LengthField,
+    /// Whether the inner codec has consumed the current frame's length, but not yet its body
+    mid_frame: bool,
+}
+
+impl FrameCodec {
+    pub fn new(limits: Limits) -> Self {
+        Self::with_field(limits, LengthField::default())
+    }
+
+    fn with_field(limits: Limits, field: LengthField) -> Self {
+        let mut builder = LengthDelimitedCodec::builder();
+        builder
+            .length_field_length(field.len)
+            .length_adjustment(field.adjustment)
+            // Limits are checked against the adjusted length before the inner codec sees the
+            // field, so only guard against the unadjusted length overflowing
+            .max_frame_length(
+                limits
+                    .max_frame_len()
+                    .saturating_add(field.adjustment.unsigned_abs()),
+            );
+        match field.big_endian {
+            true => builder.big_endian(),
+            false => builder.little_endian(),
+        };
+
+        Self {
+            inner: builder.new_codec(),
+            limits,
+            field,
+            mid_frame: false,
+        }
+    }
+}
+
+impl Default for FrameCodec {
+    fn default() -> Self {
+        Self::new(Limits::default())
+    }
+}
+
+impl Decoder for FrameCodec {
+    type Item = BytesMut;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        // Once the length has been consumed, src starts part way into the frame's body
+        let at_length = !self.mid_frame && src.len() >= self.field.len;
+        if at_length {
+            self.field
+                .frame_length(&src[..self.field.len], &self.limits)?;
+        }
+        let frame = self.inner.decode(src)?;
+        self.mid_frame = frame.is_none() && (self.mid_frame || at_length);
+
+        Ok(frame)
+    }
+}
+
+impl Encoder<Bytes> for FrameCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Bytes, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.limits.check_frame_len(item.len())?;
+        self.inner.encode(item, dst)
     }
 }
 
-impl<T> RecordInterface<Framed<T, LengthDelimitedCodec>>
+impl<T> RecordInterface<Framed<T, FrameCodec>>
 where
     T: AsyncRead + AsyncWrite,
 {
     /// Generates an Interface that implements both `Sink<T: Serialize>` and `TryStream<Ok = Record>`
     /// this function requires that the underlying io type is `AsyncRead + AsyncWrite`
     pub fn from_both(io: T) -> Self {
-        Framed::new(io, LengthDelimitedCodec::new()).into()
+        Self::from_both_limited(io, Limits::default())
+    }
+
+    /// As `RecordInterface::from_both`, rejecting any received record that breaches `limits`
+    pub fn from_both_limited(io: T, limits: Limits) -> Self {
+        Self::from(Framed::new(io, FrameCodec::new(limits))).with_limits(limits)
     }
 }
 
-impl<T> RecordInterface<FramedWrite<T, LengthDelimitedCodec>>
+impl<T> RecordInterface<FramedWrite<T, FrameCodec>>
 where
     T: AsyncWrite,
 {
     /// Generates a write only Interface that implements `Sink<T: Serialize>`
     /// this function only requires that the underlying io type is `AsyncWrite`
     pub fn from_write(io: T) -> Self {
-        FramedWrite::new(io, LengthDelimitedCodec::new()).into()
+        FramedWrite::new(io, FrameCodec::default()).into()
     }
 }
 
-impl<T> RecordInterface<FramedRead<T, LengthDelimitedCodec>>
+impl<T> RecordInterface<FramedRead<T, FrameCodec>>
 where
     T: AsyncRead,
 {
     /// Generates a read only Interface that implements `TryStream<Ok = Record>`
     /// this function only requires that the underlying io type is `AsyncRead`
     pub fn from_read(io: T) -> Self {
-        FramedRead::new(io, LengthDelimitedCodec::new()).into()
+        Self::from_read_limited(io, Limits::default())
+    }
+
+    /// As `RecordInterface::from_read`, rejecting any received record that breaches `limits`
+    pub fn from_read_limited(io: T, limits: Limits) -> Self {
+        Self::from(FramedRead::new(io, FrameCodec::new(limits))).with_limits(limits)
     }
 }
 
-impl<T> From<Framed<T, LengthDelimitedCodec>> for RecordInterface<Framed<T, LengthDelimitedCodec>>
+impl<T> From<Framed<T, FrameCodec>> for RecordInterface<Framed<T, FrameCodec>>
 where
     T: AsyncRead + AsyncWrite,
 {
-    fn from(framed_io: Framed<T, LengthDelimitedCodec>) -> Self {
+    fn from(framed_io: Framed<T, FrameCodec>) -> Self {
         RecordInterface::new_both(framed_io)
     }
 }
 
-impl<T> From<FramedRead<T, LengthDelimitedCodec>>
-    for RecordInterface<FramedRead<T, LengthDelimitedCodec>>
+impl<T> From<FramedRead<T, FrameCodec>> for RecordInterface<FramedRead<T, FrameCodec>>
 where
     T: AsyncRead,
 {
-    fn from(framed_io: FramedRead<T, LengthDelimitedCodec>) -> Self {
+    fn from(framed_io: FramedRead<T, FrameCodec>) -> Self {
         RecordInterface::new_stream(framed_io)
     }
 }
 
-impl<T> From<FramedWrite<T, LengthDelimitedCodec>>
-    for RecordInterface<FramedWrite<T, LengthDelimitedCodec>>
+impl<T> From<FramedWrite<T, FrameCodec>> for RecordInterface<FramedWrite<T, FrameCodec>>
 where
     T: AsyncWrite,
 {
-    fn from(framed_io: FramedWrite<T, LengthDelimitedCodec>) -> Self {
+    fn from(framed_io: FramedWrite<T, FrameCodec>) -> Self {
         RecordInterface::new_sink(framed_io)
     }
 }
-
-impl<IF, E> Stream for RecordInterface<IF>
-where
-    IF: Stream<Item = Result<BytesMut, E>>,
-    IF: TryStream<Ok = BytesMut, Error = E>,
-    E: From<io::Error>,
-{
-    type Item = Result<Record<'static, 'static>, IF::Error>;
-
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        match ready!(self.as_mut().project().inner.poll_next(cx)) {
-            Some(res) => match res {
-                Ok(bytes) => {
-                    let mkr = SymmetricalCbor::<Record>::default();
-                    pin_mut!(mkr);
-                    Poll::Ready(Some(Ok(mkr.deserialize(&bytes)?)))
-                }
-                Err(e) => Poll::Ready(Some(Err(e))),
-            },
-            None => Poll::Ready(None),
-        }
-    }
-}
-
-impl<IF, T> Sink<T> for RecordInterface<IF>
-where
-    IF: Sink<Bytes>,
-    IF::Error: From<io::Error>,
-    T: Serialize,
-{
-    type Error = IF::Error;
-
-    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.project().inner.poll_ready(cx)
-    }
-
-    fn start_send(mut self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
-        let mkr = SymmetricalCbor::<T>::default();
-        pin_mut!(mkr);
-        let bytes = mkr.serialize(&item)?;
-
-        self.as_mut().project().inner.start_send(bytes)?;
-        Ok(())
-    }
-
-    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.project().inner.poll_flush(cx)
-    }
-
-    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        ready!(self.as_mut().project().inner.poll_flush(cx))?;
-        self.project().inner.poll_close(cx)
-    }
-}
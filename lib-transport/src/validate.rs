@@ -0,0 +1,305 @@
+use {
+    crate::{
+        markers::{ChannelState, DataContext},
+        record::Record,
+    },
+    futures::{prelude::*, ready},
+    pin_project::pin_project,
+    std::{
+        collections::HashSet,
+        fmt,
+        pin::Pin,
+        task::{Context, Poll},
+    },
+};
+
+/// A record that breaks the ordering rules of a record stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// A record arrived before the stream was opened with `StreamStart`
+    MissingStreamStart,
+    /// `StreamStart` arrived after the stream had already been opened
+    DuplicateStreamStart,
+    /// A record arrived after the stream was closed with `StreamEnd`
+    RecordAfterStreamEnd,
+    /// A start `Header` arrived for an id that is already open
+    DuplicateHeader { id: String },
+    /// An end `Header` arrived for an id that was never opened
+    UnopenedHeaderEnd { id: String },
+    /// A `Data` record arrived for an id with no open `Header`
+    DataOutsideHeader { id: String },
+    /// A `Header` or `Data` record carried a context that isn't valid for its kind
+    InvalidContext { id: String, cxt: DataContext },
+    /// The stream was closed, or ended, with ids or channels still open
+    Unterminated { open: Vec<String> },
+    /// A channel was opened while already open
+    DuplicateChannel { stream: u32 },
+    /// A record belonging to a logical stream arrived while its channel wasn't open
+    ChannelNotOpen { stream: u32 },
+    /// A channel was closed with ids still open, which have been abandoned
+    UnfinishedChannel { stream: u32, open: Vec<String> },
+}
+
+impl Violation {
+    /// Whether this violation breaks the stream's framing, after which nothing
+    /// further in the stream can be trusted
+    pub fn is_fatal(&self) -> bool {
+        match self {
+            Self::MissingStreamStart
+            | Self::DuplicateStreamStart
+            | Self::RecordAfterStreamEnd
+            | Self::Unterminated { .. } => true,
+            Self::DuplicateHeader { .. }
+            | Self::UnopenedHeaderEnd { .. }
+            | Self::DataOutsideHeader { .. }
+            | Self::InvalidContext { .. }
+            | Self::DuplicateChannel { .. }
+            | Self::ChannelNotOpen { .. }
+            | Self::UnfinishedChannel { .. } => false,
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingStreamStart => write!(f, "record received before 'Stream Start'"),
+            Self::DuplicateStreamStart => write!(f, "'Stream Start' sent out of sequence"),
+            Self::RecordAfterStreamEnd => write!(f, "record received after 'Stream End'"),
+            Self::DuplicateHeader { id } => write!(f, "duplicate Header record (id: {})", id),
+            Self::UnopenedHeaderEnd { id } => {
+                write!(f, "Header end received before start (id: {})", id)
+            }
+            Self::DataOutsideHeader { id } => {
+                write!(f, "Data record sent out of sequence (id: {})", id)
+            }
+            Self::InvalidContext { id, cxt } => {
+                write!(f, "invalid record context {:?} (id: {})", cxt, id)
+            }
+            Self::Unterminated { open } if open.is_empty() => {
+                write!(f, "stream ended without 'Stream End'")
+            }
+            Self::Unterminated { open } => {
+                write!(
+                    f,
+                    "stream ended with unfinished records ({})",
+                    open.join(", ")
+                )
+            }
+            Self::DuplicateChannel { stream } => write!(f, "duplicate open of channel {}", stream),
+            Self::ChannelNotOpen { stream } => {
+                write!(f, "record received for unopened channel {}", stream)
+            }
+            Self::UnfinishedChannel { stream, open } => write!(
+                f,
+                "channel {} closed with unfinished Header records (ids: {})",
+                stream,
+                open.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Violation {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Framing {
+    #[default]
+    Waiting,
+    Open,
+    Closed,
+}
+
+/// State machine tracking the ordering of a record stream. A valid stream is opened
+/// by `StreamStart` and closed by `StreamEnd`, and every id in between is opened by a
+/// start `Header` and closed by an end `Header`, with its `Data` records between the two.
+/// Records tagged with a logical stream must arrive while that stream's `Channel` is
+/// open, and ids are tracked per logical stream. The connection level records (`Hello`,
+/// `Heartbeat`, `Ack`) are valid anywhere
+#[derive(Debug, Default, Clone)]
+pub struct StreamValidator {
+    framing: Framing,
+    open: HashSet<(Option<u32>, String)>,
+    channels: HashSet<u32>,
+}
+
+impl StreamValidator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks `record` against the stream seen so far, updating the validator's state if
+    /// it is valid. Invalid records leave the state untouched, except for channels closed
+    /// with ids still open, which are closed regardless
+    pub fn check(&mut self, record: &Record<'_, '_>) -> Result<(), Violation> {
+        let stream = record.common().and_then(|common| common.stream);
+
+        match (self.framing, record) {
+            // Metrics describe the stage rather than the stream, and its final report is
+            // only sent once the stream has ended
+            (_, Record::Hello(_))
+            | (_, Record::Heartbeat(_))
+            | (_, Record::Ack(_))
+            | (_, Record::Metrics(_)) => Ok(()),
+            (Framing::Waiting, Record::StreamStart) => {
+                self.framing = Framing::Open;
+                Ok(())
+            }
+            (Framing::Waiting, _) => Err(Violation::MissingStreamStart),
+            (Framing::Closed, _) => Err(Violation::RecordAfterStreamEnd),
+            (Framing::Open, Record::StreamStart) => Err(Violation::DuplicateStreamStart),
+            (Framing::Open, Record::StreamEnd) => {
+                self.framing = Framing::Closed;
+                let open = self.drain_unfinished();
+                match open.is_empty() {
+                    true => Ok(()),
+                    false => Err(Violation::Unterminated { open }),
+                }
+            }
+            (Framing::Open, Record::Channel(channel)) => {
+                let stream = channel.stream();
+                match channel.state {
+                    ChannelState::Open => match self.channels.insert(stream) {
+                        true => Ok(()),
+                        false => Err(Violation::DuplicateChannel { stream }),
+                    },
+                    ChannelState::Close if !self.channels.remove(&stream) => {
+                        Err(Violation::ChannelNotOpen { stream })
+                    }
+                    ChannelState::Close => {
+                        let mut open: Vec<String> = self
+                            .open
+                            .iter()
+                            .filter(|(s, _)| *s == Some(stream))
+                            .map(|(_, id)| id.clone())
+                            .collect();
+                        self.open.retain(|(s, _)| *s != Some(stream));
+                        open.sort();
+
+                        match open.is_empty() {
+                            true => Ok(()),
+                            false => Err(Violation::UnfinishedChannel { stream, open }),
+                        }
+                    }
+                }
+            }
+            (Framing::Open, _) if matches!(stream, Some(s) if !self.channels.contains(&s)) => {
+                Err(Violation::ChannelNotOpen {
+                    stream: stream.unwrap_or_default(),
+                })
+            }
+            (Framing::Open, Record::Header(header)) => {
+                let key = (stream, header.id.to_string());
+                match header.cxt {
+                    DataContext::Start if self.open.contains(&key) => {
+                        Err(Violation::DuplicateHeader { id: key.1 })
+                    }
+                    DataContext::Start => {
+                        self.open.insert(key);
+                        Ok(())
+                    }
+                    DataContext::End => match self.open.remove(&key) {
+                        true => Ok(()),
+                        false => Err(Violation::UnopenedHeaderEnd { id: key.1 }),
+                    },
+                    cxt => Err(Violation::InvalidContext { id: key.1, cxt }),
+                }
+            }
+            (Framing::Open, Record::Data(data)) => {
+                let key = (stream, data.id.to_string());
+                match data.cxt {
+                    DataContext::Stdout | DataContext::Stderr => match self.open.contains(&key) {
+                        true => Ok(()),
+                        false => Err(Violation::DataOutsideHeader { id: key.1 }),
+                    },
+                    cxt => Err(Violation::InvalidContext { id: key.1, cxt }),
+                }
+            }
+            (Framing::Open, Record::Log(_))
+            | (Framing::Open, Record::Error(_))
+            | (Framing::Open, Record::Exit(_))
+            | (Framing::Open, Record::Facts(_)) => Ok(()),
+        }
+    }
+
+    /// Checks that the stream was properly closed, to be called once it has ended
+    pub fn finish(&mut self) -> Result<(), Violation> {
+        match self.framing {
+            Framing::Closed => Ok(()),
+            Framing::Waiting | Framing::Open => {
+                self.framing = Framing::Closed;
+                Err(Violation::Unterminated {
+                    open: self.drain_unfinished(),
+                })
+            }
+        }
+    }
+
+    /// Empties the validator of any open ids and channels, describing each one
+    fn drain_unfinished(&mut self) -> Vec<String> {
+        let ids = self.open.drain().map(|(stream, id)| match stream {
+            Some(stream) => format!("{}/{}", stream, id),
+            None => id,
+        });
+        let channels = self
+            .channels
+            .drain()
+            .map(|stream| format!("channel {}", stream));
+
+        let mut unfinished: Vec<String> = ids.chain(channels).collect();
+        unfinished.sort();
+        unfinished
+    }
+}
+
+/// Extension trait for streams of deserialized records
+pub trait ValidateExt: Sized {
+    /// Runs every record in this stream through a `StreamValidator`, yielding the
+    /// records that are valid and a `Violation` in place of those that aren't. If the
+    /// stream ends without being properly closed, a final violation is yielded
+    fn validate(self) -> Validate<Self>;
+}
+
+impl<'i, 'd, St> ValidateExt for St
+where
+    St: Stream<Item = Record<'i, 'd>>,
+{
+    fn validate(self) -> Validate<Self> {
+        Validate {
+            inner: self,
+            validator: StreamValidator::new(),
+            done: false,
+        }
+    }
+}
+
+#[pin_project]
+pub struct Validate<St> {
+    #[pin]
+    inner: St,
+    validator: StreamValidator,
+    done: bool,
+}
+
+impl<'i, 'd, St> Stream for Validate<St>
+where
+    St: Stream<Item = Record<'i, 'd>>,
+{
+    type Item = Result<Record<'i, 'd>, Violation>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        if *this.done {
+            return Poll::Ready(None);
+        }
+
+        match ready!(this.inner.poll_next(cx)) {
+            Some(record) => Poll::Ready(Some(this.validator.check(&record).map(|_| record))),
+            None => {
+                *this.done = true;
+                Poll::Ready(this.validator.finish().err().map(Err))
+            }
+        }
+    }
+}
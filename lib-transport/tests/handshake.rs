@@ -0,0 +1,29 @@
+//! A client must never wait forever on a listener that doesn't take part in the handshake
+
+use {
+    lib_transport::{handshake, Hello, InterfaceError, InterfaceErrorKind, RecordInterface},
+    std::{io, time::Duration},
+    tokio::net::UnixStream,
+};
+
+#[tokio::test]
+async fn connect_within_times_out_on_a_silent_listener() {
+    // The listener's end is held open, but never replies
+    let (mut client, _listener) = UnixStream::pair().unwrap();
+    let mut interface = RecordInterface::from_both(&mut client);
+
+    let res = handshake::connect_within::<_, io::Error, _>(
+        &mut interface,
+        Hello::new(Vec::<String>::new()),
+        Duration::from_millis(100),
+    )
+    .await;
+
+    let e = res.expect_err("a silent listener can't complete the handshake");
+    assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+    let source = e.get_ref().and_then(|e| e.downcast_ref::<InterfaceError>());
+    assert_eq!(
+        source.map(InterfaceError::kind),
+        Some(InterfaceErrorKind::Handshake)
+    );
+}
@@ -0,0 +1,773 @@
+//! Guards the wire format. Every record in `corpus()` must encode to exactly the frame checked
+//! in under tests/corpus, and arbitrary records must survive being encoded and decoded.
+//!
+//! After an intentional change to the encoding, regenerate the corpus with
+//! `UPDATE_CORPUS=1 cargo test --test wire_format`, bumping `wire_format_version()` if any
+//! existing frame changed rather than only new ones being added. The same goes for the
+//! canonical encodings under tests/corpus/canonical, which must never change between releases
+
+use {
+    lib_transport::{
+        decode_record, encode_record, wire_format_version, Ack, BytesMut, Channel, ChannelState,
+        Common, Compression, Data, DataContext, Encoding, Error, Exit, Facts, Header, Heartbeat,
+        Hello, InterfaceError, InterfaceErrorKind, Log, LogLevel, Meta, Metrics, Record,
+        RecordCodec, RecordSerializer, TagMarker, Value, BINARY_DATA_VERSION, DEFAULT_ZSTD_LEVEL,
+        PROTOCOL_VERSION,
+    },
+    proptest::{collection, option, prelude::*, strategy::LazyJust},
+    std::{borrow::Cow, collections::HashSet, env, fs, io, path::PathBuf, time::Duration},
+};
+
+const TIME: i64 = 1_594_000_000_123_456_789;
+const PID: u32 = 4242;
+const TRACE_ID: &str = "00000000000000000000000000000001";
+
+fn corpus_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/corpus")
+        .join(format!("v{}", wire_format_version()))
+}
+
+fn canonical_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/corpus/canonical")
+        .join(format!("v{}", wire_format_version()))
+}
+
+fn common(version: u32, seq: Option<u64>, stream: Option<u32>) -> Common {
+    Common {
+        version,
+        seq,
+        stream,
+    }
+}
+
+fn meta() -> Meta {
+    // A single entry, as the encoding of a larger map depends on its iteration order
+    vec![("host".to_string(), "build-01".to_string())]
+        .into_iter()
+        .collect()
+}
+
+/// Fields as a newer producer might add them, including a known tag on a record it doesn't belong to
+fn unknown() -> Vec<(u16, Value)> {
+    vec![
+        (1000, Value::Text("from the future".to_string())),
+        (1001, Value::Integer(42)),
+        (TagMarker::Code as u16, Value::Bool(true)),
+    ]
+}
+
+fn header(cxt: DataContext, unknown: Vec<(u16, Value)>) -> Record<'static, 'static> {
+    Record::Header(Header {
+        required: common(1, Some(1), Some(3)),
+        time: TIME,
+        id: Cow::Borrowed("10_some-script.sh"),
+        pid: PID,
+        cxt,
+        trace_id: Some(Cow::Borrowed(TRACE_ID)),
+        meta: Cow::Owned(meta()),
+        unknown,
+    })
+}
+
+fn data(
+    version: u32,
+    data: &'static [u8],
+    encoding: Encoding,
+    unknown: Vec<(u16, Value)>,
+) -> Record<'static, 'static> {
+    Record::Data(Data {
+        required: common(version, Some(2), None),
+        time: TIME,
+        id: Cow::Borrowed("10_some-script.sh"),
+        pid: PID,
+        cxt: DataContext::Stdout,
+        data: Cow::Borrowed(data),
+        encoding,
+        trace_id: None,
+        meta: Cow::Owned(Meta::new()),
+        unknown,
+    })
+}
+
+fn log(version: u32) -> Record<'static, 'static> {
+    let log = Log {
+        required: common(version, None, None),
+        log: "connection established".to_string(),
+        level: LogLevel::default(),
+        target: None,
+        time: 0,
+    };
+
+    // Only structured logs carry anything beyond their message
+    match version >= Log::STRUCTURED_VERSION {
+        true => Record::Log(
+            log.level(LogLevel::Warn)
+                .target("extract::models")
+                .time(TIME),
+        ),
+        false => Record::Log(log),
+    }
+}
+
+fn error(id: Option<&str>) -> Record<'static, 'static> {
+    let error = io::Error::new(io::ErrorKind::NotFound, "No such file or directory");
+    let error = InterfaceError::new(TIME, Some(InterfaceErrorKind::Spawn), error)
+        .with_cause("bad interpreter");
+
+    Record::Error(Error {
+        required: common(1, Some(7), None),
+        error,
+        trace_id: Some(TRACE_ID.to_string()),
+        id: id.map(String::from),
+    })
+}
+
+fn exit(code: Option<i32>, signal: Option<i32>) -> Record<'static, 'static> {
+    Record::Exit(Exit {
+        required: common(1, None, Some(3)),
+        time: TIME,
+        id: Cow::Borrowed("10_some-script.sh"),
+        pid: PID,
+        code,
+        signal,
+    })
+}
+
+/// One frame of every record kind, in each encoding it can take
+fn corpus() -> Vec<(&'static str, Record<'static, 'static>)> {
+    vec![
+        ("stream_start", Record::StreamStart),
+        ("stream_end", Record::StreamEnd),
+        ("header_start", header(DataContext::Start, Vec::new())),
+        ("header_end", header(DataContext::End, Vec::new())),
+        ("header_unknown", header(DataContext::Start, unknown())),
+        (
+            "data_v1",
+            data(1, b"hello world", Encoding::Utf8, Vec::new()),
+        ),
+        (
+            "data_v2_utf8",
+            data(2, b"hello world", Encoding::Utf8, Vec::new()),
+        ),
+        (
+            "data_v2_binary",
+            data(2, b"\x00\xff\xfe hello", Encoding::Binary, Vec::new()),
+        ),
+        (
+            "data_unknown",
+            data(2, b"hello world", Encoding::Utf8, unknown()),
+        ),
+        ("log_v1", log(1)),
+        ("log_v2", log(2)),
+        ("error", error(None)),
+        ("error_id", error(Some("10_some-script.sh"))),
+        (
+            "hello",
+            Record::Hello(Hello {
+                required: common(2, None, None),
+                proto_version: 2,
+                features: vec!["ack".to_string(), "channels".to_string()],
+            }),
+        ),
+        (
+            "heartbeat",
+            Record::Heartbeat(Heartbeat {
+                required: common(1, Some(9), None),
+                time: TIME,
+            }),
+        ),
+        (
+            "ack",
+            Record::Ack(Ack {
+                required: common(1, None, None),
+                up_to_seq: 1024,
+            }),
+        ),
+        ("exit_code", exit(Some(0), None)),
+        ("exit_signal", exit(None, Some(9))),
+        (
+            "channel_open",
+            Record::Channel(Channel {
+                required: common(1, None, Some(3)),
+                state: ChannelState::Open,
+            }),
+        ),
+        (
+            "channel_close",
+            Record::Channel(Channel {
+                required: common(1, None, Some(3)),
+                state: ChannelState::Close,
+            }),
+        ),
+        (
+            "metrics",
+            Record::Metrics(Metrics {
+                required: common(1, None, None),
+                time: TIME,
+                source: "extract".into(),
+                period: Duration::from_secs(10),
+                counters: vec![("records".into(), 1024), ("bytes".into(), 65536)]
+                    .into_iter()
+                    .collect(),
+                gauges: vec![("records_per_sec".into(), 102.4)]
+                    .into_iter()
+                    .collect(),
+            }),
+        ),
+        (
+            "facts",
+            Record::Facts(Facts {
+                required: common(1, Some(2), None),
+                time: TIME,
+                facts: vec![
+                    ("hostname".into(), "box".into()),
+                    ("os".into(), "linux".into()),
+                ]
+                .into_iter()
+                .collect(),
+            }),
+        ),
+    ]
+}
+
+/// Records whose canonical encoding is pinned, including maps that are otherwise encoded in
+/// their iteration order
+fn canonical_corpus() -> Vec<(&'static str, Record<'static, 'static>)> {
+    let mut large = header(DataContext::Start, unknown());
+    if let Record::Header(header) = &mut large {
+        header.meta = Cow::Owned(
+            vec![
+                ("host", "build-01"),
+                ("arch", "x86_64"),
+                ("zone", "eu-west-1"),
+                ("build", "2041"),
+                ("a", "shortest key first"),
+            ]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+        );
+    }
+
+    let mut corpus: Vec<_> = corpus()
+        .into_iter()
+        .filter(|(name, _)| ["header_start", "data_v2_utf8", "error", "metrics"].contains(name))
+        .collect();
+    corpus.push(("header_large_meta", large));
+
+    corpus
+}
+
+fn encode_canonical(record: &Record) -> Vec<u8> {
+    RecordSerializer::default()
+        .with_canonical(true)
+        .serialize(record)
+        .expect("record failed to encode")
+        .to_vec()
+}
+
+fn encode(record: &Record) -> BytesMut {
+    let mut frame = BytesMut::new();
+    encode_record(record, &mut frame).expect("record failed to encode");
+    frame
+}
+
+fn decode(frame: &[u8]) -> Record<'static, 'static> {
+    let mut src = BytesMut::from(frame);
+    let record = decode_record(&mut src)
+        .expect("frame failed to decode")
+        .expect("frame is incomplete");
+    assert!(src.is_empty(), "frame has {} trailing bytes", src.len());
+
+    record
+}
+
+#[test]
+fn corpus_encoding_is_stable() {
+    let dir = corpus_dir();
+    let update = env::var_os("UPDATE_CORPUS").is_some();
+    if update {
+        fs::create_dir_all(&dir).unwrap();
+    }
+
+    for (name, record) in corpus() {
+        let path = dir.join(name).with_extension("bin");
+        let frame = encode(&record);
+
+        match update {
+            true => fs::write(&path, &frame).unwrap(),
+            false => {
+                let expected = fs::read(&path)
+                    .unwrap_or_else(|e| panic!("unable to read {}: {}", path.display(), e));
+                assert_eq!(
+                    &frame[..],
+                    &expected[..],
+                    "'{}' no longer encodes to its corpus frame",
+                    name
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn canonical_encoding_is_stable() {
+    let dir = canonical_dir();
+    let update = env::var_os("UPDATE_CORPUS").is_some();
+    if update {
+        fs::create_dir_all(&dir).unwrap();
+    }
+
+    for (name, record) in canonical_corpus() {
+        let path = dir.join(name).with_extension("bin");
+        let frame = encode_canonical(&record);
+
+        match update {
+            true => fs::write(&path, &frame).unwrap(),
+            false => {
+                let expected = fs::read(&path)
+                    .unwrap_or_else(|e| panic!("unable to read {}: {}", path.display(), e));
+                assert_eq!(
+                    frame, expected,
+                    "'{}' no longer encodes to its canonical frame",
+                    name
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn canonical_corpus_decodes() {
+    for (name, mut record) in canonical_corpus() {
+        let path = canonical_dir().join(name).with_extension("bin");
+        let frame =
+            fs::read(&path).unwrap_or_else(|e| panic!("unable to read {}: {}", path.display(), e));
+        let mut decoded = Record::from_frame(&frame)
+            .unwrap_or_else(|e| panic!("'{}' failed to decode: {}", name, e));
+        sort_unknown(&mut record);
+
+        assert_eq!(take_meta(&mut decoded), take_meta(&mut record));
+        assert_eq!(
+            format!("{:?}", decoded),
+            format!("{:?}", record),
+            "'{}' decoded to a different record",
+            name
+        );
+    }
+}
+
+#[test]
+fn corpus_decodes() {
+    for (name, record) in corpus() {
+        let path = corpus_dir().join(name).with_extension("bin");
+        let frame =
+            fs::read(&path).unwrap_or_else(|e| panic!("unable to read {}: {}", path.display(), e));
+
+        assert_eq!(
+            format!("{:?}", decode(&frame)),
+            format!("{:?}", record),
+            "'{}' decoded to a different record",
+            name
+        );
+    }
+}
+
+#[test]
+fn corpus_has_no_stale_frames() {
+    let names: HashSet<_> = corpus().into_iter().map(|(name, _)| name).collect();
+
+    for entry in fs::read_dir(corpus_dir()).unwrap() {
+        let path = entry.unwrap().path();
+        let name = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default();
+        assert!(
+            names.contains(name),
+            "{} has no matching record in corpus()",
+            path.display()
+        );
+    }
+}
+
+fn arb_text() -> impl Strategy<Value = String> {
+    "\\PC{0,24}"
+}
+
+fn arb_common(versions: std::ops::RangeInclusive<u32>) -> impl Strategy<Value = Common> {
+    (versions, option::of(any::<u64>()), option::of(any::<u32>()))
+        .prop_map(|(version, seq, stream)| common(version, seq, stream))
+}
+
+fn arb_meta() -> impl Strategy<Value = Meta> {
+    collection::hash_map(arb_text(), arb_text(), 0..4)
+}
+
+/// Tags well clear of any this version knows
+fn arb_unknown() -> impl Strategy<Value = Vec<(u16, Value)>> {
+    let value = prop_oneof![
+        Just(Value::Null),
+        any::<bool>().prop_map(Value::Bool),
+        any::<i64>().prop_map(|i| Value::Integer(i.into())),
+        arb_text().prop_map(Value::Text),
+        collection::vec(any::<u8>(), 0..16).prop_map(Value::Bytes),
+    ];
+
+    collection::hash_map(1000..2000u16, value, 0..3).prop_map(|map| map.into_iter().collect())
+}
+
+fn arb_cxt() -> impl Strategy<Value = DataContext> {
+    prop_oneof![
+        Just(DataContext::Start),
+        Just(DataContext::Stdout),
+        Just(DataContext::Stderr),
+        Just(DataContext::End),
+    ]
+}
+
+fn arb_kind() -> impl Strategy<Value = InterfaceErrorKind> {
+    prop_oneof![
+        Just(InterfaceErrorKind::Generic),
+        Just(InterfaceErrorKind::Checksum),
+        Just(InterfaceErrorKind::Handshake),
+        Just(InterfaceErrorKind::Limit),
+        Just(InterfaceErrorKind::Encryption),
+        Just(InterfaceErrorKind::Io),
+        Just(InterfaceErrorKind::Spawn),
+        Just(InterfaceErrorKind::Timeout),
+        Just(InterfaceErrorKind::Decode),
+        Just(InterfaceErrorKind::Config),
+    ]
+}
+
+fn arb_header() -> impl Strategy<Value = Record<'static, 'static>> {
+    (
+        arb_common(1..=PROTOCOL_VERSION),
+        any::<i64>(),
+        arb_text(),
+        any::<u32>(),
+        arb_cxt(),
+        option::of(arb_text()),
+        arb_meta(),
+        arb_unknown(),
+    )
+        .prop_map(|(required, time, id, pid, cxt, trace_id, meta, unknown)| {
+            Record::Header(Header {
+                required,
+                time,
+                id: Cow::Owned(id),
+                pid,
+                cxt,
+                trace_id: trace_id.map(Cow::Owned),
+                meta: Cow::Owned(meta),
+                unknown,
+            })
+        })
+}
+
+/// Records older than `BINARY_DATA_VERSION` can only carry text
+fn arb_payload() -> impl Strategy<Value = (Common, Vec<u8>, Encoding)> {
+    prop_oneof![
+        (arb_common(1..=BINARY_DATA_VERSION - 1), arb_text()).prop_map(|(required, text)| (
+            required,
+            text.into_bytes(),
+            Encoding::Utf8
+        )),
+        (
+            arb_common(BINARY_DATA_VERSION..=PROTOCOL_VERSION),
+            collection::vec(any::<u8>(), 0..64),
+            prop_oneof![Just(Encoding::Utf8), Just(Encoding::Binary)],
+        ),
+    ]
+}
+
+fn arb_data() -> impl Strategy<Value = Record<'static, 'static>> {
+    (
+        arb_payload(),
+        any::<i64>(),
+        arb_text(),
+        any::<u32>(),
+        arb_cxt(),
+        option::of(arb_text()),
+        arb_meta(),
+        arb_unknown(),
+    )
+        .prop_map(
+            |((required, data, encoding), time, id, pid, cxt, trace_id, meta, unknown)| {
+                Record::Data(Data {
+                    required,
+                    time,
+                    id: Cow::Owned(id),
+                    pid,
+                    cxt,
+                    data: Cow::Owned(data),
+                    encoding,
+                    trace_id: trace_id.map(Cow::Owned),
+                    meta: Cow::Owned(meta),
+                    unknown,
+                })
+            },
+        )
+}
+
+/// Records older than `Log::STRUCTURED_VERSION` only carry the message
+fn arb_log() -> impl Strategy<Value = Record<'static, 'static>> {
+    (
+        arb_common(Log::STRUCTURED_VERSION..=PROTOCOL_VERSION),
+        arb_text(),
+        prop_oneof![
+            Just(LogLevel::Trace),
+            Just(LogLevel::Debug),
+            Just(LogLevel::Info),
+            Just(LogLevel::Warn),
+            Just(LogLevel::Error),
+        ],
+        option::of(arb_text()),
+        any::<i64>(),
+    )
+        .prop_map(|(required, log, level, target, time)| {
+            Record::Log(Log {
+                required,
+                log,
+                level,
+                target,
+                time,
+            })
+        })
+}
+
+fn arb_error() -> impl Strategy<Value = Record<'static, 'static>> {
+    (
+        arb_common(1..=PROTOCOL_VERSION),
+        any::<i64>(),
+        arb_kind(),
+        arb_text(),
+        collection::vec(arb_text(), 0..3),
+        option::of(arb_text()),
+        option::of(arb_text()),
+    )
+        .prop_map(|(required, time, kind, msg, causes, trace_id, id)| {
+            let error = io::Error::other(msg);
+            let error = causes.into_iter().fold(
+                InterfaceError::new(time, Some(kind), error),
+                InterfaceError::with_cause,
+            );
+
+            Record::Error(Error {
+                required,
+                error,
+                trace_id,
+                id,
+            })
+        })
+}
+
+fn arb_exit() -> impl Strategy<Value = Record<'static, 'static>> {
+    (
+        arb_common(1..=PROTOCOL_VERSION),
+        any::<i64>(),
+        arb_text(),
+        any::<u32>(),
+        option::of(any::<i32>()),
+        option::of(any::<i32>()),
+    )
+        .prop_map(|(required, time, id, pid, code, signal)| {
+            Record::Exit(Exit {
+                required,
+                time,
+                id: Cow::Owned(id),
+                pid,
+                code,
+                signal,
+            })
+        })
+}
+
+fn arb_facts() -> impl Strategy<Value = Record<'static, 'static>> {
+    (
+        arb_common(1..=PROTOCOL_VERSION),
+        any::<i64>(),
+        collection::btree_map(arb_text(), arb_text(), 0..4),
+    )
+        .prop_map(|(required, time, facts)| {
+            Record::Facts(Facts {
+                required,
+                time,
+                facts,
+            })
+        })
+}
+
+fn arb_metrics() -> impl Strategy<Value = Record<'static, 'static>> {
+    (
+        arb_common(1..=PROTOCOL_VERSION),
+        any::<i64>(),
+        arb_text(),
+        any::<u32>(),
+        collection::btree_map(arb_text(), any::<u64>(), 0..4),
+        // Only finite gauges, NaN never compares equal to itself
+        collection::btree_map(arb_text(), -1e12..1e12f64, 0..4),
+    )
+        .prop_map(|(required, time, source, period, counters, gauges)| {
+            Record::Metrics(Metrics {
+                required,
+                time,
+                source,
+                period: Duration::from_millis(period.into()),
+                counters,
+                gauges,
+            })
+        })
+}
+
+fn arb_record() -> impl Strategy<Value = Record<'static, 'static>> {
+    prop_oneof![
+        LazyJust::new(|| Record::StreamStart),
+        LazyJust::new(|| Record::StreamEnd),
+        arb_header(),
+        arb_data(),
+        arb_log(),
+        arb_error(),
+        (
+            arb_common(1..=PROTOCOL_VERSION),
+            any::<u32>(),
+            collection::vec(arb_text(), 0..4)
+        )
+            .prop_map(|(required, proto_version, features)| Record::Hello(Hello {
+                required,
+                proto_version,
+                features,
+            })),
+        (arb_common(1..=PROTOCOL_VERSION), any::<i64>())
+            .prop_map(|(required, time)| Record::Heartbeat(Heartbeat { required, time })),
+        (arb_common(1..=PROTOCOL_VERSION), any::<u64>()).prop_map(|(required, up_to_seq)| {
+            Record::Ack(Ack {
+                required,
+                up_to_seq,
+            })
+        }),
+        arb_exit(),
+        // A Channel record always names the stream it opens or closes
+        (
+            1..=PROTOCOL_VERSION,
+            option::of(any::<u64>()),
+            any::<u32>(),
+            prop_oneof![Just(ChannelState::Open), Just(ChannelState::Close)],
+        )
+            .prop_map(|(version, seq, stream, state)| Record::Channel(Channel {
+                required: common(version, seq, Some(stream)),
+                state,
+            })),
+        arb_metrics(),
+        arb_facts(),
+    ]
+}
+
+/// Removes the record's meta, if it has any. Meta is compared on its own, as its Debug
+/// output depends on its iteration order
+fn take_meta(record: &mut Record) -> Option<Meta> {
+    match record {
+        Record::Header(r) => Some(std::mem::take(r.meta.to_mut())),
+        Record::Data(r) => Some(std::mem::take(r.meta.to_mut())),
+        _ => None,
+    }
+}
+
+/// Sorts the record's unknown fields into the order a canonical encoding leaves them in
+fn sort_unknown(record: &mut Record) {
+    match record {
+        Record::Header(r) => r.unknown.sort_by_key(|(tag, _)| *tag),
+        Record::Data(r) => r.unknown.sort_by_key(|(tag, _)| *tag),
+        _ => {}
+    }
+}
+
+proptest! {
+    #[test]
+    fn records_roundtrip(mut record in arb_record()) {
+        let mut decoded = decode(&encode(&record));
+
+        prop_assert_eq!(take_meta(&mut decoded), take_meta(&mut record));
+        prop_assert_eq!(format!("{:?}", decoded), format!("{:?}", record));
+    }
+
+    #[test]
+    fn compressed_records_roundtrip(mut record in arb_record()) {
+        let codec = RecordCodec::new().with_compression(Compression::Zstd(DEFAULT_ZSTD_LEVEL));
+        let mut frame = BytesMut::new();
+        codec.encode(&record, &mut frame).unwrap();
+        let mut decoded = codec.decode(&mut frame).unwrap().unwrap();
+
+        prop_assert!(frame.is_empty());
+        prop_assert_eq!(take_meta(&mut decoded), take_meta(&mut record));
+        prop_assert_eq!(format!("{:?}", decoded), format!("{:?}", record));
+    }
+
+    #[test]
+    fn canonical_records_roundtrip(mut record in arb_record()) {
+        let mut decoded = Record::from_frame(&encode_canonical(&record)).unwrap().into_owned();
+        sort_unknown(&mut record);
+
+        prop_assert_eq!(take_meta(&mut decoded), take_meta(&mut record));
+        prop_assert_eq!(format!("{:?}", decoded), format!("{:?}", record));
+    }
+
+    #[test]
+    fn canonical_encoding_ignores_meta_order(mut record in arb_record()) {
+        let encoded = encode_canonical(&record);
+
+        // Rebuilding the map reinserts its entries in another order, under another hasher
+        if let Some(meta) = take_meta(&mut record) {
+            let mut entries: Vec<_> = meta.into_iter().collect();
+            entries.reverse();
+            match &mut record {
+                Record::Header(r) => r.meta = Cow::Owned(entries.into_iter().collect()),
+                Record::Data(r) => r.meta = Cow::Owned(entries.into_iter().collect()),
+                _ => unreachable!(),
+            }
+        }
+
+        prop_assert_eq!(encode_canonical(&record), encoded);
+    }
+
+    #[test]
+    fn to_frame_matches_encoding(record in arb_record()) {
+        let frame = encode(&record);
+        let mut buf = vec![0; frame.len() - 4];
+
+        let written = record.to_frame(&mut buf).unwrap();
+        prop_assert_eq!(&buf[..written], &frame[4..]);
+
+        let short = record.to_frame(&mut buf[..written - 1]).unwrap_err();
+        prop_assert_eq!(short.kind(), io::ErrorKind::WriteZero);
+    }
+
+    #[test]
+    fn arbitrary_frames_never_panic(body in collection::vec(any::<u8>(), 0..256)) {
+        let mut src = BytesMut::new();
+        src.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        src.extend_from_slice(&body);
+
+        let _ = decode_record(&mut src);
+    }
+
+    #[test]
+    fn mutated_frames_never_panic(
+        record in arb_record(),
+        flips in collection::vec((any::<prop::sample::Index>(), any::<u8>()), 1..4),
+    ) {
+        let mut frame = encode(&record);
+        for (index, byte) in flips {
+            // Leave the length alone, so the mutated body is what gets decoded
+            if frame.len() > 4 {
+                let at = 4 + index.index(frame.len() - 4);
+                frame[at] ^= byte;
+            }
+        }
+
+        let _ = decode_record(&mut frame);
+    }
+}
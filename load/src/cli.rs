@@ -1,6 +1,7 @@
 #![allow(deprecated)]
 use {
     clap::{crate_authors, crate_version, App, AppSettings, Arg, SubCommand},
+    lib_transport::{Encryption, Key, LogLevel},
     std::path::{Path, PathBuf},
 };
 
@@ -40,6 +41,26 @@ fn __generate_cli<'a, 'b>() -> App<'a, 'b> {
                 .long("pretty")
                 .help("Pretty print json"),
         )
+        .arg(
+            Arg::with_name("log_level")
+                .long("log-level")
+                .takes_value(true)
+                .value_name("LEVEL")
+                .possible_values(&["trace", "debug", "info", "warn", "error"])
+                .default_value("trace")
+                .help("Discard log records less severe than LEVEL"),
+        )
+        .arg(
+            Arg::with_name("key_file")
+                .long("key-file")
+                .takes_value(true)
+                .value_name("PATH")
+                .validator(|val| Key::from_file(&val).map(|_| ()).map_err(|e| e.to_string()))
+                .help(
+                    "Require every connection to encrypt its records with the hex encoded 256 bit \
+                    key in PATH",
+                ),
+        )
         .subcommand(
             SubCommand::with_name("tcp")
                 .about("Bind a tcp socket for output")
@@ -71,6 +92,8 @@ fn __generate_cli<'a, 'b>() -> App<'a, 'b> {
 pub(crate) struct ProgramArgs {
     con_type: ConOpts,
     pretty_print: bool,
+    log_level: LogLevel,
+    encryption: Encryption,
 }
 
 impl ProgramArgs {
@@ -79,6 +102,19 @@ impl ProgramArgs {
 
         let pretty_print = store.is_present("json_pretty");
 
+        let log_level = match store.value_of("log_level").unwrap() {
+            "debug" => LogLevel::Debug,
+            "info" => LogLevel::Info,
+            "warn" => LogLevel::Warn,
+            "error" => LogLevel::Error,
+            _ => LogLevel::Trace,
+        };
+
+        let encryption = store
+            .value_of("key_file")
+            .map(|path| Encryption::XChaCha20Poly1305(Key::from_file(path).unwrap()))
+            .unwrap_or_default();
+
         let con_type;
         match store.subcommand() {
             ("socket", Some(sub)) => {
@@ -99,6 +135,8 @@ impl ProgramArgs {
         Self {
             con_type,
             pretty_print,
+            log_level,
+            encryption,
         }
     }
 
@@ -106,6 +144,16 @@ impl ProgramArgs {
         self.pretty_print
     }
 
+    /// Least severe level of Log record that should be printed
+    pub(crate) fn log_level(&self) -> LogLevel {
+        self.log_level
+    }
+
+    /// Encryption every connection must use, if any
+    pub(crate) fn encryption(&self) -> &Encryption {
+        &self.encryption
+    }
+
     pub(crate) fn con_tcp(&self) -> Option<(&str, u16)> {
         match self.con_type {
             ConOpts::Tcp((ref bind, port)) => Some((bind, port)),
@@ -10,7 +10,6 @@ use {
 };
 
 mod cli;
-mod local;
 mod models;
 mod prelude {
     pub use {
@@ -1,10 +1,16 @@
 use {
-    crate::{local::LocalRecord, prelude::*, ARGS},
+    crate::{prelude::*, ARGS},
     futures::prelude::*,
-    lib_transport::RecordInterface,
+    lib_transport::{
+        ack, batch, handshake, json::JsonRecord, metrics, Checksum, Compression, Facts,
+        OwnedRecord, Record, RecordInterface, RecvError, SequenceExt,
+    },
     serde_json::{to_writer, to_writer_pretty},
-    std::{io, path::Path},
-    tokio::{net::TcpListener, prelude::AsyncRead},
+    std::{convert::TryFrom, io, path::Path},
+    tokio::{
+        net::TcpListener,
+        prelude::{AsyncRead, AsyncWrite},
+    },
     tracing_subscriber::{EnvFilter, FmtSubscriber},
 };
 
@@ -86,21 +92,95 @@ async fn use_tcp(addr: (&str, u16)) -> Result<(), io::Error> {
     }
 }
 
-async fn handle_connection<T>(read: T)
+async fn handle_connection<T>(socket: T)
 where
-    T: AsyncRead,
+    T: AsyncRead + AsyncWrite + Unpin,
 {
     let pretty = ARGS.pretty_print();
-    RecordInterface::from_read(read)
+    let mut interface = RecordInterface::from_both(socket);
+    let encryption = ARGS.encryption();
+    let supported: Vec<&str> = [
+        Checksum::FEATURE_CRC32,
+        Compression::FEATURE_ZSTD,
+        ack::FEATURE,
+        metrics::FEATURE,
+        batch::FEATURE,
+        Facts::FEATURE,
+    ]
+    .iter()
+    .copied()
+    .chain(encryption.feature())
+    .collect();
+    let (negotiated, first) = match handshake::accept(&mut interface, &supported).await {
+        Ok(accepted) => accepted,
+        Err(e) => return warn!("Handshake failed, dropping connection: {}", e),
+    };
+    debug!(
+        version = negotiated.proto_version,
+        legacy = negotiated.is_legacy(),
+        "Handshake complete"
+    );
+    if let Some(feature) = encryption.feature() {
+        if !negotiated.has_feature(feature) {
+            return warn!(
+                "Client did not negotiate '{}' encryption, dropping connection",
+                feature
+            );
+        }
+    }
+    let checksum = match negotiated.has_feature(Checksum::FEATURE_CRC32) {
+        true => Checksum::Crc32,
+        false => Checksum::None,
+    };
+    let compression = Compression::negotiated(&negotiated);
+
+    let interface = interface
+        .with_checksum(checksum)
+        .with_compression(compression)
+        .with_encryption(encryption.clone())
+        .with_batches(negotiated.has_feature(batch::FEATURE));
+    let records = match negotiated.has_feature(ack::FEATURE) {
+        true => ack::Acknowledge::new(interface, ack::DEFAULT_INTERVAL).left_stream(),
+        false => interface.right_stream(),
+    };
+
+    stream::iter(first.map(Ok))
+        .chain(records)
+        .check_sequence(|anomaly| warn!("Inconsistent record stream: {}", anomaly))
+        .scan((), |_, item| future::ready(socket_ok(item)))
         .for_each(|item| async {
-            item.and_then(|record| print_json(pretty, io::stdout(), record.into()))
-                .unwrap_or_else(|e| warn!("Item serialization failed: {}", e))
+            match item {
+                // Heartbeats only keep the connection alive, they aren't worth printing
+                Ok(Record::Heartbeat(_)) => trace!("Heartbeat received"),
+                Ok(Record::Log(ref log)) if log.level < ARGS.log_level() => {
+                    trace!(level = ?log.level, "Filtered log record")
+                }
+                Err(e) => warn!("Invalid record detected in stream: {}... ignoring", e),
+                Ok(record) => OwnedRecord::try_from(record)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+                    .and_then(|record| print_json(pretty, io::stdout(), record.into()))
+                    .unwrap_or_else(|e| warn!("Item serialization failed: {}", e)),
+            }
         })
         .instrument(always_span!("printer.json", pretty))
         .await
 }
 
-fn print_json<W>(pretty: bool, writer: W, rcd: LocalRecord) -> Result<(), io::Error>
+/// Once the socket has failed nothing further in the stream can be trusted, so it ends the
+/// stream. A single bad frame is passed on to be skipped over
+fn socket_ok(
+    item: Result<Record<'static, 'static>, RecvError<io::Error>>,
+) -> Option<Result<Record<'static, 'static>, RecvError<io::Error>>> {
+    match item {
+        Err(RecvError::Io(e)) => {
+            warn!("{}, dropping connection", e);
+            None
+        }
+        item => Some(item),
+    }
+}
+
+fn print_json<W>(pretty: bool, writer: W, rcd: JsonRecord) -> Result<(), io::Error>
 where
     W: io::Write,
 {
@@ -2,16 +2,23 @@
 use {
     crate::{
         error::{CfgErrSubject as Subject, ConfigError},
-        load::filters::{FilterSet, FilterWrap, JoinSet, JoinWrap},
+        load::filters::{
+            de_regex, FieldsSet, FieldsWrap, FilterSet, FilterWrap, JoinSet, JoinWrap, MapSet,
+            MapWrap,
+        },
         prelude::{CrateResult as Result, *},
     },
     clap::{crate_authors, crate_version, App, AppSettings, Arg, SubCommand},
+    lib_transport::{Checksum, Encryption, Key, Limits},
+    regex::Regex,
     serde::{Deserialize, Deserializer},
     serde_yaml::from_reader as read_yaml,
     std::{
+        collections::HashMap,
         convert::{TryFrom, TryInto},
         fs::File,
-        path::Path,
+        path::{Path, PathBuf},
+        time::Duration,
     },
 };
 
@@ -33,10 +40,90 @@ pub fn generate_cli<'a, 'b>() -> App<'a, 'b> {
                 .validator(|s| Some(s.as_str()).filter(|s| Path::new(s).exists()).map(|_| ())
                     .ok_or_else(|| format!("'{}' does not exist or is an invalid path", s)))
                 .help("Read a config file, can be called multiple times (--help for more information)")
-                .long_help("Read a config file, can be called multiple times. This program requires 'filter', 'map' \
-                            'transform' and 'execute' objects, along with an optional 'config' object. These do not \
+                .long_help("Read a config file, can be called multiple times. This program requires 'filter', 'join' \
+                            and 'exec' objects, along with optional 'map', 'fields' and 'config' objects. These do not \
                             need to be stored in the same file, but each file needs to be valid .yaml and each object \
-                            should be passed only once.")
+                            should be passed only once. A 'map' object names lists of 'pattern -> replacement' \
+                            rules, rewriting the output of every 'map: NAME' in 'exec' as sed would, where \
+                            replacements may refer to capture groups as $1 or ${name}. A 'fields' object names \
+                            lists of regexes, the named capture groups of the first to match being stored in \
+                            the metadata of every record passing a 'fields: NAME' in 'exec'. An optional \
+                            'pipelines' object names other 'exec' lists, one of which a client can ask its \
+                            records go through instead with its --pipeline. An optional 'route' list sends \
+                            the records of ids matching a pattern through a pipeline, i.e \
+                            '[{match: ^net_, pipeline: network}]', the first route to match winning. Records \
+                            go through the ops of an 'exec' list in the order listed, with any 'load' last. \
+                            Earlier versions always sent them through 'join', then 'filter', 'map' and 'fields' \
+                            whatever the order listed, so older configs should list them that way to keep \
+                            their output.")
+        )
+        .arg(
+            Arg::with_name("checksum")
+                .long("checksum")
+                .takes_value(false)
+                .help("Verify the CRC32 checksum of every record, the sender must also enable this")
+        )
+        .arg(
+            Arg::with_name("key-file")
+                .long("key-file")
+                .takes_value(true)
+                .value_name("PATH")
+                .validator(|val| Key::from_file(&val).map(|_| ()).map_err(|e| e.to_string()))
+                .help("Require every connection to encrypt its records with the hex encoded 256 bit key in PATH")
+        )
+        .arg(
+            Arg::with_name("max-frame-size")
+                .long("max-frame-size")
+                .takes_value(true)
+                .value_name("BYTES")
+                .validator(|val| {
+                    val.parse::<usize>()
+                        .map(|_| ())
+                        .map_err(|_| format!("'{}' is not a valid size", &val))
+                })
+                .help("Drop any connection that sends a record larger than this (default: 8MiB)")
+        )
+        .arg(
+            Arg::with_name("max-depth")
+                .long("max-depth")
+                .takes_value(true)
+                .value_name("DEPTH")
+                .validator(|val| {
+                    val.parse::<usize>()
+                        .map(|_| ())
+                        .map_err(|_| format!("'{}' is not a valid depth", &val))
+                })
+                .help("Drop any connection that sends a record nested deeper than this (default: 16)")
+        )
+        .arg(
+            Arg::with_name("metrics")
+                .long("metrics")
+                .takes_value(true)
+                .value_name("SECONDS")
+                .validator(|val| match val.parse::<u64>() {
+                    Ok(secs) if secs > 0 => Ok(()),
+                    _ => Err(format!("'{}' is not a valid number of seconds", &val)),
+                })
+                .help("Report throughput to each loader in a metrics record every SECONDS")
+        )
+        .arg(
+            Arg::with_name("join-lines")
+                .long("join-lines")
+                .takes_value(true)
+                .value_name("BYTES")
+                .validator(|val| match val.parse::<usize>() {
+                    Ok(bytes) if bytes > 0 => Ok(()),
+                    _ => Err(format!("'{}' is not a valid number of bytes", &val)),
+                })
+                .help("Join lines split across several records by the sender's --max-line back together, up to BYTES")
+        )
+        .arg(
+            Arg::with_name("admin")
+                .long("admin")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("Serve a unix socket at PATH for listing connections, dumping or toggling filters \
+                       and changing the log level at runtime, send 'help' for its commands")
         )
         .subcommand(
         SubCommand::with_name("tcp")
@@ -71,7 +158,17 @@ pub struct ProgramArgs {
     bind: (String, u16),
     filter: FilterSet,
     join: JoinSet,
+    map: MapSet,
+    fields: FieldsSet,
     exec: ExecList,
+    pipelines: Pipelines,
+    routes: Routes,
+    checksum: Checksum,
+    encryption: Encryption,
+    limits: Limits,
+    metrics: Option<Duration>,
+    join_lines: Option<usize>,
+    admin: Option<PathBuf>,
 }
 
 impl ProgramArgs {
@@ -99,7 +196,41 @@ impl ProgramArgs {
             _ => unreachable!("No subcommand selected... this is a bug"),
         };
 
-        let (filter, join, exec) = store
+        let checksum = match store.is_present("checksum") {
+            true => Checksum::Crc32,
+            false => Checksum::None,
+        };
+
+        let encryption = store
+            .value_of("key-file")
+            .map(|path| Encryption::XChaCha20Poly1305(Key::from_file(path).unwrap()))
+            .unwrap_or_default();
+
+        let limits = Limits::new()
+            .with_max_frame_len(
+                store
+                    .value_of("max-frame-size")
+                    .map(|s| s.parse::<usize>().unwrap())
+                    .unwrap_or(lib_transport::DEFAULT_MAX_FRAME_LEN),
+            )
+            .with_max_depth(
+                store
+                    .value_of("max-depth")
+                    .map(|s| s.parse::<usize>().unwrap())
+                    .unwrap_or(lib_transport::DEFAULT_MAX_DEPTH),
+            );
+
+        let metrics = store
+            .value_of("metrics")
+            .map(|s| Duration::from_secs(s.parse::<u64>().unwrap()));
+
+        let join_lines = store
+            .value_of("join-lines")
+            .map(|s| s.parse::<usize>().unwrap());
+
+        let admin = store.value_of("admin").map(PathBuf::from);
+
+        let (filter, join, map, fields, exec, pipelines, routes) = store
             .values_of("config-file")
             .map(instantiate_sets)
             .unwrap()?;
@@ -108,7 +239,17 @@ impl ProgramArgs {
             bind,
             filter,
             join,
+            map,
+            fields,
             exec,
+            pipelines,
+            routes,
+            checksum,
+            encryption,
+            limits,
+            metrics,
+            join_lines,
+            admin,
         })
     }
 
@@ -120,10 +261,70 @@ impl ProgramArgs {
         &self.join
     }
 
+    pub fn get_map(&self) -> &MapSet {
+        &self.map
+    }
+
+    pub fn get_fields(&self) -> &FieldsSet {
+        &self.fields
+    }
+
     pub fn get_exec_list(&self) -> &ExecList {
         &self.exec
     }
 
+    /// The exec list records should go through, that of the named pipeline if given
+    pub fn get_pipeline(&self, name: Option<&str>) -> Option<&ExecList> {
+        match name {
+            Some(name) => self.pipelines.inner.get(name),
+            None => Some(&self.exec),
+        }
+    }
+
+    /// The exec list of the pipeline the first route matching `id` names, if any does
+    pub fn route(&self, id: &str) -> Option<&ExecList> {
+        self.routes
+            .inner
+            .iter()
+            .find(|route| route.pattern.is_match(id))
+            .and_then(|route| self.pipelines.inner.get(&route.pipeline))
+    }
+
+    /// The exec list of every pipeline a route names, each only once
+    pub fn routed(&self) -> impl Iterator<Item = &ExecList> {
+        let mut names: Vec<_> = self.routes.inner.iter().map(|r| &r.pipeline).collect();
+        names.sort();
+        names.dedup();
+
+        names
+            .into_iter()
+            .filter_map(move |name| self.pipelines.inner.get(name))
+    }
+
+    pub fn checksum(&self) -> Checksum {
+        self.checksum
+    }
+
+    pub fn encryption(&self) -> &Encryption {
+        &self.encryption
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    pub fn metrics(&self) -> Option<Duration> {
+        self.metrics
+    }
+
+    pub fn join_lines(&self) -> Option<usize> {
+        self.join_lines
+    }
+
+    pub fn admin(&self) -> Option<&Path> {
+        self.admin.as_deref()
+    }
+
     pub fn bind_addr(&self) -> (&str, u16) {
         (&self.bind.0, self.bind.1)
     }
@@ -141,13 +342,45 @@ impl Into<Subject> for JoinSet {
     }
 }
 
+impl Into<Subject> for MapSet {
+    fn into(self) -> Subject {
+        Subject::Map
+    }
+}
+
+impl Into<Subject> for FieldsSet {
+    fn into(self) -> Subject {
+        Subject::Fields
+    }
+}
+
 impl Into<Subject> for ExecList {
     fn into(self) -> Subject {
         Subject::Exec
     }
 }
 
-type Sets = (FilterSet, JoinSet, ExecList);
+impl Into<Subject> for Pipelines {
+    fn into(self) -> Subject {
+        Subject::Pipeline
+    }
+}
+
+impl Into<Subject> for Routes {
+    fn into(self) -> Subject {
+        Subject::Route
+    }
+}
+
+type Sets = (
+    FilterSet,
+    JoinSet,
+    MapSet,
+    FieldsSet,
+    ExecList,
+    Pipelines,
+    Routes,
+);
 
 fn instantiate_sets<I, S>(mut iter: I) -> Result<Sets>
 where
@@ -156,7 +389,11 @@ where
 {
     let mut filter: Option<Result<FilterSet>> = None;
     let mut join: Option<Result<JoinSet>> = None;
+    let mut map: Option<Result<MapSet>> = None;
+    let mut fields: Option<Result<FieldsSet>> = None;
     let mut exec: Option<Result<ExecList>> = None;
+    let mut pipelines: Option<Result<Pipelines>> = None;
+    let mut routes: Option<Result<Routes>> = None;
 
     // We allow the user to specify multiple files with a requirement that somewhere in
     // these files are all the required config options. Which means that if we can't open a file,
@@ -171,7 +408,11 @@ where
                 let ConfigDeserialize {
                     filter: f,
                     join: j,
+                    map: m,
+                    fields: d,
                     exec: e,
+                    pipelines: p,
+                    route: r,
                 } = read_yaml(file).unwrap();
 
                 // Check current file for a FilterSet
@@ -180,9 +421,21 @@ where
                 // Check current file for a JoinSet
                 lift_result(j.map(|res| res.log(Level::DEBUG)), &mut join)?;
 
+                // Check current file for a MapSet
+                lift_result(m.map(|res| res.log(Level::DEBUG)), &mut map)?;
+
+                // Check current file for a FieldsSet
+                lift_result(d.map(|res| res.log(Level::DEBUG)), &mut fields)?;
+
                 // Check current file for an Exec list
                 lift_result(e.map(Ok), &mut exec)?;
 
+                // Check current file for any Pipelines
+                lift_result(p.map(Ok), &mut pipelines)?;
+
+                // Check current file for any Routes
+                lift_result(r.map(Ok), &mut routes)?;
+
                 Ok(())
             })
             .log(Level::WARN)
@@ -197,27 +450,72 @@ where
         .transpose()
         .and_then(|o| o.ok_or_else(|| ConfigError::Missing(Subject::Join).into()))
         .log(Level::ERROR)?;
+    // Unlike the others MapSets and FieldsSets are optional, as nothing need be rewritten
+    let map = map.transpose().log(Level::ERROR)?.unwrap_or_default();
+    let fields = fields.transpose().log(Level::ERROR)?.unwrap_or_default();
+    let check = |vec: &ExecList| check_keys(vec, &filter, &map, &fields);
     let exec = exec
         .transpose()
         .and_then(|o| o.ok_or_else(|| ConfigError::Missing(Subject::Join).into()))
-        .and_then(|vec| {
-            vec.inner
+        .and_then(|vec| check(&vec).map(|_| vec))
+        .log(Level::ERROR)?;
+    let pipelines = pipelines
+        .transpose()
+        .map(Option::unwrap_or_default)
+        .and_then(|pipelines| {
+            pipelines
+                .inner
+                .values()
+                .try_for_each(check)
+                .map(|_| pipelines)
+        })
+        .log(Level::ERROR)?;
+    let routes = routes
+        .transpose()
+        .map(Option::unwrap_or_default)
+        .and_then(|routes| {
+            let missing = routes
+                .inner
                 .iter()
-                .try_for_each(|key| match key {
-                    DataOp::Filter(k) => {
-                        if filter.access_set(|_, m| m.contains_key(k.as_str())) {
-                            Ok(())
-                        } else {
-                            Err(ConfigError::InvalidExecKey(key.as_ref().into(), k.clone()).into())
-                        }
-                    }
-                    DataOp::Load(_) | DataOp::Join => Ok(()),
-                })
-                .map(|_| vec)
+                .map(|route| &route.pipeline)
+                .find(|name| !pipelines.inner.contains_key(name.as_str()))
+                .cloned();
+            match missing {
+                Some(name) => Err(ConfigError::InvalidExecKey(Subject::Pipeline, name).into()),
+                None => Ok(routes),
+            }
         })
         .log(Level::ERROR)?;
 
-    Ok((filter, join, exec))
+    Ok((filter, join, map, fields, exec, pipelines, routes))
+}
+
+/// Checks that every key in `vec` names something configured
+fn check_keys(vec: &ExecList, filter: &FilterSet, map: &MapSet, fields: &FieldsSet) -> Result<()> {
+    vec.inner.iter().try_for_each(|key| match key {
+        DataOp::Filter(k) => {
+            if filter.access_set(|_, m| m.contains_key(k.as_str())) {
+                Ok(())
+            } else {
+                Err(ConfigError::InvalidExecKey(key.as_ref().into(), k.clone()).into())
+            }
+        }
+        DataOp::Map(k) => {
+            if map.contains(k.as_str()) {
+                Ok(())
+            } else {
+                Err(ConfigError::InvalidExecKey(key.as_ref().into(), k.clone()).into())
+            }
+        }
+        DataOp::Fields(k) => {
+            if fields.contains(k.as_str()) {
+                Ok(())
+            } else {
+                Err(ConfigError::InvalidExecKey(key.as_ref().into(), k.clone()).into())
+            }
+        }
+        DataOp::Load(_) | DataOp::Join => Ok(()),
+    })
 }
 
 fn lift_result<T>(cur: Option<Result<T>>, prev: &mut Option<Result<T>>) -> Result<()>
@@ -246,7 +544,11 @@ where
 struct ConfigDeserialize {
     filter: Option<Result<FilterSet>>,
     join: Option<Result<JoinSet>>,
+    map: Option<Result<MapSet>>,
+    fields: Option<Result<FieldsSet>>,
     exec: Option<ExecList>,
+    pipelines: Option<Pipelines>,
+    route: Option<Routes>,
 }
 
 impl From<CfgInner> for ConfigDeserialize {
@@ -258,7 +560,15 @@ impl From<CfgInner> for ConfigDeserialize {
             join: inner
                 .join
                 .map(|i| i.try_into().map_err(|e| ConfigError::Other(e).into())),
+            map: inner
+                .map
+                .map(|i| i.try_into().map_err(|e| ConfigError::Other(e).into())),
+            fields: inner
+                .fields
+                .map(|i| i.try_into().map_err(|e| ConfigError::Other(e).into())),
             exec: inner.exec,
+            pipelines: inner.pipelines,
+            route: inner.route,
         }
     }
 }
@@ -269,8 +579,16 @@ struct CfgInner {
     filter: Option<FilterWrap>,
     #[serde(deserialize_with = "de_infallible", flatten)]
     join: Option<JoinWrap>,
+    #[serde(deserialize_with = "de_infallible", flatten)]
+    map: Option<MapWrap>,
+    #[serde(deserialize_with = "de_infallible", flatten)]
+    fields: Option<FieldsWrap>,
     #[serde(deserialize_with = "de_infallible")]
     exec: Option<ExecList>,
+    #[serde(default, deserialize_with = "de_infallible")]
+    pipelines: Option<Pipelines>,
+    #[serde(default, deserialize_with = "de_infallible")]
+    route: Option<Routes>,
 }
 
 fn de_infallible<'de, D, T>(de: D) -> std::result::Result<Option<T>, D::Error>
@@ -281,6 +599,43 @@ where
     Ok(Deserialize::deserialize(de).map(Some).unwrap_or(None))
 }
 
+/// Exec lists by name, any of which a client can ask its records go through instead of
+/// 'exec' during the handshake
+///
+/// ```yaml
+/// pipelines:
+///   network:
+///     - filter: greeting
+///     - load: localhost:9001
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Pipelines {
+    inner: HashMap<String, ExecList>,
+}
+
+/// Sends the records of every id matching a pattern through a pipeline rather than the
+/// connection's exec list, the first route to match an id winning
+///
+/// ```yaml
+/// route:
+///   - match: '^net_'
+///     pipeline: network
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Routes {
+    inner: Vec<Route>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Route {
+    #[serde(rename = "match", deserialize_with = "de_regex")]
+    pattern: Regex,
+    pipeline: String,
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(from = "Vec<DataOp>")]
 pub struct ExecList {
@@ -291,14 +646,16 @@ pub struct ExecList {
 
 impl ExecList {
     fn new(backing: Vec<DataOp>) -> Self {
+        // Ops run in the order given, only loads are moved after them. The sort is stable, and
+        // back to back joins would never join anything the first didn't
         let mut inner = backing;
-        inner.sort();
+        inner.sort_by_key(DataOp::is_load);
         inner.dedup_by(|a, b| *a == *b && *b == DataOp::Join);
 
         let ops_r = inner
             .iter()
             .enumerate()
-            .take_while(|(_, op)| op.is_join() || op.is_filter())
+            .take_while(|(_, op)| !op.is_load())
             .fold(None, |state, (idx, _)| {
                 state
                     .map(|(start, end)| (start, end + 1))
@@ -350,26 +707,17 @@ impl From<Vec<DataOp>> for ExecList {
     }
 }
 
-// Note that the order of variants in this enum are not arbitrary!
-// Due to the Ord derive the variants must appear in this order for
-// program correctness: Join, Filter, ..., Load
-#[derive(Debug, Deserialize, Eq, PartialOrd, Ord)]
+#[derive(Debug, Deserialize, Eq)]
 #[serde(rename_all = "lowercase")]
 enum DataOp {
     Join,
     Filter(String),
+    Map(String),
+    Fields(String),
     Load(String),
 }
 
 impl DataOp {
-    fn is_join(&self) -> bool {
-        *self == Self::Join
-    }
-
-    fn is_filter(&self) -> bool {
-        *self == Self::Filter(Default::default())
-    }
-
     fn is_load(&self) -> bool {
         *self == Self::Load(Default::default())
     }
@@ -380,6 +728,8 @@ impl PartialEq for DataOp {
         match (&self, other) {
             (Self::Join, Self::Join) => true,
             (Self::Filter(_), Self::Filter(_)) => true,
+            (Self::Map(_), Self::Map(_)) => true,
+            (Self::Fields(_), Self::Fields(_)) => true,
             (Self::Load(_), Self::Load(_)) => true,
             _ => false,
         }
@@ -391,6 +741,8 @@ impl Into<Subject> for &DataOp {
         match self {
             DataOp::Join => Subject::Join,
             DataOp::Filter(_) => Subject::Filter,
+            DataOp::Map(_) => Subject::Map,
+            DataOp::Fields(_) => Subject::Fields,
             DataOp::Load(_) => Subject::Load,
         }
     }
@@ -409,6 +761,8 @@ impl<'cli> TryFrom<&'cli DataOp> for OpKind<'cli> {
         match exec {
             DataOp::Join => Ok(OpKind::Join),
             DataOp::Filter(s) => Ok(OpKind::Filter(s.as_str())),
+            DataOp::Map(s) => Ok(OpKind::Map(s.as_str())),
+            DataOp::Fields(s) => Ok(OpKind::Fields(s.as_str())),
             _ => Err(()),
         }
     }
@@ -428,6 +782,8 @@ impl<'cli> TryFrom<&'cli DataOp> for Load<'cli> {
 #[derive(Debug, Clone, Copy)]
 pub enum OpKind<'cli> {
     Filter(&'cli str),
+    Map(&'cli str),
+    Fields(&'cli str),
     Join,
 }
 
@@ -1,6 +1,8 @@
 use {
     crate::{load::error::LoadError, models::SpanDisplay, prelude::*},
-    lib_transport::DataContext as RecordContext,
+    lib_transport::{
+        ContextError, DataContext as RecordContext, InterfaceError, InterfaceErrorKind,
+    },
     std::{error, fmt, io::Error as IoError, string::FromUtf8Error},
     thiserror::Error,
 };
@@ -13,19 +15,6 @@ pub struct CrateError {
     inner: Box<Err>,
 }
 
-impl CrateError {
-    pub fn err_invalid_record<T>(invalid: RecordContext, expected: T) -> Self
-    where
-        T: AsRef<[RecordContext]>,
-    {
-        Err::InvalidRecordContext {
-            invalid,
-            expected: rcxt_join(expected),
-        }
-        .into()
-    }
-}
-
 impl<E> From<E> for CrateError
 where
     E: Into<Err>,
@@ -45,6 +34,19 @@ impl fmt::Display for CrateError {
 
 impl error::Error for CrateError {}
 
+/// Converts into the error carried by an Error record, so it can be reported downstream
+impl From<&CrateError> for InterfaceError {
+    fn from(err: &CrateError) -> Self {
+        let kind = match err.inner.categorize() {
+            Category::Io => InterfaceErrorKind::Io,
+            Category::Config => InterfaceErrorKind::Config,
+            Category::Record => InterfaceErrorKind::Decode,
+        };
+
+        InterfaceError::from_error(kind, err.inner.as_ref())
+    }
+}
+
 /// Abstraction layer for potential early return in main if ProgramArgs init failed
 #[derive(Debug)]
 pub struct RefError {
@@ -114,6 +116,15 @@ pub enum Err {
     },
 }
 
+impl From<ContextError> for Err {
+    fn from(err: ContextError) -> Self {
+        Err::InvalidRecordContext {
+            invalid: err.found(),
+            expected: rcxt_join(err.expected()),
+        }
+    }
+}
+
 impl Err {
     fn categorize(&self) -> Category {
         self.into()
@@ -166,8 +177,11 @@ pub enum CfgErrSubject {
     Filter,
     Join,
     Map,
+    Fields,
     Transform,
     Exec,
+    Pipeline,
+    Route,
     Load,
 }
 
@@ -177,8 +191,11 @@ impl fmt::Display for CfgErrSubject {
             Self::Filter => format_args!("filter"),
             Self::Join => format_args!("join"),
             Self::Map => format_args!("map"),
+            Self::Fields => format_args!("fields"),
             Self::Transform => format_args!("transform"),
             Self::Exec => format_args!("exec"),
+            Self::Pipeline => format_args!("pipelines"),
+            Self::Route => format_args!("route"),
             Self::Load => format_args!("load"),
         };
 
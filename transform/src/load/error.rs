@@ -36,6 +36,10 @@ pub enum Err {
     DuplicateRootName(String),
     #[error("{}", JiiDisplay(*.0))]
     JoinInvalidInput((bool, bool, bool)),
+    #[error("Invalid map rule '{}': {}", .0, .1)]
+    MapRule(String, String),
+    #[error("Invalid fields regex '{}': {}", .0, .1)]
+    FieldsRegex(String, String),
     #[error("Failed to deserialize yaml: {}", .source)]
     YamlError {
         #[from]
@@ -60,6 +64,8 @@ pub enum Category {
     Yaml,
     FilterSyntax,
     JoinSyntax,
+    MapSyntax,
+    FieldsSyntax,
 }
 
 impl From<&Err> for Category {
@@ -68,6 +74,8 @@ impl From<&Err> for Category {
             Err::YamlError { .. } => Self::Yaml,
             Err::DuplicateRootName { .. } => Self::FilterSyntax,
             Err::JoinInvalidInput(_) => Self::JoinSyntax,
+            Err::MapRule(..) => Self::MapSyntax,
+            Err::FieldsRegex(..) => Self::FieldsSyntax,
         }
     }
 }
@@ -78,6 +86,8 @@ impl SpanDisplay for Category {
             Self::Yaml => write!(f, "Yaml"),
             Self::FilterSyntax => write!(f, "FilterSyntax"),
             Self::JoinSyntax => write!(f, "JoinSyntax"),
+            Self::MapSyntax => write!(f, "MapSyntax"),
+            Self::FieldsSyntax => write!(f, "FieldsSyntax"),
         }
     }
 }
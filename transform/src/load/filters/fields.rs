@@ -0,0 +1,107 @@
+use {
+    super::*,
+    lib_transport::Meta,
+    serde_yaml::from_reader as read_yaml,
+    std::{collections::HashMap, convert::TryFrom, io},
+};
+
+/// Named lists of regexes whose named capture groups are stored in a record's metadata,
+/// keyed by the group's name. Only the first regex of a list to match is used, and fields
+/// already in the metadata are never replaced
+///
+/// ```yaml
+/// fields:
+///   login:
+///     - '(?P<user>\w+) logged in after (?P<ms>\d+)ms'
+///     - '(?P<user>\w+) failed to log in'
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(try_from = "FieldsWrap")]
+pub struct FieldsSet {
+    named_set: HashMap<String, Vec<Regex>>,
+}
+
+impl FieldsSet {
+    pub fn new_fields<R>(data: R) -> Result<Self, LoadError>
+    where
+        R: io::Read,
+    {
+        read_yaml(data).map_err(|e| e.into())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.named_set.contains_key(name)
+    }
+
+    /// Stores the fields captured from `on` by the named list in `meta`, returning how many
+    /// were stored
+    pub fn extract_with(&self, name: &str, on: &str, meta: &mut Meta) -> usize {
+        let captured = self
+            .named_set
+            .get(name)
+            .unwrap()
+            .iter()
+            .find_map(|rx| rx.captures(on).map(|caps| (rx, caps)));
+
+        match captured {
+            Some((rx, caps)) => {
+                debug!(regex = %rx, "CAPTURED");
+                rx.capture_names()
+                    .flatten()
+                    .filter_map(|field| caps.name(field).map(|value| (field, value.as_str())))
+                    .fold(0, |stored, (field, value)| match meta.contains_key(field) {
+                        true => stored,
+                        false => {
+                            meta.insert(field.to_string(), value.to_string());
+                            stored + 1
+                        }
+                    })
+            }
+            None => 0,
+        }
+    }
+}
+
+impl TryFrom<FieldsWrap> for FieldsSet {
+    type Error = LoadError;
+
+    fn try_from(wrap: FieldsWrap) -> Result<Self, Self::Error> {
+        let named_set = wrap
+            .fields
+            .into_iter()
+            .map(|(name, patterns)| {
+                enter!(always_span!("init.fields", name = name.as_str()));
+                if patterns.is_empty() {
+                    warn!("Fields has no regexes, this named fields will never capture anything");
+                }
+                let patterns = patterns
+                    .iter()
+                    .map(|pattern| parse(pattern))
+                    .collect::<Result<_, _>>()?;
+
+                Ok((name, patterns))
+            })
+            .collect::<Result<_, LoadError>>()?;
+
+        Ok(Self { named_set })
+    }
+}
+
+/// A regex with at least one named capture group, as nothing could be stored without one
+fn parse(pattern: &str) -> Result<Regex, LoadError> {
+    let invalid = |reason: String| Err::FieldsRegex(pattern.to_string(), reason);
+    let rx = Regex::new(pattern).map_err(|e| invalid(e.to_string()))?;
+    if rx.capture_names().flatten().next().is_none() {
+        return Err(invalid("expected a named capture group, i.e (?P<name>...)".into()).into());
+    }
+    debug!(kind = "FIELDS", regex = %rx);
+
+    Ok(rx)
+}
+
+#[derive(Deserialize, Debug)]
+pub struct FieldsWrap {
+    fields: FieldsIntermediate,
+}
+
+type FieldsIntermediate = HashMap<String, Vec<String>>;
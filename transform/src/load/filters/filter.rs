@@ -27,11 +27,7 @@ impl FilterSet {
         f(&self.store, &self.named_set)
     }
 
-    pub fn is_match_all<T>(&self, on: T) -> bool
-    where
-        T: AsRef<str>,
-    {
-        let on = on.as_ref();
+    pub fn is_match_all(&self, on: &Target<'_>) -> bool {
         self.access_set(|store, m| {
             m.values().fold(true, |state, root| {
                 if state {
@@ -46,11 +42,25 @@ impl FilterSet {
         })
     }
 
-    pub fn is_match_with<T>(&self, name: &str, on: T) -> bool
-    where
-        T: AsRef<str>,
-    {
-        let on = on.as_ref();
+    pub fn contains(&self, name: &str) -> bool {
+        self.named_set.contains_key(name)
+    }
+
+    /// Every named filter's tree as indented yaml, in order of name
+    pub fn describe(&self) -> String {
+        let mut names: Vec<_> = self.named_set.iter().collect();
+        names.sort_by_key(|(name, _)| name.as_str());
+
+        names
+            .into_iter()
+            .fold(String::new(), |mut out, (name, root)| {
+                let _ = writeln!(out, "{}:", name);
+                let _ = describe_tree(&self.store, *root, 1, &mut out);
+                out
+            })
+    }
+
+    pub fn is_match_with(&self, name: &str, on: &Target<'_>) -> bool {
         self.access_set(|store, m| {
             let root = m.get(name).unwrap();
             store
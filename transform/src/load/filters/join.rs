@@ -35,10 +35,7 @@ pub struct JoinSetHandle<'j> {
 }
 
 impl<'j> JoinSetHandle<'j> {
-    pub fn should_join<T>(&mut self, on: T) -> bool
-    where
-        T: AsRef<str>,
-    {
+    pub fn should_join(&mut self, on: &Target<'_>) -> bool {
         let store = self.store;
         Self::internal_join(&mut self.handle, &mut self.state, store, on)
     }
@@ -52,17 +49,12 @@ impl<'j> JoinSetHandle<'j> {
     }
 
     // Required to help brwck isolate references
-    fn internal_join<T>(
+    fn internal_join(
         handle: &mut JoinInner,
         state: &mut Option<()>,
         store: &Arena<Node<FilterData>>,
-        on: T,
-    ) -> bool
-    where
-        T: AsRef<str>,
-    {
-        let item = on.as_ref();
-
+        item: &Target<'_>,
+    ) -> bool {
         handle.join(state, |idx| {
             store
                 .get(idx)
@@ -0,0 +1,109 @@
+use {
+    super::*,
+    serde_yaml::from_reader as read_yaml,
+    std::{borrow::Cow, collections::HashMap, convert::TryFrom, io},
+};
+
+/// Separates a rule's pattern from its replacement, the first occurrence is used
+const ARROW: &str = " -> ";
+
+/// Named lists of sed-style rewrites, each rule being `pattern -> replacement`. Replacements
+/// may refer to the pattern's capture groups as `$1` or `${name}`, and every rule of a list
+/// is applied in order to what the last left
+///
+/// ```yaml
+/// map:
+///   redact:
+///     - 'password=\S+ -> password=***'
+///     - '(\d+)ms -> ${1} milliseconds'
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(try_from = "MapWrap")]
+pub struct MapSet {
+    named_set: HashMap<String, Vec<MapRule>>,
+}
+
+impl MapSet {
+    pub fn new_map<R>(data: R) -> Result<Self, LoadError>
+    where
+        R: io::Read,
+    {
+        read_yaml(data).map_err(|e| e.into())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.named_set.contains_key(name)
+    }
+
+    /// Applies every rule of the named list to `on`, borrowing it if none matched
+    pub fn apply_with<'a>(&self, name: &str, on: &'a str) -> Cow<'a, str> {
+        self.named_set
+            .get(name)
+            .unwrap()
+            .iter()
+            .fold(Cow::Borrowed(on), |text, rule| {
+                match rule.regex.replace_all(&text, rule.replacement.as_str()) {
+                    Cow::Borrowed(_) => text,
+                    Cow::Owned(replaced) => {
+                        debug!(regex = %rule.regex, "REPLACED");
+                        Cow::Owned(replaced)
+                    }
+                }
+            })
+    }
+}
+
+impl TryFrom<MapWrap> for MapSet {
+    type Error = LoadError;
+
+    fn try_from(wrap: MapWrap) -> Result<Self, Self::Error> {
+        let named_set = wrap
+            .map
+            .into_iter()
+            .map(|(name, rules)| {
+                enter!(always_span!("init.map", name = name.as_str()));
+                if rules.is_empty() {
+                    warn!("Map has no rules, this named map will never change anything");
+                }
+                let rules = rules
+                    .iter()
+                    .map(|rule| MapRule::parse(rule))
+                    .collect::<Result<_, _>>()?;
+
+                Ok((name, rules))
+            })
+            .collect::<Result<_, LoadError>>()?;
+
+        Ok(Self { named_set })
+    }
+}
+
+#[derive(Debug)]
+struct MapRule {
+    regex: Regex,
+    replacement: String,
+}
+
+impl MapRule {
+    fn parse(rule: &str) -> Result<Self, LoadError> {
+        let invalid = |reason: String| Err::MapRule(rule.to_string(), reason);
+        let (pattern, replacement) = rule
+            .find(ARROW)
+            .map(|at| (&rule[..at], &rule[at + ARROW.len()..]))
+            .ok_or_else(|| invalid(format!("expected 'pattern{}replacement'", ARROW)))?;
+        let regex = Regex::new(pattern).map_err(|e| invalid(e.to_string()))?;
+        debug!(kind = "MAP", regex = %regex, replacement);
+
+        Ok(Self {
+            regex,
+            replacement: replacement.to_string(),
+        })
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MapWrap {
+    map: MapIntermediate,
+}
+
+type MapIntermediate = HashMap<String, Vec<String>>;
@@ -4,25 +4,49 @@ use {
         graph::Node,
     },
     crate::prelude::*,
+    chrono::DateTime,
     generational_arena::{Arena, Index},
+    globset::{Glob, GlobMatcher},
+    lib_transport::{OutputStream, OwnedData, RecordTime},
     regex::Regex,
     serde::{de, Deserialize, Deserializer},
+    std::{borrow::Cow, convert::TryFrom, fmt, fmt::Write as _},
 };
 
 pub use {
+    fields::{FieldsSet, FieldsWrap},
     filter::{FilterSet, FilterWrap},
     join::{JoinSet, JoinSetHandle, JoinWrap},
+    map::{MapSet, MapWrap},
 };
 
+mod fields;
 mod filter;
 mod join;
+mod map;
+
+/// A Data record as filters see it, its payload decoded once for every leaf matching text
+pub struct Target<'r> {
+    record: &'r OwnedData,
+    text: Cow<'r, str>,
+}
+
+impl<'r> Target<'r> {
+    pub fn new(record: &'r OwnedData) -> Self {
+        Self {
+            record,
+            text: record.text(),
+        }
+    }
+}
 
 pub fn recursive_match(
     arena: &Arena<Node<FilterData>>,
     data: &FilterData,
     edges: &[Index],
-    text: &str,
+    on: &Target<'_>,
 ) -> bool {
+    let text = on.text.as_ref();
     match data.ty {
         // Run regex
         NodeType::Regex(ref rx) => {
@@ -30,6 +54,36 @@ pub fn recursive_match(
             debug!(regex = %rx, negate = data.negate.as_bool(), matched = b);
             b
         }
+        NodeType::Contains(ref s) => {
+            let b = text.contains(s.as_str()).negate(data.negate);
+            debug!(contains = %s, negate = data.negate.as_bool(), matched = b);
+            b
+        }
+        NodeType::StartsWith(ref s) => {
+            let b = text.starts_with(s.as_str()).negate(data.negate);
+            debug!(starts_with = %s, negate = data.negate.as_bool(), matched = b);
+            b
+        }
+        NodeType::EndsWith(ref s) => {
+            let b = text.ends_with(s.as_str()).negate(data.negate);
+            debug!(ends_with = %s, negate = data.negate.as_bool(), matched = b);
+            b
+        }
+        NodeType::Glob(ref glob) => {
+            let b = glob.is_match(text).negate(data.negate);
+            debug!(glob = %glob.glob(), negate = data.negate.as_bool(), matched = b);
+            b
+        }
+        NodeType::Number(ref num) => {
+            let b = num.is_match(text).negate(data.negate);
+            debug!(number = %num.regex, negate = data.negate.as_bool(), matched = b);
+            b
+        }
+        NodeType::Field(ref field) => {
+            let b = field.is_match(on.record).negate(data.negate);
+            debug!(field = ?field, negate = data.negate.as_bool(), matched = b);
+            b
+        }
         // Wait for all success / return on first error
         NodeType::And => {
             let res: Result<(), ()> = edges
@@ -38,7 +92,7 @@ pub fn recursive_match(
                     arena
                         .get(*idx)
                         .unwrap()
-                        .traverse_with(&|a, d, i| recursive_match(a, d, i, text), arena)
+                        .traverse_with(&|a, d, i| recursive_match(a, d, i, on), arena)
                 })
                 .map(|b| match b {
                     true => Ok(()),
@@ -57,7 +111,7 @@ pub fn recursive_match(
                     arena
                         .get(*idx)
                         .unwrap()
-                        .traverse_with(&|a, d, i| recursive_match(a, d, i, text), arena)
+                        .traverse_with(&|a, d, i| recursive_match(a, d, i, on), arena)
                 })
                 .map(|b| match b {
                     false => Ok(()),
@@ -68,9 +122,99 @@ pub fn recursive_match(
 
             res.is_err().negate(data.negate)
         }
+        // Return once n succeed / once too few are left to
+        NodeType::AtLeast(n) => {
+            let mut left = edges.len();
+            let mut matched = 0;
+            let b = edges
+                .iter()
+                .map(|idx| {
+                    arena
+                        .get(*idx)
+                        .unwrap()
+                        .traverse_with(&|a, d, i| recursive_match(a, d, i, on), arena)
+                })
+                .any(|b| {
+                    left -= 1;
+                    matched += b as usize;
+                    matched >= n || matched + left < n
+                })
+                && matched >= n;
+
+            b.negate(data.negate)
+        }
+        // Return on the second success / wait for all to find exactly one
+        NodeType::Xor => {
+            let matched = edges
+                .iter()
+                .map(|idx| {
+                    arena
+                        .get(*idx)
+                        .unwrap()
+                        .traverse_with(&|a, d, i| recursive_match(a, d, i, on), arena)
+                })
+                .filter(|b| *b)
+                .take(2)
+                .count();
+
+            (matched == 1).negate(data.negate)
+        }
+    }
+}
+
+/// Writes the tree under `root` to `out` as indented yaml, much as it would be configured
+pub fn describe_tree(
+    arena: &Arena<Node<FilterData>>,
+    root: Index,
+    depth: usize,
+    out: &mut String,
+) -> fmt::Result {
+    let node = arena.get(root).unwrap();
+    let indent = "  ".repeat(depth);
+    let not = match node.datum.negate.as_bool() {
+        true => "not ",
+        false => "",
+    };
+    match node.datum.ty {
+        NodeType::Regex(ref rx) => writeln!(out, "{}- {}re: '{}'", indent, not, rx),
+        NodeType::Contains(ref s) => writeln!(out, "{}- {}contains: '{}'", indent, not, s),
+        NodeType::StartsWith(ref s) => writeln!(out, "{}- {}starts_with: '{}'", indent, not, s),
+        NodeType::EndsWith(ref s) => writeln!(out, "{}- {}ends_with: '{}'", indent, not, s),
+        NodeType::Glob(ref glob) => writeln!(out, "{}- {}glob: '{}'", indent, not, glob.glob()),
+        NodeType::Number(ref num) => writeln!(out, "{}- {}number: {:?}", indent, not, num),
+        NodeType::Field(ref field) => writeln!(out, "{}- {}field: {:?}", indent, not, field),
+        NodeType::And | NodeType::Or | NodeType::Xor => {
+            let kind = match node.datum.ty {
+                NodeType::And => "all",
+                NodeType::Or => "any",
+                _ => "xor",
+            };
+            writeln!(out, "{}- {}{}:", indent, not, kind)?;
+            describe_edges(arena, node, depth + 1, out)
+        }
+        NodeType::AtLeast(n) => {
+            writeln!(out, "{}- {}at_least:", indent, not)?;
+            writeln!(out, "{}    n: {}", indent, n)?;
+            writeln!(out, "{}    of:", indent)?;
+            describe_edges(arena, node, depth + 2, out)
+        }
     }
 }
 
+fn describe_edges(
+    arena: &Arena<Node<FilterData>>,
+    node: &Node<FilterData>,
+    depth: usize,
+    out: &mut String,
+) -> fmt::Result {
+    node.edges
+        .get()
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .try_for_each(|edge| describe_tree(arena, *edge, depth, out))
+}
+
 fn init_tree(arena: &mut Arena<Node<FilterData>>, seeds: Vec<FilterSeed>) -> Index {
     trace!("Starting recursive init");
     let mut top_level = init_recursive(arena, false, seeds.into_iter());
@@ -103,13 +247,49 @@ where
 
     for seed in iter {
         match seed {
-            // A Regex seed will never have children, it is guaranteed to be a leaf node.
+            // Leaf seeds will never have children, they are guaranteed to be leaf nodes.
             FilterSeed::Regex(rx) => {
                 debug!(kind = "RX", negate, regex = %&rx);
                 let node = Node::new(FilterData::new(NodeType::Regex(rx), negate), arena);
 
                 edges.push(node);
             }
+            FilterSeed::Contains(s) => {
+                debug!(kind = "CONTAINS", negate, contains = %&s);
+                let node = Node::new(FilterData::new(NodeType::Contains(s), negate), arena);
+
+                edges.push(node);
+            }
+            FilterSeed::StartsWith(s) => {
+                debug!(kind = "STARTS", negate, starts_with = %&s);
+                let node = Node::new(FilterData::new(NodeType::StartsWith(s), negate), arena);
+
+                edges.push(node);
+            }
+            FilterSeed::EndsWith(s) => {
+                debug!(kind = "ENDS", negate, ends_with = %&s);
+                let node = Node::new(FilterData::new(NodeType::EndsWith(s), negate), arena);
+
+                edges.push(node);
+            }
+            FilterSeed::Glob(glob) => {
+                debug!(kind = "GLOB", negate, glob = %glob.glob());
+                let node = Node::new(FilterData::new(NodeType::Glob(glob), negate), arena);
+
+                edges.push(node);
+            }
+            FilterSeed::Number(num) => {
+                debug!(kind = "NUM", negate, number = %&num.regex);
+                let node = Node::new(FilterData::new(NodeType::Number(num), negate), arena);
+
+                edges.push(node);
+            }
+            FilterSeed::Field(field) => {
+                debug!(kind = "FIELD", negate, field = ?&field);
+                let node = Node::new(FilterData::new(NodeType::Field(field), negate), arena);
+
+                edges.push(node);
+            }
             // Note that 'Not' seeds are _not_ themselves nodes, they merely invert nodes below and
             // pass them as children to the node above
             FilterSeed::Not(vec) => {
@@ -120,9 +300,13 @@ where
 
                 edges.extend(e);
             }
-            // 'And' and 'Or' seeds _are_ nodes, therefore allocate them in the arena and
-            // assign them their children, before pushing them to the calling node's children
-            seed @ FilterSeed::And(_) | seed @ FilterSeed::Or(_) => {
+            // 'And', 'Or', 'AtLeast' and 'Xor' seeds _are_ nodes, therefore allocate them in the
+            // arena and assign them their children, before pushing them to the calling node's
+            // children
+            seed @ FilterSeed::And(_)
+            | seed @ FilterSeed::Or(_)
+            | seed @ FilterSeed::AtLeast(_)
+            | seed @ FilterSeed::Xor(_) => {
                 let (nt, vec) = match seed {
                     FilterSeed::And(vec) => {
                         trace!(kind = "AND", negate, children = vec.len());
@@ -132,15 +316,37 @@ where
                         trace!(kind = "OR", negate, children = vec.len());
                         (NodeType::Or, vec)
                     }
+                    FilterSeed::AtLeast(AtLeast { n, of }) => {
+                        trace!(kind = "ATLEAST", negate, n, children = of.len());
+                        (NodeType::AtLeast(n), of)
+                    }
+                    FilterSeed::Xor(vec) => {
+                        trace!(kind = "XOR", negate, children = vec.len());
+                        (NodeType::Xor, vec)
+                    }
                     // Outer match guarantees other variants will not hit this branch
                     // TODO: maybe change to unreachable_unchecked!()
                     _ => unreachable!(),
                 };
 
+                // A 'Not' above an 'AtLeast' or 'Xor' inverts only its count; negating the
+                // children too would turn 'not exactly one' into 'not exactly len - 1'
+                let below = match nt {
+                    NodeType::AtLeast(_) | NodeType::Xor => false,
+                    _ => negate,
+                };
+                let children = init_recursive(arena, below, vec.into_iter());
+                if let NodeType::AtLeast(n) = nt {
+                    if n > children.len() {
+                        warn!(
+                            n,
+                            children = children.len(),
+                            "At least has fewer children than n, it will never match"
+                        );
+                    }
+                }
                 let node = Node::new_unallocated(FilterData::new(nt, negate));
-                node.edges
-                    .set(init_recursive(arena, negate, vec.into_iter()))
-                    .unwrap();
+                node.edges.set(children).unwrap();
                 let node_idx = arena.insert(node);
 
                 edges.push(node_idx);
@@ -178,8 +384,16 @@ impl From<NodeType> for FilterData {
 #[derive(Debug, Clone)]
 pub enum NodeType {
     Regex(Regex),
+    Contains(String),
+    StartsWith(String),
+    EndsWith(String),
+    Glob(GlobMatcher),
+    Number(Number),
+    Field(Field),
     And,
     Or,
+    AtLeast(usize),
+    Xor,
 }
 
 /// Extension type for use with Negate
@@ -254,11 +468,223 @@ pub enum FilterSeed {
     #[serde(alias = "any")]
     Or(Vec<FilterSeed>),
     Not(Vec<FilterSeed>),
+    #[serde(rename = "at_least")]
+    AtLeast(AtLeast),
+    /// Matches if exactly one of the filters it holds match. Under a `not` it matches if none
+    /// or more than one do, as only the count is inverted
+    Xor(Vec<FilterSeed>),
     #[serde(alias = "re", alias = "rx", deserialize_with = "de_regex")]
     Regex(Regex),
+    Contains(String),
+    #[serde(rename = "starts_with", alias = "starts")]
+    StartsWith(String),
+    #[serde(rename = "ends_with", alias = "ends")]
+    EndsWith(String),
+    #[serde(deserialize_with = "de_glob")]
+    Glob(GlobMatcher),
+    #[serde(alias = "num")]
+    Number(Number),
+    Field(Field),
+}
+
+/// Matches if `n` or more of the filters it holds match, sparing conditions like 'two of these
+/// three' from being spelled out as every pair. Under a `not` it matches if fewer than `n` do,
+/// as only the count is inverted
+///
+/// ```yaml
+/// - at_least:
+///     n: 2
+///     of:
+///       - contains: 'timeout'
+///       - contains: 'retry'
+///       - field:
+///           cxt: stderr
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "AtLeastSeed")]
+pub struct AtLeast {
+    n: usize,
+    of: Vec<FilterSeed>,
+}
+
+impl TryFrom<AtLeastSeed> for AtLeast {
+    type Error = &'static str;
+
+    fn try_from(seed: AtLeastSeed) -> Result<Self, Self::Error> {
+        match seed.n {
+            0 => Err("an at_least needs an n of 1 or more"),
+            n => Ok(Self { n, of: seed.of }),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AtLeastSeed {
+    n: usize,
+    of: Vec<FilterSeed>,
+}
+
+/// Compares the number a regex finds in the text, in its first capture group if it has one,
+/// against every bound given. Text it doesn't find a number in never matches
+///
+/// ```yaml
+/// - number:
+///     re: 'took (\d+)ms'
+///     gt: 100
+///     le: 5000
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "NumberSeed")]
+pub struct Number {
+    regex: Regex,
+    bounds: Vec<(Bound, f64)>,
 }
 
-fn de_regex<'de, D>(de: D) -> Result<Regex, D::Error>
+impl Number {
+    fn is_match(&self, text: &str) -> bool {
+        self.regex
+            .captures(text)
+            .and_then(|caps| caps.get(1).or_else(|| caps.get(0)))
+            .and_then(|found| found.as_str().parse::<f64>().ok())
+            .map(|n| self.bounds.iter().all(|(bound, to)| bound.holds(n, *to)))
+            .unwrap_or(false)
+    }
+}
+
+impl TryFrom<NumberSeed> for Number {
+    type Error = &'static str;
+
+    fn try_from(seed: NumberSeed) -> Result<Self, Self::Error> {
+        let bounds: Vec<_> = [
+            (Bound::Gt, seed.gt),
+            (Bound::Ge, seed.ge),
+            (Bound::Lt, seed.lt),
+            (Bound::Le, seed.le),
+            (Bound::Eq, seed.eq),
+            (Bound::Ne, seed.ne),
+        ]
+        .iter()
+        .filter_map(|(bound, to)| to.map(|to| (*bound, to)))
+        .collect();
+
+        match bounds.is_empty() {
+            true => Err("a number needs at least one of gt, ge, lt, le, eq or ne"),
+            false => Ok(Self {
+                regex: seed.regex,
+                bounds,
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NumberSeed {
+    #[serde(alias = "re", alias = "rx", deserialize_with = "de_regex")]
+    regex: Regex,
+    gt: Option<f64>,
+    ge: Option<f64>,
+    lt: Option<f64>,
+    le: Option<f64>,
+    eq: Option<f64>,
+    ne: Option<f64>,
+}
+
+/// Matches a record's fields rather than its payload, every one given must match. Times are
+/// RFC 3339, a record matching if it was stamped at or after `after` and before `before`
+///
+/// ```yaml
+/// - field:
+///     id: '^disk_'
+///     cxt: stderr
+///     after: 2020-06-01T00:00:00Z
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "FieldSeed")]
+pub struct Field {
+    id: Option<Regex>,
+    pid: Option<u32>,
+    cxt: Option<OutputStream>,
+    after: Option<i64>,
+    before: Option<i64>,
+}
+
+impl Field {
+    fn is_match(&self, record: &OwnedData) -> bool {
+        !matches!(&self.id, Some(rx) if !rx.is_match(&record.id))
+            && !matches!(self.pid, Some(pid) if pid != record.pid)
+            && !matches!(self.cxt, Some(cxt) if cxt != record.cxt)
+            && !matches!(self.after, Some(after) if record.time < after)
+            && !matches!(self.before, Some(before) if record.time >= before)
+    }
+}
+
+impl TryFrom<FieldSeed> for Field {
+    type Error = &'static str;
+
+    fn try_from(seed: FieldSeed) -> Result<Self, Self::Error> {
+        let FieldSeed {
+            id,
+            pid,
+            cxt,
+            after,
+            before,
+        } = seed;
+
+        match (&id, pid, cxt, after, before) {
+            (None, None, None, None, None) => {
+                Err("a field needs at least one of id, pid, cxt, after or before")
+            }
+            _ => Ok(Self {
+                id,
+                pid,
+                cxt,
+                after,
+                before,
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FieldSeed {
+    #[serde(default, deserialize_with = "de_opt_regex")]
+    id: Option<Regex>,
+    pid: Option<u32>,
+    #[serde(default, deserialize_with = "de_cxt")]
+    cxt: Option<OutputStream>,
+    #[serde(default, deserialize_with = "de_time")]
+    after: Option<i64>,
+    #[serde(default, deserialize_with = "de_time")]
+    before: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Bound {
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Eq,
+    Ne,
+}
+
+impl Bound {
+    fn holds(self, n: f64, to: f64) -> bool {
+        match self {
+            Self::Gt => n > to,
+            Self::Ge => n >= to,
+            Self::Lt => n < to,
+            Self::Le => n <= to,
+            Self::Eq => (n - to).abs() < f64::EPSILON,
+            Self::Ne => (n - to).abs() >= f64::EPSILON,
+        }
+    }
+}
+
+pub(crate) fn de_regex<'de, D>(de: D) -> Result<Regex, D::Error>
 where
     D: Deserializer<'de>,
 {
@@ -266,3 +692,148 @@ where
 
     Regex::new(&type_hint).map_err(de::Error::custom)
 }
+
+fn de_glob<'de, D>(de: D) -> Result<GlobMatcher, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let type_hint: String = Deserialize::deserialize(de)?;
+
+    Glob::new(&type_hint)
+        .map(|glob| glob.compile_matcher())
+        .map_err(de::Error::custom)
+}
+
+fn de_opt_regex<'de, D>(de: D) -> Result<Option<Regex>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    de_regex(de).map(Some)
+}
+
+fn de_cxt<'de, D>(de: D) -> Result<Option<OutputStream>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let type_hint: String = Deserialize::deserialize(de)?;
+
+    match type_hint.as_str() {
+        "stdout" => Ok(Some(OutputStream::Stdout)),
+        "stderr" => Ok(Some(OutputStream::Stderr)),
+        other => Err(de::Error::unknown_variant(other, &["stdout", "stderr"])),
+    }
+}
+
+fn de_time<'de, D>(de: D) -> Result<Option<i64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let type_hint: String = Deserialize::deserialize(de)?;
+
+    DateTime::parse_from_rfc3339(&type_hint)
+        .map(|time| Some(RecordTime::from_datetime(&time)))
+        .map_err(de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        lib_transport::{Common, Encoding, Meta, PROTOCOL_VERSION},
+    };
+
+    const CONFIG: &str = "
+filter:
+  two_of_three:
+    - at_least:
+        n: 2
+        of:
+          - contains: 'a'
+          - contains: 'b'
+          - contains: 'c'
+  four_of_three:
+    - at_least:
+        n: 4
+        of:
+          - contains: 'a'
+          - contains: 'b'
+          - contains: 'c'
+  none_of_nothing:
+    - at_least:
+        n: 1
+        of: []
+  one_of_three:
+    - xor:
+        - contains: 'a'
+        - contains: 'b'
+        - contains: 'c'
+  not_two_of_three:
+    - not:
+        - at_least:
+            n: 2
+            of:
+              - contains: 'a'
+              - contains: 'b'
+              - contains: 'c'
+  not_one_of_three:
+    - not:
+        - xor:
+            - contains: 'a'
+            - contains: 'b'
+            - contains: 'c'
+";
+
+    fn data(text: &str) -> OwnedData {
+        OwnedData {
+            required: Common::new(PROTOCOL_VERSION),
+            time: 0,
+            id: "10_some-script.sh".to_string(),
+            pid: 1,
+            cxt: OutputStream::Stdout,
+            data: text.as_bytes().to_vec(),
+            encoding: Encoding::Utf8,
+            trace_id: None,
+            meta: Meta::new(),
+            unknown: Vec::new(),
+        }
+    }
+
+    /// Returns which of `texts` the named filter matches
+    fn matches(name: &str, texts: &[&str]) -> Vec<bool> {
+        let set = FilterSet::new_filter(CONFIG.as_bytes()).unwrap();
+        texts
+            .iter()
+            .map(|text| set.is_match_with(name, &Target::new(&data(text))))
+            .collect()
+    }
+
+    #[test]
+    fn at_least_counts_matches() {
+        let texts = ["-", "a", "ab", "abc"];
+
+        assert_eq!(matches("two_of_three", &texts), [false, false, true, true]);
+        assert_eq!(matches("four_of_three", &texts), [false; 4]);
+        assert_eq!(matches("none_of_nothing", &texts), [false; 4]);
+    }
+
+    #[test]
+    fn xor_matches_exactly_one() {
+        let texts = ["-", "a", "ab", "abc"];
+
+        assert_eq!(matches("one_of_three", &texts), [false, true, false, false]);
+    }
+
+    #[test]
+    fn not_inverts_only_the_count() {
+        let texts = ["-", "a", "ab", "abc"];
+
+        assert_eq!(
+            matches("not_two_of_three", &texts),
+            [true, true, false, false]
+        );
+        assert_eq!(
+            matches("not_one_of_three", &texts),
+            [true, false, true, true]
+        );
+    }
+}
@@ -4,9 +4,10 @@ use {
     crate::{
         cli::{generate_cli, ProgramArgs},
         error::MainResult,
-        models::{check_args, init_logging, tcp::listener},
+        models::{admin, check_args, init_logging, tcp::listener},
         prelude::{CrateResult as Result, *},
     },
+    futures::FutureExt,
     lazy_static::lazy_static,
     tracing_futures::Instrument,
 };
@@ -69,6 +70,13 @@ fn main() -> MainResult<()> {
 #[tokio::main]
 async fn try_main() -> Result<()> {
     let addr = cli!().bind_addr();
+    if let Some(path) = cli!().admin() {
+        tokio::spawn(
+            admin::serve(path)
+                .map(|res| res.log(Level::ERROR))
+                .instrument(always_span!("admin", path = %path.display())),
+        );
+    }
     listener(addr)
         .instrument(always_span!("listener.tcp", bind = addr.0, port = addr.1))
         .await
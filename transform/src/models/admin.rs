@@ -0,0 +1,211 @@
+use {
+    super::set_log_filter,
+    crate::prelude::{CrateResult as Result, *},
+    futures::prelude::*,
+    lazy_static::lazy_static,
+    std::{
+        collections::{BTreeMap, BTreeSet, HashSet},
+        fmt::Write as _,
+        fs,
+        io::{self, ErrorKind},
+        net::SocketAddr,
+        os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+        path::Path,
+        process,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Mutex, RwLock,
+        },
+    },
+    tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::{UnixListener, UnixStream},
+    },
+};
+
+const HELP: &str = "\
+connections         list each connection and the ids it has open
+filters             dump every named filter's tree
+disable NAME        pass every record through the named filter
+enable NAME         filter records with the named filter again
+log DIRECTIVES      set the log filter, as RUST_LOG would
+help                print this";
+
+lazy_static! {
+    pub static ref ADMIN: Admin = Admin::default();
+}
+
+/// What the admin socket reports on and changes, shared with every connection
+#[derive(Debug, Default)]
+pub struct Admin {
+    next: AtomicU64,
+    connections: Mutex<BTreeMap<u64, Connection>>,
+    disabled: RwLock<HashSet<String>>,
+}
+
+#[derive(Debug)]
+struct Connection {
+    client: SocketAddr,
+    ids: BTreeSet<String>,
+}
+
+impl Admin {
+    /// Registers a new connection from `client`, returning its number
+    pub fn connected(&self, client: SocketAddr) -> u64 {
+        let conn = self.next.fetch_add(1, Ordering::Relaxed);
+        self.connections.lock().unwrap().insert(
+            conn,
+            Connection {
+                client,
+                ids: BTreeSet::new(),
+            },
+        );
+
+        conn
+    }
+
+    pub fn disconnected(&self, conn: u64) {
+        self.connections.lock().unwrap().remove(&conn);
+    }
+
+    pub fn id_opened(&self, conn: u64, id: &str) {
+        if let Some(connection) = self.connections.lock().unwrap().get_mut(&conn) {
+            connection.ids.insert(id.to_string());
+        }
+    }
+
+    pub fn id_closed(&self, conn: u64, id: &str) {
+        if let Some(connection) = self.connections.lock().unwrap().get_mut(&conn) {
+            connection.ids.remove(id);
+        }
+    }
+
+    /// Whether the named filter has been disabled, passing every record through
+    pub fn is_disabled(&self, filter: &str) -> bool {
+        self.disabled.read().unwrap().contains(filter)
+    }
+
+    /// Runs a single command, returning what to reply with
+    fn command(&self, line: &str) -> std::result::Result<String, String> {
+        let mut words = line.split_whitespace();
+        let filter_name = |name: Option<&str>| match name {
+            Some(name) if cli!().get_filter().contains(name) => Ok(name.to_string()),
+            Some(name) => Err(format!("no filter named '{}'", name)),
+            None => Err("expected a filter name".to_string()),
+        };
+
+        match words.next() {
+            Some("connections") => Ok(self.connections.lock().unwrap().iter().fold(
+                String::new(),
+                |mut out, (conn, connection)| {
+                    let ids: Vec<_> = connection.ids.iter().map(String::as_str).collect();
+                    let _ = writeln!(
+                        out,
+                        "{} client={} ids={}",
+                        conn,
+                        connection.client,
+                        ids.join(",")
+                    );
+                    out
+                },
+            )),
+            Some("filters") => {
+                let mut out = cli!().get_filter().describe();
+                for name in self.disabled.read().unwrap().iter() {
+                    let _ = writeln!(out, "# {} is disabled", name);
+                }
+                Ok(out)
+            }
+            Some("disable") => filter_name(words.next()).map(|name| {
+                info!(filter = %name, "Filter disabled");
+                self.disabled.write().unwrap().insert(name);
+                String::new()
+            }),
+            Some("enable") => filter_name(words.next()).map(|name| {
+                info!(filter = %name, "Filter enabled");
+                self.disabled.write().unwrap().remove(&name);
+                String::new()
+            }),
+            Some("log") => {
+                let directives = words.collect::<Vec<_>>().join(",");
+                set_log_filter(&directives).map(|_| {
+                    info!(%directives, "Log filter changed");
+                    String::new()
+                })
+            }
+            Some("help") => Ok(format!("{}\n", HELP)),
+            Some(other) => Err(format!("unknown command '{}', try 'help'", other)),
+            None => Ok(String::new()),
+        }
+    }
+}
+
+/// Serves the admin socket at `path`, replacing any socket left there by an earlier run,
+/// though nothing else. Only its owner may connect to it. Each line read is a command,
+/// answered by its output followed by `ok`, or by `error: REASON`
+pub async fn serve(path: &Path) -> Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => fs::remove_file(path)?,
+        Ok(_) => {
+            return Err(io::Error::new(
+                ErrorKind::AlreadyExists,
+                format!("'{}' exists and isn't a socket", path.display()),
+            )
+            .into())
+        }
+        Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+        Err(_) => (),
+    }
+    let mut listener = bind_private(path)?;
+    info!(path = %path.display(), "Admin socket listening");
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(
+                    handle_admin(stream)
+                        .unwrap_or_else(|e| debug!("Admin connection closed: {}", e))
+                        .instrument(always_span!("admin.con")),
+                );
+            }
+            Err(e) => warn!("Failed to accept admin connection: {}", e),
+        }
+    }
+}
+
+/// Binds a socket only its owner may connect to at `path`. It is bound inside a directory
+/// only the owner can enter and narrowed there, as bound in place it would be open to anyone
+/// the umask allows until then, and only then moved to `path`
+fn bind_private(path: &Path) -> io::Result<UnixListener> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let private = path.with_file_name(format!(".{}.{}", name, process::id()));
+    fs::DirBuilder::new().mode(0o700).create(&private)?;
+
+    let bound = private.join("admin.sock");
+    let listener = UnixListener::bind(&bound).and_then(|listener| {
+        fs::set_permissions(&bound, fs::Permissions::from_mode(0o600))?;
+        fs::rename(&bound, path)?;
+        Ok(listener)
+    });
+    // Only left behind if binding or moving the socket failed
+    let _ = fs::remove_file(&bound);
+    let removed = fs::remove_dir(&private);
+
+    listener.and_then(|listener| removed.map(|_| listener))
+}
+
+async fn handle_admin(mut stream: UnixStream) -> std::io::Result<()> {
+    let (read, mut write) = stream.split();
+    let mut lines = BufReader::new(read).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        debug!(command = %line, "Admin command");
+        let reply = match ADMIN.command(&line) {
+            Ok(out) => format!("{}ok\n", out),
+            Err(e) => format!("error: {}\n", e),
+        };
+        write.write_all(reply.as_bytes()).await?;
+    }
+
+    Ok(())
+}
@@ -1,31 +1,51 @@
 use {
     crate::{error::MainResult, prelude::*, ARGS},
-    lib_transport::{
-        Common, Data as RecordData, DataContext as RecordContext, Header as RecordHeader, Record,
+    lib_transport::Record,
+    once_cell::sync::OnceCell,
+    std::{fmt, io},
+    tracing_subscriber::{
+        fmt::{
+            format::{DefaultFields, Format, Full},
+            Formatter,
+        },
+        reload::Handle,
+        EnvFilter, FmtSubscriber,
     },
-    std::{
-        convert::{TryFrom, TryInto},
-        fmt,
-    },
-    tracing_subscriber::{EnvFilter, FmtSubscriber},
 };
 
+pub mod admin;
 pub mod tcp;
 
+type LogHandle = Handle<EnvFilter, Formatter<DefaultFields, Format<Full>, fn() -> io::Stderr>>;
+
+/// Swaps the global logger's filter at runtime, set once logging is initialized
+static LOG_FILTER: OnceCell<LogHandle> = OnceCell::new();
+
 /// Initialize the global logger. This function must be called before ARGS is initialized,
 /// otherwise logs generated during CLI parsing will be silently ignored
 pub fn init_logging() {
-    let root_subscriber = FmtSubscriber::builder()
-        .with_writer(std::io::stderr)
+    let builder = FmtSubscriber::builder()
+        .with_writer(io::stderr as fn() -> io::Stderr)
         .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| {
             EnvFilter::default().add_directive(tracing_subscriber::filter::LevelFilter::INFO.into())
         }))
-        .with_filter_reloading()
-        .finish();
+        .with_filter_reloading();
+    let _ = LOG_FILTER.set(builder.reload_handle());
+    let root_subscriber = builder.finish();
     tracing::subscriber::set_global_default(root_subscriber).expect("Failed to init logging");
     info!("<== Logs Start ==>")
 }
 
+/// Replaces the global logger's filter with `directives`, as RUST_LOG would set them
+pub fn set_log_filter(directives: &str) -> std::result::Result<(), String> {
+    let filter = EnvFilter::try_new(directives).map_err(|e| e.to_string())?;
+    LOG_FILTER
+        .get()
+        .ok_or_else(|| "logging isn't initialized".to_string())?
+        .reload(filter)
+        .map_err(|e| e.to_string())
+}
+
 /// This function should be the first to deref ARGS,
 /// giving the program a chance to bail if anything went wrong on initialization.
 /// It is an invariant of this program that any call to ARGs after this call will never fail
@@ -79,6 +99,13 @@ impl SpanDisplay for Record<'_, '_> {
             Record::StreamEnd => "StreamEnd",
             Record::Log { .. } => "Log",
             Record::Error { .. } => "Error",
+            Record::Hello { .. } => "Hello",
+            Record::Heartbeat { .. } => "Heartbeat",
+            Record::Ack { .. } => "Ack",
+            Record::Exit { .. } => "Exit",
+            Record::Channel { .. } => "Channel",
+            Record::Metrics { .. } => "Metrics",
+            Record::Facts { .. } => "Facts",
         };
 
         write!(f, "{}", s)
@@ -139,173 +166,3 @@ where
         self.1.next().map(|e| (first, self.1.peek().is_none(), e))
     }
 }
-
-#[derive(Debug)]
-enum LocalRecord {
-    Header(Header),
-    Data(Data),
-}
-
-impl Into<Record<'static, 'static>> for LocalRecord {
-    fn into(self) -> Record<'static, 'static> {
-        match self {
-            Self::Header(r) => r.into(),
-            Self::Data(r) => r.into(),
-        }
-    }
-}
-
-impl<'i> TryFrom<RecordHeader<'i>> for LocalRecord {
-    type Error = CrateError;
-
-    fn try_from(value: RecordHeader) -> Result<Self, Self::Error> {
-        Ok(Self::Header(value.try_into()?))
-    }
-}
-
-impl<'i, 'd> TryFrom<RecordData<'i, 'd>> for LocalRecord {
-    type Error = CrateError;
-
-    fn try_from(value: RecordData) -> Result<Self, Self::Error> {
-        Ok(Self::Data(value.try_into()?))
-    }
-}
-
-#[derive(Debug)]
-struct Header {
-    pub version: u32,
-    pub time: i64,
-    pub id: String,
-    pub pid: u32,
-    pub cxt: HeaderContext,
-}
-
-impl<'i> TryFrom<RecordHeader<'i>> for Header {
-    type Error = CrateError;
-
-    fn try_from(value: RecordHeader) -> Result<Self, Self::Error> {
-        Ok(Self {
-            version: value.required.version,
-            time: value.time,
-            id: value.id.into(),
-            pid: value.pid,
-            cxt: HeaderContext::try_from(value.cxt)?,
-        })
-    }
-}
-
-impl Into<Record<'static, 'static>> for Header {
-    fn into(self) -> Record<'static, 'static> {
-        Record::Header(RecordHeader {
-            required: Common {
-                version: self.version,
-            },
-            time: self.time,
-            id: self.id.into(),
-            pid: self.pid,
-            cxt: self.cxt.into(),
-        })
-    }
-}
-
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum HeaderContext {
-    Start,
-    End,
-}
-
-impl HeaderContext {
-    const VALID: [RecordContext; 2] = [RecordContext::Start, RecordContext::End];
-}
-
-impl Into<RecordContext> for HeaderContext {
-    fn into(self) -> RecordContext {
-        match self {
-            Self::Start => RecordContext::Start,
-            Self::End => RecordContext::End,
-        }
-    }
-}
-
-impl TryFrom<RecordContext> for HeaderContext {
-    type Error = CrateError;
-
-    fn try_from(value: RecordContext) -> Result<Self, Self::Error> {
-        match value {
-            RecordContext::Start => Ok(Self::Start),
-            RecordContext::End => Ok(Self::End),
-            invald => Err(CrateError::err_invalid_record(invald, &Self::VALID)),
-        }
-    }
-}
-
-#[derive(Debug)]
-struct Data {
-    pub version: u32,
-    pub time: i64,
-    pub id: String,
-    pub pid: u32,
-    pub cxt: DataContext,
-    pub data: String,
-}
-
-impl<'i, 'd> TryFrom<RecordData<'i, 'd>> for Data {
-    type Error = CrateError;
-
-    fn try_from(value: RecordData) -> Result<Self, Self::Error> {
-        Ok(Self {
-            version: value.required.version,
-            time: value.time,
-            id: value.id.into(),
-            pid: value.pid,
-            cxt: DataContext::try_from(value.cxt)?,
-            data: value.data.into(),
-        })
-    }
-}
-
-impl Into<Record<'static, 'static>> for Data {
-    fn into(self) -> Record<'static, 'static> {
-        Record::Data(RecordData {
-            required: Common {
-                version: self.version,
-            },
-            time: self.time,
-            id: self.id.into(),
-            pid: self.pid,
-            cxt: self.cxt.into(),
-            data: self.data.into(),
-        })
-    }
-}
-
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum DataContext {
-    Stdout,
-    Stderr,
-}
-
-impl DataContext {
-    const VALID: [RecordContext; 2] = [RecordContext::Stdout, RecordContext::Stderr];
-}
-
-impl Into<RecordContext> for DataContext {
-    fn into(self) -> RecordContext {
-        match self {
-            Self::Stdout => RecordContext::Stdout,
-            Self::Stderr => RecordContext::Stderr,
-        }
-    }
-}
-
-impl TryFrom<RecordContext> for DataContext {
-    type Error = CrateError;
-
-    fn try_from(value: RecordContext) -> Result<Self, Self::Error> {
-        match value {
-            RecordContext::Stdout => Ok(Self::Stdout),
-            RecordContext::Stderr => Ok(Self::Stderr),
-            invald => Err(CrateError::err_invalid_record(invald, &Self::VALID)),
-        }
-    }
-}
@@ -2,23 +2,27 @@
 
 use {
     crate::{
-        cli::OpKind,
-        load::filters::{FilterSet, JoinSetHandle},
-        models::{Data, DataContext, Header, HeaderContext, LocalRecord},
+        cli::{ExecList, OpKind},
+        load::filters::{FieldsSet, FilterSet, JoinSet, JoinSetHandle, MapSet, Target},
+        models::admin::ADMIN,
         prelude::{CrateResult as Result, *},
     },
     futures::{
         pin_mut,
         prelude::*,
         ready,
-        stream::{Peekable, Stream},
+        stream::Stream,
         task::{Context, Poll},
     },
-    lib_transport::{Record, RecordFrame, RecordInterface, SymmetricalCbor},
-    once_cell::sync::OnceCell,
+    lib_transport::{
+        ack, batch, handshake, metrics, ChannelState, Checksum, Compression, DemuxExt, Demuxed,
+        Encoding, Facts, HeaderContext, IdStream, MetricsExt, OutputStream, OwnedData, OwnedHeader,
+        OwnedRecord, ReassembleExt, Record, RecordFrame, RecordInterface, RecordSerializer,
+        RecvError, SequenceExt, ValidateExt,
+    },
     pin_project::pin_project,
-    std::{collections::HashMap, iter::FromIterator},
-    std::{convert::TryFrom, pin::Pin},
+    std::iter::FromIterator,
+    std::{borrow::Cow, convert::TryFrom, pin::Pin},
     tokio::{
         net::{TcpListener, TcpStream, ToSocketAddrs},
         sync::{
@@ -28,7 +32,6 @@ use {
         task::JoinHandle,
         time::Duration,
     },
-    tokio_serde::Serializer,
 };
 
 pub async fn listener(addr: impl ToSocketAddrs) -> Result<()> {
@@ -51,19 +54,20 @@ pub async fn listener(addr: impl ToSocketAddrs) -> Result<()> {
                 |e| warn!("Failed to accept connection: {}", e),
                 |(socket, client)| {
                     debug!("Accepted connection from: {}", client);
+                    let conn = ADMIN.connected(client);
 
                     tokio::spawn(
                         async move {
-                            let (tx_out, rx_out) = channel::<LocalRecord>(256);
-                            let input = handle_connection(socket)
-                                .then(|stream| split_and_join(stream, tx_out))
+                            let (exec, stream) = handle_connection(socket)
                                 .instrument(always_span!("con.input"))
-                                .map(|_| ());
-                            let output =
-                                handle_output(rx_out).instrument(always_span!("con.output"));
+                                .await;
+                            let (router, outputs) = Router::new(exec);
+                            let input = split_and_join(stream, router, conn)
+                                .instrument(always_span!("con.input"));
 
-                            // Await both the joined records and the final output
-                            tokio::join!(tokio::spawn(input), tokio::spawn(output))
+                            // Await both the joined records and the final outputs
+                            let _ = tokio::join!(tokio::spawn(input), future::join_all(outputs));
+                            ADMIN.disconnected(conn);
                         }
                         .instrument(always_span!("tcp.handler", client = %client)),
                     );
@@ -73,15 +77,111 @@ pub async fn listener(addr: impl ToSocketAddrs) -> Result<()> {
     }
 }
 
-async fn handle_connection<T>(socket: T) -> impl Stream<Item = LocalRecord>
+/// Handshakes with the client, returning the exec list its records go through along with them
+async fn handle_connection<T>(socket: T) -> (&'static ExecList, impl Stream<Item = OwnedRecord>)
 where
-    T: tokio::io::AsyncRead + tokio::io::AsyncWrite,
+    T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
 {
-    let unbound = RecordInterface::from_read(socket);
-    tokio::stream::StreamExt::timeout(unbound, Duration::from_secs(3))
+    let mut interface = RecordInterface::from_both_limited(socket, cli!().limits());
+    let encryption = cli!().encryption();
+    let supported: Vec<&str> = [
+        Checksum::FEATURE_CRC32,
+        Compression::FEATURE_ZSTD,
+        ack::FEATURE,
+        metrics::FEATURE,
+        batch::FEATURE,
+        Facts::FEATURE,
+    ]
+    .iter()
+    .copied()
+    .chain(encryption.feature())
+    .collect();
+    let (accepted, negotiated, first) = match handshake::accept(&mut interface, &supported).await {
+        Ok((negotiated, first)) => (true, negotiated, first),
+        Err(e) => {
+            error!("Handshake failed: {}... terminating connection", e);
+            (false, handshake::Negotiated::legacy(), None)
+        }
+    };
+    debug!(
+        version = negotiated.proto_version,
+        legacy = negotiated.is_legacy(),
+        "Handshake complete"
+    );
+
+    // Once configured with a key, unencrypted connections are refused outright
+    let accepted = match encryption.feature() {
+        Some(feature) if accepted && !negotiated.has_feature(feature) => {
+            error!(
+                "Client did not negotiate '{}' encryption... terminating connection",
+                feature
+            );
+            false
+        }
+        _ => accepted,
+    };
+
+    // Clients may ask for one of the configured pipelines rather than 'exec'
+    let exec = cli!().get_pipeline(negotiated.pipeline.as_deref());
+    let accepted = match (exec, &negotiated.pipeline) {
+        (None, Some(pipeline)) if accepted => {
+            error!(
+                "Client asked for unknown pipeline '{}'... terminating connection",
+                pipeline
+            );
+            false
+        }
+        _ => accepted,
+    };
+    let exec = exec.unwrap_or_else(|| cli!().get_exec_list());
+    if let Some(pipeline) = &negotiated.pipeline {
+        debug!(pipeline = pipeline.as_str(), "Client selected a pipeline");
+    }
+
+    // Clients that predate the handshake can't advertise their checksum, fall back to the CLI
+    let checksum = match (
+        negotiated.is_legacy(),
+        negotiated.has_feature(Checksum::FEATURE_CRC32),
+    ) {
+        (true, _) => cli!().checksum(),
+        (false, true) => Checksum::Crc32,
+        (false, false) => Checksum::None,
+    };
+    let compression = Compression::negotiated(&negotiated);
+
+    let interface = interface
+        .with_checksum(checksum)
+        .with_compression(compression)
+        .with_encryption(encryption.clone())
+        .with_batches(negotiated.has_feature(batch::FEATURE));
+    let records = match negotiated.has_feature(ack::FEATURE) {
+        true => ack::Acknowledge::new(interface, ack::DEFAULT_INTERVAL).left_stream(),
+        false => interface.right_stream(),
+    };
+
+    let unbound = stream::iter(first.map(Ok))
+        .chain(records)
+        .check_sequence(|anomaly| warn!("Inconsistent record stream: {}", anomaly))
+        .take_while(move |_| future::ready(accepted));
+    let records = tokio::stream::StreamExt::timeout(unbound, Duration::from_secs(3))
         .inspect(|record| debug!("=> {:?}", record))
-        .take_while(|timer| future::ready(timer.is_ok()))
+        .take_while(|res| future::ready(match res {
+            Err(_) => false,
+            // Once the socket has failed (including a frame too large to read past)
+            // nothing further in the stream can be trusted
+            Ok(Err(RecvError::Io(e))) => {
+                error!("{}... terminating connection", e);
+                false
+            }
+            Ok(_) => true,
+        }))
         .filter_map(|res| match res.unwrap() {
+            // Heartbeats only exist to keep the connection alive, they've
+            // already reset the timeout so there's nothing left to do with them
+            Ok(Record::Heartbeat(_)) => future::ready({
+                trace!("Heartbeat received");
+                None
+            }),
             Ok(record) => future::ready(Some(record)),
             Err(e) => future::ready({
                 warn!(
@@ -91,133 +191,267 @@ where
                 None
             }),
         })
-        .first_last()
-        .inspect(|(first, last, _)| debug!(first, last))
-        .take_while(|(first, last, record)| future::ready(match record {
-            Record::StreamStart if !first => {
-                error!("Malformed stream, client sent: 'Stream Start' out of sequence... terminating connection");
-                false
-            }
-            Record::StreamEnd if !last => {
-                error!("Malformed stream, client sent: 'Stream End' out of sequence... terminating connection");
+        .validate()
+        .take_while(|res| future::ready(match res {
+            Err(violation) if violation.is_fatal() => {
+                error!("Malformed stream, {}... terminating connection", violation);
                 false
             }
             _ => true
         }))
-        .filter_map(|(_, _, record)| future::ready(match record {
-            Record::Header(rcd) => LocalRecord::try_from(rcd).inspect(|res| if let Err(e) = res {
+        .filter_map(|res| future::ready(match res {
+            Ok(record) => Some(record),
+            Err(violation) => {
+                warn!("Malformed stream, {}... discarding record", violation);
+                None
+            }
+        }))
+        .filter_map(|record| future::ready(match record {
+            Record::Header(rcd) => OwnedHeader::try_from(rcd).map(OwnedRecord::Header).map_err(CrateError::from).inspect(|res| if let Err(e) = res {
                 warn!("{}... discarding record", e)
             }).ok(),
-            Record::Data(rcd) => LocalRecord::try_from(rcd).inspect(|res| if let Err(e) = res {
+            Record::Data(rcd) => OwnedData::try_from(rcd).map(OwnedRecord::Data).map_err(CrateError::from).inspect(|res| if let Err(e) = res {
                 warn!("{}... discarding record", e)
             }).ok(),
+            Record::Channel(rcd) => {
+                debug!(stream = rcd.stream(), state = ?rcd.state, "Channel state changed");
+                Some(OwnedRecord::Channel(rcd))
+            }
+            Record::Error(rcd) => {
+                warn!(id = rcd.id.as_deref().unwrap_or_default(), trace_id = rcd.trace_id.as_deref().unwrap_or_default(), "Upstream reported: {}", rcd.error);
+                Some(OwnedRecord::Error(rcd))
+            }
+            Record::Metrics(rcd) => {
+                trace!(source = %rcd.source, "Metrics received");
+                Some(OwnedRecord::Metrics(rcd))
+            }
+            Record::Facts(rcd) => {
+                debug!(facts = ?rcd.facts, "Upstream described itself");
+                Some(OwnedRecord::Facts(rcd))
+            }
+            Record::Exit(rcd) => {
+                match (rcd.code, rcd.signal) {
+                    (Some(0), _) => debug!(id = %rcd.id, pid = rcd.pid, "Process exited successfully"),
+                    (Some(code), _) => warn!(id = %rcd.id, pid = rcd.pid, code, "Process exited with a failure"),
+                    (None, signal) => warn!(id = %rcd.id, pid = rcd.pid, ?signal, "Process was terminated"),
+                }
+                None
+            }
             other => {info!(kind = %other.span_display(), "Discarding record"); None}
-        }))
+        }));
+
+    (exec, records)
 }
 
-type HandleMap = HashMap<
-    String,
-    (
-        Sender<LocalRecord>,
-        Sender<LocalRecord>,
-        (JoinHandle<()>, JoinHandle<()>),
-    ),
->;
+/// Sends each id's records to the output of the exec list they go through, that of the
+/// connection unless a route names another pipeline
+struct Router {
+    outputs: Vec<(&'static ExecList, Sender<OwnedRecord>)>,
+}
 
-async fn split_and_join<St>(stream: St, output_tx: Sender<LocalRecord>)
-where
-    St: Stream<Item = LocalRecord>,
-{
-    let mut map = HandleMap::new();
-    futures::pin_mut!(stream);
+impl Router {
+    /// Spawns an output for `exec` and each routed pipeline, returning their handles
+    fn new(exec: &'static ExecList) -> (Self, Vec<JoinHandle<Result<()>>>) {
+        let mut handles = Vec::new();
+        let mut outputs: Vec<(&'static ExecList, _)> = Vec::new();
+        for exec in std::iter::once(exec).chain(cli!().routed()) {
+            if outputs.iter().any(|(seen, _)| std::ptr::eq(*seen, exec)) {
+                continue;
+            }
+            let (tx, rx) = channel::<OwnedRecord>(256);
+            handles.push(tokio::spawn(
+                handle_output(rx, exec).instrument(always_span!("con.output")),
+            ));
+            outputs.push((exec, tx));
+        }
+
+        (Self { outputs }, handles)
+    }
+
+    fn route(&self, id: &str) -> (&'static ExecList, Sender<OwnedRecord>) {
+        let (exec, tx) = cli!()
+            .route(id)
+            .and_then(|routed| {
+                self.outputs
+                    .iter()
+                    .find(|(exec, _)| std::ptr::eq(*exec, routed))
+            })
+            .unwrap_or(&self.outputs[0]);
+        trace!(
+            routed = !std::ptr::eq(*exec, self.outputs[0].0),
+            "Routed id"
+        );
+
+        (exec, tx.clone())
+    }
+
+    /// Where a record that isn't an id's goes. Error records follow the id they're of, if
+    /// they have one, anything else describes the connection so every output gets a copy
+    fn copies(&self, record: OwnedRecord) -> Vec<(Sender<OwnedRecord>, OwnedRecord)> {
+        let copy = |record: &OwnedRecord| match record {
+            OwnedRecord::Channel(rcd) => Some(OwnedRecord::Channel(rcd.clone())),
+            OwnedRecord::Metrics(rcd) => Some(OwnedRecord::Metrics(rcd.clone())),
+            OwnedRecord::Facts(rcd) => Some(OwnedRecord::Facts(rcd.clone())),
+            _ => None,
+        };
 
-    while let Some(record) = stream.next().await {
         match record {
-            LocalRecord::Header(header) => handle_header(header, &mut map, output_tx.clone()).await,
-            LocalRecord::Data(data) => handle_data(data, &mut map).await,
+            OwnedRecord::Error(ref rcd) if rcd.id.is_some() => {
+                let (_, tx) = self.route(rcd.id.as_deref().unwrap());
+                vec![(tx, record)]
+            }
+            record => {
+                let mut copies: Vec<_> = self.outputs[1..]
+                    .iter()
+                    .filter_map(|(_, tx)| copy(&record).map(|copy| (tx.clone(), copy)))
+                    .collect();
+                copies.push((self.outputs[0].1.clone(), record));
+                copies
+            }
         }
     }
 }
 
-async fn handle_header(header: Header, map: &mut HandleMap, output_tx: Sender<LocalRecord>) {
-    match (header.cxt, map.contains_key(header.id.as_str())) {
-        (HeaderContext::Start, false) => header_start(header, map, output_tx).await,
-        (HeaderContext::End, true) => header_end(header, map, output_tx).await,
-        (HeaderContext::Start, true) => error!("Duplicate Header record (id: {})", &header.id),
-        (HeaderContext::End, false) => error!(
-            "Malformed stream, received Header end before start (id: {})",
-            &header.id
-        ),
+async fn split_and_join<St>(stream: St, router: Router, conn: u64)
+where
+    St: Stream<Item = OwnedRecord>,
+{
+    // Join-ers of ids that may still be running, by the logical stream they belong to
+    let mut running: Vec<(Option<u32>, JoinHandle<()>)> = Vec::new();
+    let stream = match cli!().join_lines() {
+        Some(max) => future::Either::Left(stream.reassemble_lines(max)),
+        None => future::Either::Right(stream),
+    };
+    let stream = stream.demux_by_id();
+    pin_mut!(stream);
+
+    while let Some(demuxed) = stream.next().await {
+        match demuxed {
+            Demuxed::Id(records) => {
+                running.retain_mut(|(_, handle)| handle.now_or_never().is_none());
+                let stream = records.stream();
+                let span = always_span!("id", id = records.id(), stream = ?stream);
+                let (exec, output_tx) = router.route(records.id());
+                let handle =
+                    tokio::spawn(handle_id(records, output_tx, conn, exec).instrument(span));
+                running.push((stream, handle));
+            }
+            // Only Channel, Error, Metrics and Facts records make it this far otherwise. Every id in a channel
+            // has finished by the time it closes, so wait on that channel's join-ers before
+            // sending it on
+            Demuxed::Record(record) => {
+                if let OwnedRecord::Channel(ref channel) = record {
+                    if channel.state == ChannelState::Close {
+                        let (closed, open) = running
+                            .drain(..)
+                            .partition(|(stream, _)| *stream == Some(channel.stream()));
+                        running = open;
+                        for (_, handle) in closed {
+                            let _ = handle.await;
+                        }
+                    }
+                }
+
+                for (mut output_tx, record) in router.copies(record) {
+                    output_tx
+                        .send(record)
+                        .unwrap_or_else(|e| error!("join TX closed unexpectedly: {}", e))
+                        .await
+                }
+            }
+            Demuxed::Unmatched(record) => match record {
+                OwnedRecord::Header(header) if header.cxt == HeaderContext::Start => {
+                    error!("Duplicate Header record (id: {})", &header.id)
+                }
+                OwnedRecord::Header(header) => error!(
+                    "Malformed stream, received Header end before start (id: {})",
+                    &header.id
+                ),
+                OwnedRecord::Data(data) => warn!(
+                    "Data record (id: {}) sent out of sequence... discarding",
+                    &data.id
+                ),
+                _ => unreachable!(),
+            },
+        }
     }
 }
 
-async fn header_start(header: Header, map: &mut HandleMap, mut output_tx: Sender<LocalRecord>) {
-    let (out_tx, out_rx) = channel::<LocalRecord>(256);
-    let (err_tx, err_rx) = channel::<LocalRecord>(256);
+async fn handle_id(
+    mut records: IdStream,
+    mut output_tx: Sender<OwnedRecord>,
+    conn: u64,
+    exec: &'static ExecList,
+) {
+    let id = records.id().to_string();
+    ADMIN.id_opened(conn, &id);
 
-    // Spawn join-er tasks
-    let stdout =
-        tokio::spawn(handle_stream(out_rx, output_tx.clone()).instrument(always_span!("stdout")));
-    let stderr =
-        tokio::spawn(handle_stream(err_rx, output_tx.clone()).instrument(always_span!("stderr")));
+    let (mut out_tx, out_rx) = channel::<OwnedRecord>(256);
+    let (mut err_tx, err_rx) = channel::<OwnedRecord>(256);
 
-    map.insert(header.id.clone(), (out_tx, err_tx, (stdout, stderr)));
-
-    trace!(id = header.id.as_str(), "Added stream to map");
+    // Spawn join-er tasks
+    let stdout = tokio::spawn(
+        handle_stream(out_rx, output_tx.clone(), exec).instrument(always_span!("stdout")),
+    );
+    let stderr = tokio::spawn(
+        handle_stream(err_rx, output_tx.clone(), exec).instrument(always_span!("stderr")),
+    );
 
-    // Send header to output
-    output_tx
-        .send(LocalRecord::Header(header))
-        .unwrap_or_else(|e| error!("join TX closed unexpectedly: {}", e))
-        .await;
-}
+    let mut end = None;
+    while let Some(record) = records.next().await {
+        match record {
+            OwnedRecord::Data(data) => {
+                let tx = match data.cxt {
+                    OutputStream::Stdout => &mut out_tx,
+                    OutputStream::Stderr => &mut err_tx,
+                };
+                tx.send(OwnedRecord::Data(data))
+                    .unwrap_or_else(|e| error!("join TX closed unexpectedly: {}", e))
+                    .await
+            }
+            // The Header end must follow every record the join-ers produce
+            OwnedRecord::Header(header) if header.cxt == HeaderContext::End => end = Some(header),
+            start => {
+                trace!("Opened id");
+                output_tx
+                    .send(start)
+                    .unwrap_or_else(|e| error!("join TX closed unexpectedly: {}", e))
+                    .await
+            }
+        }
+    }
 
-async fn header_end(header: Header, map: &mut HandleMap, mut output_tx: Sender<LocalRecord>) {
-    let (o, e, barrier) = map.remove(header.id.as_str()).unwrap();
-    let id = header.id.as_str();
     // Indicate to join-ers that input is finished
-    drop((o, e));
+    drop((out_tx, err_tx));
 
     // Synchronize with join-ers
-    trace!(id, "Just before waiting on stdout/err streams");
-    let (_, _) = tokio::join!(barrier.0, barrier.1);
-
-    trace!(id, "Removed stream from map");
-
-    output_tx
-        .send(LocalRecord::Header(header))
-        .unwrap_or_else(|e| error!("join TX closed unexpectedly: {}", e))
-        .await;
-}
+    trace!("Just before waiting on stdout/err streams");
+    let (_, _) = tokio::join!(stdout, stderr);
 
-async fn handle_data(data: Data, map: &mut HandleMap) {
-    match (data.cxt, map.contains_key(data.id.as_str())) {
-        (DataContext::Stdout, true) => {
-            map.get_mut(data.id.as_str())
-                .unwrap()
-                .0
-                .send(LocalRecord::Data(data))
-                .unwrap_or_else(|e| error!("join TX closed unexpectedly: {}", e))
-                .await;
-        }
-        (DataContext::Stderr, true) => {
-            map.get_mut(data.id.as_str())
-                .unwrap()
-                .1
-                .send(LocalRecord::Data(data))
-                .unwrap_or_else(|e| error!("join TX closed unexpectedly: {}", e))
-                .await;
-        }
-        _ => warn!(
-            "Data record (id: {}) sent out of sequence... discarding",
-            &data.id
-        ),
+    if let Some(header) = end {
+        trace!("Closed id");
+        output_tx
+            .send(OwnedRecord::Header(header))
+            .unwrap_or_else(|e| error!("join TX closed unexpectedly: {}", e))
+            .await;
     }
+    ADMIN.id_closed(conn, &id);
 }
 
-async fn handle_stream(rx: Receiver<LocalRecord>, mut output_tx: Sender<LocalRecord>) {
+async fn handle_stream(
+    rx: Receiver<OwnedRecord>,
+    mut output_tx: Sender<OwnedRecord>,
+    exec: &'static ExecList,
+) {
     let stream = rx.inspect(|record| trace!("pre-ops: {:?}", &record));
-    let mut stream = apply_ops(stream, cli!().get_exec_list().get_ops());
+    let cli = cli!();
+    let sets = (
+        cli.get_join(),
+        cli.get_filter(),
+        cli.get_map(),
+        cli.get_fields(),
+    );
+    let mut stream = apply_ops(stream, exec.get_ops(), sets);
 
     while let Some(record) = stream.next().await {
         trace!("post-ops: {:?}", &record);
@@ -225,26 +459,37 @@ async fn handle_stream(rx: Receiver<LocalRecord>, mut output_tx: Sender<LocalRec
     }
 }
 
+/// The sets each kind of op reads from
+type OpSets<'cli> = (
+    &'cli JoinSet,
+    &'cli FilterSet,
+    &'cli MapSet,
+    &'cli FieldsSet,
+);
+
+/// Chains `ops` onto `stream` in the order given
 fn apply_ops<'a, 'cli: 'a, St: 'a, I>(
     stream: St,
     ops: Option<I>,
-) -> Box<dyn Stream<Item = LocalRecord> + Unpin + Send + 'a>
+    (join, filter, map, fields): OpSets<'cli>,
+) -> Box<dyn Stream<Item = OwnedRecord> + Unpin + Send + 'a>
 where
-    St: Stream<Item = LocalRecord> + Unpin + Send,
+    St: Stream<Item = OwnedRecord> + Unpin + Send,
     I: Iterator<Item = OpKind<'cli>>,
 {
     match ops {
         Some(ops) => ops.fold(Box::new(stream), |state, op| match op {
-            OpKind::Join => Box::new(state.join_records(cli!().get_join().new_handle())),
-            OpKind::Filter(name) => Box::new(state.filter_records(cli!().get_filter(), name)),
+            OpKind::Join => Box::new(state.join_records(join.new_handle())),
+            OpKind::Filter(name) => Box::new(state.filter_records(filter, name)),
+            OpKind::Map(name) => Box::new(state.map_records(map, name)),
+            OpKind::Fields(name) => Box::new(state.extract_fields(fields, name)),
         }),
         None => Box::new(stream),
     }
 }
 
-async fn handle_output(output_rx: Receiver<LocalRecord>) -> Result<()> {
-    let loaders = cli!()
-        .get_exec_list()
+async fn handle_output(output_rx: Receiver<OwnedRecord>, exec: &'static ExecList) -> Result<()> {
+    let loaders = exec
         .get_loaders()
         .map(|iter| {
             iter.fold(broadcast::channel(256), |(tx, rx), load| {
@@ -261,23 +506,43 @@ async fn handle_output(output_rx: Receiver<LocalRecord>) -> Result<()> {
     match loaders {
         Some(tx) => {
             pin_mut!(tx);
-            stream::once(future::ready(Record::StreamStart))
+            let mut serializer = RecordSerializer::new();
+            let serialized = stream::once(future::ready(Record::StreamStart))
                 .chain(output_rx.map(|local| local.into()))
                 .chain(stream::once(future::ready(Record::StreamEnd)))
-                .map(|record| {
-                    let mkr = SymmetricalCbor::<Record>::default();
-                    pin_mut!(mkr);
-                    Serializer::serialize(mkr, &record).map_err(CrateError::from)
-                })
-                // Due to a [compiler bug](https://github.com/rust-lang/rust/issues/64552) as of 2020/03/23 we must box this stream.
-                // The bug occurs due to the compiler erasing certain lifetime bounds in a generator (namely 'static ones) leading to the false
-                // assumption that lifetime 'a: 'static and 'b: 'static do not live as long as each other. This leads to inscrutable error messages.
-                // TODO: Once said issue is resolved remove this allocation.
-                .boxed()
-                .try_for_each(|serialized_record| {
-                    future::ready(tx.send(serialized_record)).map(|_| Ok(()))
-                })
-                .await
+                .map(move |record| serializer.serialize(&record).map_err(CrateError::from));
+
+            match cli!().metrics() {
+                Some(period) => {
+                    // Metrics are counted over serialized records, so stop at the first
+                    // record that couldn't be serialized and report it after the fact
+                    let mut failed = None;
+                    serialized
+                        .map(|res| res.map_err(|e| failed = Some(e)).ok())
+                        .take_while(|record| future::ready(record.is_some()))
+                        .map(Option::unwrap)
+                        .metrics("transform", period, RecordSerializer::new())
+                        // See the comment below for why this is boxed
+                        .boxed()
+                        .for_each(|serialized_record| {
+                            future::ready(tx.send(serialized_record)).map(|_| ())
+                        })
+                        .await;
+                    failed.take().map_or(Ok(()), Err)
+                }
+                None => {
+                    serialized
+                        // Due to a [compiler bug](https://github.com/rust-lang/rust/issues/64552) as of 2020/03/23 we must box this stream.
+                        // The bug occurs due to the compiler erasing certain lifetime bounds in a generator (namely 'static ones) leading to the false
+                        // assumption that lifetime 'a: 'static and 'b: 'static do not live as long as each other. This leads to inscrutable error messages.
+                        // TODO: Once said issue is resolved remove this allocation.
+                        .boxed()
+                        .try_for_each(|serialized_record| {
+                            future::ready(tx.send(serialized_record)).map(|_| Ok(()))
+                        })
+                        .await
+                }
+            }
         }
         None => {
             output_rx
@@ -326,74 +591,6 @@ where
     Ok(())
 }
 
-pub trait FindFirstLast: Stream + Sized {
-    fn first_last(self) -> FirstLast<Self>;
-}
-
-impl<St> FindFirstLast for St
-where
-    St: Stream,
-{
-    fn first_last(self) -> FirstLast<Self> {
-        FirstLast {
-            first: OnceCell::new(),
-            inner: self.peekable(),
-            item: None,
-        }
-    }
-}
-
-/// A stream that checks whether the current item is the first or last item in the stream.
-/// Be aware that this type makes use of a peekable stream and as such every item in the stream
-/// awaits the item after it, not itself, i.e: given a stream `[1 ,2 ,3 ,4]`, `1` will only be returned
-/// once `2` as been successfully polled
-#[pin_project]
-pub struct FirstLast<St>
-where
-    St: Stream,
-{
-    #[pin]
-    inner: Peekable<St>,
-    first: OnceCell<()>,
-    item: Option<St::Item>,
-}
-
-impl<St> Stream for FirstLast<St>
-where
-    St: Stream,
-{
-    type Item = (bool, bool, St::Item);
-
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let mut this = self;
-
-        // If we have an item already, don't poll_next, just see if poll_peek is ready
-        if this.item.is_some() {
-            let last = ready!(this.as_mut().project().inner.poll_peek(cx)).is_none();
-            let item = this.as_mut().project().item.take().unwrap();
-            let first = this.first.set(()).is_ok();
-            return Poll::Ready(Some((first, last, item)));
-        }
-
-        // Else get the next item in the stream
-        match ready!(this.as_mut().project().inner.poll_next(cx)) {
-            Some(item) => match this.as_mut().project().inner.poll_peek(cx) {
-                Poll::Pending => {
-                    // If the peek isn't ready, place the current item into local storage
-                    *this.as_mut().project().item = Some(item);
-                    Poll::Pending
-                }
-                Poll::Ready(peek) => {
-                    let last = peek.is_none();
-                    let first = this.first.set(()).is_ok();
-                    Poll::Ready(Some((first, last, item)))
-                }
-            },
-            None => Poll::Ready(None),
-        }
-    }
-}
-
 trait JoinRecords: Stream + Sized {
     fn join_records(self, handle: JoinSetHandle<'_>) -> Join<Self>;
 }
@@ -407,6 +604,7 @@ where
             inner: self,
             overflow: None,
             ongoing: None,
+            finished: false,
             handle,
         }
     }
@@ -419,37 +617,40 @@ where
 {
     #[pin]
     inner: St,
-    overflow: Option<Data>,
-    ongoing: Option<Data>,
+    /// The record that ended the last join, returned right after it
+    overflow: Option<OwnedRecord>,
+    ongoing: Option<OwnedData>,
+    /// Whether inner has ended, so that it is never polled again
+    finished: bool,
     handle: JoinSetHandle<'j>,
 }
 
 impl<St> Stream for Join<'_, St>
 where
-    St: Stream<Item = LocalRecord>,
+    St: Stream<Item = OwnedRecord>,
 {
-    type Item = LocalRecord;
+    type Item = OwnedRecord;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let mut this = self;
 
         // If the last call had overflow data, return it before polling for the next item
         if this.overflow.is_some() {
-            return Poll::Ready(
-                this.as_mut()
-                    .project()
-                    .overflow
-                    .take()
-                    .map(LocalRecord::Data),
-            );
+            return Poll::Ready(this.as_mut().project().overflow.take());
+        }
+        if this.finished {
+            return Poll::Ready(None);
         }
 
         loop {
             match ready!(this.as_mut().project().inner.poll_next(cx)) {
-                None => return Poll::Ready(None),
+                // Any join still ongoing is complete once there is nothing left to join
+                None => {
+                    *this.as_mut().project().finished = true;
+                    return Poll::Ready(this.project().ongoing.take().map(OwnedRecord::Data));
+                }
                 Some(record) => match record {
-                    header @ LocalRecord::Header(_) => return Poll::Ready(Some(header)),
-                    LocalRecord::Data(data) => {
+                    OwnedRecord::Data(data) => {
                         // There are 4 possible outcomes for a Data record depending of the state of
                         // (A, B) where A and B are bools and represent:
                         // A: Whether we currently have an ongoing join
@@ -459,31 +660,47 @@ where
                             this.as_mut()
                                 .project()
                                 .handle
-                                .should_join(data.data.as_str()),
+                                .should_join(&Target::new(&data)),
                         ) {
                             // No ongoing join & current record is not a join
-                            (false, false) => return Poll::Ready(Some(LocalRecord::Data(data))),
+                            (false, false) => return Poll::Ready(Some(OwnedRecord::Data(data))),
                             // No ongoing join, but the current record IS a join... set it as the ongoing join
                             (false, true) => *this.as_mut().project().ongoing = Some(data),
                             // Ongoing join, which has now finished because the current record IS NOT a join
                             (true, false) => {
                                 // Put the overflow item in local storage
-                                *this.as_mut().project().overflow = Some(data);
-                                let join = this.project().ongoing.take().map(LocalRecord::Data);
+                                *this.as_mut().project().overflow = Some(OwnedRecord::Data(data));
+                                let join = this.project().ongoing.take().map(OwnedRecord::Data);
                                 return Poll::Ready(join);
                             }
                             // Ongoing join, which will continue as the current record is a join
                             (true, true) => {
-                                // Append a newline and extend the base data with the current data
-                                // Note that copied() here does not copy data only a reference
+                                // Append a newline and extend the base data with the current data,
+                                // the join is only as well formed as its least well formed part
                                 if let Some(ongoing) = this.as_mut().project().ongoing.as_mut() {
-                                    ongoing
-                                        .data
-                                        .extend(["\n", data.data.as_str()].iter().copied())
+                                    ongoing.data.push(b'\n');
+                                    ongoing.data.extend_from_slice(&data.data);
+                                    if data.encoding == Encoding::Binary {
+                                        ongoing.encoding = Encoding::Binary
+                                    }
+                                    // Keep the first value seen of any field we don't understand
+                                    for (tag, value) in data.unknown {
+                                        if ongoing.unknown.iter().all(|(t, _)| *t != tag) {
+                                            ongoing.unknown.push((tag, value))
+                                        }
+                                    }
                                 };
                             }
                         }
                     }
+                    // Records that aren't Data end any ongoing join, which must come first
+                    other => match this.as_mut().project().ongoing.take() {
+                        Some(join) => {
+                            *this.as_mut().project().overflow = Some(other);
+                            return Poll::Ready(Some(OwnedRecord::Data(join)));
+                        }
+                        None => return Poll::Ready(Some(other)),
+                    },
                 },
             }
         }
@@ -525,7 +742,7 @@ where
 
 impl<St> Stream for RecordFilter<'_, St>
 where
-    St: Stream<Item = LocalRecord>,
+    St: Stream<Item = OwnedRecord>,
 {
     type Item = St::Item;
 
@@ -535,18 +752,321 @@ where
         loop {
             match ready!(this.as_mut().project().inner.poll_next(cx)) {
                 Some(record) => match record {
-                    header @ LocalRecord::Header(_) => return Poll::Ready(Some(header)),
-                    LocalRecord::Data(record) => {
-                        if this.set.is_match_with(this.filter_name, &record.data) {
-                            trace!(data = %record.data, "MATCH");
-                            return Poll::Ready(Some(LocalRecord::Data(record)));
+                    OwnedRecord::Data(record) if ADMIN.is_disabled(this.filter_name) => {
+                        return Poll::Ready(Some(OwnedRecord::Data(record)))
+                    }
+                    OwnedRecord::Data(record) => {
+                        if this
+                            .set
+                            .is_match_with(this.filter_name, &Target::new(&record))
+                        {
+                            trace!(data = %record.text(), "MATCH");
+                            return Poll::Ready(Some(OwnedRecord::Data(record)));
                         } else {
-                            trace!(data = %record.data, "NO MATCH");
+                            trace!(data = %record.text(), "NO MATCH");
                         }
                     }
+                    other => return Poll::Ready(Some(other)),
                 },
                 None => return Poll::Ready(None),
             }
         }
     }
 }
+
+trait MapRecords: Stream + Sized {
+    fn map_records<'cli>(self, set: &'cli MapSet, key: &'cli str) -> RecordMap<'cli, Self>;
+}
+
+impl<St> MapRecords for St
+where
+    St: Stream,
+{
+    fn map_records<'cli>(self, set: &'cli MapSet, key: &'cli str) -> RecordMap<'cli, Self> {
+        RecordMap {
+            inner: self,
+            map_name: key,
+            set,
+        }
+    }
+}
+
+#[pin_project]
+struct RecordMap<'m, St>
+where
+    St: Stream,
+{
+    #[pin]
+    inner: St,
+    map_name: &'m str,
+    set: &'m MapSet,
+}
+
+impl<St> Stream for RecordMap<'_, St>
+where
+    St: Stream<Item = OwnedRecord>,
+{
+    type Item = St::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        match ready!(this.inner.poll_next(cx)) {
+            // Binary payloads are passed through untouched, as rewriting their lossy text
+            // would corrupt them
+            Some(OwnedRecord::Data(mut record)) if record.encoding == Encoding::Utf8 => {
+                let replaced = match this.set.apply_with(this.map_name, &record.text()) {
+                    Cow::Owned(replaced) => Some(replaced),
+                    Cow::Borrowed(_) => None,
+                };
+                if let Some(replaced) = replaced {
+                    trace!(data = %replaced, "MAPPED");
+                    record.data = replaced.into_bytes();
+                }
+
+                Poll::Ready(Some(OwnedRecord::Data(record)))
+            }
+            other => Poll::Ready(other),
+        }
+    }
+}
+
+trait ExtractFields: Stream + Sized {
+    fn extract_fields<'cli>(self, set: &'cli FieldsSet, key: &'cli str)
+        -> RecordFields<'cli, Self>;
+}
+
+impl<St> ExtractFields for St
+where
+    St: Stream,
+{
+    fn extract_fields<'cli>(
+        self,
+        set: &'cli FieldsSet,
+        key: &'cli str,
+    ) -> RecordFields<'cli, Self> {
+        RecordFields {
+            inner: self,
+            fields_name: key,
+            set,
+        }
+    }
+}
+
+#[pin_project]
+struct RecordFields<'f, St>
+where
+    St: Stream,
+{
+    #[pin]
+    inner: St,
+    fields_name: &'f str,
+    set: &'f FieldsSet,
+}
+
+impl<St> Stream for RecordFields<'_, St>
+where
+    St: Stream<Item = OwnedRecord>,
+{
+    type Item = St::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        match ready!(this.inner.poll_next(cx)) {
+            Some(OwnedRecord::Data(mut record)) => {
+                let text = record.text().into_owned();
+                let stored = this
+                    .set
+                    .extract_with(this.fields_name, &text, &mut record.meta);
+                trace!(data = %text, stored, "FIELDS");
+
+                Poll::Ready(Some(OwnedRecord::Data(record)))
+            }
+            other => Poll::Ready(other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        lib_transport::{Common, Meta, PROTOCOL_VERSION},
+    };
+
+    /// A stack trace is joined onto the error it follows, and any password redacted
+    const CONFIG: &str = "
+filter:
+  errors:
+    - contains: 'Error'
+  quiet:
+    - not:
+        - contains: 'noise'
+join:
+  start:
+    - starts_with: 'Error'
+  while:
+    - starts_with: ' '
+map:
+  redact:
+    - 'password=\\S+ -> password=***'
+fields:
+  timing:
+    - '(?P<task>\\w+) took (?P<ms>\\d+)ms'
+";
+
+    fn data(text: &str) -> OwnedRecord {
+        OwnedRecord::Data(OwnedData {
+            required: Common::new(PROTOCOL_VERSION),
+            time: 0,
+            id: "10_some-script.sh".to_string(),
+            pid: 1,
+            cxt: OutputStream::Stdout,
+            data: text.as_bytes().to_vec(),
+            encoding: Encoding::Utf8,
+            trace_id: None,
+            meta: Meta::new(),
+            unknown: Vec::new(),
+        })
+    }
+
+    /// Runs `records` through `exec`, returning the text of each Data record out, or the
+    /// kind of any other
+    fn run(exec: &str, records: Vec<OwnedRecord>) -> Vec<String> {
+        let join = JoinSet::new_filter(CONFIG.as_bytes()).unwrap();
+        let filter = FilterSet::new_filter(CONFIG.as_bytes()).unwrap();
+        let map = MapSet::new_map(CONFIG.as_bytes()).unwrap();
+        let fields = FieldsSet::new_fields(CONFIG.as_bytes()).unwrap();
+        let exec: ExecList = serde_yaml::from_str(exec).unwrap();
+
+        let stream = apply_ops(
+            stream::iter(records),
+            exec.get_ops(),
+            (&join, &filter, &map, &fields),
+        );
+        futures::executor::block_on(stream.collect::<Vec<_>>())
+            .into_iter()
+            .map(|record| match record {
+                OwnedRecord::Data(data) => data.text().into_owned(),
+                OwnedRecord::StreamStart => "StreamStart".to_string(),
+                OwnedRecord::StreamEnd => "StreamEnd".to_string(),
+                other => format!("{:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn join_then_filter_keeps_whole_joins() {
+        let records = vec![
+            data("Error: boom"),
+            data("  at main.rs:1"),
+            data("ok"),
+            data("  at main.rs:2"),
+        ];
+
+        let out = run("[join, filter: errors]", records);
+
+        assert_eq!(out, vec!["Error: boom\n  at main.rs:1"]);
+    }
+
+    #[test]
+    fn filter_then_join_then_map_joins_what_passes() {
+        let records = vec![
+            data("Error: boom"),
+            data("noise"),
+            data("  password=hunter2"),
+            data("done"),
+        ];
+
+        let out = run("[filter: quiet, join, map: redact]", records);
+
+        assert_eq!(out, vec!["Error: boom\n  password=***", "done"]);
+    }
+
+    #[test]
+    fn join_pending_at_stream_end_is_flushed() {
+        let records = vec![data("Error: boom"), data("  at main.rs:1")];
+        assert_eq!(run("[join]", records), vec!["Error: boom\n  at main.rs:1"]);
+
+        let records = vec![data("Error: boom"), OwnedRecord::StreamEnd];
+        assert_eq!(run("[join]", records), vec!["Error: boom", "StreamEnd"]);
+    }
+
+    /// Generates `count` records from `seed`, returning them with the lines expected out of
+    /// any ordering of `join`, `filter: quiet` and `map: redact`: every line except noise,
+    /// redacted, with each StreamStart and StreamEnd where it was
+    fn generate(seed: u64, count: usize) -> (Vec<OwnedRecord>, Vec<String>) {
+        let mut state = seed | 1;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        let mut records = Vec::with_capacity(count);
+        let mut expected = Vec::with_capacity(count);
+        for i in 0..count {
+            let (record, line) = match next() % 8 {
+                0 => (data(&format!("Error: boom {}", i)), None),
+                1 => (data(&format!("  at main.rs:{}", i)), None),
+                2 => (
+                    data(&format!("  password=hunter{}", i)),
+                    Some("  password=***".to_string()),
+                ),
+                3 => (data(&format!("noise {}", i)), Some(String::new())),
+                4 => (data(&format!("build took {}ms", i)), None),
+                5 => (OwnedRecord::StreamStart, Some("StreamStart".to_string())),
+                6 => (OwnedRecord::StreamEnd, Some("StreamEnd".to_string())),
+                _ => (data(&format!("ok {}", i)), None),
+            };
+            let line = line.unwrap_or_else(|| match &record {
+                OwnedRecord::Data(data) => data.text().into_owned(),
+                _ => unreachable!(),
+            });
+            if !line.is_empty() {
+                expected.push(line);
+            }
+            records.push(record);
+        }
+
+        (records, expected)
+    }
+
+    /// Returns every ordering of `ops`
+    fn orderings(ops: &[&'static str]) -> Vec<Vec<&'static str>> {
+        if ops.len() <= 1 {
+            return vec![ops.to_vec()];
+        }
+        (0..ops.len())
+            .flat_map(|i| {
+                let mut rest = ops.to_vec();
+                let first = rest.remove(i);
+                orderings(&rest).into_iter().map(move |mut order| {
+                    order.insert(0, first);
+                    order
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_op_order_loses_nothing() {
+        let ops = ["join", "filter: quiet", "map: redact", "fields: timing"];
+
+        for seed in 1..=4 {
+            for order in orderings(&ops) {
+                let (records, expected) = generate(seed, 2000);
+                let exec = format!("[{}]", order.join(", "));
+                let out: Vec<String> = run(&exec, records)
+                    .iter()
+                    .flat_map(|text| text.split('\n'))
+                    .map(str::to_string)
+                    .collect();
+
+                assert_eq!(out, expected, "seed {} with {}", seed, exec);
+            }
+        }
+    }
+}